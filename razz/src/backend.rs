@@ -0,0 +1,132 @@
+use crate::controller::CameraController;
+use crate::display::Display;
+use crate::output::save_png;
+
+use razz_lib::{Dither, Image, Scene};
+use winit::event::*;
+
+// One way of producing the image the viewer shows. The `Viewer` owns the window,
+// camera controls and presentation, and drives a backend through these stages each
+// frame, so a new backend only has to know how to trace.
+pub trait RenderBackend {
+    fn scene(&self) -> &Scene;
+
+    fn scene_mut(&mut self) -> &mut Scene;
+
+    // Moves the camera with the controller, starting over if it moved
+    fn update_camera(&mut self, controller: &mut CameraController) {
+        if controller.update_camera(&mut self.scene_mut().sampler) {
+            self.reset();
+        }
+    }
+
+    // Drops the samples accumulated so far
+    fn reset(&mut self);
+
+    // Called after the display's textures are recreated at the new size
+    fn resize(&mut self, display: &Display);
+
+    // Writes this frame into `display.target()`, directly or by recording into
+    // `encoder`, which is submitted before the target is presented
+    fn render(&mut self, display: &Display, encoder: &mut wgpu::CommandEncoder);
+
+    // The accumulated image in linear light
+    fn read_image(&self, display: &Display) -> anyhow::Result<Image>;
+
+    fn samples_per_pixel(&self) -> usize;
+
+    // Keys the backend handles itself
+    fn input(&mut self, _event: &WindowEvent) -> bool {
+        false
+    }
+
+    // Writes `{stem}.png`, along with anything else the backend records
+    fn save_frame(&self, display: &Display, stem: &str, dither: Dither) -> anyhow::Result<()> {
+        let image = self.read_image(display)?;
+        save_png(
+            &self.scene().post.apply(&image),
+            format!("{}.png", stem),
+            dither,
+        )
+    }
+}
+
+pub struct Viewer {
+    display: Display,
+    backend: Box<dyn RenderBackend>,
+    camera_controller: CameraController,
+    dither: Dither,
+}
+
+impl Viewer {
+    pub fn new(display: Display, backend: Box<dyn RenderBackend>, dither: Dither) -> Self {
+        Self {
+            display,
+            backend,
+            camera_controller: CameraController::new(),
+            dither,
+        }
+    }
+
+    pub fn size(&self) -> winit::dpi::PhysicalSize<u32> {
+        self.display.size
+    }
+
+    pub fn scene(&self) -> &Scene {
+        self.backend.scene()
+    }
+
+    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        self.display.resize(new_size);
+        self.backend.resize(&self.display);
+    }
+
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        if self.camera_controller.process_event(event) {
+            return true;
+        }
+
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::F12),
+                        ..
+                    },
+                ..
+            } => {
+                self.save_frame();
+                true
+            }
+            _ => self.backend.input(event),
+        }
+    }
+
+    pub fn update(&mut self) {
+        self.backend.update_camera(&mut self.camera_controller);
+    }
+
+    pub fn render(&mut self) -> Result<(), wgpu::SwapChainError> {
+        let mut encoder =
+            self.display
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Render Encoder"),
+                });
+        self.backend.render(&self.display, &mut encoder);
+        self.display.present(encoder)
+    }
+
+    fn save_frame(&self) {
+        let stem = format!("razz_{}", self.display.frame_number());
+        match self.backend.save_frame(&self.display, &stem, self.dither) {
+            Ok(_) => println!(
+                "Saved {} at {} samples",
+                stem,
+                self.backend.samples_per_pixel()
+            ),
+            Err(e) => eprintln!("Failed to save {}: {:?}", stem, e),
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use crate::basic_scene_02;
+use crate::output::{
+    load_image, load_image_file, parse_dither, print_progress, save_png, save_png_tiled,
+};
+use crate::registry;
+
+use razz_lib::{
+    export_obj, import_mtl, AssetResolver, BandRenderer, Dither, Encoding, ScenePack, TiledImage,
+    WorldBuilder, PACK_EXTENSION,
+};
+
+pub fn diff(args: &[String]) -> anyhow::Result<()> {
+    let (path_a, path_b) = match args {
+        [a, b, ..] => (a, b),
+        _ => anyhow::bail!("usage: razz diff <imageA> <imageB> [heatmap.png]"),
+    };
+    let heat_map_path = args.get(2).map(|s| s.as_str()).unwrap_or("diff.png");
+
+    let image_a = load_image_file(path_a)?;
+    let image_b = load_image_file(path_b)?;
+
+    let diff = image_a.diff(&image_b)?;
+    println!(
+        "RMSE r: {:.6} g: {:.6} b: {:.6} a: {:.6}",
+        diff.rmse[0], diff.rmse[1], diff.rmse[2], diff.rmse[3]
+    );
+    println!(
+        "Max error {:.6} at ({}, {})",
+        diff.max_error, diff.max_error_location.0, diff.max_error_location.1
+    );
+
+    save_png(&diff.heat_map, heat_map_path, Dither::None)?;
+    println!("Wrote heat map to {}", heat_map_path);
+    Ok(())
+}
+
+const POSTER_TILE_SIZE: usize = 256;
+
+pub fn poster(args: &[String]) -> anyhow::Result<()> {
+    let usage = "usage: razz poster <width> <height> <output.png> [samples] [dither]";
+    let (width, height, path) = match args {
+        [w, h, path, ..] => (w.parse::<usize>()?, h.parse::<usize>()?, path),
+        _ => anyhow::bail!(usage),
+    };
+    let samples = match args.get(3) {
+        Some(s) => s.parse::<usize>()?,
+        None => 16,
+    };
+    let dither = match args.get(4) {
+        Some(name) => parse_dither(name)?,
+        None => Dither::default(),
+    };
+
+    // Tiles are kept next to the output and removed once the png is written
+    let tiles_path = format!("{}.tiles", path);
+    let tiles_x = width.div_ceil(POSTER_TILE_SIZE);
+    let mut image = TiledImage::create(&tiles_path, width, height, POSTER_TILE_SIZE, tiles_x)?;
+
+    let scene = basic_scene_02();
+    println!("Rendering {}x{} at {} spp", width, height, samples);
+    BandRenderer::new(width, height, POSTER_TILE_SIZE, 5, samples)
+        .on_progress(print_progress)
+        .render_to(&scene, &mut image)?;
+    println!();
+
+    save_png_tiled(&mut image, path, dither)?;
+    drop(image);
+    std::fs::remove_file(&tiles_path)?;
+    println!("Wrote {}", path);
+    Ok(())
+}
+
+// Bundles a scene file with the assets it references, found relative to the scene
+pub fn pack(args: &[String]) -> anyhow::Result<()> {
+    let (scene_path, assets) = match args {
+        [scene, assets @ ..] => (scene, assets),
+        _ => anyhow::bail!("usage: razz pack <scene> [assets...]"),
+    };
+
+    let resolver = AssetResolver::relative_to(scene_path);
+    for asset in assets {
+        resolver.read(asset)?;
+    }
+    let pack = ScenePack::package(scene_path, &resolver)?;
+
+    let path = std::path::Path::new(scene_path).with_extension(PACK_EXTENSION);
+    pack.save(&path)?;
+    println!(
+        "Wrote {} with {} files",
+        path.display(),
+        pack.names().count()
+    );
+    Ok(())
+}
+
+// Summarizes what a scene from `--scene` is made of
+pub fn info(args: &[String]) -> anyhow::Result<()> {
+    let scene = match args.first() {
+        Some(name_or_path) => registry::load_scene(name_or_path)?,
+        None => basic_scene_02(),
+    };
+    let world = &scene.world;
+    let bounds = world.bounds();
+
+    println!("Primitives: {}", world.primitive_count());
+    println!("Materials:  {}", world.materials().len());
+    println!("Textures:   {}", world.textures().len());
+    println!("Lights:     {}", world.lights().len());
+    println!(
+        "Bounds:     ({:.3}, {:.3}, {:.3}) to ({:.3}, {:.3}, {:.3})",
+        bounds.min.x, bounds.min.y, bounds.min.z, bounds.max.x, bounds.max.y, bounds.max.z
+    );
+    let missing = world.missing_textures();
+    if missing > 0 {
+        println!("Missing textures: {}", missing);
+    }
+    Ok(())
+}
+
+// Writes a scene from `--scene` out as OBJ and MTL, for opening in other tools
+pub fn export(args: &[String]) -> anyhow::Result<()> {
+    let (name_or_path, path) = match args {
+        [scene, path, ..] => (scene, path),
+        _ => anyhow::bail!("usage: razz export <scene> <output.obj>"),
+    };
+    let scene = registry::load_scene(name_or_path)?;
+    let skipped = export_obj(&scene.world, path)?;
+    println!("Wrote {}", path);
+    if skipped > 0 {
+        println!(
+            "Skipped {} planes, curves, volumes or other shapes without triangles",
+            skipped
+        );
+    }
+    Ok(())
+}
+
+// Shows how the materials of an MTL file map onto razz materials, optionally saving
+// the report as JSON
+pub fn materials(args: &[String]) -> anyhow::Result<()> {
+    let path = match args.first() {
+        Some(path) => path,
+        None => anyhow::bail!("usage: razz materials <file.mtl> [report.json]"),
+    };
+    // Absolute, so the file named is the one read even with `RAZZ_ASSET_PATH` set
+    let path = std::fs::canonicalize(path)?;
+    let assets = AssetResolver::relative_to(&path);
+    let mut world_builder = WorldBuilder::default();
+    let report = import_mtl(&path, &assets, &mut world_builder, |map| {
+        // Color maps are stored sRGB encoded
+        load_image(map, &assets)
+            .ok()
+            .map(|image| image.decode(Encoding::Srgb))
+    })?;
+
+    for conversion in &report.materials {
+        println!("{} -> {}", conversion.source, conversion.material);
+        for note in &conversion.approximated {
+            println!("  approximated {}", note);
+        }
+        for note in &conversion.dropped {
+            println!("  dropped {}", note);
+        }
+    }
+    println!(
+        "{} of {} materials converted exactly",
+        report.materials.len() - report.lossy().count(),
+        report.materials.len()
+    );
+    if let Some(report_path) = args.get(1) {
+        std::fs::write(report_path, report.to_json())?;
+        println!("Wrote {}", report_path);
+    }
+    Ok(())
+}
@@ -1,28 +1,126 @@
-[[group(0), binding(0)]] 
+[[group(0), binding(0)]]
 var out_texture: [[access(write)]] texture_storage_2d<rgba32float>;
-[[group(0), binding(1)]] 
+[[group(0), binding(1)]]
 var in_texture: [[access(read)]] texture_storage_2d<rgba32float>;
 
-[[stage(compute), workgroup_size(32, 32)]]
-fn main([[builtin(global_invocation_id)]] global_id: vec3<u32>) {
-    let window_size = vec2<f32>(800.0, 600.0);
-    let pixel_coordinates: vec2<i32> = vec2<i32>(global_id.xy);
-    let uv = vec2<f32>(pixel_coordinates) / (window_size - 1.0);
+// Written every frame by GpuBackend::render, laid out as in `GpuBackend::frame_bytes`
+[[block]]
+struct Frame {
+    camera: GpuCamera;
+    background_top: vec4<f32>;
+    background_bottom: vec4<f32>;
+    size: vec2<u32>;
+    frame_number: u32;
+    max_depth: u32;
+    epsilon: f32;
+    // Samples already averaged into `in_texture`, zero to start over
+    sample_count: u32;
+    // First row and number of rows this device traces. The textures hold only those,
+    // from their top row.
+    rows: vec2<u32>;
+};
+
+[[group(0), binding(7)]]
+var<uniform> frame: Frame;
+
+let MIN_ROULETTE_BOUNCES: u32 = 3u;
+
+var<private> rng_state: u32;
+
+// PCG, one stream per pixel and frame
+fn random() -> f32 {
+    rng_state = rng_state * 747796405u + 2891336453u;
+    let word = ((rng_state >> ((rng_state >> 28u) + 4u)) ^ rng_state) * 277803737u;
+    return f32(((word >> 22u) ^ word) >> 8u) / 16777216.0;
+}
+
+struct Ray {
+    origin: vec3<f32>;
+    direction: vec3<f32>;
+};
 
-    // let aspect_ratio = window_size.x / window_size.y;
-    // let viewport_height = 2.0;
-    // let viewport_width = aspect_ratio * viewport_height;
-    // let focal_length = 1.0;
+// Matches Camera::get_ray
+fn camera_ray(pixel: vec2<u32>) -> Ray {
+    let camera = frame.camera;
+    let jitter = vec2<f32>(random(), random());
+    if (camera.w.w > 0.5) {
+        let st = (vec2<f32>(pixel) + jitter) / vec2<f32>(frame.size);
+        let phi = (st.x - 0.5) * 2.0 * SCENE_PI;
+        let theta = clamp(st.y, 0.0, 1.0) * SCENE_PI;
+        let direction = sin(theta) * (sin(phi) * camera.u.xyz - cos(phi) * camera.w.xyz)
+            + cos(theta) * camera.v.xyz;
+        return Ray(camera.origin.xyz, direction);
+    }
 
-    // let origin = Vector(0.0);
-    // let horizontal = Vector(viewport_width, 0.0, 0.0);
-    // let vertical = Vector(0.0, viewport_height, 0.0);
-    // let camera = Camera(origin, horizontal, vertical, origin - 0.5*horizontal + 0.5*vertical - Vector(0.0, 0.0, focal_length));
+    let st = (vec2<f32>(pixel) + jitter) / vec2<f32>(max(frame.size, vec2<u32>(2u)) - vec2<u32>(1u));
+    let r = camera.origin.w * sqrt(random());
+    let angle = 2.0 * SCENE_PI * random();
+    let offset = camera.u.xyz * r * cos(angle) + camera.v.xyz * r * sin(angle);
+    let origin = camera.origin.xyz + offset;
+    let direction = camera.corner.xyz + st.x * camera.horizontal.xyz - st.y * camera.vertical.xyz - origin;
+    return Ray(origin, direction);
+}
+
+fn background(direction: vec3<f32>) -> vec3<f32> {
+    let t = 0.5 * (normalize(direction).y + 1.0);
+    return mix(frame.background_bottom.xyz, frame.background_top.xyz, vec3<f32>(t));
+}
+
+// World::trace without light sampling, so emitters are only found by scattering
+fn ray_color(camera_ray: Ray) -> vec3<f32> {
+    var ray = camera_ray;
+    var throughput = vec3<f32>(1.0);
+    var radiance = vec3<f32>(0.0);
+    var bounce: u32 = 0u;
+    loop {
+        if (bounce >= frame.max_depth) {
+            break;
+        }
+        let hit = trace_scene(ray.origin, ray.direction, frame.epsilon);
+        if (!hit.hit) {
+            radiance = radiance + throughput * background(ray.direction);
+            break;
+        }
 
-    // let r = get_ray(camera, uv.x, uv.y);
+        let m = scene_materials.materials[hit.material];
+        radiance = radiance + throughput * material_emit(m, hit.uv);
+        let xi = vec4<f32>(random(), random(), random(), random());
+        let scatter = scatter_material(m, ray.direction, hit.normal, hit.front_face, hit.uv, xi);
+        if (!scatter.scattered) {
+            break;
+        }
+        throughput = throughput * scatter.attenuation;
+        ray = Ray(hit.point, scatter.direction);
 
-    // let pixel_Color = ray_Color(r);
+        // Russian roulette, reweighted so the estimate stays unbiased
+        if (bounce >= MIN_ROULETTE_BOUNCES) {
+            let survival = min(max(throughput.x, max(throughput.y, throughput.z)), 1.0);
+            if (survival <= 0.0 || random() >= survival) {
+                break;
+            }
+            throughput = throughput / survival;
+        }
+        bounce = bounce + 1u;
+    }
+    return radiance;
+}
+
+[[stage(compute), workgroup_size(8, 8)]]
+fn main([[builtin(global_invocation_id)]] global_id: vec3<u32>) {
+    if (global_id.x >= frame.size.x || global_id.y >= frame.rows.y) {
+        return;
+    }
+    let pixel = vec2<u32>(global_id.x, global_id.y + frame.rows.x);
+    let pixel_coordinates = vec2<i32>(global_id.xy);
+    rng_state = (pixel.y * frame.size.x + pixel.x) * 9781u + frame.frame_number * 6271u;
+    random();
 
-    let pixel_color = textureLoad(in_texture, pixel_coordinates);
-    textureStore(out_texture, pixel_coordinates, vec4<f32>(uv.x, uv.y, 0.25, 1.0));
+    let color = vec4<f32>(ray_color(camera_ray(pixel)), 1.0);
+    // A running mean, as ParallelRenderer accumulates on the CPU
+    var mean = color;
+    if (frame.sample_count > 0u) {
+        let previous = textureLoad(in_texture, pixel_coordinates);
+        mean = previous + (color - previous) / f32(frame.sample_count + 1u);
+    }
+    textureStore(out_texture, pixel_coordinates, mean);
 }
@@ -0,0 +1,175 @@
+use razz_lib::{Camera, Vec3A};
+use winit::{dpi::PhysicalPosition, event::*};
+
+const WORLD_UP: Vec3A = Vec3A::Y;
+// Furthest the orbit pitches above or below `look_at`, short of straight up or down
+// where yaw no longer turns the view
+const MAX_PITCH: f32 = 1.55;
+
+#[derive(Debug, Default)]
+pub struct CameraController {
+    forward: bool,
+    backward: bool,
+    left: bool,
+    right: bool,
+    up: bool,
+    down: bool,
+
+    is_dragging: bool,
+    last_cursor: Option<PhysicalPosition<f64>>,
+    orbit: (f32, f32),
+    zoom: f32,
+}
+
+impl CameraController {
+    const MOVE_SPEED: f32 = 0.02;
+    const ORBIT_SPEED: f32 = 0.005;
+    const ZOOM_SPEED: f32 = 0.1;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn process_event(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state,
+                        virtual_keycode: Some(keycode),
+                        ..
+                    },
+                ..
+            } => {
+                let is_pressed = *state == ElementState::Pressed;
+                match keycode {
+                    VirtualKeyCode::W => self.forward = is_pressed,
+                    VirtualKeyCode::S => self.backward = is_pressed,
+                    VirtualKeyCode::A => self.left = is_pressed,
+                    VirtualKeyCode::D => self.right = is_pressed,
+                    VirtualKeyCode::E => self.up = is_pressed,
+                    VirtualKeyCode::Q => self.down = is_pressed,
+                    _ => return false,
+                }
+                true
+            }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => {
+                self.is_dragging = *state == ElementState::Pressed;
+                self.last_cursor = None;
+                true
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                if !self.is_dragging {
+                    return false;
+                }
+                if let Some(last) = self.last_cursor {
+                    self.orbit.0 += (position.x - last.x) as f32;
+                    self.orbit.1 += (position.y - last.y) as f32;
+                }
+                self.last_cursor = Some(*position);
+                true
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.zoom += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 50.0,
+                };
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Returns true if the camera moved and accumulation must restart
+    pub fn update_camera(&mut self, camera: &mut Camera) -> bool {
+        let mut look_from = camera.look_from();
+        let mut look_at = camera.look_at();
+        let offset = look_from - look_at;
+        let distance = offset.length();
+
+        let forward = -offset.normalize();
+        // Looking straight up or down leaves no sideways direction to strafe along
+        let right = forward
+            .cross(WORLD_UP)
+            .try_normalize()
+            .unwrap_or_else(|| forward.any_orthonormal_vector());
+
+        let mut translation = Vec3A::ZERO;
+        if self.forward {
+            translation += forward;
+        }
+        if self.backward {
+            translation -= forward;
+        }
+        if self.right {
+            translation += right;
+        }
+        if self.left {
+            translation -= right;
+        }
+        if self.up {
+            translation += WORLD_UP;
+        }
+        if self.down {
+            translation -= WORLD_UP;
+        }
+        let moved = translation != Vec3A::ZERO;
+        translation *= distance * Self::MOVE_SPEED;
+        look_from += translation;
+        look_at += translation;
+
+        let orbited = self.orbit != (0.0, 0.0);
+        if orbited {
+            // Yaw about the world up, with the pitch as an angle from the horizon so it
+            // can be clamped
+            let direction = offset / distance;
+            let yaw = direction.z.atan2(direction.x) + self.orbit.0 * Self::ORBIT_SPEED;
+            let pitch = (direction.y.clamp(-1.0, 1.0).asin() + self.orbit.1 * Self::ORBIT_SPEED)
+                .clamp(-MAX_PITCH, MAX_PITCH);
+            let direction = Vec3A::new(
+                pitch.cos() * yaw.cos(),
+                pitch.sin(),
+                pitch.cos() * yaw.sin(),
+            );
+            look_from = look_at + distance * direction;
+            self.orbit = (0.0, 0.0);
+        }
+
+        let zoomed = self.zoom != 0.0;
+        if zoomed {
+            let scale = (1.0 - self.zoom * Self::ZOOM_SPEED).max(0.1);
+            look_from = look_at + (look_from - look_at) * scale;
+            self.zoom = 0.0;
+        }
+
+        let changed = moved || orbited || zoomed;
+        if changed {
+            camera.set_view(look_from, look_at);
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looking_straight_down_stays_finite() {
+        let mut camera = Camera::new(5.0 * WORLD_UP, Vec3A::ZERO, 40.0, 1.0, 0.0, 5.0);
+        let mut controller = CameraController::new();
+        controller.right = true;
+        controller.orbit = (30.0, 30.0);
+        assert!(controller.update_camera(&mut camera));
+
+        let offset = camera.look_from() - camera.look_at();
+        assert!(offset.is_finite() && camera.look_at().is_finite());
+        assert!((offset.length() - 5.0).abs() < 1e-3);
+        // Pulled off the pole, where yaw turns the view again
+        assert!(offset.normalize().dot(WORLD_UP) < MAX_PITCH.sin() + 1e-4);
+    }
+}
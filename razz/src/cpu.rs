@@ -1,341 +1,449 @@
-use crate::{basic_scene_02, RenderData, State};
-
-use rand::thread_rng;
-use razz_lib::{ParallelRenderer, Scene};
-use winit::{event::*, window::Window};
-
-pub struct CpuState {
-    surface: wgpu::Surface,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
-    sc_desc: wgpu::SwapChainDescriptor,
-    swap_chain: wgpu::SwapChain,
-    size: winit::dpi::PhysicalSize<u32>,
-
-    render_data: RenderData,
-
+use crate::backend::RenderBackend;
+use crate::display::Display;
+use crate::output::{print_progress, save_bracketed, save_png, save_report};
+
+use razz_lib::{
+    dominant_light_map, ATrousDenoiser, ColorGrade, Denoiser, Dither, Encoding, Float, Image,
+    ParallelRenderer, RenderMode, Rgba, SampleSequence, Scene, SpectralIntegrator, Technique,
+    Tonemap,
+};
+use std::time::Duration;
+use winit::event::*;
+
+// Traces on the CPU with `ParallelRenderer` and uploads each frame to the display
+pub struct CpuBackend {
     renderer: ParallelRenderer,
     scene: Scene,
-    frame_number: u32,
+    bracket: bool,
+    denoise: bool,
+    split_view: bool,
+    show_lights: bool,
+    // What `[` and `]` adjust, and on which channel, with none for all three
+    grade_control: GradeControl,
+    grade_channel: Option<usize>,
+    settings: RenderSettings,
 }
 
-// https://sotrh.github.io/learn-wgpu/beginner/tutorial2-swapchain/
-impl CpuState {
-    // Creating some of the wgpu types requires async code
-    pub async fn new(window: &Window) -> Self {
-        let size = window.inner_size();
-
-        let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
-        let surface = unsafe { instance.create_surface(window) };
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-            })
-            .await
-            .unwrap();
-
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::default(),
-                    label: None,
-                },
-                None, // Trace path
-            )
-            .await
-            .unwrap();
-
-        let sc_desc = wgpu::SwapChainDescriptor {
-            usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
-            format: adapter.get_swap_chain_preferred_format(&surface).unwrap(),
-            width: size.width,
-            height: size.height,
-            present_mode: wgpu::PresentMode::Fifo,
-        };
-        let swap_chain = device.create_swap_chain(&surface, &sc_desc);
-
-        let (render_pipeline, render_bind_group_layout) =
-            Self::make_render_pipeline(&device, &sc_desc);
-
-        let new_texture_data = Self::make_render_textures(&device, &size);
-        let render_textures = new_texture_data.0;
-        let render_texture_views = new_texture_data.1;
+// The settings of the scene's color grade the viewer can adjust
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GradeControl {
+    Lift,
+    Gamma,
+    Gain,
+    Saturation,
+    Contrast,
+}
 
-        let render_bind_groups = [
-            device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: Some("render_bind_group_0"),
-                layout: &render_bind_group_layout,
-                entries: &[wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&render_texture_views[0]),
-                }],
-            }),
-            device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: Some("render_bind_group_1"),
-                layout: &render_bind_group_layout,
-                entries: &[wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&render_texture_views[1]),
-                }],
-            }),
-        ];
+impl GradeControl {
+    fn next(self) -> Self {
+        match self {
+            Self::Lift => Self::Gamma,
+            Self::Gamma => Self::Gain,
+            Self::Gain => Self::Saturation,
+            Self::Saturation => Self::Contrast,
+            Self::Contrast => Self::Lift,
+        }
+    }
 
-        let render_data = RenderData {
-            render_pipeline,
-            render_bind_group_layout,
-            render_bind_groups,
-            render_textures,
-            render_texture_views,
+    // Nudges the control up or down a step. Lift, gamma and gain change on `channel`
+    // alone, or every channel without one.
+    fn adjust(self, grade: &mut ColorGrade, channel: Option<usize>, up: bool) {
+        let sign = if up { 1.0 } else { -1.0 };
+        let per_channel = |values: &mut [Float; 3], step: Float, min: Float| {
+            for (i, value) in values.iter_mut().enumerate() {
+                if channel.map_or(true, |c| c == i) {
+                    *value = (*value + sign * step).max(min);
+                }
+            }
         };
-
-        // let renderer = ProgressiveRenderer::new(size.width as usize, size.height as usize, 5);
-        let renderer = ParallelRenderer::new(size.width as usize, size.height as usize, 5);
-
-        let scene = basic_scene_02();
-
-        Self {
-            surface,
-            device,
-            queue,
-            sc_desc,
-            swap_chain,
-            size,
-            render_data,
-            renderer,
-            scene,
-            frame_number: 0,
+        match self {
+            Self::Lift => per_channel(&mut grade.lift, 0.02, -1.0),
+            Self::Gamma => per_channel(&mut grade.gamma, 0.1, 0.1),
+            Self::Gain => per_channel(&mut grade.gain, 0.1, 0.0),
+            Self::Saturation => grade.saturation = (grade.saturation + sign * 0.1).max(0.0),
+            Self::Contrast => grade.contrast = (grade.contrast + sign * 0.1).max(0.0),
         }
     }
+}
 
-    fn make_render_textures(
-        device: &wgpu::Device,
-        size: &winit::dpi::PhysicalSize<u32>,
-    ) -> ([wgpu::Texture; 2], [wgpu::TextureView; 2]) {
-        let textures = [
-            device.create_texture(&wgpu::TextureDescriptor {
-                label: Some("Image"),
-                size: wgpu::Extent3d {
-                    width: size.width,
-                    height: size.height,
-                    depth_or_array_layers: 1,
-                },
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba32Float,
-                usage: wgpu::TextureUsage::STORAGE
-                    | wgpu::TextureUsage::COPY_DST
-                    | wgpu::TextureUsage::COPY_SRC,
-            }),
-            device.create_texture(&wgpu::TextureDescriptor {
-                label: Some("Image"),
-                size: wgpu::Extent3d {
-                    width: size.width,
-                    height: size.height,
-                    depth_or_array_layers: 1,
-                },
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba32Float,
-                usage: wgpu::TextureUsage::STORAGE
-                    | wgpu::TextureUsage::COPY_DST
-                    | wgpu::TextureUsage::COPY_SRC,
-            }),
-        ];
-        let texture_views = [
-            textures[0].create_view(&wgpu::TextureViewDescriptor::default()),
-            textures[1].create_view(&wgpu::TextureViewDescriptor::default()),
-        ];
+// How much tracing the viewer does, set from the command line
+#[derive(Debug, Clone, Copy)]
+pub struct RenderSettings {
+    pub max_depth: usize,
+    // Full passes per frame, unless `frame_budget` is set
+    pub samples_per_frame: usize,
+    // Rendering stops once every pixel has this many samples
+    pub target_samples: Option<usize>,
+    // Time spent tracing per frame, instead of a fixed number of passes
+    pub frame_budget: Option<Duration>,
+    // Profile which objects the first passes hit and test those first after
+    pub profile_traversal: bool,
+    // Record each sampling technique's mean and variance, saved with every frame
+    pub technique_aovs: bool,
+    pub sample_sequence: SampleSequence,
+    // Pixel spacing of the sparse previews shown before the first full pass, 1 for none
+    pub preview_stride: usize,
+    // Noise added when saved frames are rounded to 8 bits
+    pub dither: Dither,
+    // Path tracing, or one of the quick looks at geometry and lighting
+    pub render_mode: RenderMode,
+    // Path traced a wavelength at a time, so dispersive glass splits light into colors
+    pub spectral: bool,
+}
 
-        (textures, texture_views)
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            max_depth: 5,
+            samples_per_frame: 1,
+            target_samples: None,
+            frame_budget: None,
+            profile_traversal: false,
+            technique_aovs: false,
+            sample_sequence: SampleSequence::Random,
+            preview_stride: 1,
+            dither: Dither::None,
+            render_mode: RenderMode::Path,
+            spectral: false,
+        }
     }
+}
 
-    fn make_render_pipeline(
-        device: &wgpu::Device,
-        sc_desc: &wgpu::SwapChainDescriptor,
-    ) -> (wgpu::RenderPipeline, wgpu::BindGroupLayout) {
-        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-            label: Some("Render"),
-            flags: wgpu::ShaderFlags::all(),
-            source: wgpu::ShaderSource::Wgsl(include_str!("render.wgsl").into()),
-        });
-
-        let render_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("texture_bind_group_layout"),
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStage::COMPUTE | wgpu::ShaderStage::FRAGMENT,
-                    ty: wgpu::BindingType::StorageTexture {
-                        access: wgpu::StorageTextureAccess::ReadOnly,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        format: wgpu::TextureFormat::Rgba32Float,
-                    },
-                    count: None,
-                }],
-            });
-
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&render_bind_group_layout],
-                push_constant_ranges: &[],
+impl CpuBackend {
+    pub fn new(display: &Display, bracket: bool, settings: RenderSettings, scene: Scene) -> Self {
+        let size = display.size;
+        let mut renderer = ParallelRenderer::new(
+            size.width as usize,
+            size.height as usize,
+            settings.max_depth,
+        );
+        renderer.set_target_samples(settings.target_samples);
+        renderer.set_sample_sequence(settings.sample_sequence);
+        renderer.set_preview_stride(settings.preview_stride);
+        renderer.set_render_mode(settings.render_mode);
+        if settings.spectral && settings.render_mode == RenderMode::Path {
+            renderer.set_integrator(SpectralIntegrator {
+                max_depth: settings.max_depth,
             });
+        }
+        renderer.on_progress(print_progress);
+        if settings.technique_aovs {
+            renderer.enable_technique_aovs();
+        }
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "main",
-                buffers: &[],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "main",
-                targets: &[wgpu::ColorTargetState {
-                    format: sc_desc.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrite::ALL,
-                }],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                clamp_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-        });
-
-        (render_pipeline, render_bind_group_layout)
+        let mut backend = Self {
+            renderer,
+            scene,
+            bracket,
+            denoise: false,
+            split_view: false,
+            show_lights: false,
+            grade_control: GradeControl::Saturation,
+            grade_channel: None,
+            settings,
+        };
+        backend.resize(display);
+        if settings.profile_traversal {
+            let pixels = size.width as u64 * size.height as u64;
+            backend.scene.world.enable_traversal_profile(4 * pixels);
+        }
+        backend
     }
 }
 
-impl State for CpuState {
-    fn size(&self) -> winit::dpi::PhysicalSize<u32> {
-        self.size
+impl RenderBackend for CpuBackend {
+    fn scene(&self) -> &Scene {
+        &self.scene
     }
 
-    fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
-        self.size = new_size;
-        self.sc_desc.width = new_size.width;
-        self.sc_desc.height = new_size.height;
-        self.swap_chain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
-
-        let new_texture_data = Self::make_render_textures(&self.device, &self.size);
-        self.render_data.render_textures = new_texture_data.0;
-        self.render_data.render_texture_views = new_texture_data.1;
+    fn scene_mut(&mut self) -> &mut Scene {
+        &mut self.scene
+    }
 
-        self.render_data.render_bind_groups = [
-            self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: Some("render_bind_group_0"),
-                layout: &self.render_data.render_bind_group_layout,
-                entries: &[wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(
-                        &self.render_data.render_texture_views[0],
-                    ),
-                }],
-            }),
-            self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: Some("render_bind_group_1"),
-                layout: &self.render_data.render_bind_group_layout,
-                entries: &[wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(
-                        &self.render_data.render_texture_views[1],
-                    ),
-                }],
-            }),
-        ];
+    fn reset(&mut self) {
+        self.renderer.reset();
+    }
 
-        // self.renderer =
-        //     ProgressiveRenderer::new(self.size.width as usize, self.size.height as usize, 5);
-        self.renderer =
-            ParallelRenderer::new(self.size.width as usize, self.size.height as usize, 5);
+    fn resize(&mut self, display: &Display) {
+        self.renderer.resize(
+            display.size.width as usize,
+            display.size.height as usize,
+            &mut self.scene,
+        );
     }
 
-    fn input(&mut self, _event: &WindowEvent) -> bool {
-        false
+    fn read_image(&self, _display: &Display) -> anyhow::Result<Image> {
+        Ok(self.renderer.image().clone())
     }
 
-    fn update(&mut self) {}
+    fn samples_per_pixel(&self) -> usize {
+        self.renderer.samples_per_pixel()
+    }
 
-    fn render(&mut self) -> Result<(), wgpu::SwapChainError> {
-        if self.frame_number % 10 == 0 {
-            println!("Frame number: {}", self.frame_number);
+    fn input(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::N),
+                        ..
+                    },
+                ..
+            } => {
+                self.denoise = !self.denoise;
+                if self.denoise {
+                    self.renderer.enable_aovs();
+                }
+                println!("Denoise: {}", self.denoise);
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::V),
+                        ..
+                    },
+                ..
+            } => {
+                self.split_view = !self.split_view;
+                if self.split_view {
+                    self.renderer.enable_aovs();
+                }
+                println!("Denoise split view: {}", self.split_view);
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::L),
+                        ..
+                    },
+                ..
+            } => {
+                self.show_lights = !self.show_lights;
+                if self.show_lights {
+                    self.renderer.enable_light_aovs();
+                }
+                println!("Dominant light view: {}", self.show_lights);
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::T),
+                        ..
+                    },
+                ..
+            } => {
+                let display = &mut self.scene.post.display;
+                display.tonemap = match display.tonemap {
+                    Tonemap::Clamp => Tonemap::Reinhard { white: 4.0 },
+                    Tonemap::Reinhard { .. } => Tonemap::Aces,
+                    Tonemap::Aces => Tonemap::Clamp,
+                };
+                println!("Tonemap: {:?}", display.tonemap);
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode:
+                            Some(keycode @ (VirtualKeyCode::Comma | VirtualKeyCode::Period)),
+                        ..
+                    },
+                ..
+            } => {
+                let display = &mut self.scene.post.display;
+                display.exposure += match keycode {
+                    VirtualKeyCode::Comma => -0.5,
+                    _ => 0.5,
+                };
+                println!("Exposure: {:+.1} EV", display.exposure);
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(keycode @ (VirtualKeyCode::G | VirtualKeyCode::C)),
+                        ..
+                    },
+                ..
+            } => {
+                match keycode {
+                    VirtualKeyCode::G => self.grade_control = self.grade_control.next(),
+                    _ => {
+                        self.grade_channel = match self.grade_channel {
+                            None => Some(0),
+                            Some(c) if c < 2 => Some(c + 1),
+                            Some(_) => None,
+                        }
+                    }
+                }
+                let channel = match self.grade_channel {
+                    Some(c) => ["red", "green", "blue"][c],
+                    None => "all channels",
+                };
+                println!("Grading: {:?} on {}", self.grade_control, channel);
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode:
+                            Some(keycode @ (VirtualKeyCode::LBracket | VirtualKeyCode::RBracket)),
+                        ..
+                    },
+                ..
+            } => {
+                let (control, channel) = (self.grade_control, self.grade_channel);
+                let up = *keycode == VirtualKeyCode::RBracket;
+                let grade = self.scene.post.edit_grade(|grade| {
+                    control.adjust(grade, channel, up);
+                    *grade
+                });
+                println!(
+                    "Lift: {:.2?} Gamma: {:.2?} Gain: {:.2?} Saturation: {:.1} Contrast: {:.1}",
+                    grade.lift, grade.gamma, grade.gain, grade.saturation, grade.contrast
+                );
+                true
+            }
+            _ => false,
         }
+    }
 
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Render Encoder"),
-            });
-
-        let mut _rng = thread_rng();
-        self.queue.write_texture(
+    fn render(&mut self, display: &Display, _encoder: &mut wgpu::CommandEncoder) {
+        let target_samples = self.settings.target_samples;
+        let finished = |renderer: &ParallelRenderer| match target_samples {
+            Some(target) => renderer.samples_per_pixel() >= target,
+            None => false,
+        };
+        if !finished(&self.renderer) {
+            match self.settings.frame_budget {
+                Some(budget) => {
+                    self.renderer.render_for(&self.scene, budget);
+                }
+                None => {
+                    for _ in 0..self.settings.samples_per_frame {
+                        self.renderer.render(&self.scene);
+                        if finished(&self.renderer) {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        let raw = self.renderer.image();
+        let processed = match (self.show_lights, self.renderer.aovs()) {
+            (true, _) => self.renderer.light_aovs().map(dominant_light_map),
+            (false, Some(aovs)) if self.split_view => Some(split_screen(
+                raw,
+                &ATrousDenoiser::default().denoise(raw, aovs),
+            )),
+            (false, Some(aovs)) if self.denoise => {
+                Some(ATrousDenoiser::default().denoise(raw, aovs))
+            }
+            _ => None,
+        };
+        let image = processed.as_ref().unwrap_or(raw);
+        let presented = match display.format() {
+            // The swap chain encodes these itself
+            wgpu::TextureFormat::Bgra8UnormSrgb | wgpu::TextureFormat::Rgba8UnormSrgb => {
+                let mut post = self.scene.post.clone();
+                post.display.encoding = Encoding::Linear;
+                post.apply(image)
+            }
+            _ => self.scene.post.apply(image),
+        };
+        let size = display.size;
+        display.queue.write_texture(
             wgpu::ImageCopyTexture {
-                texture: &self.render_data.render_textures[(self.frame_number % 2) as usize],
+                texture: display.target(),
                 mip_level: 0,
                 origin: wgpu::Origin3d::ZERO,
             },
-            // self.renderer.render(&self.scene, &mut rng).as_bytes(),
-            self.renderer.render(&self.scene).as_bytes(),
+            presented.as_bytes(),
             wgpu::ImageDataLayout {
                 offset: 0,
-                bytes_per_row: std::num::NonZeroU32::new(4 * 4 * self.size.width),
-                rows_per_image: std::num::NonZeroU32::new(self.size.height),
+                bytes_per_row: std::num::NonZeroU32::new(4 * 4 * size.width),
+                rows_per_image: std::num::NonZeroU32::new(size.height),
             },
             wgpu::Extent3d {
-                width: self.size.width,
-                height: self.size.height,
+                width: size.width,
+                height: size.height,
                 depth_or_array_layers: 1,
             },
         );
+    }
 
-        let frame = self.swap_chain.get_current_frame()?.output;
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &frame.view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
-                        store: true,
-                    },
-                }],
-                depth_stencil_attachment: None,
-            });
-            render_pass.set_pipeline(&self.render_data.render_pipeline);
-            render_pass.set_bind_group(
-                0,
-                &self.render_data.render_bind_groups[(self.frame_number % 2) as usize],
-                &[],
-            );
-            render_pass.draw(0..3, 0..1);
+    // Along with the image, the render report and any light or technique AOVs
+    fn save_frame(&self, _display: &Display, stem: &str, dither: Dither) -> anyhow::Result<()> {
+        match self.bracket {
+            true => save_bracketed(
+                self.renderer.image(),
+                &self.scene.post.display,
+                dither,
+                stem,
+            ),
+            false => save_png(
+                &self.renderer.display_image(&self.scene),
+                format!("{}.png", stem),
+                dither,
+            ),
         }
-        self.queue.submit(std::iter::once(encoder.finish()));
-
-        self.frame_number += 1;
+        .and_then(|_| save_report(&self.renderer.report(&self.scene), format!("{}.json", stem)))
+        .and_then(|_| {
+            let buffers = self.renderer.light_aovs().unwrap_or_default();
+            for (index, buffer) in buffers.iter().enumerate() {
+                save_png(
+                    &buffer.display(&self.scene.post.display),
+                    format!("{}_light_{}.png", stem, index),
+                    dither,
+                )?;
+            }
+            Ok(())
+        })
+        .and_then(|_| {
+            let techniques = match self.renderer.technique_aovs() {
+                Some(techniques) => techniques,
+                None => return Ok(()),
+            };
+            for technique in Technique::ALL.iter() {
+                for (kind, image) in [
+                    ("mean", techniques.mean(*technique)),
+                    ("variance", techniques.variance(*technique)),
+                ] {
+                    save_png(
+                        &image.display(&self.scene.post.display),
+                        format!("{}_{}_{}.png", stem, technique.name(), kind),
+                        dither,
+                    )?;
+                }
+            }
+            std::fs::write(format!("{}_techniques.json", stem), techniques.to_json())?;
+            Ok(())
+        })
+    }
+}
 
-        Ok(())
+// Raw accumulation on the left and denoised on the right, split by a one pixel divider
+fn split_screen(raw: &Image, denoised: &Image) -> Image {
+    let split = raw.width / 2;
+    let mut image = raw.clone();
+    for y in 0..image.height {
+        for x in split..image.width {
+            let color = match x == split {
+                true => Rgba::ONE,
+                false => denoised.get_pixel_color(x, y),
+            };
+            image.set_pixel_color(x, y, color);
+        }
     }
+    image
 }
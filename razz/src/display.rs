@@ -0,0 +1,386 @@
+use razz_lib::Image;
+use winit::window::Window;
+
+// The window's swap chain and the pair of Rgba32Float textures backends render into.
+// Each frame one texture is written and shown, while the other holds the frame before,
+// so backends that accumulate on the GPU can read it back.
+pub struct Display {
+    surface: wgpu::Surface,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    sc_desc: wgpu::SwapChainDescriptor,
+    swap_chain: wgpu::SwapChain,
+    pub size: winit::dpi::PhysicalSize<u32>,
+    adapter_info: wgpu::AdapterInfo,
+    render_data: RenderData,
+    frame_number: u32,
+}
+
+struct RenderData {
+    render_pipeline: wgpu::RenderPipeline,
+    render_bind_group_layout: wgpu::BindGroupLayout,
+    render_bind_groups: [wgpu::BindGroup; 2],
+    render_textures: [wgpu::Texture; 2],
+    render_texture_views: [wgpu::TextureView; 2],
+}
+
+// https://sotrh.github.io/learn-wgpu/beginner/tutorial2-swapchain/
+impl Display {
+    // Tries the primary backends and then the secondary ones (GL, DX11), failing with
+    // every adapter wgpu can see if none of them can present to the window
+    pub async fn new(window: &Window) -> anyhow::Result<Self> {
+        let mut failures = Vec::new();
+        for backends in [wgpu::BackendBit::PRIMARY, wgpu::BackendBit::SECONDARY] {
+            match Self::with_backends(window, backends).await {
+                Ok(display) => return Ok(display),
+                Err(e) => failures.push(format!("{:?}: {}", backends, e)),
+            }
+        }
+
+        let adapters: Vec<_> = wgpu::Instance::new(wgpu::BackendBit::all())
+            .enumerate_adapters(wgpu::BackendBit::all())
+            .map(|adapter| {
+                let info = adapter.get_info();
+                format!(
+                    "  {} ({:?}, {:?})",
+                    info.name, info.backend, info.device_type
+                )
+            })
+            .collect();
+        anyhow::bail!(
+            "No usable GPU adapter\n{}\nAdapters found:\n{}",
+            failures.join("\n"),
+            match adapters.is_empty() {
+                true => "  none".to_string(),
+                false => adapters.join("\n"),
+            }
+        )
+    }
+
+    // Creating some of the wgpu types requires async code
+    async fn with_backends(window: &Window, backends: wgpu::BackendBit) -> anyhow::Result<Self> {
+        let size = window.inner_size();
+
+        let instance = wgpu::Instance::new(backends);
+        let surface = unsafe { instance.create_surface(window) };
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: Some(&surface),
+            })
+            .await
+            .ok_or_else(|| anyhow::anyhow!("no adapter can present to the window"))?;
+        let info = adapter.get_info();
+
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    features: wgpu::Features::empty(),
+                    limits: wgpu::Limits::default(),
+                    label: None,
+                },
+                None, // Trace path
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("{} refused a device: {}", info.name, e))?;
+
+        let format = adapter
+            .get_swap_chain_preferred_format(&surface)
+            .ok_or_else(|| anyhow::anyhow!("{} has no swap chain format", info.name))?;
+        let sc_desc = wgpu::SwapChainDescriptor {
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
+            format,
+            width: size.width,
+            height: size.height,
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+        let swap_chain = device.create_swap_chain(&surface, &sc_desc);
+        println!("Using {} ({:?})", info.name, info.backend);
+
+        let (render_pipeline, render_bind_group_layout) =
+            Self::make_render_pipeline(&device, &sc_desc);
+        let (render_textures, render_texture_views) = Self::make_render_textures(&device, &size);
+        let render_bind_groups = Self::make_render_bind_groups(
+            &device,
+            &render_bind_group_layout,
+            &render_texture_views,
+        );
+
+        Ok(Self {
+            surface,
+            device,
+            queue,
+            sc_desc,
+            swap_chain,
+            size,
+            adapter_info: info,
+            render_data: RenderData {
+                render_pipeline,
+                render_bind_group_layout,
+                render_bind_groups,
+                render_textures,
+                render_texture_views,
+            },
+            frame_number: 0,
+        })
+    }
+
+    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        self.size = new_size;
+        self.sc_desc.width = new_size.width;
+        self.sc_desc.height = new_size.height;
+        self.swap_chain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
+
+        let (textures, views) = Self::make_render_textures(&self.device, &self.size);
+        self.render_data.render_bind_groups = Self::make_render_bind_groups(
+            &self.device,
+            &self.render_data.render_bind_group_layout,
+            &views,
+        );
+        self.render_data.render_textures = textures;
+        self.render_data.render_texture_views = views;
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.sc_desc.format
+    }
+
+    pub fn adapter_info(&self) -> &wgpu::AdapterInfo {
+        &self.adapter_info
+    }
+
+    pub fn frame_number(&self) -> u32 {
+        self.frame_number
+    }
+
+    // The texture shown this frame
+    pub fn target(&self) -> &wgpu::Texture {
+        &self.render_data.render_textures[(self.frame_number % 2) as usize]
+    }
+
+    // Views of both textures, indexed as `frame_number % 2` picks the target
+    pub fn target_views(&self) -> &[wgpu::TextureView; 2] {
+        &self.render_data.render_texture_views
+    }
+
+    // Draws this frame's target to the window after whatever the backend recorded in
+    // `encoder`, then moves on to the other texture
+    pub fn present(
+        &mut self,
+        mut encoder: wgpu::CommandEncoder,
+    ) -> Result<(), wgpu::SwapChainError> {
+        let frame = self.swap_chain.get_current_frame()?.output;
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &frame.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color {
+                            r: 0.1,
+                            g: 0.2,
+                            b: 0.3,
+                            a: 1.0,
+                        }),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&self.render_data.render_pipeline);
+            render_pass.set_bind_group(
+                0,
+                &self.render_data.render_bind_groups[(self.frame_number % 2) as usize],
+                &[],
+            );
+            render_pass.draw(0..3, 0..1);
+        }
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        self.frame_number += 1;
+        Ok(())
+    }
+
+    // Copies back the texture presented last
+    pub fn read_image(&self) -> anyhow::Result<Image> {
+        let (width, height) = (self.size.width, self.size.height);
+        // Buffer rows of a texture copy have to be aligned
+        let row_bytes = 16 * width;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row_bytes = (row_bytes + align - 1) / align * align;
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback_buffer"),
+            size: (padded_row_bytes * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Readback Encoder"),
+            });
+        let written = (self.frame_number + 1) % 2;
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.render_data.render_textures[written as usize],
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_row_bytes),
+                    rows_per_image: std::num::NonZeroU32::new(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        self.device.poll(wgpu::Maintain::Wait);
+        pollster::block_on(mapping)?;
+
+        let mut data = Vec::with_capacity((4 * width * height) as usize);
+        {
+            let bytes = slice.get_mapped_range();
+            for row in bytes.chunks(padded_row_bytes as usize) {
+                data.extend(
+                    row[..row_bytes as usize]
+                        .chunks_exact(4)
+                        .map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]])),
+                );
+            }
+        }
+        buffer.unmap();
+        Ok(Image::from_vec(width as usize, height as usize, data))
+    }
+
+    fn make_render_textures(
+        device: &wgpu::Device,
+        size: &winit::dpi::PhysicalSize<u32>,
+    ) -> ([wgpu::Texture; 2], [wgpu::TextureView; 2]) {
+        let make = || {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Image"),
+                size: wgpu::Extent3d {
+                    width: size.width,
+                    height: size.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: wgpu::TextureUsage::STORAGE
+                    | wgpu::TextureUsage::COPY_DST
+                    | wgpu::TextureUsage::COPY_SRC,
+            })
+        };
+        let textures = [make(), make()];
+        let texture_views = [
+            textures[0].create_view(&wgpu::TextureViewDescriptor::default()),
+            textures[1].create_view(&wgpu::TextureViewDescriptor::default()),
+        ];
+
+        (textures, texture_views)
+    }
+
+    fn make_render_bind_groups(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        views: &[wgpu::TextureView; 2],
+    ) -> [wgpu::BindGroup; 2] {
+        let make = |label, view| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(label),
+                layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                }],
+            })
+        };
+        [
+            make("render_bind_group_0", &views[0]),
+            make("render_bind_group_1", &views[1]),
+        ]
+    }
+
+    fn make_render_pipeline(
+        device: &wgpu::Device,
+        sc_desc: &wgpu::SwapChainDescriptor,
+    ) -> (wgpu::RenderPipeline, wgpu::BindGroupLayout) {
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Render"),
+            flags: wgpu::ShaderFlags::all(),
+            source: wgpu::ShaderSource::Wgsl(include_str!("render.wgsl").into()),
+        });
+
+        let render_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("texture_bind_group_layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::COMPUTE | wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::ReadOnly,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        format: wgpu::TextureFormat::Rgba32Float,
+                    },
+                    count: None,
+                }],
+            });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &[&render_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Render Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState {
+                    format: sc_desc.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                clamp_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        (render_pipeline, render_bind_group_layout)
+    }
+}
@@ -1,10 +1,22 @@
+mod backend;
+mod commands;
+mod controller;
 mod cpu;
+mod display;
 mod gpu;
+mod output;
+mod registry;
+mod session;
 
-use cpu::CpuState;
-use gpu::GpuState;
+use backend::{RenderBackend, Viewer};
+use cpu::{CpuBackend, RenderSettings};
+use display::Display;
+use gpu::GpuBackend;
+use session::{Session, SESSION_FILE};
 
 use std::env::args;
+use std::str::FromStr;
+use std::time::Duration;
 
 use razz_lib::*;
 use winit::{
@@ -13,21 +25,207 @@ use winit::{
     window::WindowBuilder,
 };
 
+// Image size for headless renders when no session remembers a window
+const HEADLESS_SIZE: (u32, u32) = (800, 450);
+
 fn main() {
+    let cli_args: Vec<String> = args().collect();
+    let command = match cli_args.get(1).map(|s| s.as_str()) {
+        Some("diff") => Some(commands::diff as fn(&[String]) -> anyhow::Result<()>),
+        Some("poster") => Some(commands::poster as fn(&[String]) -> anyhow::Result<()>),
+        Some("pack") => Some(commands::pack as fn(&[String]) -> anyhow::Result<()>),
+        Some("info") => Some(commands::info as fn(&[String]) -> anyhow::Result<()>),
+        Some("export") => Some(commands::export as fn(&[String]) -> anyhow::Result<()>),
+        Some("materials") => Some(commands::materials as fn(&[String]) -> anyhow::Result<()>),
+        _ => None,
+    };
+    if let Some(command) = command {
+        if let Err(e) = command(&cli_args[2..]) {
+            eprintln!("{:?}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let gpu = args().any(|a| a == "--gpu");
+    // Picks up where the last run stopped, unless started `--fresh`. Flags given on the
+    // command line win over the saved values.
+    let session_path =
+        flag_value::<String>(&cli_args, "--session").unwrap_or_else(|| SESSION_FILE.into());
+    let session = match args().any(|a| a == "--fresh") {
+        true => None,
+        false => Session::load(&session_path).unwrap_or_else(|e| {
+            eprintln!("{:?}", e);
+            None
+        }),
+    };
+    // A missing session leaves the scene's own display settings alone
+    let saved_display = session.as_ref().map(|session| session.display);
+    let session = session.unwrap_or_default();
+
+    // A name from `registry::SCENES`, or the path of a model
+    let scene_name = flag_value::<String>(&cli_args, "--scene").or_else(|| session.scene.clone());
+    let mut scene = match scene_name.as_deref() {
+        Some(scene) => match registry::load_scene(scene) {
+            Ok(scene) => scene,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                std::process::exit(1);
+            }
+        },
+        None if gpu => basic_scene_01(),
+        None => basic_scene_02(),
+    };
+    // The camera only makes sense in the scene it was saved with
+    if let (Some((look_from, look_at)), true) = (session.camera, scene_name == session.scene) {
+        scene.sampler.set_view(look_from, look_at);
+    }
+    if let Some(display) = saved_display {
+        scene.post.display.exposure = display.exposure;
+        scene.post.display.tonemap = display.tonemap;
+    }
+    // Glow intensity, e.g. `--bloom 0.1`
+    if let Some(intensity) = flag_value(&cli_args, "--bloom") {
+        scene.post.push(PostStage::Bloom {
+            threshold: 1.0,
+            intensity,
+            radius: 0.01,
+        });
+    }
+    // Darkening at the corners, e.g. `--vignette 0.4`
+    if let Some(strength) = flag_value(&cli_args, "--vignette") {
+        scene.post.push(PostStage::Vignette {
+            strength,
+            radius: 0.4,
+        });
+    }
+
+    let bracket = args().any(|a| a == "--bracket");
+    let defaults = session.settings;
+    let target_samples = flag_value(&cli_args, "--target-samples").or(defaults.target_samples);
+    let sample_sequence = match flag_value::<String>(&cli_args, "--sampler") {
+        Some(name) => match output::parse_sample_sequence(&name, target_samples) {
+            Ok(sequence) => sequence,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        None => defaults.sample_sequence,
+    };
+    let dither = match flag_value::<String>(&cli_args, "--dither") {
+        Some(name) => match output::parse_dither(&name) {
+            Ok(dither) => dither,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        None => defaults.dither,
+    };
+    let render_mode = match flag_value::<String>(&cli_args, "--mode") {
+        Some(name) => {
+            // Depth fades to white across the scene and occlusion reaches a tenth of the
+            // way, unless `--mode-distance` says otherwise
+            let distance = flag_value(&cli_args, "--mode-distance").unwrap_or_else(|| {
+                let bounds = scene.world.bounds();
+                let size = (bounds.max - bounds.min).length();
+                match (size.is_finite() && size > 0.0, name.as_str()) {
+                    (false, _) => 1.0,
+                    (true, "depth") => size,
+                    (true, _) => 0.1 * size,
+                }
+            });
+            match output::parse_render_mode(&name, distance) {
+                Ok(mode) => mode,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => defaults.render_mode,
+    };
+    let settings = RenderSettings {
+        max_depth: flag_value(&cli_args, "--max-depth").unwrap_or(defaults.max_depth),
+        samples_per_frame: flag_value(&cli_args, "--samples-per-frame")
+            .unwrap_or(defaults.samples_per_frame),
+        target_samples,
+        // Milliseconds of tracing per frame, e.g. `--frame-budget 16` for 60 FPS
+        frame_budget: flag_value(&cli_args, "--frame-budget")
+            .map(Duration::from_millis)
+            .or(defaults.frame_budget),
+        profile_traversal: args().any(|a| a == "--profile-bvh") || defaults.profile_traversal,
+        technique_aovs: args().any(|a| a == "--technique-aovs") || defaults.technique_aovs,
+        sample_sequence,
+        // e.g. `--preview-stride 8` for quick first frames at large sizes
+        preview_stride: flag_value(&cli_args, "--preview-stride")
+            .unwrap_or(defaults.preview_stride),
+        dither,
+        render_mode,
+        spectral: args().any(|a| a == "--spectral") || defaults.spectral,
+    };
+    // The CPU renderer doesn't need a window or a GPU to write the image out, only to
+    // show it. `--headless` skips the window outright, for machines without a display.
+    let headless_size = session.window_size.unwrap_or(HEADLESS_SIZE);
+    if args().any(|a| a == "--headless") && !gpu {
+        render_headless(scene, scene_name, headless_size, settings, &session_path);
+    }
+
     let event_loop = EventLoop::new();
-    let window = WindowBuilder::new().build(&event_loop).unwrap();
+    let mut window_builder = WindowBuilder::new();
+    if let Some((width, height)) = session.window_size {
+        window_builder =
+            window_builder.with_inner_size(winit::dpi::PhysicalSize::new(width, height));
+    }
+    let window = match window_builder.build(&event_loop) {
+        Ok(window) => window,
+        Err(e) if !gpu => {
+            eprintln!("{:?}", e);
+            render_headless(scene, scene_name, headless_size, settings, &session_path);
+        }
+        Err(e) => {
+            eprintln!("{:?}", e);
+            std::process::exit(1);
+        }
+    };
 
-    let mut state = match args().any(|a| a == "--gpu") {
-        true => StateType::Gpu(pollster::block_on(GpuState::new(&window))),
-        false => StateType::Cpu(pollster::block_on(CpuState::new(&window))),
+    let display = match pollster::block_on(Display::new(&window)) {
+        Ok(display) => display,
+        Err(e) if !gpu => {
+            eprintln!("{:?}", e);
+            let size = window.inner_size();
+            render_headless(
+                scene,
+                scene_name,
+                (size.width, size.height),
+                settings,
+                &session_path,
+            );
+        }
+        Err(e) => {
+            eprintln!("{:?}", e);
+            std::process::exit(1);
+        }
+    };
+    let backend: Box<dyn RenderBackend> = match gpu {
+        true => Box::new(GpuBackend::new(
+            &display,
+            settings.max_depth,
+            scene,
+            // Splits the rows between every GPU, not just the one showing the window
+            args().any(|a| a == "--multi-gpu"),
+        )),
+        false => Box::new(CpuBackend::new(&display, bracket, settings, scene)),
     };
+    let mut viewer = Viewer::new(display, backend, settings.dither);
 
     event_loop.run(move |event, _, control_flow| match event {
         Event::WindowEvent {
             ref event,
             window_id,
         } if window_id == window.id() => {
-            if !state.input(event) {
+            if !viewer.input(event) {
                 match event {
                     WindowEvent::CloseRequested
                     | WindowEvent::KeyboardInput {
@@ -40,21 +238,21 @@ fn main() {
                         ..
                     } => *control_flow = ControlFlow::Exit,
                     WindowEvent::Resized(physical_size) => {
-                        state.resize(*physical_size);
+                        viewer.resize(*physical_size);
                     }
                     WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                        state.resize(**new_inner_size);
+                        viewer.resize(**new_inner_size);
                     }
                     _ => {}
                 }
             }
         }
         Event::RedrawRequested(_) => {
-            state.update();
-            match state.render() {
+            viewer.update();
+            match viewer.render() {
                 Ok(_) => {}
                 // Recreate the swap_chain if lost
-                Err(wgpu::SwapChainError::Lost) => state.resize(state.size()),
+                Err(wgpu::SwapChainError::Lost) => viewer.resize(viewer.size()),
                 // The system is out of memory, we should probably quit
                 Err(wgpu::SwapChainError::OutOfMemory) => *control_flow = ControlFlow::Exit,
                 // All other errors (Outdated, Timeout) should be resolved by the next frame
@@ -65,78 +263,94 @@ fn main() {
             // RedrawRequested will only trigger once, unless we manually request it.
             window.request_redraw();
         }
+        Event::LoopDestroyed => {
+            let size = viewer.size();
+            save_session(
+                viewer.scene(),
+                scene_name.clone(),
+                (size.width, size.height),
+                settings,
+                &session_path,
+            );
+        }
         _ => {}
     });
 }
 
-trait State {
-    fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>);
-    fn input(&mut self, event: &WindowEvent) -> bool;
-    fn update(&mut self);
-    fn render(&mut self) -> Result<(), wgpu::SwapChainError>;
-    fn size(&self) -> winit::dpi::PhysicalSize<u32>;
-}
-
-struct RenderData {
-    render_pipeline: wgpu::RenderPipeline,
-    render_bind_group_layout: wgpu::BindGroupLayout,
-    render_bind_groups: [wgpu::BindGroup; 2],
-    render_textures: [wgpu::Texture; 2],
-    render_texture_views: [wgpu::TextureView; 2],
+// The value following `flag`, if it's there and parses
+fn flag_value<T: FromStr>(cli_args: &[String], flag: &str) -> Option<T> {
+    cli_args
+        .iter()
+        .position(|a| a == flag)
+        .and_then(|i| cli_args.get(i + 1))
+        .and_then(|value| value.parse().ok())
 }
 
-enum StateType {
-    Cpu(CpuState),
-    Gpu(GpuState),
-}
-
-impl State for StateType {
-    fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
-        match self {
-            StateType::Cpu(state) => state.resize(new_size),
-            StateType::Gpu(state) => state.resize(new_size),
-        }
-    }
-
-    fn input(&mut self, event: &WindowEvent) -> bool {
-        match self {
-            StateType::Cpu(state) => state.input(event),
-            StateType::Gpu(state) => state.input(event),
-        }
-    }
-
-    fn update(&mut self) {
-        match self {
-            StateType::Cpu(state) => state.update(),
-            StateType::Gpu(state) => state.update(),
-        }
-    }
-
-    fn render(&mut self) -> Result<(), wgpu::SwapChainError> {
-        match self {
-            StateType::Cpu(state) => state.render(),
-            StateType::Gpu(state) => state.render(),
-        }
+fn save_session(
+    scene: &Scene,
+    scene_name: Option<String>,
+    window_size: (u32, u32),
+    settings: RenderSettings,
+    path: &str,
+) {
+    let session = Session {
+        scene: scene_name,
+        window_size: Some(window_size),
+        camera: Some((scene.sampler.look_from(), scene.sampler.look_at())),
+        settings,
+        display: scene.post.display,
+    };
+    if let Err(e) = session.save(path) {
+        eprintln!("Failed to save session: {:?}", e);
     }
+}
 
-    fn size(&self) -> winit::dpi::PhysicalSize<u32> {
-        match self {
-            StateType::Cpu(state) => state.size(),
-            StateType::Gpu(state) => state.size(),
-        }
+// Renders to a fixed sample count without a window, saves the result and the
+// session, then exits
+fn render_headless(
+    mut scene: Scene,
+    scene_name: Option<String>,
+    (width, height): (u32, u32),
+    settings: RenderSettings,
+    session_path: &str,
+) -> ! {
+    let path = "razz_headless.png";
+    let (width, height) = (width as usize, height as usize);
+    scene
+        .sampler
+        .set_aspect(width as Float / height.max(1) as Float);
+    let mut plan = RenderPlan::auto(&scene, (width, height));
+    plan.max_ray_depth = settings.max_depth;
+    plan.samples_per_pixel = settings.target_samples.unwrap_or(plan.samples_per_pixel);
+    println!(
+        "Rendering {}x{} at {} samples to {} without a window",
+        width, height, plan.samples_per_pixel, path
+    );
+    let image = Renderer::from_plan(plan).render(&scene);
+    let saved = output::save_png(&scene.post.apply(&image), path, settings.dither);
+    save_session(
+        &scene,
+        scene_name,
+        (width as u32, height as u32),
+        settings,
+        session_path,
+    );
+    if let Err(e) = saved {
+        eprintln!("{:?}", e);
+        std::process::exit(1);
     }
+    std::process::exit(0)
 }
 
 fn basic_scene_01() -> Scene {
     let aspect_ratio = 16.0 / 9.0;
-    let camera = Camera::new(
-        Vec3A::new(0.0, 0.0, 0.0),
-        Vec3A::new(0.0, 0.0, -1.0),
-        90.0,
-        aspect_ratio,
-        0.0,
-        1.0,
-    );
+    let camera = Camera::builder()
+        .look_from(Vec3A::new(0.0, 0.0, 0.0))
+        .look_at(Vec3A::new(0.0, 0.0, -1.0))
+        .vfov(90.0)
+        .aspect(aspect_ratio)
+        .build()
+        .expect("valid camera");
 
     let mut world_builder = WorldBuilder::default();
     let texture = world_builder.push_texture(Texture::default());
@@ -165,7 +379,8 @@ fn basic_scene_01() -> Scene {
         ],
         vec![(0, 1, 2), (3, 4, 5)],
         material_key,
-    );
+    )
+    .expect("both triangles index the six vertices");
 
     let _mesh = world_builder.push_hittable(_mesh);
 
@@ -177,7 +392,7 @@ fn basic_scene_01() -> Scene {
 fn basic_scene_02() -> Scene {
     let mut world_builder = WorldBuilder::default();
 
-    let camera = build_cornell_box(&mut world_builder);
+    let camera = scenes::cornell_box_room(&mut world_builder, 1.0);
 
     let blue_texture = world_builder.push_texture(Texture::Solid {
         color: Rgba::new(0.2, 0.2, 0.6, 1.0),
@@ -186,145 +401,46 @@ fn basic_scene_02() -> Scene {
         albedo: blue_texture,
         fuzz: 0.01,
     });
-    let _glass_material = world_builder.push_material(Material::Dielectric { ir: 1.7 });
+    let _glass_material = world_builder.push_material(Material::dielectric(1.7));
     let light_texture = world_builder.push_texture(Texture::Solid {
         color: Rgba::new(1.0, 1.0, 1.0, 1.0),
     });
     let light_material = world_builder.push_material(Material::DiffuseLight {
         emit: light_texture,
+        intensity: 1.0,
     });
     world_builder.push_hittable(Primative::sphere(
         Vec3A::new(550.0 / 2.0, 220.0, 550.0 / 2.0),
         15.0,
         light_material,
     ));
-    let mesh = Primative::from_obj("./obj/torus_knot.obj", metal_material);
-    world_builder.push_hittable(mesh);
+    // Every directory above the executable, so the example meshes load from a build
+    // in the repository whatever the working directory
+    let mut assets = AssetResolver::new();
+    if let Ok(exe) = std::env::current_exe() {
+        for dir in exe.ancestors().skip(1) {
+            assets = assets.with_search_path(dir);
+        }
+    }
+    let mesh = Primative::from_obj(
+        "obj/torus_knot.obj",
+        &assets,
+        metal_material,
+        ObjOptions {
+            transform: Some(Transform::new(
+                Vec3A::new(550.0 / 2.0, 220.0, 550.0 / 2.0),
+                glam::Quat::from_rotation_x(std::f32::consts::FRAC_PI_2),
+                10.0,
+            )),
+            ..ObjOptions::default()
+        },
+    );
+    // Without the example meshes the box is still worth showing
+    match mesh {
+        Ok(mesh) => world_builder.push_hittable(mesh),
+        Err(e) => eprintln!("{:?}", e),
+    }
 
     let scene: Scene = Scene::new(world_builder.into(), camera);
     scene
 }
-
-fn build_cornell_box(world_builder: &mut WorldBuilder) -> Camera {
-    let camera = Camera::new(
-        Vec3A::new(278.0, 278.0, -800.0),
-        Vec3A::new(278.0, 278.0, 0.0),
-        40.0,
-        1.0,
-        0.0,
-        10.0,
-    );
-
-    let red_texture = world_builder.push_texture(Texture::Solid {
-        color: Rgba::new(0.65, 0.05, 0.05, 1.0),
-    });
-    let white_texture = world_builder.push_texture(Texture::Solid {
-        color: Rgba::new(0.73, 0.73, 0.73, 1.0),
-    });
-    let green_texture = world_builder.push_texture(Texture::Solid {
-        color: Rgba::new(0.12, 0.45, 0.15, 1.0),
-    });
-    let light_texture = world_builder.push_texture(Texture::Solid {
-        color: Rgba::new(5.0, 5.0, 5.0, 1.0),
-    });
-
-    let red_material = world_builder.push_material(Material::Lambertian {
-        albedo: red_texture,
-    });
-    let white_material = world_builder.push_material(Material::Lambertian {
-        albedo: white_texture,
-    });
-    let green_material = world_builder.push_material(Material::Lambertian {
-        albedo: green_texture,
-    });
-    let light_material = world_builder.push_material(Material::DiffuseLight {
-        emit: light_texture,
-    });
-
-    let red_wall = Primative::mesh(
-        vec![
-            [555.0, 0.0, 0.0].into(),
-            [555.0, 555.0, 0.0].into(),
-            [555.0, 555.0, 555.0].into(),
-            [555.0, 555.0, 555.0].into(),
-            [555.0, 0.0, 555.0].into(),
-            [555.0001, 0.0, 0.0].into(),
-        ],
-        vec![(0, 1, 2), (3, 4, 5)],
-        red_material,
-    );
-
-    let green_wall = Primative::mesh(
-        vec![
-            [0.0, 0.0, 0.0].into(),
-            [0.0, 0.0, 555.0].into(),
-            [0.0, 555.0, 555.0].into(),
-            [0.0, 555.0, 555.0].into(),
-            [0.0, 555.0, 0.0].into(),
-            [0.0001, 0.0, 0.0].into(),
-        ],
-        vec![(0, 1, 2), (3, 4, 5)],
-        green_material,
-    );
-
-    let white_wall = Primative::mesh(
-        vec![
-            [555.0, 0.0, 555.0].into(),
-            [0.0, 0.0, 555.0].into(),
-            [0.0, 555.0, 555.0].into(),
-            [0.0, 555.0, 555.0].into(),
-            [555.0, 555.0, 555.0].into(),
-            [555.0, 0.0, 555.0001].into(),
-        ],
-        vec![(0, 1, 2), (3, 4, 5)],
-        white_material,
-    );
-
-    let floor = Primative::mesh(
-        vec![
-            [555.0, 0.0, 0.0].into(),
-            [0.0, 0.0, 0.0].into(),
-            [0.0, 0.0, 555.0].into(),
-            [0.0, 0.0, 555.0].into(),
-            [555.0, 0.0, 555.0].into(),
-            [555.0, 0.0001, 0.0].into(),
-        ],
-        vec![(0, 1, 2), (3, 4, 5)],
-        white_material,
-    );
-
-    let ceiling = Primative::mesh(
-        vec![
-            [555.0, 555.0, 0.0].into(),
-            [0.0, 555.0, 0.0].into(),
-            [0.0, 555.0, 555.0].into(),
-            [0.0, 555.0, 555.0].into(),
-            [555.0, 555.0, 555.0].into(),
-            [555.0, 555.0001, 0.0].into(),
-        ],
-        vec![(0, 1, 2), (3, 4, 5)],
-        white_material,
-    );
-
-    let light = Primative::mesh(
-        vec![
-            [213.0, 554.0, 227.0].into(),
-            [343.0, 554.0, 227.0].into(),
-            [343.0, 554.0, 332.0].into(),
-            [343.0, 554.0, 332.0].into(),
-            [213.0, 554.0, 332.0].into(),
-            [213.0, 554.0001, 227.0].into(),
-        ],
-        vec![(0, 1, 2), (3, 4, 5)],
-        light_material,
-    );
-
-    world_builder.push_hittable(red_wall);
-    world_builder.push_hittable(green_wall);
-    world_builder.push_hittable(white_wall);
-    world_builder.push_hittable(floor);
-    world_builder.push_hittable(ceiling);
-    world_builder.push_hittable(light);
-
-    camera
-}
@@ -0,0 +1,199 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use razz_lib::{
+    fuse_exposures, AssetResolver, DisplayTransform, Dither, Float, Image, RenderMode,
+    RenderProgress, RenderReport, SampleSequence, TiledImage,
+};
+
+const BRACKET_EVS: [f32; 3] = [-2.0, 0.0, 2.0];
+const PROGRESS_BAR_WIDTH: usize = 30;
+
+pub fn save_png(img: &Image, path: impl AsRef<Path>, dither: Dither) -> anyhow::Result<()> {
+    let buffer = image::RgbaImage::from_fn(img.width as u32, img.height as u32, |x, y| {
+        let (x, y) = (x as usize, y as usize);
+        let color = img.get_pixel_color(x, y).to_array();
+        let channel = |c: usize| dither.quantize(color[c], x, y, c);
+        image::Rgba([channel(0), channel(1), channel(2), 255])
+    });
+    buffer.save(path)?;
+    Ok(())
+}
+
+// Streams one row at a time so the whole image never has to be in memory
+pub fn save_png_tiled(
+    img: &mut TiledImage,
+    path: impl AsRef<Path>,
+    dither: Dither,
+) -> anyhow::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, img.width as u32, img.height as u32);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    let mut stream = writer.stream_writer();
+
+    for y in 0..img.height {
+        let row = img.read_region(0, y, img.width, 1)?;
+        let bytes: Vec<u8> = row
+            .data
+            .chunks(4)
+            .enumerate()
+            .flat_map(|(x, c)| {
+                let channel = |i: usize| dither.quantize(c[i], x, y, i);
+                [channel(0), channel(1), channel(2), 255]
+            })
+            .collect();
+        stream.write_all(&bytes)?;
+    }
+    stream.finish()?;
+    Ok(())
+}
+
+// The names accepted by `--sampler`. Stratified grids are sized to `samples`, or
+// repeat every 64 samples without it.
+pub fn parse_sample_sequence(name: &str, samples: Option<usize>) -> anyhow::Result<SampleSequence> {
+    match name {
+        "random" => Ok(SampleSequence::Random),
+        "stratified" => Ok(SampleSequence::Stratified {
+            samples: samples.unwrap_or(64),
+        }),
+        "halton" => Ok(SampleSequence::Halton),
+        "sobol" => Ok(SampleSequence::Sobol),
+        "blue-noise" => Ok(SampleSequence::BlueNoise),
+        other => anyhow::bail!(
+            "unknown sampler `{}`, expected random, stratified, halton, sobol or blue-noise",
+            other
+        ),
+    }
+}
+
+// The names accepted by `--dither`
+pub fn parse_dither(name: &str) -> anyhow::Result<Dither> {
+    match name {
+        "none" => Ok(Dither::None),
+        "triangular" => Ok(Dither::Triangular),
+        "blue-noise" => Ok(Dither::BlueNoise),
+        other => anyhow::bail!(
+            "unknown dither `{}`, expected none, triangular or blue-noise",
+            other
+        ),
+    }
+}
+
+// The names accepted by `--mode`. `distance` is the occlusion radius, or the depth
+// shown white.
+pub fn parse_render_mode(name: &str, distance: Float) -> anyhow::Result<RenderMode> {
+    match name {
+        "path" => Ok(RenderMode::Path),
+        "ao" => Ok(RenderMode::AmbientOcclusion { radius: distance }),
+        "normals" => Ok(RenderMode::Normals),
+        "depth" => Ok(RenderMode::Depth {
+            max_distance: distance,
+        }),
+        "clay" => Ok(RenderMode::Clay),
+        other => anyhow::bail!(
+            "unknown mode `{}`, expected path, ao, normals, depth or clay",
+            other
+        ),
+    }
+}
+
+// Each exposure of the linear `image` is shown through `display` before fusing
+pub fn save_bracketed(
+    image: &Image,
+    display: &DisplayTransform,
+    dither: Dither,
+    stem: &str,
+) -> anyhow::Result<()> {
+    let exposures: Vec<Image> = image
+        .bracket(&BRACKET_EVS)
+        .iter()
+        .map(|exposure| exposure.display(display))
+        .collect();
+    for (ev, exposure) in BRACKET_EVS.iter().zip(&exposures) {
+        save_png(exposure, format!("{}_ev{:+}.png", stem, ev), dither)?;
+    }
+    let fused = fuse_exposures(&exposures).expect("brackets are never empty");
+    save_png(&fused, format!("{}_fused.png", stem), dither)
+}
+
+// An asset referenced by a scene or material file, found through `assets`
+pub fn load_image(path: impl AsRef<Path>, assets: &AssetResolver) -> anyhow::Result<Image> {
+    decode_image(&assets.read(path)?)
+}
+
+// A file named on the command line, read from exactly that path
+pub fn load_image_file(path: impl AsRef<Path>) -> anyhow::Result<Image> {
+    decode_image(&std::fs::read(path)?)
+}
+
+fn decode_image(bytes: &[u8]) -> anyhow::Result<Image> {
+    let buffer = image::load_from_memory(bytes)?.to_rgba8();
+    let data = buffer
+        .pixels()
+        .flat_map(|p| p.0.iter().map(|c| *c as f32 / 255.0).collect::<Vec<_>>())
+        .collect();
+
+    Ok(Image::from_vec(
+        buffer.width() as usize,
+        buffer.height() as usize,
+        data,
+    ))
+}
+
+pub fn save_report(report: &RenderReport, path: impl AsRef<Path>) -> anyhow::Result<()> {
+    let mut report = report.clone();
+    report.peak_memory_bytes = peak_memory_bytes();
+    std::fs::write(path, report.to_json())?;
+    Ok(())
+}
+
+// Redraws a single status line, with a bar when there's a target to fill
+pub fn print_progress(progress: &RenderProgress) {
+    let mut line = match progress.fraction() {
+        Some(fraction) => {
+            let filled = (fraction * PROGRESS_BAR_WIDTH as f32).round() as usize;
+            format!(
+                "[{}{}] {:.1}/{} spp",
+                "#".repeat(filled),
+                " ".repeat(PROGRESS_BAR_WIDTH - filled),
+                progress.samples_completed,
+                progress.target_samples.unwrap_or_default()
+            )
+        }
+        None => format!("{:.1} spp", progress.samples_completed),
+    };
+    line += &format!(
+        "  {:.2} Mrays/s  {} elapsed",
+        progress.rays_per_second() / 1e6,
+        clock(progress.elapsed)
+    );
+    if let Some(remaining) = progress.remaining {
+        line += &format!("  {} left", clock(remaining));
+    }
+
+    let mut stdout = std::io::stdout();
+    let _ = write!(stdout, "\r{:<100}", line);
+    let _ = stdout.flush();
+}
+
+fn clock(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+// Only available where procfs exists, reported as null elsewhere
+fn peak_memory_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
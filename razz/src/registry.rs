@@ -0,0 +1,89 @@
+use crate::{basic_scene_01, basic_scene_02};
+
+use anyhow::Context;
+use razz_lib::*;
+use std::path::Path;
+
+type BuildScene = fn() -> Scene;
+
+// Scenes `--scene` knows by name. Cameras are built square, the renderer matches
+// them to the window.
+pub const SCENES: [(&str, BuildScene); 8] = [
+    ("basic", basic_scene_01),
+    ("torus_knot", basic_scene_02),
+    ("cornell_box", || scenes::cornell_box(1.0)),
+    ("veach_mis", || scenes::veach_mis(1.0)),
+    ("rtiow_final", || scenes::rtiow_final(0, 1.0)),
+    ("skyscape", || scenes::skyscape(0, 1.0)),
+    ("city", || demo::city(0, 8)),
+    ("forest", || demo::forest(0, 400)),
+];
+
+// A built-in scene by name, or a model by path shown in the Cornell box. Models are
+// OBJ files, or packs made with `razz pack` from one.
+pub fn load_scene(name_or_path: &str) -> anyhow::Result<Scene> {
+    if let Some((_, build)) = SCENES.iter().find(|(name, _)| *name == name_or_path) {
+        return Ok(build());
+    }
+
+    let path = Path::new(name_or_path);
+    let (assets, model) = match path.extension().and_then(|e| e.to_str()) {
+        Some("obj") => (
+            AssetResolver::relative_to(path),
+            path.file_name()
+                .map(Path::new)
+                .unwrap_or(path)
+                .to_path_buf(),
+        ),
+        Some(PACK_EXTENSION) => {
+            let pack = ScenePack::open(path)
+                .with_context(|| format!("failed to open {}", path.display()))?;
+            let model = pack.scene_name().into();
+            (AssetResolver::from_pack(pack), model)
+        }
+        _ => {
+            let names: Vec<_> = SCENES.iter().map(|(name, _)| *name).collect();
+            anyhow::bail!(
+                "unknown scene `{}`, expected one of {} or an .obj or .{} file",
+                name_or_path,
+                names.join(", "),
+                PACK_EXTENSION
+            )
+        }
+    };
+    model_scene(&model, &assets).with_context(|| format!("failed to load {}", model.display()))
+}
+
+// Scaled to 300 units, standing on the floor in the middle of the box
+fn model_scene(model: &Path, assets: &AssetResolver) -> std::io::Result<Scene> {
+    let mut world_builder = WorldBuilder::default();
+    let camera = scenes::cornell_box_room(&mut world_builder, 1.0);
+
+    let albedo = world_builder.push_texture(Texture::Solid {
+        color: Rgba::new(0.73, 0.73, 0.73, 1.0),
+    });
+    let material = world_builder.push_material(Material::Lambertian { albedo });
+    let options = ObjOptions {
+        orient_outward: true,
+        ..ObjOptions::default()
+    };
+    let mesh = Mesh::from_obj(model, assets, material, options)?;
+
+    let bounds = mesh.bounds();
+    let scale = 300.0 / (bounds.max - bounds.min).max_element().max(Float::EPSILON);
+    let base = Vec3A::new(
+        0.5 * (bounds.min.x + bounds.max.x),
+        bounds.min.y,
+        0.5 * (bounds.min.z + bounds.max.z),
+    );
+    world_builder.push_hittable(Primative::instance(
+        std::sync::Arc::new(Primative::Mesh(mesh)),
+        Transform::new(
+            Vec3A::new(277.5, 0.0, 277.5) - scale * base,
+            glam::Quat::IDENTITY,
+            scale,
+        ),
+    ));
+
+    Ok(Scene::new(world_builder.into(), camera))
+}
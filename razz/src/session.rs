@@ -0,0 +1,229 @@
+use crate::cpu::RenderSettings;
+use crate::output::{parse_dither, parse_render_mode, parse_sample_sequence};
+
+use anyhow::Context;
+use razz_lib::{DisplayTransform, Dither, RenderMode, SampleSequence, Tonemap, Vec3A};
+use std::fmt::Write;
+use std::path::Path;
+use std::time::Duration;
+
+// Where the viewer keeps its session unless `--session` names another file
+pub const SESSION_FILE: &str = "razz.session";
+
+// Viewer state written on exit and restored on the next launch, as `key value` lines.
+// Unknown keys are skipped, so older files still load.
+#[derive(Debug, Clone, Default)]
+pub struct Session {
+    // As passed to `--scene`
+    pub scene: Option<String>,
+    pub window_size: Option<(u32, u32)>,
+    // Camera position and target
+    pub camera: Option<(Vec3A, Vec3A)>,
+    pub settings: RenderSettings,
+    pub display: DisplayTransform,
+}
+
+impl Session {
+    // `None` when there's no session file yet
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Option<Self>> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = std::fs::read_to_string(path)?;
+        Self::parse(&text)
+            .with_context(|| format!("invalid session file {}", path.display()))
+            .map(Some)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_text())?;
+        Ok(())
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = String::new();
+        if let Some(scene) = &self.scene {
+            let _ = writeln!(text, "scene {}", scene);
+        }
+        if let Some((width, height)) = self.window_size {
+            let _ = writeln!(text, "window {} {}", width, height);
+        }
+        if let Some((from, at)) = self.camera {
+            let _ = writeln!(
+                text,
+                "camera {} {} {} {} {} {}",
+                from.x, from.y, from.z, at.x, at.y, at.z
+            );
+        }
+
+        let settings = &self.settings;
+        let _ = writeln!(text, "max_depth {}", settings.max_depth);
+        let _ = writeln!(text, "samples_per_frame {}", settings.samples_per_frame);
+        if let Some(target) = settings.target_samples {
+            let _ = writeln!(text, "target_samples {}", target);
+        }
+        if let Some(budget) = settings.frame_budget {
+            let _ = writeln!(text, "frame_budget {}", budget.as_millis());
+        }
+        let _ = writeln!(text, "profile_traversal {}", settings.profile_traversal);
+        let _ = writeln!(text, "technique_aovs {}", settings.technique_aovs);
+        let sampler = match settings.sample_sequence {
+            SampleSequence::Random => "random".to_string(),
+            SampleSequence::Stratified { samples } => format!("stratified {}", samples),
+            SampleSequence::Halton => "halton".to_string(),
+            SampleSequence::Sobol => "sobol".to_string(),
+            SampleSequence::BlueNoise => "blue-noise".to_string(),
+        };
+        let _ = writeln!(text, "sampler {}", sampler);
+        let _ = writeln!(text, "preview_stride {}", settings.preview_stride);
+        let dither = match settings.dither {
+            Dither::None => "none",
+            Dither::Triangular => "triangular",
+            Dither::BlueNoise => "blue-noise",
+        };
+        let _ = writeln!(text, "dither {}", dither);
+        let mode = match settings.render_mode {
+            RenderMode::Path => "path".to_string(),
+            RenderMode::AmbientOcclusion { radius } => format!("ao {}", radius),
+            RenderMode::Normals => "normals".to_string(),
+            RenderMode::Depth { max_distance } => format!("depth {}", max_distance),
+            RenderMode::Clay => "clay".to_string(),
+        };
+        let _ = writeln!(text, "mode {}", mode);
+        let _ = writeln!(text, "spectral {}", settings.spectral);
+
+        let _ = writeln!(text, "exposure {}", self.display.exposure);
+        let tonemap = match self.display.tonemap {
+            Tonemap::Clamp => "clamp".to_string(),
+            Tonemap::Reinhard { white } => format!("reinhard {}", white),
+            Tonemap::Aces => "aces".to_string(),
+        };
+        let _ = writeln!(text, "tonemap {}", tonemap);
+        text
+    }
+
+    fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut session = Session::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let (key, value) = match line.split_once(' ') {
+                Some((key, value)) => (key, value.trim()),
+                None if line.is_empty() => continue,
+                None => anyhow::bail!("line {}: expected a key and a value", number + 1),
+            };
+            session
+                .set(key, value)
+                .with_context(|| format!("line {}: bad {}", number + 1, key))?;
+        }
+        Ok(session)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> anyhow::Result<()> {
+        let numbers = || -> anyhow::Result<Vec<f32>> {
+            Ok(value
+                .split_whitespace()
+                .map(|v| v.parse())
+                .collect::<Result<_, _>>()?)
+        };
+        let settings = &mut self.settings;
+        match key {
+            "scene" => self.scene = Some(value.to_string()),
+            "window" => {
+                let size: Vec<u32> = value
+                    .split_whitespace()
+                    .map(|v| v.parse())
+                    .collect::<Result<_, _>>()?;
+                match size[..] {
+                    [width, height] => self.window_size = Some((width, height)),
+                    _ => anyhow::bail!("expected a width and a height"),
+                }
+            }
+            "camera" => match numbers()?[..] {
+                [fx, fy, fz, ax, ay, az] => {
+                    self.camera = Some((Vec3A::new(fx, fy, fz), Vec3A::new(ax, ay, az)))
+                }
+                _ => anyhow::bail!("expected a position and a target"),
+            },
+            "max_depth" => settings.max_depth = value.parse()?,
+            "samples_per_frame" => settings.samples_per_frame = value.parse()?,
+            "target_samples" => settings.target_samples = Some(value.parse()?),
+            "frame_budget" => settings.frame_budget = Some(Duration::from_millis(value.parse()?)),
+            "profile_traversal" => settings.profile_traversal = value.parse()?,
+            "technique_aovs" => settings.technique_aovs = value.parse()?,
+            "sampler" => {
+                let mut parts = value.split_whitespace();
+                let name = parts.next().unwrap_or_default();
+                let samples = parts.next().map(|s| s.parse()).transpose()?;
+                settings.sample_sequence = parse_sample_sequence(name, samples)?;
+            }
+            "preview_stride" => settings.preview_stride = value.parse()?,
+            "dither" => settings.dither = parse_dither(value)?,
+            "mode" => {
+                let mut parts = value.split_whitespace();
+                let name = parts.next().unwrap_or_default();
+                let distance = parts.next().map(|s| s.parse()).transpose()?;
+                settings.render_mode = parse_render_mode(name, distance.unwrap_or(1.0))?;
+            }
+            "spectral" => settings.spectral = value.parse()?,
+            "exposure" => self.display.exposure = value.parse()?,
+            "tonemap" => {
+                self.display.tonemap = match value.split_once(' ') {
+                    Some(("reinhard", white)) => Tonemap::Reinhard {
+                        white: white.parse()?,
+                    },
+                    None if value == "clamp" => Tonemap::Clamp,
+                    None if value == "aces" => Tonemap::Aces,
+                    _ => anyhow::bail!("unknown tonemap `{}`", value),
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions_read_back_what_they_wrote() {
+        let session = Session {
+            scene: Some("cornell_box".to_string()),
+            window_size: Some((640, 360)),
+            camera: Some((Vec3A::new(1.5, -2.0, 0.1), Vec3A::new(0.0, 0.25, -3.0))),
+            settings: RenderSettings {
+                max_depth: 8,
+                target_samples: Some(64),
+                frame_budget: Some(Duration::from_millis(16)),
+                sample_sequence: SampleSequence::Stratified { samples: 16 },
+                dither: Dither::BlueNoise,
+                render_mode: RenderMode::AmbientOcclusion { radius: 0.5 },
+                spectral: true,
+                ..RenderSettings::default()
+            },
+            display: DisplayTransform {
+                exposure: -1.5,
+                tonemap: Tonemap::Reinhard { white: 4.0 },
+                ..DisplayTransform::default()
+            },
+        };
+        let text = session.to_text();
+        let loaded = Session::parse(&text).unwrap();
+
+        assert_eq!(loaded.scene, session.scene);
+        assert_eq!(loaded.window_size, session.window_size);
+        assert_eq!(loaded.camera, session.camera);
+        assert_eq!(loaded.settings.max_depth, 8);
+        assert_eq!(loaded.settings.frame_budget, session.settings.frame_budget);
+        assert_eq!(loaded.display, session.display);
+        assert_eq!(loaded.to_text(), text);
+    }
+
+    #[test]
+    fn unknown_keys_are_skipped_and_bad_values_are_errors() {
+        assert!(Session::parse("future_setting 3\nmax_depth 2\n").is_ok());
+        assert!(Session::parse("max_depth deep\n").is_err());
+    }
+}
@@ -0,0 +1,139 @@
+// A torus added to a scene as a custom shape, found by sphere tracing its signed
+// distance function. Writes `custom_shape.ppm` to the working directory.
+//
+//   cargo run --release -p razz_lib --example custom_shape
+
+use razz_lib::*;
+
+use std::io::Write;
+
+#[derive(Debug, Clone)]
+struct Torus {
+    major: Float,
+    minor: Float,
+    material_key: MaterialKey,
+}
+
+impl Torus {
+    // Lying in the xz plane around the origin
+    fn distance(&self, p: Vec3A) -> Float {
+        let ring = (p.x * p.x + p.z * p.z).sqrt() - self.major;
+        (ring * ring + p.y * p.y).sqrt() - self.minor
+    }
+
+    fn normal(&self, p: Vec3A) -> Vec3A {
+        let h = 1e-4;
+        Vec3A::new(
+            self.distance(p + h * Vec3A::X) - self.distance(p - h * Vec3A::X),
+            self.distance(p + h * Vec3A::Y) - self.distance(p - h * Vec3A::Y),
+            self.distance(p + h * Vec3A::Z) - self.distance(p - h * Vec3A::Z),
+        )
+        .normalize()
+    }
+}
+
+impl Bounded<Bounds3A> for Torus {
+    fn bounds(&self) -> Bounds3A {
+        let extent = Vec3A::new(self.major + self.minor, self.minor, self.major + self.minor);
+        Bounds3A::new(-extent, extent)
+    }
+}
+
+impl RayHittable<Bounds3A> for Torus {
+    type Item = HitRecord;
+
+    fn ray_hit(&self, ray: &Ray3A, t_min: Float, t_max: Float) -> Option<(Float, HitRecord)> {
+        let speed = ray.direction.length();
+        // Rays leaving the surface start within the hit threshold, so only count
+        // hits once the ray has been clear of it
+        let mut t = t_min.max(0.0);
+        let mut clear = false;
+        for _ in 0..256 {
+            let p = ray.origin + t * ray.direction;
+            let d = self.distance(p);
+            clear |= d.abs() > 2e-4;
+            if clear && d.abs() < 1e-4 {
+                let outward = self.normal(p);
+                let front = ray.direction.dot(outward) < 0.0;
+                return Some((
+                    t,
+                    HitRecord {
+                        point: p,
+                        normal: if front { outward } else { -outward },
+                        u: p.z.atan2(p.x),
+                        v: 0.0,
+                        face: if front { Face::Front } else { Face::Back },
+                        material_key: self.material_key,
+                    },
+                ));
+            }
+            t += d.abs().max(1e-4) / speed;
+            if t > t_max {
+                break;
+            }
+        }
+        None
+    }
+}
+
+impl CustomShape for Torus {
+    fn clone_box(&self) -> Box<dyn CustomShape> {
+        Box::new(self.clone())
+    }
+}
+
+fn main() -> std::io::Result<()> {
+    let mut world_builder = WorldBuilder::default();
+    let copper = world_builder.push_texture(Texture::Solid {
+        color: Rgba::new(0.95, 0.64, 0.54, 1.0),
+    });
+    let copper = world_builder.push_material(Material::RoughMetal {
+        albedo: copper,
+        roughness: 0.3,
+    });
+    world_builder.push_hittable(Primative::custom(Torus {
+        major: 1.0,
+        minor: 0.35,
+        material_key: copper,
+    }));
+
+    let grey = world_builder.push_texture(Texture::Solid {
+        color: Rgba::new(0.5, 0.5, 0.5, 1.0),
+    });
+    let grey = world_builder.push_material(Material::Lambertian { albedo: grey });
+    world_builder.push_hittable(Primative::plane(-0.35 * Vec3A::Y, Vec3A::Y, grey));
+
+    let white = world_builder.push_texture(Texture::Solid { color: Rgba::ONE });
+    let sky = world_builder.push_material(Material::DiffuseLight {
+        emit: white,
+        intensity: 1.0,
+    });
+    world_builder.push_hittable(Primative::sphere(Vec3A::ZERO, 50.0, sky));
+
+    let camera = Camera::builder()
+        .look_from(Vec3A::new(0.0, 2.0, 3.5))
+        .vfov(40.0)
+        .aspect(1.5)
+        .build()
+        .expect("valid camera");
+    let scene = Scene::new(world_builder.into(), camera);
+
+    let (width, height) = (300, 200);
+    let mut renderer = ParallelRenderer::new(width, height, 8);
+    for _ in 0..32 {
+        renderer.render(&scene);
+    }
+    let image = scene.post.apply(renderer.image());
+
+    // Stored with gamma 2, so the square root gives display values
+    let mut file = std::io::BufWriter::new(std::fs::File::create("custom_shape.ppm")?);
+    writeln!(file, "P6 {} {} 255", width, height)?;
+    for y in 0..height {
+        for x in 0..width {
+            let [r, g, b, _] = image.get_pixel_color(x, y).to_array();
+            let byte = |c: Float| (c.max(0.0).sqrt().min(1.0) * 255.0) as u8;
+            file.write_all(&[byte(r), byte(g), byte(b)])?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use bumpalo::Bump;
+use std::cell::RefCell;
+
+// Scratch memory for a row's samplers, camera rays, first hits and light tallies, and
+// for the irradiance prepass, one per thread. Allocations are freed all at once when
+// the arena is next handed out, so after the first few rows the hot path reuses the
+// same blocks instead of going to the heap. What a row hands back outlives the arena
+// and stays on the heap.
+thread_local! {
+    static ARENA: RefCell<Bump> = RefCell::new(Bump::new());
+}
+
+// Runs `f` with this thread's arena, emptied first. Renderers call it once per row or
+// tile. A nested call, as when rayon steals work onto a thread already inside `f`, gets
+// a fresh arena instead of clearing one that's in use.
+pub(crate) fn with_arena<R>(f: impl FnOnce(&Bump) -> R) -> R {
+    ARENA.with(|arena| match arena.try_borrow_mut() {
+        Ok(mut arena) => {
+            arena.reset();
+            f(&arena)
+        }
+        Err(_) => f(&Bump::new()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arena_is_reused_between_calls() {
+        let fill = |arena: &Bump| {
+            let floats = arena.alloc_slice_fill_copy(4096, 1.0f32);
+            assert_eq!(floats.len(), 4096);
+            arena.allocated_bytes()
+        };
+        let first = with_arena(fill);
+        let second = with_arena(fill);
+        assert_eq!(first, second);
+
+        let nested = with_arena(|outer| {
+            let kept = outer.alloc(7u32);
+            with_arena(fill);
+            *kept
+        });
+        assert_eq!(nested, 7);
+    }
+}
@@ -0,0 +1,145 @@
+use crate::ScenePack;
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+// Directories listed here, separated like PATH, are searched before anything else
+pub const ASSET_PATH_VAR: &str = "RAZZ_ASSET_PATH";
+
+// Turns the relative paths written in scenes into files on disk, independent of
+// the working directory. Relative paths are tried against, in order: the
+// directories in `RAZZ_ASSET_PATH`, the scene file's directory, any added search
+// paths and finally the working directory. Absolute paths are used as given.
+// A resolver made from a `ScenePack` serves the pack's entries before any of those.
+#[derive(Debug, Clone, Default)]
+pub struct AssetResolver {
+    overrides: Vec<PathBuf>,
+    scene_dir: Option<PathBuf>,
+    search_paths: Vec<PathBuf>,
+    pack: Option<Arc<ScenePack>>,
+    // Every path read so far, shared between clones, for packaging
+    requested: Arc<Mutex<Vec<PathBuf>>>,
+}
+
+impl AssetResolver {
+    pub fn new() -> Self {
+        Self {
+            overrides: std::env::var_os(ASSET_PATH_VAR)
+                .map(|paths| std::env::split_paths(&paths).collect())
+                .unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    // Resolves relative to the directory holding `scene_file`
+    pub fn relative_to(scene_file: impl AsRef<Path>) -> Self {
+        Self {
+            scene_dir: scene_file.as_ref().parent().map(Path::to_path_buf),
+            ..Self::new()
+        }
+    }
+
+    pub fn from_pack(pack: ScenePack) -> Self {
+        Self {
+            pack: Some(Arc::new(pack)),
+            ..Self::new()
+        }
+    }
+
+    pub fn pack(&self) -> Option<&ScenePack> {
+        self.pack.as_deref()
+    }
+
+    pub fn with_search_path(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.search_paths.push(dir.into());
+        self
+    }
+
+    // Every location `path` would be looked for, in order
+    pub fn candidates(&self, path: impl AsRef<Path>) -> Vec<PathBuf> {
+        let path = path.as_ref();
+        if path.is_absolute() {
+            return vec![path.to_path_buf()];
+        }
+
+        self.overrides
+            .iter()
+            .chain(self.scene_dir.iter())
+            .chain(self.search_paths.iter())
+            .map(|dir| dir.join(path))
+            .chain(std::iter::once(path.to_path_buf()))
+            .collect()
+    }
+
+    // The asset's contents, from the pack if there is one and otherwise from disk.
+    // Loaders should read through here so packaging sees what they used.
+    pub fn read(&self, path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+        let path = path.as_ref();
+        let data = match self.pack.as_ref().and_then(|pack| pack.get(path)) {
+            Some(data) => data.to_vec(),
+            None => std::fs::read(self.resolve(path)?)?,
+        };
+
+        let mut requested = self.requested.lock().unwrap();
+        if !requested.iter().any(|p| p == path) {
+            requested.push(path.to_path_buf());
+        }
+        Ok(data)
+    }
+
+    // Paths passed to `read`, in the order they were first asked for
+    pub fn requested(&self) -> Vec<PathBuf> {
+        self.requested.lock().unwrap().clone()
+    }
+
+    pub fn resolve(&self, path: impl AsRef<Path>) -> io::Result<PathBuf> {
+        let candidates = self.candidates(&path);
+        match candidates.iter().find(|candidate| candidate.is_file()) {
+            Some(found) => Ok(found.clone()),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "asset `{}` not found, tried {:?}",
+                    path.as_ref().display(),
+                    candidates
+                ),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scene_directory_wins_over_search_paths() {
+        let root = std::env::temp_dir().join(format!("razz_assets_{}", std::process::id()));
+        let (scene_dir, shared) = (root.join("scene"), root.join("shared"));
+        std::fs::create_dir_all(scene_dir.join("meshes")).unwrap();
+        std::fs::create_dir_all(shared.join("meshes")).unwrap();
+        for dir in [&scene_dir, &shared].iter() {
+            std::fs::write(dir.join("meshes/a.obj"), "").unwrap();
+        }
+        std::fs::write(shared.join("meshes/b.obj"), "").unwrap();
+
+        let assets = AssetResolver {
+            overrides: Vec::new(),
+            ..AssetResolver::relative_to(scene_dir.join("scene.json"))
+        }
+        .with_search_path(&shared);
+        assert_eq!(
+            assets.resolve("meshes/a.obj").unwrap(),
+            scene_dir.join("meshes/a.obj")
+        );
+        assert_eq!(
+            assets.resolve("meshes/b.obj").unwrap(),
+            shared.join("meshes/b.obj")
+        );
+        let missing = assets.resolve("meshes/c.obj").unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,168 @@
+use crate::image::Image;
+use crate::texture::Texture;
+use crate::{TextureKey, World};
+
+use slotmap::SecondaryMap;
+
+// Texel rectangle of one texture within the atlas. Laid out to match the WGSL struct.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct AtlasRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// Every image texture of a scene packed into one image, for backends that can't
+// bind a texture per key. `regions` is the lookup table the shaders index into.
+#[derive(Debug, Clone)]
+pub struct TextureAtlas {
+    pub image: Image,
+    pub regions: Vec<AtlasRegion>,
+    indices: SecondaryMap<TextureKey, u32>,
+}
+
+impl TextureAtlas {
+    // Shelf packs the images tallest first. Rows are kept near square, but no wider
+    // than `max_width` unless a single image is wider still.
+    pub fn pack<'a>(
+        images: impl IntoIterator<Item = (TextureKey, &'a Image)>,
+        max_width: usize,
+    ) -> Self {
+        let mut images: Vec<_> = images.into_iter().collect();
+        images.sort_by_key(|(_, image)| std::cmp::Reverse(image.height));
+
+        let area: usize = images
+            .iter()
+            .map(|(_, image)| image.width * image.height)
+            .sum();
+        let widest = images
+            .iter()
+            .map(|(_, image)| image.width)
+            .max()
+            .unwrap_or(0);
+        let width = ((area as f64).sqrt().ceil() as usize)
+            .min(max_width)
+            .max(widest)
+            .max(1);
+
+        let mut placements = Vec::with_capacity(images.len());
+        let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+        for (key, image) in images.iter() {
+            if x + image.width > width {
+                x = 0;
+                y += shelf_height;
+                shelf_height = 0;
+            }
+            placements.push((*key, x, y));
+            x += image.width;
+            shelf_height = shelf_height.max(image.height);
+        }
+
+        let mut atlas = Image::new(width, (y + shelf_height).max(1));
+        let mut regions = Vec::with_capacity(images.len());
+        let mut indices = SecondaryMap::new();
+        for ((key, image), (_, x, y)) in images.iter().zip(placements) {
+            for row in 0..image.height {
+                let src = row * image.width * 4;
+                let dst = ((y + row) * width + x) * 4;
+                atlas.data[dst..dst + image.width * 4]
+                    .copy_from_slice(&image.data[src..src + image.width * 4]);
+            }
+            indices.insert(*key, regions.len() as u32);
+            regions.push(AtlasRegion {
+                x: x as u32,
+                y: y as u32,
+                width: image.width as u32,
+                height: image.height as u32,
+            });
+        }
+
+        Self {
+            image: atlas,
+            regions,
+            indices,
+        }
+    }
+
+    // Index into `regions` for an image texture
+    pub fn index(&self, key: TextureKey) -> Option<u32> {
+        self.indices.get(key).copied()
+    }
+
+    pub fn region_bytes(&self) -> Vec<u8> {
+        self.regions
+            .iter()
+            .flat_map(|r| [r.x, r.y, r.width, r.height])
+            .flat_map(|v| v.to_ne_bytes())
+            .collect()
+    }
+}
+
+impl World {
+    // Flipbooks are packed as whole sheets
+    pub fn texture_atlas(&self, max_width: usize) -> TextureAtlas {
+        TextureAtlas::pack(
+            self.textures
+                .iter()
+                .filter_map(|(key, texture)| match texture {
+                    Texture::Image { image, .. } => Some((key, image)),
+                    _ => None,
+                }),
+            max_width,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::Rgba;
+    use crate::Float;
+    use slotmap::SlotMap;
+
+    #[test]
+    fn packed_images_keep_their_texels() {
+        let mut keys: SlotMap<TextureKey, ()> = SlotMap::with_key();
+        let sizes = [(5, 3), (2, 7), (4, 4), (6, 1)];
+        let images: Vec<(TextureKey, Image)> = sizes
+            .iter()
+            .enumerate()
+            .map(|(i, (w, h))| {
+                let mut image = Image::new(*w, *h);
+                for y in 0..*h {
+                    for x in 0..*w {
+                        image.set_pixel_color(
+                            x,
+                            y,
+                            Rgba::new(i as Float, x as Float, y as Float, 1.0),
+                        );
+                    }
+                }
+                (keys.insert(()), image)
+            })
+            .collect();
+
+        let atlas = TextureAtlas::pack(images.iter().map(|(k, image)| (*k, image)), 8);
+        assert!(atlas.image.width <= 8);
+        for (key, image) in images.iter() {
+            let region = atlas.regions[atlas.index(*key).unwrap() as usize];
+            assert_eq!(
+                (region.width as usize, region.height as usize),
+                (image.width, image.height)
+            );
+            for y in 0..image.height {
+                for x in 0..image.width {
+                    assert_eq!(
+                        atlas
+                            .image
+                            .get_pixel_color(region.x as usize + x, region.y as usize + y)
+                            .to_array(),
+                        image.get_pixel_color(x, y).to_array()
+                    );
+                }
+            }
+        }
+    }
+}
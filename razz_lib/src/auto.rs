@@ -0,0 +1,327 @@
+use crate::image::Image;
+use crate::{
+    BandRenderer, Float, ParallelRenderer, Primative, Scene, TiledImage, WavefrontRenderer,
+};
+
+use std::io;
+
+// Above this many pixels the progressive buffers get too large to be worth holding
+const MAX_PROGRESSIVE_PIXELS: usize = 4096 * 4096;
+// Pixels per band the band renderer aims for
+const BAND_PIXELS: usize = 1 << 20;
+const MAX_BAND_HEIGHT: usize = 256;
+// Pixels each thread should have at the least before another is worth starting
+const PIXELS_PER_THREAD: usize = 4096;
+// Triangles above which streaming paths through the stages beats following each one
+const WAVEFRONT_TRIANGLES: usize = 250_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderStrategy {
+    // Whole passes over an image held in memory, with `ParallelRenderer`
+    Progressive,
+    // Every sample of one band of rows at a time, with `BandRenderer`
+    Bands,
+    // Whole passes like `Progressive`, a stage at a time with `WavefrontRenderer`
+    Wavefront,
+}
+
+// How a `Renderer` goes about an image. `RenderPlan::auto` picks one from the scene,
+// and any field can be overridden before handing it to `Renderer::from_plan`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderPlan {
+    pub width: usize,
+    pub height: usize,
+    pub strategy: RenderStrategy,
+    // Rows per band, only used by `RenderStrategy::Bands`
+    pub tile_size: usize,
+    // Zero renders on rayon's global pool
+    pub threads: usize,
+    // See `ParallelRenderer::set_preview_stride`
+    pub preview_stride: usize,
+    pub samples_per_pixel: usize,
+    pub max_ray_depth: usize,
+}
+
+impl Default for RenderPlan {
+    fn default() -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            strategy: RenderStrategy::Progressive,
+            tile_size: 64,
+            threads: 0,
+            preview_stride: 1,
+            samples_per_pixel: 64,
+            max_ray_depth: 5,
+        }
+    }
+}
+
+impl RenderPlan {
+    pub fn auto(scene: &Scene, (width, height): (usize, usize)) -> Self {
+        let pixels = width * height;
+        let triangles: usize = scene.world.primitives().map(triangle_count).sum();
+        let lights = scene.world.lights().len();
+
+        let strategy = match pixels > MAX_PROGRESSIVE_PIXELS {
+            true => RenderStrategy::Bands,
+            false if triangles > WAVEFRONT_TRIANGLES => RenderStrategy::Wavefront,
+            false => RenderStrategy::Progressive,
+        };
+
+        let available = rayon::current_num_threads();
+        let tile_size = (BAND_PIXELS / width.max(1))
+            .clamp(available, MAX_BAND_HEIGHT.max(available))
+            .min(height.max(1));
+        // Threads split the work by rows, so more than there are rows would sit idle
+        let rows = match strategy {
+            RenderStrategy::Progressive | RenderStrategy::Wavefront => height,
+            RenderStrategy::Bands => tile_size,
+        };
+        let threads = (pixels / PIXELS_PER_THREAD)
+            .clamp(1, available)
+            .min(rows.max(1));
+
+        // Traversal cost grows with the depth of the BVH, so previews kick in sooner for
+        // heavy meshes
+        let pass_cost = pixels as Float * (triangles.max(1) as Float).log2().max(1.0);
+        let preview_stride = match pass_cost {
+            c if c > 2e7 => 4,
+            c if c > 5e6 => 2,
+            _ => 1,
+        };
+
+        // Light sampling picks one light per vertex, so many lights take more samples
+        // to converge
+        let samples_per_pixel = match lights {
+            0..=4 => 64,
+            _ => 128,
+        };
+
+        Self {
+            width,
+            height,
+            strategy,
+            tile_size,
+            threads: match threads == available {
+                true => 0,
+                false => threads,
+            },
+            preview_stride,
+            samples_per_pixel,
+            ..Self::default()
+        }
+    }
+}
+
+// Instances count once, as they share their object's hierarchy
+fn triangle_count(primative: &Primative) -> usize {
+    match primative {
+        Primative::Mesh(mesh) => mesh.triangles().count(),
+        _ => 1,
+    }
+}
+
+#[derive(Debug)]
+enum Strategy {
+    Progressive(Box<ParallelRenderer>),
+    Bands(BandRenderer),
+    Wavefront(Box<WavefrontRenderer>),
+}
+
+// Renders to a fixed sample count with whichever renderer suits the image, for callers
+// that don't want to tune one themselves
+#[derive(Debug)]
+pub struct Renderer {
+    plan: RenderPlan,
+    pool: Option<rayon::ThreadPool>,
+    strategy: Strategy,
+}
+
+impl Renderer {
+    pub fn auto(scene: &Scene, size: (usize, usize)) -> Self {
+        Self::from_plan(RenderPlan::auto(scene, size))
+    }
+
+    pub fn from_plan(plan: RenderPlan) -> Self {
+        let pool = match plan.threads {
+            0 => None,
+            threads => rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .ok(),
+        };
+        let strategy = match plan.strategy {
+            RenderStrategy::Progressive => {
+                let mut renderer =
+                    ParallelRenderer::new(plan.width, plan.height, plan.max_ray_depth);
+                renderer.set_preview_stride(plan.preview_stride);
+                renderer.set_target_samples(Some(plan.samples_per_pixel));
+                Strategy::Progressive(Box::new(renderer))
+            }
+            RenderStrategy::Bands => Strategy::Bands(BandRenderer::new(
+                plan.width,
+                plan.height,
+                plan.tile_size,
+                plan.max_ray_depth,
+                plan.samples_per_pixel,
+            )),
+            RenderStrategy::Wavefront => Strategy::Wavefront(Box::new(WavefrontRenderer::new(
+                plan.width,
+                plan.height,
+                plan.max_ray_depth,
+            ))),
+        };
+        Self {
+            plan,
+            pool,
+            strategy,
+        }
+    }
+
+    pub fn plan(&self) -> &RenderPlan {
+        &self.plan
+    }
+
+    // The progressive renderer, for driving it a pass at a time instead
+    pub fn progressive(&mut self) -> Option<&mut ParallelRenderer> {
+        match &mut self.strategy {
+            Strategy::Progressive(renderer) => Some(renderer.as_mut()),
+            Strategy::Bands(_) | Strategy::Wavefront(_) => None,
+        }
+    }
+
+    // The finished image, in linear light
+    pub fn render(&mut self, scene: &Scene) -> Image {
+        let Self {
+            plan,
+            pool,
+            strategy,
+        } = self;
+        let mut render = || match strategy {
+            Strategy::Progressive(renderer) => {
+                while renderer.samples_per_pixel() < plan.samples_per_pixel {
+                    renderer.render(scene);
+                }
+                renderer.image().clone()
+            }
+            Strategy::Bands(renderer) => {
+                let mut image = Image::new(plan.width, plan.height);
+                let stride = plan.width * 4;
+                renderer.render(scene, |row_start, band| {
+                    image.data[row_start * stride..][..band.data.len()].copy_from_slice(&band.data);
+                });
+                image
+            }
+            Strategy::Wavefront(renderer) => {
+                while renderer.samples_per_pixel() < plan.samples_per_pixel {
+                    renderer.render(scene);
+                }
+                renderer.image().clone()
+            }
+        };
+        match pool {
+            Some(pool) => pool.install(render),
+            None => render(),
+        }
+    }
+
+    // Renders into a disk backed image through the scene's display transform. Only
+    // bands are stored as they finish, whole pass images are written at the end.
+    pub fn render_to(&mut self, scene: &Scene, target: &mut TiledImage) -> io::Result<()> {
+        target.check_size(self.plan.width, self.plan.height)?;
+        let Self {
+            plan,
+            pool,
+            strategy,
+        } = self;
+        let mut render = || {
+            let image = match strategy {
+                Strategy::Bands(renderer) => return renderer.render_to(scene, target),
+                Strategy::Progressive(renderer) => {
+                    while renderer.samples_per_pixel() < plan.samples_per_pixel {
+                        renderer.render(scene);
+                    }
+                    renderer.display_image(scene)
+                }
+                Strategy::Wavefront(renderer) => {
+                    while renderer.samples_per_pixel() < plan.samples_per_pixel {
+                        renderer.render(scene);
+                    }
+                    renderer.display_image(scene)
+                }
+            };
+            target.write_region(0, 0, &image)?;
+            target.flush()
+        };
+        match pool {
+            Some(pool) => pool.install(render),
+            None => render(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Background, Camera, Material, Rgba, Texture, Vec3A, WorldBuilder};
+
+    #[test]
+    fn plans_follow_the_image_size() {
+        let mut world_builder = WorldBuilder::default();
+        let grey = world_builder.push_texture(Texture::Solid {
+            color: Rgba::splat(0.5),
+        });
+        let grey = world_builder.push_material(Material::Lambertian { albedo: grey });
+        world_builder.push_hittable(Primative::sphere(Vec3A::ZERO, 1.0, grey));
+        world_builder.set_background(Background::rtiow_sky());
+        let camera = Camera::new(3.0 * Vec3A::Z, Vec3A::ZERO, 40.0, 1.0, 0.0, 1.0);
+        let scene = Scene::new(world_builder.into(), camera);
+
+        let large = RenderPlan::auto(&scene, (8192, 8192));
+        assert_eq!(large.strategy, RenderStrategy::Bands);
+        assert!(large.tile_size <= 8192);
+
+        // Too small to share between threads
+        let mut plan = RenderPlan::auto(&scene, (16, 8));
+        assert_eq!(plan.strategy, RenderStrategy::Progressive);
+        assert!(plan.threads <= 1);
+
+        plan.samples_per_pixel = 2;
+        let progressive = Renderer::from_plan(plan.clone()).render(&scene);
+        plan.strategy = RenderStrategy::Bands;
+        plan.tile_size = 3;
+        let bands = Renderer::from_plan(plan.clone()).render(&scene);
+        plan.strategy = RenderStrategy::Wavefront;
+        let wavefront = Renderer::from_plan(plan).render(&scene);
+        assert_eq!((bands.width, bands.height), (16, 8));
+        // Both trace the same samples
+        for ((a, b), c) in progressive
+            .data
+            .iter()
+            .zip(&bands.data)
+            .zip(&wavefront.data)
+        {
+            assert!((a - b).abs() < 1e-4 && (a - c).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn mismatched_targets_are_errors() {
+        let mut world_builder = WorldBuilder::default();
+        world_builder.set_background(Background::rtiow_sky());
+        let camera = Camera::new(3.0 * Vec3A::Z, Vec3A::ZERO, 40.0, 1.0, 0.0, 1.0);
+        let scene = Scene::new(world_builder.into(), camera);
+
+        let path = std::env::temp_dir().join(format!("razz_auto_{}.bin", std::process::id()));
+        let mut target = TiledImage::create(&path, 8, 8, 8, 1).unwrap();
+        let result = Renderer::from_plan(RenderPlan {
+            width: 16,
+            height: 8,
+            ..RenderPlan::default()
+        })
+        .render_to(&scene, &mut target);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}
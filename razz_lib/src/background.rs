@@ -0,0 +1,105 @@
+use crate::image::Rgba;
+use crate::{Ray3A, Vec3A};
+
+use std::fmt;
+use std::sync::Arc;
+
+// What rays that leave the scene see
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Background {
+    Solid { color: Rgba },
+    // Blended by the height of the ray direction, from `bottom` straight down to `top`
+    // straight up
+    VerticalGradient { top: Rgba, bottom: Rgba },
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Solid { color: Rgba::ZERO }
+    }
+}
+
+impl Background {
+    // The blue to white sky from Ray Tracing in One Weekend
+    pub fn rtiow_sky() -> Self {
+        Background::VerticalGradient {
+            top: Rgba::new(0.5, 0.7, 1.0, 1.0),
+            bottom: Rgba::ONE,
+        }
+    }
+
+    pub fn color(&self, direction: Vec3A) -> Rgba {
+        match self {
+            Background::Solid { color } => *color,
+            Background::VerticalGradient { top, bottom } => {
+                let t = 0.5 * (direction.normalize_or_zero().y + 1.0);
+                *bottom * (1.0 - t) + *top * t
+            }
+        }
+    }
+}
+
+// Colors rays that leave the scene in place of the world's `Background`, for custom sky
+// models or environments driven by data
+#[derive(Clone)]
+pub struct MissShader(Arc<dyn Fn(&Ray3A) -> Rgba + Send + Sync>);
+
+impl MissShader {
+    pub fn new(shader: impl Fn(&Ray3A) -> Rgba + Send + Sync + 'static) -> Self {
+        Self(Arc::new(shader))
+    }
+
+    pub fn color(&self, ray: &Ray3A) -> Rgba {
+        (self.0)(ray)
+    }
+}
+
+impl fmt::Debug for MissShader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MissShader")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Camera, ParallelRenderer, Scene, WorldBuilder};
+
+    #[test]
+    fn gradient_runs_from_bottom_to_top() {
+        let sky = Background::rtiow_sky();
+        assert_eq!(sky.color(Vec3A::new(0.0, -2.0, 0.0)), Rgba::ONE);
+        assert_eq!(sky.color(Vec3A::Y), Rgba::new(0.5, 0.7, 1.0, 1.0));
+        assert_eq!(sky.color(Vec3A::X), Rgba::new(0.75, 0.85, 1.0, 1.0));
+    }
+
+    #[test]
+    fn miss_shaders_replace_the_background() {
+        let mut world_builder = WorldBuilder::default();
+        world_builder.set_background(Background::rtiow_sky());
+        let camera = Camera::new(Vec3A::ZERO, -Vec3A::Z, 40.0, 1.0, 0.0, 1.0);
+        let mut scene = Scene::new(world_builder.into(), camera);
+        // Red looking left, blue looking right
+        scene
+            .world
+            .set_miss_shader(Some(MissShader::new(|ray: &Ray3A| {
+                match ray.direction.x < 0.0 {
+                    true => Rgba::new(1.0, 0.0, 0.0, 1.0),
+                    false => Rgba::new(0.0, 0.0, 1.0, 1.0),
+                }
+            })));
+
+        let mut renderer = ParallelRenderer::new(4, 4, 2);
+        renderer.render(&scene);
+        let image = renderer.image();
+        assert_eq!(image.get_pixel_color(0, 2), Rgba::new(1.0, 0.0, 0.0, 1.0));
+        assert_eq!(image.get_pixel_color(3, 2), Rgba::new(0.0, 0.0, 1.0, 1.0));
+
+        scene.world.set_miss_shader(None);
+        renderer.reset();
+        renderer.render(&scene);
+        // Back to the sky, which has green in every direction
+        assert!(renderer.image().get_pixel_color(0, 2).to_array()[1] > 0.5);
+    }
+}
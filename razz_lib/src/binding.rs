@@ -0,0 +1,262 @@
+use crate::image::Rgba;
+use crate::{
+    invalidate_texture_caches, Float, Material, MaterialKey, Point3, PrimativeKey, Scene, Texture,
+    TextureKey, Transform,
+};
+
+use std::fmt;
+use std::io;
+
+// A value that changes over time, read once per frame of an animation
+pub enum Channel {
+    // Pairs of time and value sorted by time, interpolated linearly between them and
+    // held past either end
+    Samples(Vec<(Float, Float)>),
+    Callback(Box<dyn Fn(Float) -> Float + Send + Sync>),
+}
+
+impl Channel {
+    pub fn callback(f: impl Fn(Float) -> Float + Send + Sync + 'static) -> Self {
+        Channel::Callback(Box::new(f))
+    }
+
+    pub fn value(&self, time: Float) -> Float {
+        match self {
+            Channel::Samples(samples) => {
+                let next = samples.partition_point(|(t, _)| *t <= time);
+                match (next.checked_sub(1).map(|i| samples[i]), samples.get(next)) {
+                    (Some((t0, v0)), Some(&(t1, v1))) => v0 + (v1 - v0) * (time - t0) / (t1 - t0),
+                    (Some((_, v)), None) | (None, Some(&(_, v))) => v,
+                    (None, None) => 0.0,
+                }
+            }
+            Channel::Callback(f) => f(time),
+        }
+    }
+
+    // One channel per column after the first, named by the header row. The first
+    // column is the time in seconds, or whatever unit the frames are timed in.
+    pub fn from_csv(text: &str) -> io::Result<Vec<(String, Channel)>> {
+        let invalid = |line: usize, message: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", line + 1, message),
+            )
+        };
+
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| !l.trim().is_empty());
+        let names: Vec<String> = match lines.next() {
+            Some((_, header)) => header.split(',').skip(1).map(|n| n.trim().into()).collect(),
+            None => return Ok(Vec::new()),
+        };
+
+        let mut columns = vec![Vec::new(); names.len()];
+        for (number, line) in lines {
+            let values = line
+                .split(',')
+                .map(|v| v.trim().parse::<Float>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| invalid(number, "expected numbers"))?;
+            if values.len() != names.len() + 1 {
+                return Err(invalid(number, "wrong number of columns"));
+            }
+            for (column, value) in columns.iter_mut().zip(&values[1..]) {
+                column.push((values[0], *value));
+            }
+        }
+
+        Ok(names
+            .into_iter()
+            .zip(columns)
+            .map(|(name, mut samples)| {
+                samples.sort_by(|a, b| a.0.total_cmp(&b.0));
+                (name, Channel::Samples(samples))
+            })
+            .collect())
+    }
+}
+
+impl fmt::Debug for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Channel::Samples(samples) => f.debug_tuple("Samples").field(samples).finish(),
+            Channel::Callback(_) => f.write_str("Callback"),
+        }
+    }
+}
+
+// The scene parameter a channel drives. Geometry is fixed once the world is built, so
+// only the camera and instances pushed with `WorldBuilder::push_instance` move.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BindingTarget {
+    // Of a `DiffuseLight`, or the emission strength of a `Principled` material
+    LightIntensity(MaterialKey),
+    // Sets a solid texture to `from` blended toward `to` by the value
+    Color {
+        texture: TextureKey,
+        from: Rgba,
+        to: Rgba,
+    },
+    // Moves the camera from `from` toward `to` by the value, still facing its target
+    CameraPosition {
+        from: Point3,
+        to: Point3,
+    },
+    // Places an instance part way from `from` to `to` by the value
+    InstanceTransform {
+        instance: PrimativeKey,
+        from: Transform,
+        to: Transform,
+    },
+}
+
+#[derive(Debug)]
+pub struct Binding {
+    pub channel: Channel,
+    pub target: BindingTarget,
+}
+
+impl Binding {
+    pub fn new(channel: Channel, target: BindingTarget) -> Self {
+        Self { channel, target }
+    }
+}
+
+// Sets every bound parameter to its value at `time`, along with the time of the
+// world's animated textures. Bindings to missing or mismatched keys are ignored.
+pub fn apply_bindings(scene: &mut Scene, bindings: &[Binding], time: Float) {
+    scene.world.set_time(time);
+    let mut textures_changed = false;
+    let mut moves = Vec::new();
+    for binding in bindings {
+        let value = binding.channel.value(time);
+        match binding.target {
+            BindingTarget::LightIntensity(key) => match scene.world.materials.get_mut(key) {
+                Some(Material::DiffuseLight { intensity, .. }) => *intensity = value,
+                Some(Material::Principled {
+                    emission_strength, ..
+                }) => *emission_strength = value,
+                _ => {}
+            },
+            BindingTarget::Color { texture, from, to } => {
+                if let Some(Texture::Solid { color }) = scene.world.textures.get_mut(texture) {
+                    *color = from * (1.0 - value) + to * value;
+                    textures_changed = true;
+                }
+            }
+            BindingTarget::CameraPosition { from, to } => {
+                let look_at = scene.sampler.look_at();
+                scene.sampler.set_view(from + (to - from) * value, look_at);
+            }
+            BindingTarget::InstanceTransform { instance, from, to } => {
+                moves.push((instance, from.lerp(&to, value)))
+            }
+        }
+    }
+    // Together, so the BVH over the instances is rebuilt once
+    if !moves.is_empty() {
+        scene.world.move_instances(moves);
+    }
+    // Cached textures may wrap the ones just changed
+    if textures_changed {
+        invalidate_texture_caches();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Camera, MediumRng, Primative, Ray3A, Vec3A, WorldBuilder};
+    use std::sync::Arc;
+
+    #[test]
+    fn csv_channels_drive_lights_and_colors() {
+        let channels = Channel::from_csv("time, bass, hue\n0, 0, 0\n2, 4, 1\n").unwrap();
+        assert_eq!(channels[0].0, "bass");
+        assert_eq!(channels[0].1.value(1.0), 2.0);
+        assert_eq!(channels[0].1.value(5.0), 4.0);
+        assert!(Channel::from_csv("time, bass\n0, loud\n").is_err());
+
+        let mut world_builder = WorldBuilder::default();
+        let emit = world_builder.push_texture(Texture::Solid { color: Rgba::ONE });
+        let light = world_builder.push_material(Material::DiffuseLight {
+            emit,
+            intensity: 1.0,
+        });
+        let camera = Camera::new(Vec3A::Z, Vec3A::ZERO, 40.0, 1.0, 0.0, 1.0);
+        let mut scene = Scene::new(world_builder.into(), camera);
+
+        let mut channels = channels.into_iter().map(|(_, channel)| channel);
+        let bindings = [
+            Binding::new(
+                channels.next().unwrap(),
+                BindingTarget::LightIntensity(light),
+            ),
+            Binding::new(
+                channels.next().unwrap(),
+                BindingTarget::Color {
+                    texture: emit,
+                    from: Rgba::ZERO,
+                    to: Rgba::new(1.0, 0.5, 0.0, 1.0),
+                },
+            ),
+            Binding::new(
+                Channel::callback(|t| 0.5 * t),
+                BindingTarget::CameraPosition {
+                    from: Vec3A::Z,
+                    to: 3.0 * Vec3A::Z,
+                },
+            ),
+        ];
+        apply_bindings(&mut scene, &bindings, 1.0);
+
+        match scene.world.materials()[light] {
+            Material::DiffuseLight { intensity, .. } => assert_eq!(intensity, 2.0),
+            _ => unreachable!(),
+        }
+        match scene.world.textures()[emit] {
+            Texture::Solid { color } => assert_eq!(color, Rgba::new(0.5, 0.25, 0.0, 0.5)),
+            _ => unreachable!(),
+        }
+        assert_eq!(scene.sampler.look_from(), 2.0 * Vec3A::Z);
+    }
+
+    #[test]
+    fn bindings_move_instances() {
+        let mut world_builder = WorldBuilder::default();
+        let grey = world_builder.push_texture(Texture::Solid {
+            color: Rgba::splat(0.5),
+        });
+        let grey = world_builder.push_material(Material::Lambertian { albedo: grey });
+        let ball = Arc::new(Primative::sphere(Vec3A::ZERO, 1.0, grey));
+        let at = |x: Float| Transform::new(Vec3A::new(x, 0.0, 0.0), glam::Quat::IDENTITY, 1.0);
+        let instance = world_builder.push_instance(ball, at(0.0));
+        let camera = Camera::new(Vec3A::Z, Vec3A::ZERO, 40.0, 1.0, 0.0, 1.0);
+        let mut scene = Scene::new(world_builder.into(), camera);
+
+        let bindings = [Binding::new(
+            Channel::callback(|t| t),
+            BindingTarget::InstanceTransform {
+                instance,
+                from: at(0.0),
+                to: at(10.0),
+            },
+        )];
+        apply_bindings(&mut scene, &bindings, 0.5);
+
+        let blocked = |x: Float| {
+            let ray = Ray3A {
+                origin: Point3::new(x, 0.0, 10.0),
+                direction: -Vec3A::Z,
+            };
+            scene
+                .world
+                .ray_occluded(&ray, Float::INFINITY, &mut MediumRng::default())
+        };
+        assert!(blocked(5.0));
+        assert!(!blocked(0.0));
+    }
+}
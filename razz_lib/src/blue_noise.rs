@@ -0,0 +1,142 @@
+use crate::Float;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::OnceLock;
+
+pub(crate) const BLUE_NOISE_SIZE: usize = 64;
+const SIGMA: Float = 1.5;
+// Share of pixels set in the starting pattern
+const INITIAL_DENSITY: Float = 0.1;
+
+// A tiling texture of values in [0, 1) where neighbouring pixels differ as much as
+// possible, built once with Ulichney's void-and-cluster method. Rows of
+// `BLUE_NOISE_SIZE` pixels.
+pub(crate) fn blue_noise() -> &'static [Float] {
+    static MASK: OnceLock<Vec<Float>> = OnceLock::new();
+    MASK.get_or_init(void_and_cluster)
+}
+
+#[derive(Clone)]
+struct Pattern {
+    set: Vec<bool>,
+    // Sum of a Gaussian around every set pixel, wrapping at the edges
+    energy: Vec<Float>,
+    kernel: Vec<Float>,
+}
+
+impl Pattern {
+    fn new() -> Self {
+        let n = BLUE_NOISE_SIZE;
+        let wrapped = |d: usize| d.min(n - d) as Float;
+        let kernel = (0..n * n)
+            .map(|i| {
+                let (dx, dy) = (wrapped(i % n), wrapped(i / n));
+                (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp()
+            })
+            .collect();
+        Self {
+            set: vec![false; n * n],
+            energy: vec![0.0; n * n],
+            kernel,
+        }
+    }
+
+    fn toggle(&mut self, pixel: usize) {
+        let n = BLUE_NOISE_SIZE;
+        let sign = if self.set[pixel] { -1.0 } else { 1.0 };
+        self.set[pixel] = !self.set[pixel];
+        let (px, py) = (pixel % n, pixel / n);
+        for (i, energy) in self.energy.iter_mut().enumerate() {
+            let (dx, dy) = ((i % n + n - px) % n, (i / n + n - py) % n);
+            *energy += sign * self.kernel[dy * n + dx];
+        }
+    }
+
+    // The set pixel with the most set neighbours
+    fn tightest_cluster(&self) -> usize {
+        self.extreme(true, |a, b| a > b)
+    }
+
+    // The unset pixel furthest from any set one
+    fn largest_void(&self) -> usize {
+        self.extreme(false, |a, b| a < b)
+    }
+
+    fn extreme(&self, set: bool, better: impl Fn(Float, Float) -> bool) -> usize {
+        let mut best = None;
+        for (i, energy) in self.energy.iter().enumerate() {
+            if self.set[i] == set && best.is_none_or(|b: usize| better(*energy, self.energy[b])) {
+                best = Some(i);
+            }
+        }
+        best.expect("pattern has pixels of both kinds")
+    }
+}
+
+fn void_and_cluster() -> Vec<Float> {
+    let pixels = BLUE_NOISE_SIZE * BLUE_NOISE_SIZE;
+    let mut rng = StdRng::seed_from_u64(0);
+
+    // Spread a random starting pattern out evenly
+    let mut pattern = Pattern::new();
+    let initial = (INITIAL_DENSITY * pixels as Float) as usize;
+    while pattern.set.iter().filter(|s| **s).count() < initial {
+        let pixel = rng.gen_range(0..pixels);
+        if !pattern.set[pixel] {
+            pattern.toggle(pixel);
+        }
+    }
+    loop {
+        let cluster = pattern.tightest_cluster();
+        pattern.toggle(cluster);
+        let void = pattern.largest_void();
+        pattern.toggle(void);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0; pixels];
+    // Unwinding the starting pattern ranks its pixels, tightest clusters last
+    let mut unwinding = pattern.clone();
+    for r in (0..initial).rev() {
+        let cluster = unwinding.tightest_cluster();
+        unwinding.toggle(cluster);
+        rank[cluster] = r;
+    }
+    // Then filling the largest voids ranks the rest
+    for r in initial..pixels {
+        let void = pattern.largest_void();
+        pattern.toggle(void);
+        rank[void] = r;
+    }
+
+    rank.into_iter()
+        .map(|r| (r as Float + 0.5) / pixels as Float)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn neighbours_differ_more_than_white_noise() {
+        let mask = blue_noise();
+        let n = BLUE_NOISE_SIZE;
+        let mut sorted = mask.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        // Every rank appears once
+        for (i, value) in sorted.iter().enumerate() {
+            assert!((value - (i as Float + 0.5) / (n * n) as Float).abs() < 1e-6);
+        }
+
+        // White noise averages 1/3 between neighbours
+        let difference: Float = (0..n * n)
+            .map(|i| (mask[i] - mask[(i / n) * n + (i + 1) % n]).abs())
+            .sum::<Float>()
+            / (n * n) as Float;
+        assert!(difference > 0.4, "{}", difference);
+    }
+}
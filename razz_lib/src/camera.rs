@@ -1,9 +1,37 @@
-use crate::{Float, Ray3A, Vec3A};
+use crate::{Float, GpuCamera, Polarizer, Ray3A, SampleDimension, Sampler, Vec3A};
 
 use rand::Rng;
+use std::io;
+
+const PI: Float = std::f64::consts::PI as Float;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Projection {
+    #[default]
+    Perspective,
+    Equirectangular,
+}
 
 #[derive(Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Camera {
+    projection: Projection,
+    polarizer: Option<Float>,
+    look_from: Vec3A,
+    look_at: Vec3A,
+    #[cfg_attr(feature = "serde", serde(default = "default_up"))]
+    up: Vec3A,
+    #[cfg_attr(feature = "serde", serde(default))]
+    roll: Float,
+    vfov: Float,
+    aperture: Float,
+    focus_dist: Float,
+    // Times the exposure opens and closes. Each camera ray is sent at a time drawn
+    // between them, so whatever moves in that span blurs.
+    #[cfg_attr(feature = "serde", serde(default))]
+    shutter: (Float, Float),
+
     origin: Vec3A,
     top_right: Vec3A,
     horizontal: Vec3A,
@@ -23,19 +51,88 @@ impl Camera {
         pixel_y: usize,
         width: usize,
         height: usize,
-        rng: &mut impl Rng,
+        sampler: &mut Sampler,
     ) -> Ray3A {
-        let u: Float = (pixel_x as Float + rng.gen::<Float>()) / ((width - 1) as Float);
-        let v: Float = (pixel_y as Float + rng.gen::<Float>()) / ((height - 1) as Float);
+        sampler.start(0, SampleDimension::Time);
+        let (open, close) = self.shutter;
+        let time = open + (close - open) * sampler.gen::<Float>();
+        sampler.set_time(time);
+
+        sampler.start(0, SampleDimension::PixelJitter);
+        if self.projection == Projection::Equirectangular {
+            return self.get_equirect_ray(pixel_x, pixel_y, width, height, sampler);
+        }
+
+        let u: Float = (pixel_x as Float + sampler.gen::<Float>()) / ((width - 1) as Float);
+        let v: Float = (pixel_y as Float + sampler.gen::<Float>()) / ((height - 1) as Float);
 
+        sampler.start(0, SampleDimension::Lens);
+        let rd = self.lens_radius * sample_unit_disk(sampler);
+        let offset = self.u * rd.x + self.v * rd.y;
+
+        Ray3A {
+            origin: self.origin + offset,
+            direction: self.top_right + (u * self.horizontal)
+                - (v * self.vertical)
+                - self.origin
+                - offset,
+        }
+    }
+
+    // The ray through the lens center toward (`u`, `v`) on the image, both in [0, 1]
+    // from the top left, ignoring depth of field
+    pub fn pinhole_ray(&self, u: Float, v: Float) -> Ray3A {
         Ray3A {
             origin: self.origin,
             direction: self.top_right + (u * self.horizontal) - (v * self.vertical) - self.origin,
         }
     }
+
+    // The image plane and lens for shaders, which jitter and project rays themselves
+    pub fn gpu_camera(&self) -> GpuCamera {
+        let equirect = match self.projection {
+            Projection::Perspective => 0.0,
+            Projection::Equirectangular => 1.0,
+        };
+        GpuCamera {
+            origin: self.origin.extend(self.lens_radius).to_array(),
+            corner: self.top_right.extend(0.0).to_array(),
+            horizontal: self.horizontal.extend(0.0).to_array(),
+            vertical: self.vertical.extend(0.0).to_array(),
+            u: self.u.extend(0.0).to_array(),
+            v: self.v.extend(0.0).to_array(),
+            w: self.w.extend(equirect).to_array(),
+        }
+    }
+
+    // Longitude spans [0, width) so the left and right edges meet without a seam
+    fn get_equirect_ray(
+        &self,
+        pixel_x: usize,
+        pixel_y: usize,
+        width: usize,
+        height: usize,
+        rng: &mut impl Rng,
+    ) -> Ray3A {
+        let u: Float = (pixel_x as Float + rng.gen::<Float>()) / width as Float;
+        let v: Float = (pixel_y as Float + rng.gen::<Float>()) / height as Float;
+
+        let phi = (u - 0.5) * 2.0 * PI;
+        let theta = v.clamp(0.0, 1.0) * PI;
+
+        Ray3A {
+            origin: self.origin,
+            direction: theta.sin() * (phi.sin() * self.u - phi.cos() * self.w)
+                + theta.cos() * self.v,
+        }
+    }
 }
 
 impl Camera {
+    pub fn builder() -> CameraBuilder {
+        CameraBuilder::default()
+    }
+
     pub fn new(
         look_from: Vec3A,
         look_at: Vec3A,
@@ -44,30 +141,407 @@ impl Camera {
         aperture: Float,
         focus_dist: Float,
     ) -> Self {
-        let theta = vfov.to_radians();
+        let mut camera = Self {
+            projection: Projection::Perspective,
+            polarizer: None,
+            look_from,
+            look_at,
+            up: default_up(),
+            roll: 0.0,
+            vfov,
+            aperture,
+            focus_dist,
+            ar,
+            ..Default::default()
+        };
+        camera.orient();
+        camera
+    }
+
+    // Rebuilds the view basis and image plane from the settings
+    fn orient(&mut self) {
+        let theta = self.vfov.to_radians();
         let h = (theta * 0.5).tan();
         let viewport_height = 2.0 * h;
-        let viewport_width = ar * viewport_height;
+        let viewport_width = self.ar * viewport_height;
 
-        let w = (look_from - look_at).normalize();
-        let u = Vec3A::cross(Vec3A::new(0.0, 1.0, 0.0), w).normalize();
+        let w = (self.look_from - self.look_at).normalize();
+        // Looking straight along `up` leaves it no sideways direction to give
+        let up = match self.up.cross(w).length_squared() > 1e-8 {
+            true => self.up,
+            false => w.any_orthonormal_vector(),
+        };
+        let u = Vec3A::cross(up, w).normalize();
         let v = Vec3A::cross(w, u);
+        let (sin, cos) = self.roll.sin_cos();
+        let (u, v) = (cos * u + sin * v, cos * v - sin * u);
+
+        self.origin = self.look_from;
+        self.lens_radius = 0.5 * self.aperture;
+        self.horizontal = self.focus_dist * viewport_width * u;
+        self.vertical = self.focus_dist * viewport_height * v;
+        self.top_right =
+            self.origin - (0.5 * self.horizontal) + (0.5 * self.vertical) - self.focus_dist * w;
+        self.u = u;
+        self.v = v;
+        self.w = w;
+    }
+
+    pub fn look_from(&self) -> Vec3A {
+        self.look_from
+    }
+
+    pub fn look_at(&self) -> Vec3A {
+        self.look_at
+    }
+
+    // The world direction that appears upward in the image, `Vec3A::Y` by default
+    pub fn with_up(mut self, up: Vec3A) -> Self {
+        self.up = up.normalize_or_zero();
+        self.orient();
+        self
+    }
+
+    pub fn up(&self) -> Vec3A {
+        self.up
+    }
+
+    // Turns the camera `roll` radians counterclockwise about its view direction
+    pub fn with_roll(mut self, roll: Float) -> Self {
+        self.roll = roll;
+        self.orient();
+        self
+    }
+
+    pub fn roll(&self) -> Float {
+        self.roll
+    }
+
+    // Width over height of the image
+    pub fn aspect(&self) -> Float {
+        self.ar
+    }
+
+    // The vertical field of view is kept, so a wider image sees more to the sides
+    pub fn set_aspect(&mut self, aspect: Float) {
+        self.ar = aspect;
+        self.orient();
+    }
+
+    pub fn focus_distance(&self) -> Float {
+        self.focus_dist
+    }
+
+    pub fn set_focus_distance(&mut self, focus_distance: Float) {
+        self.focus_dist = focus_distance;
+        self.orient();
+    }
+
+    pub fn with_projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    pub fn with_shutter(mut self, open: Float, close: Float) -> Self {
+        self.shutter = (open, close);
+        self
+    }
+
+    pub fn shutter(&self) -> (Float, Float) {
+        self.shutter
+    }
 
-        let origin = look_from;
-        let horizontal = focus_dist * viewport_width * u;
-        let vertical = focus_dist * viewport_height * v;
-        let top_right = origin - (0.5 * horizontal) + (0.5 * vertical) - focus_dist * w;
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
 
+    // Experimental. Places a linear polarizing filter with its axis `angle`
+    // radians from the image horizontal in front of the lens.
+    pub fn with_polarizer(mut self, angle: Float) -> Self {
+        self.polarizer = Some(angle);
+        self
+    }
+
+    pub fn polarizer(&self) -> Option<Polarizer> {
+        self.polarizer.map(|angle| Polarizer::linear(angle, self.u))
+    }
+
+    pub fn set_view(&mut self, look_from: Vec3A, look_at: Vec3A) {
+        self.look_from = look_from;
+        self.look_at = look_at;
+        self.orient();
+    }
+}
+
+// Named settings for `Camera`, checked when built. Unset, the camera looks from +z
+// at the origin with a 40 degree field of view, a square image, no depth of field
+// and focus on `look_at`.
+#[derive(Debug, Clone, Copy)]
+pub struct CameraBuilder {
+    look_from: Vec3A,
+    look_at: Vec3A,
+    up: Vec3A,
+    roll: Float,
+    vfov: Float,
+    aspect: Float,
+    aperture: Float,
+    focus_distance: Option<Float>,
+    shutter: (Float, Float),
+    projection: Projection,
+}
+
+impl Default for CameraBuilder {
+    fn default() -> Self {
         Self {
-            origin,
-            horizontal,
-            vertical,
-            top_right,
-            lens_radius: 0.5 * aperture,
-            ar,
-            u,
-            v,
-            w,
+            look_from: Vec3A::Z,
+            look_at: Vec3A::ZERO,
+            up: default_up(),
+            roll: 0.0,
+            vfov: 40.0,
+            aspect: 1.0,
+            aperture: 0.0,
+            focus_distance: None,
+            shutter: (0.0, 0.0),
+            projection: Projection::Perspective,
         }
     }
 }
+
+impl CameraBuilder {
+    pub fn look_from(mut self, look_from: Vec3A) -> Self {
+        self.look_from = look_from;
+        self
+    }
+
+    pub fn look_at(mut self, look_at: Vec3A) -> Self {
+        self.look_at = look_at;
+        self
+    }
+
+    pub fn up(mut self, up: Vec3A) -> Self {
+        self.up = up;
+        self
+    }
+
+    // Radians, counterclockwise about the view direction
+    pub fn roll(mut self, roll: Float) -> Self {
+        self.roll = roll;
+        self
+    }
+
+    // Vertical field of view in degrees
+    pub fn vfov(mut self, vfov: Float) -> Self {
+        self.vfov = vfov;
+        self
+    }
+
+    // Image width over height
+    pub fn aspect(mut self, aspect: Float) -> Self {
+        self.aspect = aspect;
+        self
+    }
+
+    // Lens diameter, zero for everything in focus
+    pub fn aperture(mut self, aperture: Float) -> Self {
+        self.aperture = aperture;
+        self
+    }
+
+    // Defaults to the distance to `look_at`
+    pub fn focus_distance(mut self, focus_distance: Float) -> Self {
+        self.focus_distance = Some(focus_distance);
+        self
+    }
+
+    // Times the exposure opens and closes, a single instant unless set
+    pub fn shutter(mut self, open: Float, close: Float) -> Self {
+        self.shutter = (open, close);
+        self
+    }
+
+    pub fn projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    pub fn build(self) -> io::Result<Camera> {
+        let invalid = |message: String| Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+
+        let view = self.look_at - self.look_from;
+        if !view.is_finite() || view.length_squared() == 0.0 {
+            return invalid(format!(
+                "camera looks from {} at {}, which must be distinct points",
+                self.look_from, self.look_at
+            ));
+        }
+        if !self.up.is_finite() || self.up.length_squared() == 0.0 {
+            return invalid(format!("camera up {} must be a non-zero vector", self.up));
+        }
+        if !(self.vfov > 0.0 && self.vfov < 180.0) {
+            return invalid(format!(
+                "camera vfov is {} degrees, it must be between 0 and 180",
+                self.vfov
+            ));
+        }
+        if !(self.aspect > 0.0 && self.aspect.is_finite()) {
+            return invalid(format!("camera aspect {} must be positive", self.aspect));
+        }
+        if !(self.aperture >= 0.0 && self.aperture.is_finite()) {
+            return invalid(format!(
+                "camera aperture {} can't be negative",
+                self.aperture
+            ));
+        }
+        let focus_distance = self.focus_distance.unwrap_or_else(|| view.length());
+        if !(focus_distance > 0.0 && focus_distance.is_finite()) {
+            return invalid(format!(
+                "camera focus distance {} must be positive",
+                focus_distance
+            ));
+        }
+        let (open, close) = self.shutter;
+        if !(open.is_finite() && close.is_finite() && open <= close) {
+            return invalid(format!(
+                "camera shutter closes at {} before it opens at {}",
+                close, open
+            ));
+        }
+
+        Ok(Camera::new(
+            self.look_from,
+            self.look_at,
+            self.vfov,
+            self.aspect,
+            self.aperture,
+            focus_distance,
+        )
+        .with_up(self.up)
+        .with_roll(self.roll)
+        .with_projection(self.projection)
+        .with_shutter(open, close))
+    }
+}
+
+fn default_up() -> Vec3A {
+    Vec3A::Y
+}
+
+#[inline]
+fn sample_unit_disk(rng: &mut impl Rng) -> glam::Vec2 {
+    let r = rng.gen::<Float>().sqrt();
+    let theta = 2.0 * PI * rng.gen::<Float>();
+    glam::Vec2::new(r * theta.cos(), r * theta.sin())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cameras_can_look_straight_down_and_roll() {
+        let camera = Camera::new(Vec3A::Y, Vec3A::ZERO, 40.0, 1.0, 0.0, 1.0);
+        assert!(camera.u.is_finite() && camera.v.is_finite());
+        assert!(camera.u.dot(camera.v).abs() < 1e-6);
+        assert!((camera.w - Vec3A::Y).length() < 1e-6);
+
+        // Looking down the z axis with the image's up along x
+        let level = Camera::new(Vec3A::Z, Vec3A::ZERO, 40.0, 1.0, 0.0, 1.0);
+        let tilted = Camera::new(Vec3A::Z, Vec3A::ZERO, 40.0, 1.0, 0.0, 1.0).with_up(Vec3A::X);
+        assert!((tilted.v - Vec3A::X).length() < 1e-6);
+        let rolled = level.with_roll(-PI / 2.0);
+        assert!((rolled.v - tilted.v).length() < 1e-6);
+        assert!((rolled.u - tilted.u).length() < 1e-6);
+    }
+
+    #[test]
+    fn autofocus_finds_the_subject() {
+        let mut world_builder = crate::WorldBuilder::default();
+        world_builder.push_hittable(crate::Primative::sphere(
+            Vec3A::ZERO,
+            1.0,
+            Default::default(),
+        ));
+        world_builder.push_hittable(crate::Primative::sphere(
+            Vec3A::new(3.0, 0.0, -4.0),
+            1.0,
+            Default::default(),
+        ));
+        let camera = Camera::builder()
+            .look_from(Vec3A::new(0.0, 0.0, 10.0))
+            .vfov(60.0)
+            .aperture(0.5)
+            .focus_distance(1.0)
+            .build()
+            .unwrap();
+        let mut scene = crate::Scene::new(world_builder.into(), camera);
+
+        assert!((scene.autofocus(None).unwrap() - 9.0).abs() < 1e-3);
+        assert!((scene.sampler.focus_distance() - 9.0).abs() < 1e-3);
+
+        // The focus plane is parallel to the image, so off-center subjects focus at
+        // their depth rather than their distance
+        let v = 0.5;
+        let u = 0.5 + 3.0 / 14.0 / (2.0 * (30.0 as Float).to_radians().tan());
+        let depth = scene.autofocus(Some((u, v))).unwrap();
+        assert!((depth - 13.0).abs() < 0.05, "{}", depth);
+
+        // Nothing to focus on leaves the distance alone
+        assert!(scene.autofocus(Some((0.0, 0.0))).is_none());
+        assert_eq!(scene.sampler.focus_distance(), depth);
+    }
+
+    #[test]
+    fn builder_matches_new_and_validates() {
+        let built = Camera::builder()
+            .look_from(Vec3A::new(0.0, 1.0, 5.0))
+            .look_at(Vec3A::ZERO)
+            .vfov(30.0)
+            .aspect(2.0)
+            .aperture(0.1)
+            .build()
+            .unwrap();
+        let distance = Vec3A::new(0.0, 1.0, 5.0).length();
+        let new = Camera::new(
+            Vec3A::new(0.0, 1.0, 5.0),
+            Vec3A::ZERO,
+            30.0,
+            2.0,
+            0.1,
+            distance,
+        );
+        assert_eq!(built.focus_dist, new.focus_dist);
+        assert!((built.top_right - new.top_right).length() < 1e-6);
+        assert!((built.horizontal - new.horizontal).length() < 1e-6);
+
+        assert!(Camera::builder().look_from(Vec3A::ZERO).build().is_err());
+        assert!(Camera::builder().vfov(180.0).build().is_err());
+        assert!(Camera::builder().aspect(0.0).build().is_err());
+        assert!(Camera::builder().aperture(-1.0).build().is_err());
+        assert!(Camera::builder().shutter(1.0, 0.5).build().is_err());
+        assert!(Camera::builder().up(Vec3A::ZERO).build().is_err());
+    }
+
+    #[test]
+    fn rays_are_sent_while_the_shutter_is_open() {
+        let camera = Camera::builder().shutter(2.0, 3.0).build().unwrap();
+        let times: Vec<_> = (0..16)
+            .map(|s| {
+                let mut sampler = Sampler::new(0, 0, s, 0);
+                camera.get_ray(0, 0, 4, 4, &mut sampler);
+                sampler.time()
+            })
+            .collect();
+        assert!(times.iter().all(|t| (2.0..=3.0).contains(t)));
+        assert!(times.iter().any(|t| *t != times[0]));
+    }
+
+    #[test]
+    fn aspect_widens_the_view_at_the_same_height() {
+        let mut camera = Camera::new(Vec3A::Z, Vec3A::ZERO, 40.0, 1.0, 0.0, 1.0);
+        let (width, height) = (camera.horizontal.length(), camera.vertical.length());
+        camera.set_aspect(2.0);
+        assert_eq!(camera.aspect(), 2.0);
+        assert!((camera.vertical.length() - height).abs() < 1e-6);
+        assert!((camera.horizontal.length() - 2.0 * width).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::image::Image;
+
+// A fingerprint of one tile of an accumulation. Rendering is deterministic, so workers
+// given the same scene, sample counts and library version hash their tiles identically,
+// and a coordinator comparing hashes catches corrupt or mismatched results before
+// merging them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TileHash {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub hash: u64,
+}
+
+impl Image {
+    // Tiles are `tile_size` square apart from those on the right and bottom edges, in
+    // row order
+    pub fn tile_hashes(&self, tile_size: usize) -> Vec<TileHash> {
+        hash_tiles(self, tile_size, None)
+    }
+}
+
+// The tiles of `actual` whose hash differs from, or has no match in, `expected`
+pub fn mismatched_tiles(expected: &[TileHash], actual: &[TileHash]) -> Vec<TileHash> {
+    actual
+        .iter()
+        .filter(|tile| !expected.contains(tile))
+        .copied()
+        .collect()
+}
+
+// With `row_samples`, each row's sample count is hashed along with its pixels, so
+// images that happen to match but would diverge on the next pass still differ
+pub(crate) fn hash_tiles(
+    image: &Image,
+    tile_size: usize,
+    row_samples: Option<&[usize]>,
+) -> Vec<TileHash> {
+    let tile_size = tile_size.max(1);
+    let mut tiles = Vec::new();
+    for y in (0..image.height).step_by(tile_size) {
+        for x in (0..image.width).step_by(tile_size) {
+            let width = tile_size.min(image.width - x);
+            let height = tile_size.min(image.height - y);
+
+            let mut hash = Fnv::default();
+            hash.write(&[x, y, width, height].map(|v| v as u64));
+            for row in y..y + height {
+                if let Some(samples) = row_samples {
+                    hash.write(&[samples[row] as u64]);
+                }
+                let start = (row * image.width + x) * 4;
+                for value in &image.data[start..start + width * 4] {
+                    // Every NaN hashes the same, whatever produced it
+                    let bits = match value.is_nan() {
+                        true => f32::NAN.to_bits(),
+                        false => value.to_bits(),
+                    };
+                    hash.write(&[bits as u64]);
+                }
+            }
+
+            tiles.push(TileHash {
+                x,
+                y,
+                width,
+                height,
+                hash: hash.0,
+            });
+        }
+    }
+    tiles
+}
+
+// FNV-1a, which unlike the standard library's hasher is fixed across versions and
+// platforms
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf29ce484222325)
+    }
+}
+
+impl Fnv {
+    fn write(&mut self, values: &[u64]) {
+        for value in values {
+            for byte in value.to_le_bytes() {
+                self.0 = (self.0 ^ byte as u64).wrapping_mul(0x100000001b3);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Background, Camera, Material, ParallelRenderer, Primative, Rgba, Scene, Texture, Vec3A,
+        WorldBuilder,
+    };
+
+    #[test]
+    fn workers_agree_on_identical_tiles() {
+        let mut world_builder = WorldBuilder::default();
+        let grey = world_builder.push_texture(Texture::Solid {
+            color: Rgba::splat(0.5),
+        });
+        let grey = world_builder.push_material(Material::Lambertian { albedo: grey });
+        world_builder.push_hittable(Primative::sphere(Vec3A::ZERO, 1.0, grey));
+        world_builder.set_background(Background::rtiow_sky());
+        let camera = Camera::new(3.0 * Vec3A::Z, Vec3A::ZERO, 40.0, 1.0, 0.0, 1.0);
+        let scene = Scene::new(world_builder.into(), camera);
+
+        let render = |passes| {
+            let mut renderer = ParallelRenderer::new(20, 20, 4);
+            for _ in 0..passes {
+                renderer.render(&scene);
+            }
+            renderer
+        };
+        let (a, b) = (render(2), render(2));
+        let tiles = a.tile_hashes(8);
+        assert_eq!(tiles.len(), 9);
+        assert_eq!((tiles[8].width, tiles[8].height), (4, 4));
+        assert!(mismatched_tiles(&tiles, &b.tile_hashes(8)).is_empty());
+        assert_eq!(mismatched_tiles(&tiles, &render(3).tile_hashes(8)).len(), 9);
+
+        let mut corrupt = a.image().clone();
+        corrupt.set_pixel_color(10, 3, Rgba::ONE);
+        let mismatched = mismatched_tiles(&a.image().tile_hashes(8), &corrupt.tile_hashes(8));
+        assert_eq!(mismatched.len(), 1);
+        assert_eq!((mismatched[0].x, mismatched[0].y), (8, 0));
+    }
+}
@@ -0,0 +1,257 @@
+// Large procedural scenes for performance work. The same seed always builds the same
+// scene, so timings can be compared between changes.
+
+use crate::scenes::{cuboid, quad};
+use crate::{
+    Camera, Float, Material, MaterialKey, Mesh, Primative, Rgba, Scene, Texture, Transform, Vec3A,
+    WorldBuilder,
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::sync::Arc;
+
+const PI: Float = std::f64::consts::PI as Float;
+
+// A `blocks` x `blocks` grid of city blocks, each with four lots of instanced buildings
+// and a street lamp on every corner
+pub fn city(seed: u64, blocks: usize) -> Scene {
+    const BLOCK: Float = 20.0;
+    const STREET: Float = 6.0;
+    const HEIGHTS: [Float; 4] = [0.8, 1.5, 3.0, 6.0];
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut world_builder = WorldBuilder::default();
+    let extent = blocks as Float * (BLOCK + STREET) + STREET;
+
+    let asphalt = solid(&mut world_builder, Rgba::new(0.1, 0.1, 0.1, 1.0));
+    world_builder.push_hittable(Primative::Mesh(ground(extent, asphalt)));
+
+    // Buildings have a unit footprint and are placed by uniform scale, so a few
+    // heights and facades cover every lot
+    let facades: Vec<MaterialKey> = [
+        Rgba::new(0.6, 0.6, 0.58, 1.0),
+        Rgba::new(0.45, 0.42, 0.4, 1.0),
+        Rgba::new(0.7, 0.66, 0.55, 1.0),
+    ]
+    .iter()
+    .map(|color| solid(&mut world_builder, *color))
+    .collect();
+    let buildings: Vec<Arc<Primative>> = HEIGHTS
+        .iter()
+        .flat_map(|height| {
+            facades.iter().map(move |facade| {
+                Arc::new(Primative::Mesh(cuboid(
+                    Vec3A::new(-0.5, 0.0, -0.5),
+                    Vec3A::new(0.5, *height, 0.5),
+                    *facade,
+                )))
+            })
+        })
+        .collect();
+
+    let lot = 0.5 * BLOCK;
+    for bx in 0..blocks {
+        for bz in 0..blocks {
+            let corner = Vec3A::new(
+                STREET + bx as Float * (BLOCK + STREET),
+                0.0,
+                STREET + bz as Float * (BLOCK + STREET),
+            );
+            for (lx, lz) in [(0, 0), (0, 1), (1, 0), (1, 1)].iter() {
+                let center = corner
+                    + Vec3A::new((*lx as Float + 0.5) * lot, 0.0, (*lz as Float + 0.5) * lot);
+                let building = &buildings[rng.gen_range(0..buildings.len())];
+                let quarter_turns = rng.gen_range(0..4) as Float;
+                world_builder.push_hittable(Primative::instance(
+                    Arc::clone(building),
+                    Transform::new(
+                        center,
+                        glam::Quat::from_rotation_y(0.5 * PI * quarter_turns),
+                        lot * rng.gen_range(0.6..0.9),
+                    ),
+                ));
+            }
+        }
+    }
+
+    // Each lamp is its own light for many-light sampling
+    let warm = world_builder.push_texture(Texture::Solid {
+        color: Rgba::new(1.0, 0.8, 0.5, 1.0),
+    });
+    for x in 0..=blocks {
+        for z in 0..=blocks {
+            let material = world_builder.push_material(Material::DiffuseLight {
+                emit: warm,
+                intensity: 10.0,
+            });
+            let base = Vec3A::new(
+                0.5 * STREET + x as Float * (BLOCK + STREET),
+                4.0,
+                0.5 * STREET + z as Float * (BLOCK + STREET),
+            );
+            let lamp = cuboid(base - Vec3A::splat(0.3), base + Vec3A::splat(0.3), material);
+            world_builder.push_area_light(lamp, true);
+        }
+    }
+    push_sky(&mut world_builder, extent, 60.0, 3.0);
+
+    let center = Vec3A::new(0.5 * extent, 0.0, 0.5 * extent);
+    let camera = Camera::new(
+        center + Vec3A::new(-0.6 * extent, 0.35 * extent, -0.6 * extent),
+        center,
+        45.0,
+        16.0 / 9.0,
+        0.0,
+        1.0,
+    );
+    Scene::new(world_builder.into(), camera)
+}
+
+// `trees` trees scattered over a square patch. Trees near the middle are full models,
+// while the rest use crossed quad billboards.
+pub fn forest(seed: u64, trees: usize) -> Scene {
+    const SPACING: Float = 4.0;
+    const BILLBOARD_DISTANCE: Float = 0.35;
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut world_builder = WorldBuilder::default();
+    let extent = (trees as Float).sqrt().max(1.0) * SPACING;
+
+    let soil = solid(&mut world_builder, Rgba::new(0.25, 0.2, 0.12, 1.0));
+    world_builder.push_hittable(Primative::Mesh(ground(extent, soil)));
+
+    let bark = solid(&mut world_builder, Rgba::new(0.3, 0.2, 0.1, 1.0));
+    let leaves = solid(&mut world_builder, Rgba::new(0.1, 0.35, 0.1, 1.0));
+    let trunk = Arc::new(Primative::Mesh(cuboid(
+        Vec3A::new(-0.1, 0.0, -0.1),
+        Vec3A::new(0.1, 0.6, 0.1),
+        bark,
+    )));
+    let canopy = Arc::new(Primative::Mesh(cone(0.5, 1.6, 0.4, 8, leaves)));
+    let billboard = Arc::new(Primative::Mesh(crossed_quads(1.0, 2.0, leaves)));
+
+    let center = Vec3A::new(0.5 * extent, 0.0, 0.5 * extent);
+    for _ in 0..trees {
+        let position = Vec3A::new(rng.gen_range(0.0..extent), 0.0, rng.gen_range(0.0..extent));
+        let transform = Transform::new(
+            position,
+            glam::Quat::from_rotation_y(rng.gen_range(0.0..2.0 * PI)),
+            rng.gen_range(1.5..3.0),
+        );
+
+        match (position - center).length() < BILLBOARD_DISTANCE * extent {
+            true => {
+                world_builder.push_hittable(Primative::instance(Arc::clone(&trunk), transform));
+                world_builder.push_hittable(Primative::instance(Arc::clone(&canopy), transform));
+            }
+            false => {
+                world_builder.push_hittable(Primative::instance(Arc::clone(&billboard), transform))
+            }
+        }
+    }
+    push_sky(&mut world_builder, extent, 10.0, 3.0);
+
+    let camera = Camera::new(
+        center + Vec3A::new(0.0, 6.0, -0.8 * extent),
+        center + Vec3A::new(0.0, 1.0, 0.0),
+        60.0,
+        16.0 / 9.0,
+        0.0,
+        1.0,
+    );
+    Scene::new(world_builder.into(), camera)
+}
+
+fn solid(world_builder: &mut WorldBuilder, color: Rgba) -> MaterialKey {
+    let albedo = world_builder.push_texture(Texture::Solid { color });
+    world_builder.push_material(Material::Lambertian { albedo })
+}
+
+fn push_sky(world_builder: &mut WorldBuilder, extent: Float, height: Float, intensity: Float) {
+    let emit = world_builder.push_texture(Texture::Solid { color: Rgba::ONE });
+    let material = world_builder.push_material(Material::DiffuseLight { emit, intensity });
+    let margin = extent;
+    world_builder.push_area_light(
+        quad(
+            [
+                Vec3A::new(-margin, height, -margin),
+                Vec3A::new(extent + margin, height, -margin),
+                Vec3A::new(extent + margin, height, extent + margin),
+                Vec3A::new(-margin, height, extent + margin),
+            ],
+            material,
+        ),
+        true,
+    );
+}
+
+fn ground(extent: Float, material: MaterialKey) -> Arc<Mesh> {
+    quad(
+        [
+            Vec3A::new(0.0, 0.0, 0.0),
+            Vec3A::new(0.0, 0.0, extent),
+            Vec3A::new(extent, 0.0, extent),
+            Vec3A::new(extent, 0.0, 0.0),
+        ],
+        material,
+    )
+}
+
+// Open cone standing on y = `base` with its tip `height` above
+fn cone(
+    radius: Float,
+    height: Float,
+    base: Float,
+    segments: usize,
+    material: MaterialKey,
+) -> Arc<Mesh> {
+    let mut vertices = vec![Vec3A::new(0.0, base + height, 0.0)];
+    vertices.extend((0..segments).map(|i| {
+        let angle = 2.0 * PI * i as Float / segments as Float;
+        Vec3A::new(radius * angle.cos(), base, radius * angle.sin())
+    }));
+    let indices = (0..segments)
+        .map(|i| (0, 1 + i, 1 + (i + 1) % segments))
+        .collect();
+    Mesh::build(vertices, None, indices, None, material)
+}
+
+// Two vertical quads crossing at right angles, a cheap stand in for distant foliage
+fn crossed_quads(width: Float, height: Float, material: MaterialKey) -> Arc<Mesh> {
+    let w = 0.5 * width;
+    let vertices = vec![
+        Vec3A::new(-w, 0.0, 0.0),
+        Vec3A::new(w, 0.0, 0.0),
+        Vec3A::new(w, height, 0.0),
+        Vec3A::new(-w, height, 0.0),
+        Vec3A::new(0.0, 0.0, -w),
+        Vec3A::new(0.0, 0.0, w),
+        Vec3A::new(0.0, height, w),
+        Vec3A::new(0.0, height, -w),
+    ];
+    Mesh::build(
+        vertices,
+        None,
+        vec![(0, 1, 2), (0, 2, 3), (4, 5, 6), (4, 6, 7)],
+        None,
+        material,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ParallelRenderer;
+
+    #[test]
+    fn the_same_seed_renders_the_same_image() {
+        let render = |scene: Scene| {
+            let mut renderer = ParallelRenderer::new(24, 16, 3);
+            renderer.render(&scene);
+            renderer.image().data.clone()
+        };
+        assert_eq!(render(city(7, 2)), render(city(7, 2)));
+        assert_eq!(render(forest(7, 40)), render(forest(7, 40)));
+        assert_ne!(render(forest(7, 40)), render(forest(8, 40)));
+    }
+}
@@ -0,0 +1,191 @@
+use crate::image::Image;
+use crate::Float;
+
+use rayon::prelude::*;
+
+// First-hit feature buffers used to guide denoising. Albedo is the surface
+// reflectance and normals are world space in [-1, 1], both averaged per pixel.
+#[derive(Debug, Clone)]
+pub struct Aovs {
+    pub albedo: Image,
+    pub normal: Image,
+}
+
+impl Aovs {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            albedo: Image::new(width, height),
+            normal: Image::new(width, height),
+        }
+    }
+}
+
+pub trait Denoiser {
+    fn denoise(&self, color: &Image, aovs: &Aovs) -> Image;
+}
+
+// Edge-avoiding À-trous wavelet filter (Dammertz et al. 2010)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ATrousDenoiser {
+    pub iterations: usize,
+    pub sigma_color: Float,
+    pub sigma_normal: Float,
+    pub sigma_albedo: Float,
+}
+
+impl Default for ATrousDenoiser {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            sigma_color: 0.5,
+            sigma_normal: 0.3,
+            sigma_albedo: 0.1,
+        }
+    }
+}
+
+impl Denoiser for ATrousDenoiser {
+    fn denoise(&self, color: &Image, aovs: &Aovs) -> Image {
+        const KERNEL: [Float; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+        let (width, height) = (color.width, color.height);
+        let mut current = color.clone();
+
+        for iteration in 0..self.iterations {
+            let step = 1 << iteration;
+            // Later passes cover larger, already smoothed, neighbourhoods
+            let sigma_color = self.sigma_color / (1 << iteration) as Float;
+            let inv_color = 1.0 / (sigma_color * sigma_color).max(Float::EPSILON);
+            let inv_normal = 1.0 / (self.sigma_normal * self.sigma_normal).max(Float::EPSILON);
+            let inv_albedo = 1.0 / (self.sigma_albedo * self.sigma_albedo).max(Float::EPSILON);
+
+            let source = &current;
+            let data: Vec<Float> = (0..height)
+                .into_par_iter()
+                .flat_map(|y| {
+                    let mut row = Vec::with_capacity(width * 4);
+                    for x in 0..width {
+                        let p = (y * width + x) * 4;
+                        let mut sum = [0.0; 4];
+                        let mut total_weight = 0.0;
+
+                        for (j, kj) in KERNEL.iter().enumerate() {
+                            let qy = y as isize + (j as isize - 2) * step;
+                            if qy < 0 || qy >= height as isize {
+                                continue;
+                            }
+                            for (i, ki) in KERNEL.iter().enumerate() {
+                                let qx = x as isize + (i as isize - 2) * step;
+                                if qx < 0 || qx >= width as isize {
+                                    continue;
+                                }
+                                let q = (qy as usize * width + qx as usize) * 4;
+
+                                let weight = kj
+                                    * ki
+                                    * (-distance2(&source.data, p, q) * inv_color
+                                        - distance2(&aovs.normal.data, p, q) * inv_normal
+                                        - distance2(&aovs.albedo.data, p, q) * inv_albedo)
+                                        .exp();
+
+                                for (c, s) in sum.iter_mut().enumerate() {
+                                    *s += weight * source.data[q + c];
+                                }
+                                total_weight += weight;
+                            }
+                        }
+
+                        row.extend(sum.iter().map(|v| v / total_weight));
+                    }
+                    row
+                })
+                .collect();
+
+            current = Image::from_vec(width, height, data);
+        }
+
+        current
+    }
+}
+
+#[inline]
+fn distance2(data: &[Float], p: usize, q: usize) -> Float {
+    (0..3).map(|c| (data[p + c] - data[q + c]).powi(2)).sum()
+}
+
+#[cfg(feature = "oidn")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OidnDenoiser;
+
+#[cfg(feature = "oidn")]
+impl Denoiser for OidnDenoiser {
+    fn denoise(&self, color: &Image, aovs: &Aovs) -> Image {
+        let rgb = |image: &Image| -> Vec<f32> {
+            image
+                .data
+                .chunks(4)
+                .flat_map(|c| c[..3].iter().copied())
+                .collect()
+        };
+        let input = rgb(color);
+        let albedo = rgb(&aovs.albedo);
+        let normal = rgb(&aovs.normal);
+        let mut output = vec![0.0; input.len()];
+
+        let device = oidn::Device::new();
+        let filtered = oidn::RayTracing::new(&device)
+            .srgb(true)
+            .image_dimensions(color.width, color.height)
+            .albedo_normal(&albedo, &normal)
+            .filter(&input, &mut output);
+        if filtered.is_err() || device.get_error().is_err() {
+            return color.clone();
+        }
+
+        let data = output
+            .chunks(3)
+            .zip(color.data.chunks(4))
+            .flat_map(|(rgb, original)| [rgb[0], rgb[1], rgb[2], original[3]])
+            .collect();
+        Image::from_vec(color.width, color.height, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::Rgba;
+
+    #[test]
+    fn smooths_noise_but_keeps_geometric_edges() {
+        let (width, height) = (16, 16);
+        let mut color = Image::new(width, height);
+        let mut aovs = Aovs::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let left = x < width / 2;
+                let noise = if (x * 7 + y * 13) % 3 == 0 { 0.2 } else { -0.1 };
+                let base = if left { 0.2 } else { 0.8 };
+                color.set_pixel_color(x, y, Rgba::new(base + noise, base, base, 1.0));
+                let normal = if left { 1.0 } else { -1.0 };
+                aovs.normal
+                    .set_pixel_color(x, y, Rgba::new(normal, 0.0, 0.0, 0.0));
+                aovs.albedo.set_pixel_color(x, y, Rgba::ONE);
+            }
+        }
+
+        let denoised = ATrousDenoiser::default().denoise(&color, &aovs);
+        let spread = |image: &Image| {
+            let reds: Vec<Float> = (0..height)
+                .map(|y| image.get_pixel_color(3, y).to_array()[0])
+                .collect();
+            reds.iter().cloned().fold(Float::MIN, Float::max)
+                - reds.iter().cloned().fold(Float::MAX, Float::min)
+        };
+
+        assert!(spread(&denoised) < 0.5 * spread(&color));
+        let left = denoised.get_pixel_color(width / 2 - 1, 8).to_array()[1];
+        let right = denoised.get_pixel_color(width / 2, 8).to_array()[1];
+        assert!((left - 0.2).abs() < 0.01 && (right - 0.8).abs() < 0.01);
+    }
+}
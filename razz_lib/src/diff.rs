@@ -0,0 +1,158 @@
+use crate::image::{Image, Rgba};
+use crate::Float;
+
+use std::io;
+
+#[derive(Debug, Clone)]
+pub struct ImageDiff {
+    pub rmse: [Float; 4],
+    pub max_error: Float,
+    pub max_error_location: (usize, usize),
+    pub heat_map: Image,
+}
+
+impl Image {
+    // Images of different sizes can't be compared pixel by pixel and are an error
+    pub fn diff(&self, other: &Image) -> io::Result<ImageDiff> {
+        if (self.width, self.height) != (other.width, other.height) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "image sizes differ: {}x{} vs {}x{}",
+                    self.width, self.height, other.width, other.height
+                ),
+            ));
+        }
+
+        let mut squared_error = [0.0; 4];
+        let mut errors = Vec::with_capacity(self.width * self.height);
+        let mut max_error = 0.0;
+        let mut max_error_location = (0, 0);
+
+        for j in 0..self.height {
+            for i in 0..self.width {
+                let a = self.get_pixel_color(i, j).to_array();
+                let b = other.get_pixel_color(i, j).to_array();
+
+                let mut pixel_error: Float = 0.0;
+                for c in 0..4 {
+                    let delta = a[c] - b[c];
+                    squared_error[c] += delta * delta;
+                    if c < 3 {
+                        pixel_error = pixel_error.max(delta.abs());
+                    }
+                }
+
+                if pixel_error > max_error {
+                    max_error = pixel_error;
+                    max_error_location = (i, j);
+                }
+                errors.push(pixel_error);
+            }
+        }
+
+        let pixel_count = (self.width * self.height).max(1) as Float;
+        let mut rmse = [0.0; 4];
+        for c in 0..4 {
+            rmse[c] = (squared_error[c] / pixel_count).sqrt();
+        }
+
+        let mut heat_map = Image::new(self.width, self.height);
+        for (index, error) in errors.into_iter().enumerate() {
+            let t = match max_error > 0.0 {
+                true => error / max_error,
+                false => 0.0,
+            };
+            heat_map.set_pixel_color(index % self.width, index / self.width, heat(t));
+        }
+
+        Ok(ImageDiff {
+            rmse,
+            max_error,
+            max_error_location,
+            heat_map,
+        })
+    }
+}
+
+// Black -> red -> yellow -> white
+fn heat(t: Float) -> Rgba {
+    Rgba::new(
+        (3.0 * t).clamp(0.0, 1.0),
+        (3.0 * t - 1.0).clamp(0.0, 1.0),
+        (3.0 * t - 2.0).clamp(0.0, 1.0),
+        1.0,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{scenes, Material, ParallelRenderer, Texture, TextureKey};
+
+    #[test]
+    fn diff_finds_max_error() {
+        let a = Image::new(4, 3);
+        let mut b = Image::new(4, 3);
+        b.set_pixel_color(2, 1, Rgba::new(0.5, 0.0, 0.0, 0.0));
+
+        let diff = a.diff(&b).unwrap();
+        assert_eq!(diff.max_error, 0.5);
+        assert_eq!(diff.max_error_location, (2, 1));
+        assert!(diff.rmse[0] > 0.0);
+        assert_eq!(diff.rmse[1], 0.0);
+        assert_eq!(diff.heat_map.get_pixel_color(2, 1), Rgba::ONE);
+    }
+
+    #[test]
+    fn identical_images_have_no_error() {
+        let a = Image::new(2, 2);
+        let diff = a.diff(&a.clone()).unwrap();
+        assert_eq!(diff.rmse, [0.0; 4]);
+        assert_eq!(diff.max_error, 0.0);
+    }
+
+    #[test]
+    fn mismatched_sizes_are_an_error() {
+        let error = Image::new(4, 3).diff(&Image::new(3, 4)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    // Materials that neither absorb nor emit vanish in the white furnace, so their
+    // golden image is white everywhere
+    fn furnace_diff(material: impl FnOnce(TextureKey) -> Material) -> ImageDiff {
+        let scene = scenes::furnace(|world_builder| {
+            let white = world_builder.push_texture(Texture::Solid { color: Rgba::ONE });
+            world_builder.push_material(material(white))
+        });
+        let mut renderer = ParallelRenderer::new(16, 16, 8);
+        for _ in 0..4 {
+            renderer.render(&scene);
+        }
+        let golden = Image::from_vec(16, 16, vec![1.0; 16 * 16 * 4]);
+        renderer.image().diff(&golden).unwrap()
+    }
+
+    #[test]
+    fn white_diffuse_matches_the_furnace_golden_image() {
+        let diff = furnace_diff(|albedo| Material::Lambertian { albedo });
+        assert!(diff.max_error < 1e-4, "{:?}", diff.max_error_location);
+    }
+
+    #[test]
+    fn mirror_matches_the_furnace_golden_image() {
+        let diff = furnace_diff(|albedo| Material::Metal { albedo, fuzz: 0.0 });
+        assert!(diff.max_error < 1e-4, "{:?}", diff.max_error_location);
+    }
+
+    #[test]
+    fn glass_matches_the_furnace_golden_image() {
+        // Paths caught inside by total internal reflection can run out of bounces
+        let diff = furnace_diff(|_| Material::dielectric(1.5));
+        assert!(
+            diff.rmse[..3].iter().all(|rmse| *rmse < 0.05),
+            "{:?}",
+            diff.rmse
+        );
+    }
+}
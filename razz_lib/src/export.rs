@@ -0,0 +1,164 @@
+use crate::{Material, MaterialKey, Point3, World, D_LINE_NM};
+
+use slotmap::SecondaryMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// Writes the world's geometry as an OBJ file, with its materials approximated in an
+// MTL file of the same name. Returns how many primitives had no triangles to write.
+pub fn export_obj(world: &World, path: impl AsRef<Path>) -> io::Result<usize> {
+    let path = path.as_ref();
+    let mtl_path = path.with_extension("mtl");
+
+    let mut names = SecondaryMap::new();
+    for (index, key) in world.materials().keys().enumerate() {
+        names.insert(key, format!("material_{}", index));
+    }
+    write_mtl(world, &names, &mut BufWriter::new(File::create(&mtl_path)?))?;
+
+    let mut obj = BufWriter::new(File::create(path)?);
+    if let Some(mtl_name) = mtl_path.file_name() {
+        writeln!(obj, "mtllib {}", mtl_name.to_string_lossy())?;
+    }
+    let mut skipped = 0;
+    let mut vertex_count = 0;
+    for (index, primative) in world.primitives().enumerate() {
+        let surfaces = match primative.surfaces() {
+            Some(surfaces) => surfaces,
+            None => {
+                skipped += 1;
+                continue;
+            }
+        };
+        writeln!(obj, "o primitive_{}", index)?;
+        for surface in surfaces {
+            if let Some(name) = names.get(surface.material_key) {
+                writeln!(obj, "usemtl {}", name)?;
+            }
+            for p in surface.vertices.iter() {
+                writeln!(obj, "v {} {} {}", p.x, p.y, p.z)?;
+            }
+            // OBJ indices count from 1 across the whole file
+            for (a, b, c) in surface.indices.iter() {
+                let base = vertex_count + 1;
+                writeln!(obj, "f {} {} {}", a + base, b + base, c + base)?;
+            }
+            vertex_count += surface.vertices.len();
+        }
+    }
+    obj.flush()?;
+    Ok(skipped)
+}
+
+// Textures are sampled once in the middle, so only their average color survives
+fn write_mtl(
+    world: &World,
+    names: &SecondaryMap<MaterialKey, String>,
+    mtl: &mut impl Write,
+) -> io::Result<()> {
+    let textures = world.textures();
+    for (key, material) in world.materials().iter() {
+        let [r, g, b, _] = material.albedo(0.5, 0.5, Point3::ZERO, textures).to_array();
+        writeln!(mtl, "newmtl {}", names[key])?;
+        match material {
+            Material::DiffuseLight { .. } => {
+                let [r, g, b, _] = material.emit(0.5, 0.5, Point3::ZERO, textures).to_array();
+                writeln!(mtl, "Kd 0 0 0\nKe {} {} {}", r, g, b)?;
+            }
+            Material::Metal { fuzz, .. } => writeln!(
+                mtl,
+                "Kd 0 0 0\nKs {} {} {}\nNs {}\nillum 3",
+                r,
+                g,
+                b,
+                1000.0 * (1.0 - fuzz.clamp(0.0, 1.0))
+            )?,
+            Material::RoughMetal { roughness, .. }
+            | Material::Conductor {
+                fuzz: roughness, ..
+            } => writeln!(
+                mtl,
+                "Kd 0 0 0\nKs {} {} {}\nNs {}\nillum 3",
+                r,
+                g,
+                b,
+                1000.0 * (1.0 - roughness.clamp(0.0, 1.0))
+            )?,
+            Material::Water { ir, .. } => {
+                writeln!(mtl, "Kd 0 0 0\nKs 1 1 1\nTf 1 1 1\nNi {}\nillum 7", ir)?
+            }
+            Material::Dielectric { ir, .. } => writeln!(
+                mtl,
+                "Kd 0 0 0\nKs 1 1 1\nTf 1 1 1\nNi {}\nillum 7",
+                ir.ior(D_LINE_NM)
+            )?,
+            Material::Principled {
+                metallic,
+                roughness,
+                ior,
+                transmission,
+                ..
+            } => writeln!(
+                mtl,
+                "Kd {} {} {}\nNi {}\nd {}\nPm {}\nPr {}",
+                r,
+                g,
+                b,
+                ior,
+                1.0 - transmission,
+                metallic,
+                roughness
+            )?,
+            _ => writeln!(mtl, "Kd {} {} {}", r, g, b)?,
+        }
+        writeln!(mtl)?;
+    }
+    mtl.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Primative, Rgba, Texture, Vec3A, WorldBuilder};
+
+    #[test]
+    fn shapes_are_written_as_indexed_triangles() {
+        let mut world_builder = WorldBuilder::default();
+        let red = world_builder.push_texture(Texture::Solid {
+            color: Rgba::new(1.0, 0.0, 0.0, 1.0),
+        });
+        let red = world_builder.push_material(Material::Lambertian { albedo: red });
+        world_builder.push_hittable(Primative::cuboid(Vec3A::ZERO, Vec3A::ONE, red));
+        world_builder.push_hittable(Primative::plane(Vec3A::ZERO, Vec3A::Y, red));
+        let world: World = world_builder.into();
+
+        let path = std::env::temp_dir().join(format!("razz_export_{}.obj", std::process::id()));
+        assert_eq!(export_obj(&world, &path).unwrap(), 1);
+        let obj = std::fs::read_to_string(&path).unwrap();
+        let mtl = std::fs::read_to_string(path.with_extension("mtl")).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(path.with_extension("mtl")).unwrap();
+
+        assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), 24);
+        assert_eq!(obj.lines().filter(|l| l.starts_with("f ")).count(), 12);
+        assert!(obj.contains("usemtl material_0"));
+        assert!(mtl.contains("newmtl material_0\nKd 1 0 0"));
+
+        // Every face of the box points away from its center
+        let vertices: Vec<Vec3A> = obj
+            .lines()
+            .filter_map(|l| l.strip_prefix("v "))
+            .map(|l| {
+                let v: Vec<f32> = l.split(' ').map(|x| x.parse().unwrap()).collect();
+                Vec3A::new(v[0], v[1], v[2])
+            })
+            .collect();
+        for face in obj.lines().filter_map(|l| l.strip_prefix("f ")) {
+            let i: Vec<usize> = face.split(' ').map(|x| x.parse().unwrap()).collect();
+            let [a, b, c] = [vertices[i[0] - 1], vertices[i[1] - 1], vertices[i[2] - 1]];
+            let normal = (b - a).cross(c - a);
+            assert!(normal.dot((a + b + c) / 3.0 - Vec3A::splat(0.5)) > 0.0);
+        }
+    }
+}
@@ -0,0 +1,751 @@
+use crate::atlas::TextureAtlas;
+use crate::image::Rgba;
+use crate::material::Material;
+use crate::texture::Texture;
+use crate::{
+    Background, Float, MaterialKey, Point3, Primative, TextureKey, Vec3A, World, D_LINE_NM,
+};
+
+use slotmap::SecondaryMap;
+use std::collections::BTreeMap;
+
+pub use crate::atlas::AtlasRegion;
+
+// A world as plain arrays of fixed size records, for backends that can't walk the
+// CPU structures: the wgpu compute shaders, and any other that needs its own copy.
+// Every record is `#[repr(C)]` and indices between them are plain `u32`s.
+
+// Declares a `#[repr(C)]` record along with `wgsl`, the same struct for the shaders
+// under the name after `as`, so the two can't drift apart. Fields are `u32`, `i32`,
+// `f32`, `[f32; 3]` or `[f32; 4]`, laid out so both sides agree without padding.
+macro_rules! gpu_struct {
+    (
+        $(#[$attr:meta])*
+        pub struct $name:ident as $wgsl_name:ident {
+            $(pub $field:ident: $ty:ty,)*
+        }
+    ) => {
+        $(#[$attr])*
+        #[repr(C)]
+        pub struct $name {
+            $(pub $field: $ty,)*
+        }
+
+        impl $name {
+            // The struct as the shaders see it
+            pub fn wgsl() -> String {
+                let mut source = format!("struct {} {{\n", stringify!($wgsl_name));
+                $(
+                    source += &format!(
+                        "    {}: {};\n",
+                        stringify!($field),
+                        $crate::flatten::wgsl_type(stringify!($ty))
+                    );
+                )*
+                source += "};\n";
+                source
+            }
+        }
+    };
+}
+pub(crate) use gpu_struct;
+
+pub(crate) fn wgsl_type(rust_type: &str) -> &'static str {
+    match rust_type.replace(' ', "").as_str() {
+        "u32" => "u32",
+        "i32" => "i32",
+        "f32" => "f32",
+        "[f32;3]" => "vec3<f32>",
+        "[f32;4]" => "vec4<f32>",
+        other => panic!("no WGSL type for {}", other),
+    }
+}
+
+// Value of `PrimitiveRecord::kind`
+pub const PRIMITIVE_SPHERE: u32 = 0;
+pub const PRIMITIVE_TRIANGLE: u32 = 1;
+// `BvhNode::count` of an interior node
+pub const BVH_INTERIOR: u32 = u32::MAX;
+// Deepest the BVH gets. Median splits keep the tree balanced, so this covers far more
+// primitives than fit in a storage buffer.
+pub const BVH_MAX_DEPTH: usize = 32;
+
+// Most primitives left in one BVH leaf
+const LEAF_SIZE: usize = 4;
+
+// Values of `MaterialRecord::kind`
+pub const MATERIAL_LAMBERTIAN: u32 = 0;
+pub const MATERIAL_METAL: u32 = 1;
+pub const MATERIAL_DIELECTRIC: u32 = 2;
+pub const MATERIAL_ROUGH_METAL: u32 = 3;
+pub const MATERIAL_ROUGH_DIELECTRIC: u32 = 4;
+pub const MATERIAL_CONDUCTOR: u32 = 5;
+pub const MATERIAL_DIFFUSE_LIGHT: u32 = 6;
+pub const MATERIAL_ISOTROPIC: u32 = 7;
+pub const MATERIAL_PRINCIPLED: u32 = 8;
+
+// Every kind by name, for generating the shader constants
+pub const MATERIAL_KINDS: [(&str, u32); 9] = [
+    ("LAMBERTIAN", MATERIAL_LAMBERTIAN),
+    ("METAL", MATERIAL_METAL),
+    ("DIELECTRIC", MATERIAL_DIELECTRIC),
+    ("ROUGH_METAL", MATERIAL_ROUGH_METAL),
+    ("ROUGH_DIELECTRIC", MATERIAL_ROUGH_DIELECTRIC),
+    ("CONDUCTOR", MATERIAL_CONDUCTOR),
+    ("DIFFUSE_LIGHT", MATERIAL_DIFFUSE_LIGHT),
+    ("ISOTROPIC", MATERIAL_ISOTROPIC),
+    ("PRINCIPLED", MATERIAL_PRINCIPLED),
+];
+
+// One material. Textures are atlas regions, or -1 when the color alone is used. The meaning of `params` and `extra` depends on `kind`:
+//   Metal, Conductor: params.x is fuzz, Conductor keeps eta in color and k in extra
+//   Dielectric: params.x is the index of refraction
+//   RoughMetal: params.y is roughness
+//   Dielectric with roughness: params.y is roughness and params.w the index of refraction
+//   Principled: params is metallic, roughness, specular, ior and extra.x transmission
+// Emission is rgb with the strength in w, and zero for anything that doesn't glow.
+gpu_struct! {
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct MaterialRecord as GpuMaterial {
+        pub kind: u32,
+        pub texture: i32,
+        pub emission_texture: i32,
+        pub padding: u32,
+        pub color: [f32; 4],
+        pub emission: [f32; 4],
+        pub params: [f32; 4],
+        pub extra: [f32; 4],
+    }
+}
+
+impl Material {
+    pub fn flat_kind(&self) -> u32 {
+        match self {
+            Self::Lambertian { .. } => MATERIAL_LAMBERTIAN,
+            Self::Metal { .. } => MATERIAL_METAL,
+            Self::Dielectric { roughness, .. } if *roughness > 0.0 => MATERIAL_ROUGH_DIELECTRIC,
+            // Drawn clear, without tint or dispersion
+            Self::Dielectric { .. } | Self::Water { .. } => MATERIAL_DIELECTRIC,
+            Self::RoughMetal { .. } => MATERIAL_ROUGH_METAL,
+            Self::Conductor { .. } => MATERIAL_CONDUCTOR,
+            Self::DiffuseLight { .. } => MATERIAL_DIFFUSE_LIGHT,
+            Self::Isotropic { .. } => MATERIAL_ISOTROPIC,
+            Self::Principled { .. } => MATERIAL_PRINCIPLED,
+            // Drawn as a diffuse stand-in, see `flatten_materials`
+            Self::Custom(_) => MATERIAL_LAMBERTIAN,
+        }
+    }
+}
+
+// The scene's materials in `World::materials` order, with a lookup from `MaterialKey`
+#[derive(Debug, Clone)]
+pub struct MaterialRecords {
+    pub materials: Vec<MaterialRecord>,
+    indices: SecondaryMap<MaterialKey, u32>,
+}
+
+impl MaterialRecords {
+    pub fn index(&self, key: MaterialKey) -> Option<u32> {
+        self.indices.get(key).copied()
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes =
+            Vec::with_capacity(self.materials.len() * std::mem::size_of::<MaterialRecord>());
+        for m in self.materials.iter() {
+            bytes.extend(m.kind.to_ne_bytes());
+            bytes.extend(m.texture.to_ne_bytes());
+            bytes.extend(m.emission_texture.to_ne_bytes());
+            bytes.extend(m.padding.to_ne_bytes());
+            for v in [m.color, m.emission, m.params, m.extra].iter().flatten() {
+                bytes.extend(v.to_ne_bytes());
+            }
+        }
+        bytes
+    }
+}
+
+impl World {
+    // Texture indices refer to `atlas`, which should come from `texture_atlas`.
+    // Procedural textures have no flat version and are baked to their value at the origin.
+    pub fn flatten_materials(&self, atlas: &TextureAtlas) -> MaterialRecords {
+        let texture = |key: TextureKey| -> (i32, [f32; 4]) {
+            match self.textures.get(key) {
+                Some(Texture::Image { .. }) => match atlas.index(key) {
+                    Some(index) => (index as i32, Rgba::ONE.to_array()),
+                    None => (-1, Rgba::new(1.0, 0.0, 1.0, 1.0).to_array()),
+                },
+                Some(texture) => (
+                    -1,
+                    texture
+                        .value(0.0, 0.0, Point3::ZERO, &self.textures)
+                        .to_array(),
+                ),
+                None => (-1, Rgba::new(1.0, 0.0, 1.0, 1.0).to_array()),
+            }
+        };
+
+        let mut materials = Vec::with_capacity(self.materials.len());
+        let mut indices = SecondaryMap::new();
+        for (key, material) in self.materials.iter() {
+            let mut record = MaterialRecord {
+                kind: material.flat_kind(),
+                texture: -1,
+                emission_texture: -1,
+                padding: 0,
+                color: [1.0; 4],
+                emission: [0.0; 4],
+                params: [0.0; 4],
+                extra: [0.0; 4],
+            };
+
+            match material {
+                Material::Lambertian { albedo } | Material::Isotropic { albedo } => {
+                    let (index, color) = texture(*albedo);
+                    record.texture = index;
+                    record.color = color;
+                }
+                Material::Metal { albedo, fuzz } => {
+                    let (index, color) = texture(*albedo);
+                    record.texture = index;
+                    record.color = color;
+                    record.params[0] = *fuzz;
+                }
+                Material::Dielectric { ir, roughness } if *roughness > 0.0 => {
+                    record.params[1] = *roughness;
+                    record.params[3] = ir.ior(D_LINE_NM);
+                }
+                Material::Dielectric { ir, .. } => record.params[0] = ir.ior(D_LINE_NM),
+                Material::Water { ir, .. } => record.params[0] = *ir,
+                Material::RoughMetal { albedo, roughness } => {
+                    let (index, color) = texture(*albedo);
+                    record.texture = index;
+                    record.color = color;
+                    record.params[1] = *roughness;
+                }
+                Material::Conductor { eta, k, fuzz } => {
+                    record.color = [eta[0], eta[1], eta[2], 1.0];
+                    record.extra = [k[0], k[1], k[2], 0.0];
+                    record.params[0] = *fuzz;
+                }
+                Material::DiffuseLight { emit, intensity } => {
+                    let (index, [r, g, b, _]) = texture(*emit);
+                    record.emission_texture = index;
+                    record.emission = [r, g, b, *intensity];
+                }
+                Material::Principled {
+                    base_color,
+                    metallic,
+                    roughness,
+                    specular,
+                    ior,
+                    transmission,
+                    emission,
+                    emission_strength,
+                    ..
+                } => {
+                    let (index, color) = texture(*base_color);
+                    record.texture = index;
+                    record.color = color;
+                    record.params = [*metallic, *roughness, *specular, *ior];
+                    record.extra[0] = *transmission;
+                    if let Some(emission) = emission {
+                        let (index, [r, g, b, _]) = texture(*emission);
+                        record.emission_texture = index;
+                        record.emission = [r, g, b, *emission_strength];
+                    }
+                }
+                // Records can't hold a custom BSDF, so it becomes a diffuse surface
+                // with its albedo and emission at the origin
+                Material::Custom(bsdf) => {
+                    record.color = bsdf
+                        .albedo(0.0, 0.0, Point3::ZERO, &self.textures)
+                        .to_array();
+                    let [r, g, b, _] = bsdf.emit(0.0, 0.0, Point3::ZERO, &self.textures).to_array();
+                    record.emission = [r, g, b, 1.0];
+                }
+            }
+
+            indices.insert(key, materials.len() as u32);
+            materials.push(record);
+        }
+
+        MaterialRecords { materials, indices }
+    }
+}
+
+// One node of the scene BVH in depth first order. Leaves hold primitives
+// `first..first + count`. Interior nodes have a count of `BVH_INTERIOR`, their
+// left child right after them and their right child at `first`.
+gpu_struct! {
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct BvhNode as GpuBvhNode {
+        pub min: [f32; 3],
+        pub first: u32,
+        pub max: [f32; 3],
+        pub count: u32,
+    }
+}
+
+// A sphere with its center in `a` and radius in a.w, or a triangle with its corners in
+// `a`, `b` and `c`. Triangles report barycentric coordinates as uv, as meshes do.
+gpu_struct! {
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct PrimitiveRecord as GpuPrimitive {
+        pub kind: u32,
+        // Index into `MaterialRecords::materials`
+        pub material: u32,
+        // Index in `World::primitives` of the primitive this came from
+        pub source: u32,
+        pub padding: u32,
+        pub a: [f32; 4],
+        pub b: [f32; 4],
+        pub c: [f32; 4],
+    }
+}
+
+// Everything a backend needs to trace a world
+#[derive(Debug, Clone)]
+pub struct FlatScene {
+    pub nodes: Vec<BvhNode>,
+    pub primitives: Vec<PrimitiveRecord>,
+    pub materials: MaterialRecords,
+    pub atlas: TextureAtlas,
+    // Top and bottom of the sky, the same for a solid background
+    pub background: [[f32; 4]; 2],
+    pub epsilon: f32,
+    // Primitives with no flat version, which are left out, counted by what they are:
+    // planes, curves, volumes, CSG and custom shapes, or anything with a missing material
+    pub skipped: BTreeMap<&'static str, usize>,
+}
+
+impl FlatScene {
+    pub fn node_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.nodes.len() * std::mem::size_of::<BvhNode>());
+        for node in self.nodes.iter() {
+            node.min.iter().for_each(|v| bytes.extend(v.to_ne_bytes()));
+            bytes.extend(node.first.to_ne_bytes());
+            node.max.iter().for_each(|v| bytes.extend(v.to_ne_bytes()));
+            bytes.extend(node.count.to_ne_bytes());
+        }
+        bytes
+    }
+
+    pub fn primitive_bytes(&self) -> Vec<u8> {
+        let mut bytes =
+            Vec::with_capacity(self.primitives.len() * std::mem::size_of::<PrimitiveRecord>());
+        for p in self.primitives.iter() {
+            for v in [p.kind, p.material, p.source, p.padding] {
+                bytes.extend(v.to_ne_bytes());
+            }
+            for v in [p.a, p.b, p.c].iter().flatten() {
+                bytes.extend(v.to_ne_bytes());
+            }
+        }
+        bytes
+    }
+}
+
+impl World {
+    // Spheres stay spheres and every other shape with a surface becomes triangles,
+    // flat shaded. Detail normals and the irradiance cache have no flat version.
+    pub fn flatten(&self, max_atlas_width: usize) -> FlatScene {
+        let atlas = self.texture_atlas(max_atlas_width);
+        let materials = self.flatten_materials(&atlas);
+
+        let mut primitives = Vec::new();
+        let mut skipped = BTreeMap::new();
+        let mut skip = |what: &'static str| *skipped.entry(what).or_insert(0) += 1;
+        for (source, primative) in self.primitives().enumerate() {
+            let source = source as u32;
+            if let Primative::Sphere(sphere) = primative {
+                match materials.index(sphere.material_key()) {
+                    Some(material) => primitives.push(PrimitiveRecord {
+                        kind: PRIMITIVE_SPHERE,
+                        material,
+                        source,
+                        padding: 0,
+                        a: sphere.center.extend(sphere.radius).to_array(),
+                        b: [0.0; 4],
+                        c: [0.0; 4],
+                    }),
+                    None => skip("missing material"),
+                }
+                continue;
+            }
+
+            let surfaces = match primative.surfaces() {
+                Some(surfaces) => surfaces,
+                None => {
+                    skip(unflattened_kind(primative));
+                    continue;
+                }
+            };
+            for surface in surfaces {
+                let material = match materials.index(surface.material_key) {
+                    Some(material) => material,
+                    None => {
+                        skip("missing material");
+                        continue;
+                    }
+                };
+                let corner = |i: usize| surface.vertices[i].extend(0.0).to_array();
+                primitives.extend(surface.indices.iter().map(|&(a, b, c)| PrimitiveRecord {
+                    kind: PRIMITIVE_TRIANGLE,
+                    material,
+                    source,
+                    padding: 0,
+                    a: corner(a),
+                    b: corner(b),
+                    c: corner(c),
+                }));
+            }
+        }
+
+        let mut nodes = Vec::new();
+        if primitives.is_empty() {
+            // An empty leaf, so the buffer isn't empty and every ray misses
+            nodes.push(BvhNode {
+                min: [0.0; 3],
+                first: 0,
+                max: [0.0; 3],
+                count: 0,
+            });
+        } else {
+            let len = primitives.len();
+            build_node(&mut nodes, &mut primitives, 0, len);
+        }
+
+        let background = match self.background() {
+            Background::Solid { color } => [color.to_array(); 2],
+            Background::VerticalGradient { top, bottom } => [top.to_array(), bottom.to_array()],
+        };
+
+        FlatScene {
+            nodes,
+            primitives,
+            materials,
+            atlas,
+            background,
+            epsilon: self.epsilon(),
+            skipped,
+        }
+    }
+}
+
+// What to call a primitive `surfaces` has nothing for
+fn unflattened_kind(primative: &Primative) -> &'static str {
+    match primative {
+        Primative::Plane(_) => "plane",
+        Primative::Curves(_) => "curves",
+        Primative::Medium(_) | Primative::Volume(_) | Primative::Clouds(_) => "volume",
+        Primative::Csg(_) => "CSG",
+        Primative::Instance(_) => "instance",
+        Primative::Custom(_) => "custom",
+        _ => "other",
+    }
+}
+
+fn primitive_bounds(p: &PrimitiveRecord) -> (Point3, Point3) {
+    let point = |v: [f32; 4]| Vec3A::new(v[0], v[1], v[2]);
+    match p.kind {
+        PRIMITIVE_SPHERE => {
+            // Negative radii turn the normals inward but bound the same sphere
+            let radius = Vec3A::splat(p.a[3].abs());
+            (point(p.a) - radius, point(p.a) + radius)
+        }
+        _ => {
+            let (a, b, c) = (point(p.a), point(p.b), point(p.c));
+            (a.min(b).min(c), a.max(b).max(c))
+        }
+    }
+}
+
+// Splits `primitives[start..end]` at the median along the longest axis of their
+// centers, writing nodes in depth first order
+fn build_node(
+    nodes: &mut Vec<BvhNode>,
+    primitives: &mut [PrimitiveRecord],
+    start: usize,
+    end: usize,
+) {
+    let bounds: Vec<(Point3, Point3)> = primitives[start..end]
+        .iter()
+        .map(primitive_bounds)
+        .collect();
+    let (min, max) = bounds.iter().fold(
+        (
+            Vec3A::splat(Float::INFINITY),
+            Vec3A::splat(Float::NEG_INFINITY),
+        ),
+        |(min, max), (lo, hi)| (min.min(*lo), max.max(*hi)),
+    );
+
+    let index = nodes.len();
+    nodes.push(BvhNode {
+        min: min.to_array(),
+        first: start as u32,
+        max: max.to_array(),
+        count: (end - start) as u32,
+    });
+    if end - start <= LEAF_SIZE {
+        return;
+    }
+
+    let centers = bounds.iter().map(|(lo, hi)| 0.5 * (*lo + *hi));
+    let (center_min, center_max) = centers.fold(
+        (
+            Vec3A::splat(Float::INFINITY),
+            Vec3A::splat(Float::NEG_INFINITY),
+        ),
+        |(min, max), c| (min.min(c), max.max(c)),
+    );
+    let extent = center_max - center_min;
+    let axis = match extent.max_element() {
+        e if e == extent.x => 0,
+        e if e == extent.y => 1,
+        _ => 2,
+    };
+    let center = |p: &PrimitiveRecord| {
+        let (lo, hi) = primitive_bounds(p);
+        0.5 * (lo[axis] + hi[axis])
+    };
+    let mid = (start + end) / 2;
+    primitives[start..end]
+        .select_nth_unstable_by(mid - start, |a, b| center(a).total_cmp(&center(b)));
+
+    build_node(nodes, primitives, start, mid);
+    let right = nodes.len() as u32;
+    build_node(nodes, primitives, mid, end);
+    nodes[index].first = right;
+    nodes[index].count = BVH_INTERIOR;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MediumRng, Ray3A, WorldBuilder};
+
+    fn point(v: [f32; 4]) -> Vec3A {
+        Vec3A::new(v[0], v[1], v[2])
+    }
+
+    // Mirrors `trace_scene` in gpu_scene.wgsl, without the shading
+    fn trace(scene: &FlatScene, ray: &Ray3A) -> Option<Float> {
+        let mut closest = None::<Float>;
+        let mut stack = vec![0usize];
+        while let Some(index) = stack.pop() {
+            let node = scene.nodes[index];
+            let (lo, hi) = (Vec3A::from(node.min), Vec3A::from(node.max));
+            let t0 = (lo - ray.origin) / ray.direction;
+            let t1 = (hi - ray.origin) / ray.direction;
+            let near = t0.min(t1).max_element();
+            let far = t0
+                .max(t1)
+                .min_element()
+                .min(closest.unwrap_or(Float::INFINITY));
+            if near > far || far < scene.epsilon {
+                continue;
+            }
+            if node.count == BVH_INTERIOR {
+                stack.push(node.first as usize);
+                stack.push(index + 1);
+                continue;
+            }
+            let first = node.first as usize;
+            for p in &scene.primitives[first..first + node.count as usize] {
+                let t = match p.kind {
+                    PRIMITIVE_SPHERE => {
+                        let oc = ray.origin - point(p.a);
+                        let a = ray.direction.length_squared();
+                        let half_b = oc.dot(ray.direction);
+                        let disc = half_b * half_b - a * (oc.length_squared() - p.a[3] * p.a[3]);
+                        if disc < 0.0 {
+                            continue;
+                        }
+                        let near = (-half_b - disc.sqrt()) / a;
+                        match near > scene.epsilon {
+                            true => near,
+                            false => (-half_b + disc.sqrt()) / a,
+                        }
+                    }
+                    _ => {
+                        let (v0, v1, v2) = (point(p.a), point(p.b), point(p.c));
+                        let (e1, e2) = (v1 - v0, v2 - v0);
+                        let pvec = ray.direction.cross(e2);
+                        let det = e1.dot(pvec);
+                        if det.abs() < 1e-9 {
+                            continue;
+                        }
+                        let tvec = ray.origin - v0;
+                        let u = tvec.dot(pvec) / det;
+                        let qvec = tvec.cross(e1);
+                        let v = ray.direction.dot(qvec) / det;
+                        if u < 0.0 || v < 0.0 || u + v > 1.0 {
+                            continue;
+                        }
+                        e2.dot(qvec) / det
+                    }
+                };
+                if t > scene.epsilon && closest.is_none_or(|c| t < c) {
+                    closest = Some(t);
+                }
+            }
+        }
+        closest
+    }
+
+    #[test]
+    fn flattened_bvh_finds_the_same_hits() {
+        assert_eq!(std::mem::size_of::<BvhNode>(), 32);
+        assert_eq!(std::mem::size_of::<PrimitiveRecord>(), 64);
+
+        let mut world_builder = WorldBuilder::default();
+        let white = world_builder.push_texture(Texture::Solid { color: Rgba::ONE });
+        let white = world_builder.push_material(Material::Lambertian { albedo: white });
+        for i in 0..6 {
+            for j in 0..6 {
+                let center = Point3::new(i as Float - 2.5, j as Float - 2.5, 0.0);
+                world_builder.push_hittable(Primative::sphere(center, 0.3, white));
+            }
+        }
+        world_builder.push_hittable(Primative::cuboid(
+            Point3::new(-3.0, -3.0, -2.0),
+            Point3::new(3.0, 3.0, -1.0),
+            white,
+        ));
+        world_builder.push_hittable(Primative::plane(Point3::ZERO, Vec3A::Y, white));
+        let world: World = world_builder.into();
+
+        let scene = world.flatten(64);
+        assert_eq!(
+            scene
+                .skipped
+                .iter()
+                .map(|(k, v)| (*k, *v))
+                .collect::<Vec<_>>(),
+            [("plane", 1)]
+        );
+        assert_eq!(scene.primitives.len(), 36 + 12);
+        assert_eq!(scene.node_bytes().len(), 32 * scene.nodes.len());
+        assert_eq!(scene.primitive_bytes().len(), 64 * scene.primitives.len());
+        let sources: Vec<&Primative> = world.primitives().collect();
+        for p in scene.primitives.iter() {
+            let is_sphere = matches!(sources[p.source as usize], Primative::Sphere(_));
+            assert_eq!(is_sphere, p.kind == PRIMITIVE_SPHERE);
+        }
+
+        for i in 0..40 {
+            for j in 0..40 {
+                let ray = Ray3A {
+                    origin: Point3::new(0.0, 0.0, 10.0),
+                    direction: Vec3A::new(i as Float / 40.0 - 0.5, j as Float / 40.0 - 0.5, -1.5),
+                };
+                // The plane isn't on the GPU, so compare against the rest
+                let expected = world
+                    .bvh
+                    .ray_hit(
+                        &ray,
+                        world.epsilon(),
+                        Float::INFINITY,
+                        &mut MediumRng::default(),
+                    )
+                    .map(|(t, _)| t);
+                match (trace(&scene, &ray), expected) {
+                    (Some(t), Some(expected)) => assert!((t - expected).abs() < 1e-3),
+                    (found, expected) => assert_eq!(found.is_some(), expected.is_some()),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn negative_spheres_are_still_bounded() {
+        let mut world_builder = WorldBuilder::default();
+        let white = world_builder.push_texture(Texture::Solid { color: Rgba::ONE });
+        let white = world_builder.push_material(Material::Lambertian { albedo: white });
+        world_builder.push_hittable(Primative::sphere(Point3::ZERO, -1.0, white));
+        let world: World = world_builder.into();
+
+        let scene = world.flatten(64);
+        assert_eq!(scene.nodes[0].min, [-1.0; 3]);
+        assert_eq!(scene.nodes[0].max, [1.0; 3]);
+        let ray = Ray3A {
+            origin: Point3::new(0.0, 0.0, 10.0),
+            direction: -Vec3A::Z,
+        };
+        assert!(trace(&scene, &ray).is_some());
+    }
+
+    #[test]
+    fn materials_pack_with_their_textures() {
+        assert_eq!(std::mem::size_of::<MaterialRecord>(), 80);
+
+        let mut world_builder = WorldBuilder::default();
+        let red = world_builder.push_texture(Texture::Solid {
+            color: Rgba::new(1.0, 0.0, 0.0, 1.0),
+        });
+        let image = world_builder.push_texture(Texture::Image {
+            image: crate::image::Image::new(2, 2),
+            flipbook: None,
+        });
+        let lambertian = world_builder.push_material(Material::Lambertian { albedo: red });
+        let metal = world_builder.push_material(Material::RoughMetal {
+            albedo: image,
+            roughness: 0.3,
+        });
+        let world: World = world_builder.into();
+
+        let atlas = world.texture_atlas(64);
+        let records = world.flatten_materials(&atlas);
+        assert_eq!(records.bytes().len(), 2 * 80);
+
+        let lambertian = records.materials[records.index(lambertian).unwrap() as usize];
+        assert_eq!(lambertian.kind, Material::default().flat_kind());
+        assert_eq!(
+            (lambertian.texture, lambertian.color),
+            (-1, [1.0, 0.0, 0.0, 1.0])
+        );
+
+        let metal = records.materials[records.index(metal).unwrap() as usize];
+        assert_eq!(metal.texture, atlas.index(image).unwrap() as i32);
+        assert_eq!(metal.params[1], 0.3);
+    }
+
+    // Size of a WGSL struct in a storage buffer, from its member types
+    fn wgsl_size(source: &str) -> usize {
+        let (mut offset, mut align) = (0usize, 4);
+        for member in source
+            .lines()
+            .filter_map(|line| line.trim().strip_suffix(';'))
+        {
+            let (member_size, member_align) = match member.split(": ").nth(1) {
+                Some("vec3<f32>") => (12, 16),
+                Some("vec4<f32>") => (16, 16),
+                Some(_) => (4, 4),
+                None => continue,
+            };
+            offset = offset.div_ceil(member_align) * member_align + member_size;
+            align = usize::max(align, member_align);
+        }
+        offset.div_ceil(align) * align
+    }
+
+    #[test]
+    fn records_match_their_wgsl_layout() {
+        use crate::GpuCamera;
+        use std::mem::size_of;
+
+        assert!(MaterialRecord::wgsl().starts_with("struct GpuMaterial {"));
+        assert_eq!(
+            wgsl_size(&MaterialRecord::wgsl()),
+            size_of::<MaterialRecord>()
+        );
+        assert_eq!(wgsl_size(&BvhNode::wgsl()), size_of::<BvhNode>());
+        assert_eq!(
+            wgsl_size(&PrimitiveRecord::wgsl()),
+            size_of::<PrimitiveRecord>()
+        );
+        assert_eq!(wgsl_size(&GpuCamera::wgsl()), size_of::<GpuCamera>());
+    }
+}
@@ -0,0 +1,241 @@
+// Random scenes built from pathological pieces: NaN and infinite positions, zero
+// sizes, degenerate triangles, empty meshes and worlds, keys that were never pushed
+// and images with no pixels. Each is validated, and if it passes built and rendered,
+// which must either fail with an error or finish without panicking.
+
+use crate::{
+    BandRenderer, Camera, DensityField, Float, Image, Material, MaterialKey, Mesh, Noise,
+    ParallelRenderer, Point3, Primative, Rgba, Scene, Texture, TextureKey, TextureSpace, Transform,
+    UvTransform, Vec3A, WorldBuilder, WrapMode,
+};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+use std::time::Duration;
+
+const SCENES: u64 = 300;
+
+fn float(rng: &mut StdRng) -> Float {
+    match rng.gen_range(0..12) {
+        0 => Float::NAN,
+        1 => Float::INFINITY,
+        2 => Float::NEG_INFINITY,
+        3 => 0.0,
+        4 => 1e30,
+        5 => -1e-30,
+        _ => rng.gen_range(-10.0..10.0),
+    }
+}
+
+// Mostly well behaved, so the rest of the scene gets exercised too
+fn tame(rng: &mut StdRng) -> Float {
+    match rng.gen_bool(0.9) {
+        true => rng.gen_range(-10.0..10.0),
+        false => float(rng),
+    }
+}
+
+fn point(rng: &mut StdRng) -> Point3 {
+    Point3::new(tame(rng), tame(rng), tame(rng))
+}
+
+fn texture_key(rng: &mut StdRng, textures: &[TextureKey]) -> TextureKey {
+    match textures.is_empty() || rng.gen_bool(0.1) {
+        true => TextureKey::default(),
+        false => textures[rng.gen_range(0..textures.len())],
+    }
+}
+
+fn material_key(rng: &mut StdRng, materials: &[MaterialKey]) -> MaterialKey {
+    match materials.is_empty() || rng.gen_bool(0.1) {
+        true => MaterialKey::default(),
+        false => materials[rng.gen_range(0..materials.len())],
+    }
+}
+
+// Triangles over a handful of vertices, with repeats, lines and points among them,
+// and now and then an index past the end or normals and faces that don't line up
+fn mesh(rng: &mut StdRng, material_key: MaterialKey) -> std::io::Result<Arc<Mesh>> {
+    let corner = point(rng);
+    let vertices: Vec<_> = (0..rng.gen_range(0..6))
+        .map(|_| match rng.gen_range(0..4) {
+            0 => corner,
+            1 => corner + tame(rng) * Vec3A::X,
+            _ => point(rng),
+        })
+        .collect();
+    let past_end = rng.gen_bool(0.1) as usize;
+    let indices: Vec<_> = match vertices.len() + past_end {
+        0 => Vec::new(),
+        count => (0..rng.gen_range(0..8))
+            .map(|_| {
+                let mut index = || rng.gen_range(0..count);
+                (index(), index(), index())
+            })
+            .collect(),
+    };
+    // Usually one per vertex or triangle, sometimes one more
+    let mut count = |len: usize| len + rng.gen_bool(0.1) as usize;
+    let normals: Vec<_> = (0..count(vertices.len())).map(|_| Vec3A::Y).collect();
+    let faces = (0..count(indices.len())).collect();
+    match rng.gen_range(0..3) {
+        0 => Mesh::with_normals(vertices, normals, indices, material_key),
+        1 => Mesh::with_source_faces(vertices, Some(normals), indices, faces, material_key),
+        _ => Mesh::new(vertices, indices, material_key),
+    }
+}
+
+// `None` when a mesh among its pieces is rejected
+fn primative(rng: &mut StdRng, materials: &[MaterialKey], depth: usize) -> Option<Primative> {
+    let key = material_key(rng, materials);
+    Some(match rng.gen_range(0..10) {
+        0 => Primative::sphere(point(rng), float(rng), key),
+        1 => Primative::quad(point(rng), point(rng), point(rng), key),
+        2 => Primative::cuboid(point(rng), point(rng), key),
+        3 => Primative::disk(point(rng), point(rng), float(rng), key),
+        4 => Primative::plane(point(rng), point(rng), key),
+        5 if depth < 2 => {
+            let boundary = primative(rng, materials, depth + 1)?;
+            Primative::constant_medium(boundary, float(rng), key)
+        }
+        6 if depth < 2 => {
+            let object = Arc::new(primative(rng, materials, depth + 1)?);
+            let rotation = glam::Quat::from_rotation_y(tame(rng));
+            Primative::instance(object, Transform::new(point(rng), rotation, float(rng)))
+        }
+        7 if depth < 2 => {
+            let boundary = primative(rng, materials, depth + 1)?;
+            let resolution = [(); 3].map(|_| rng.gen_range(0..3));
+            let count = match rng.gen_bool(0.8) {
+                true => resolution.iter().product(),
+                false => rng.gen_range(0..9),
+            };
+            let values = (0..count).map(|_| float(rng)).collect();
+            let density = DensityField::grid(resolution, values, point(rng), point(rng))
+                .unwrap_or_else(|| DensityField::Noise {
+                    noise: Box::new(Noise::perlin(rng)),
+                    scale: float(rng),
+                    density: float(rng),
+                });
+            Primative::heterogeneous_medium(boundary, density, key)
+        }
+        8 if depth < 2 => Primative::difference(
+            primative(rng, materials, depth + 1)?,
+            primative(rng, materials, depth + 1)?,
+        ),
+        _ => Primative::Mesh(mesh(rng, key).ok()?),
+    })
+}
+
+fn scene(rng: &mut StdRng) -> Option<Scene> {
+    let mut world_builder = WorldBuilder::default();
+
+    let mut textures = Vec::new();
+    for _ in 0..rng.gen_range(0..4) {
+        let texture = match rng.gen_range(0..4) {
+            0 => Texture::Checker {
+                odd: texture_key(rng, &textures),
+                even: texture_key(rng, &textures),
+                scale: float(rng),
+                space: match rng.gen() {
+                    true => TextureSpace::World,
+                    false => TextureSpace::Uv,
+                },
+            },
+            1 => Texture::Image {
+                image: Image::new(rng.gen_range(0..3), rng.gen_range(0..3)),
+                flipbook: None,
+            },
+            2 => Texture::Transformed {
+                texture: texture_key(rng, &textures),
+                transform: UvTransform {
+                    offset: (float(rng), float(rng)),
+                    scale: (float(rng), float(rng)),
+                    rotation: float(rng),
+                    wrap: [WrapMode::Repeat, WrapMode::Clamp, WrapMode::Mirror]
+                        [rng.gen_range(0..3)],
+                },
+            },
+            _ => Texture::Solid {
+                color: Rgba::new(tame(rng), tame(rng), tame(rng), 1.0),
+            },
+        };
+        textures.push(world_builder.push_texture(texture));
+    }
+
+    let mut materials = Vec::new();
+    for _ in 0..rng.gen_range(0..4) {
+        let material = match rng.gen_range(0..6) {
+            0 => Material::Metal {
+                albedo: texture_key(rng, &textures),
+                fuzz: float(rng),
+            },
+            1 => Material::dielectric(float(rng)),
+            2 => Material::DiffuseLight {
+                emit: texture_key(rng, &textures),
+                intensity: float(rng),
+            },
+            3 => Material::Isotropic {
+                albedo: texture_key(rng, &textures),
+            },
+            _ => Material::Lambertian {
+                albedo: texture_key(rng, &textures),
+            },
+        };
+        materials.push(world_builder.push_material(material));
+    }
+
+    for _ in 0..rng.gen_range(0..6) {
+        if let Some(primative) = primative(rng, &materials, 0) {
+            world_builder.push_hittable(primative);
+        }
+    }
+    if rng.gen_bool(0.3) {
+        let key = material_key(rng, &materials);
+        if let Ok(mesh) = mesh(rng, key) {
+            world_builder.push_area_light(mesh, rng.gen());
+        }
+    }
+
+    let world = world_builder.build().ok()?;
+    let camera = Camera::builder()
+        .look_from(point(rng))
+        .look_at(point(rng))
+        .vfov(rng.gen_range(-10.0..200.0))
+        .build()
+        .ok()?;
+    Some(Scene::new(world, camera))
+}
+
+fn render(rng: &mut StdRng, mut scene: Scene) {
+    let (width, height) = (rng.gen_range(0..5), rng.gen_range(0..5));
+    let depth = rng.gen_range(0..4);
+
+    let mut renderer = ParallelRenderer::new(width, height, depth);
+    renderer.resize(width, height, &mut scene);
+    renderer.set_preview_stride(rng.gen_range(0..3));
+    renderer.enable_aovs();
+    renderer.render(&scene);
+    renderer.render(&scene);
+    renderer.render_for(&scene, Duration::from_millis(1));
+    renderer.report(&scene);
+    renderer.display_image(&scene);
+
+    let bands = BandRenderer::new(width, height, rng.gen_range(0..3), depth, 1);
+    bands.render(&scene, |_, _| {});
+}
+
+#[test]
+fn fuzzed_scenes_fail_gracefully() {
+    for seed in 0..SCENES {
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            if let Some(scene) = scene(&mut rng) {
+                render(&mut rng, scene);
+            }
+        }));
+        assert!(result.is_ok(), "scene {} panicked", seed);
+    }
+}
@@ -0,0 +1,15 @@
+use crate::flatten::{MaterialRecord, MATERIAL_KINDS};
+
+// Constants, the material struct and the evaluation functions. Expects the scene
+// texture atlas bindings and `sample_scene_texture` from `scene_wgsl` first.
+pub fn material_wgsl() -> String {
+    let mut source = String::new();
+    for (name, kind) in MATERIAL_KINDS.iter() {
+        source += &format!("let MATERIAL_{}: u32 = {}u;\n", name, kind);
+    }
+    source += "\n";
+    source += &MaterialRecord::wgsl();
+    source += "\n";
+    source += include_str!("gpu_material.wgsl");
+    source
+}
@@ -0,0 +1,273 @@
+// Material evaluation mirroring Material::scatter and Material::emit in material.rs.
+// Keep the two in step. Random numbers are passed in so callers choose the generator.
+
+[[block]]
+struct Materials {
+    materials: array<GpuMaterial>;
+};
+
+[[group(0), binding(4)]]
+var<storage> scene_materials: [[access(read)]] Materials;
+
+struct MaterialScatter {
+    direction: vec3<f32>;
+    attenuation: vec3<f32>;
+    // False when the path was absorbed
+    scattered: bool;
+};
+
+let MATERIAL_PI: f32 = 3.14159265;
+
+fn absorbed() -> MaterialScatter {
+    return MaterialScatter(vec3<f32>(0.0), vec3<f32>(0.0), false);
+}
+
+fn material_texture(index: i32, color: vec4<f32>, uv: vec2<f32>) -> vec3<f32> {
+    if (index < 0) {
+        return color.xyz;
+    }
+    return color.xyz * sample_scene_texture(u32(index), fract(uv)).xyz;
+}
+
+fn material_emit(m: GpuMaterial, uv: vec2<f32>) -> vec3<f32> {
+    return material_texture(m.emission_texture, m.emission, uv) * m.emission.w;
+}
+
+// Uniform on the unit sphere
+fn material_sphere(xi: vec2<f32>) -> vec3<f32> {
+    let z = 2.0 * xi.x - 1.0;
+    let phi = 2.0 * MATERIAL_PI * xi.y;
+    let r = sqrt(max(1.0 - z * z, 0.0));
+    return vec3<f32>(r * cos(phi), r * sin(phi), z);
+}
+
+fn schlick_ior(cosine: f32, eta: f32) -> f32 {
+    let root = (1.0 - eta) / (1.0 + eta);
+    let r0 = root * root;
+    return r0 + (1.0 - r0) * pow(1.0 - cosine, 5.0);
+}
+
+fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let sin2_t = max(1.0 - cos_i * cos_i, 0.0) / (eta * eta);
+    if (sin2_t >= 1.0) {
+        return 1.0;
+    }
+    let cos_t = sqrt(1.0 - sin2_t);
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    return 0.5 * (rs * rs + rp * rp);
+}
+
+fn conductor_reflectance(cos_theta: f32, eta: f32, k: f32) -> f32 {
+    let cos2 = cos_theta * cos_theta;
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = sqrt(t0 * t0 + 4.0 * eta2 * k2);
+    let t1 = a2_plus_b2 + cos2;
+    let a = sqrt(max(0.5 * (a2_plus_b2 + t0), 0.0));
+    let t2 = 2.0 * cos_theta * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    return clamp(0.5 * (rs + rp), 0.0, 1.0);
+}
+
+fn ggx_alpha(roughness: f32) -> f32 {
+    return max(roughness * roughness, 0.001);
+}
+
+fn ggx_lambda(alpha: f32, cosine: f32) -> f32 {
+    let cos2 = max(cosine * cosine, 0.0000001);
+    let tan2 = max(1.0 - cos2, 0.0) / cos2;
+    return 0.5 * (-1.0 + sqrt(1.0 + alpha * alpha * tan2));
+}
+
+// G2 / G1, the weight left after sampling visible normals
+fn ggx_masking_ratio(alpha: f32, cos_o: f32, cos_i: f32) -> f32 {
+    let lambda_o = ggx_lambda(alpha, cos_o);
+    return (1.0 + lambda_o) / (1.0 + lambda_o + ggx_lambda(alpha, cos_i));
+}
+
+// Visible normal sampling (Heitz 2018)
+fn ggx_sample_visible(alpha: f32, wo: vec3<f32>, normal: vec3<f32>, xi: vec2<f32>) -> vec3<f32> {
+    var helper = vec3<f32>(1.0, 0.0, 0.0);
+    if (abs(normal.x) > 0.9) {
+        helper = vec3<f32>(0.0, 1.0, 0.0);
+    }
+    let tangent = normalize(cross(helper, normal));
+    let bitangent = cross(normal, tangent);
+    let local = vec3<f32>(dot(wo, tangent), dot(wo, bitangent), dot(wo, normal));
+
+    let vh = normalize(vec3<f32>(alpha * local.x, alpha * local.y, local.z));
+    let length2 = vh.x * vh.x + vh.y * vh.y;
+    var t1 = vec3<f32>(1.0, 0.0, 0.0);
+    if (length2 > 0.0) {
+        t1 = vec3<f32>(-vh.y, vh.x, 0.0) / sqrt(length2);
+    }
+    let t2 = cross(vh, t1);
+
+    let r = sqrt(xi.x);
+    let phi = 2.0 * MATERIAL_PI * xi.y;
+    let p1 = r * cos(phi);
+    let s = 0.5 * (1.0 + vh.z);
+    let p2 = (1.0 - s) * sqrt(max(1.0 - p1 * p1, 0.0)) + s * r * sin(phi);
+    let nh = p1 * t1 + p2 * t2 + sqrt(max(1.0 - p1 * p1 - p2 * p2, 0.0)) * vh;
+
+    let h = normalize(vec3<f32>(alpha * nh.x, alpha * nh.y, max(nh.z, 0.0)));
+    return h.x * tangent + h.y * bitangent + h.z * normal;
+}
+
+fn ggx_reflect(f0: vec3<f32>, roughness: f32, wo: vec3<f32>, normal: vec3<f32>, xi: vec2<f32>) -> MaterialScatter {
+    let alpha = ggx_alpha(roughness);
+    let h = ggx_sample_visible(alpha, wo, normal, xi);
+    let wi = reflect(-wo, h);
+    let cos_o = dot(wo, normal);
+    let cos_i = dot(wi, normal);
+    if (cos_o <= 0.0 || cos_i <= 0.0) {
+        return absorbed();
+    }
+
+    let w = pow(1.0 - clamp(dot(wo, h), 0.0, 1.0), 5.0);
+    let fresnel = f0 * (1.0 - w) + vec3<f32>(w);
+    return MaterialScatter(wi, fresnel * ggx_masking_ratio(alpha, cos_o, cos_i), true);
+}
+
+// `eta` is the index on the far side of the surface over the near side
+fn ggx_transmit(eta: f32, roughness: f32, wo: vec3<f32>, normal: vec3<f32>, xi: vec3<f32>) -> MaterialScatter {
+    let cos_o = dot(wo, normal);
+    if (cos_o <= 0.0) {
+        return absorbed();
+    }
+
+    let alpha = ggx_alpha(roughness);
+    let h = ggx_sample_visible(alpha, wo, normal, xi.xy);
+    let reflected = fresnel_dielectric(dot(wo, h), eta) > xi.z;
+    var wi = refract(-wo, h, 1.0 / eta);
+    if (reflected) {
+        wi = reflect(-wo, h);
+    }
+
+    // Reflections must stay above the surface and refractions below it
+    let cos_i = dot(wi, normal);
+    if ((cos_i > 0.0) != reflected || cos_i == 0.0) {
+        return absorbed();
+    }
+    return MaterialScatter(wi, vec3<f32>(ggx_masking_ratio(alpha, cos_o, abs(cos_i))), true);
+}
+
+// `normal` faces against `ray_direction` and `front_face` says whether the ray
+// arrived from outside. Uses xi.x to pick lobes, xi.yz for directions and xi.w for Fresnel.
+fn scatter_material(
+    m: GpuMaterial,
+    ray_direction: vec3<f32>,
+    normal: vec3<f32>,
+    front_face: bool,
+    uv: vec2<f32>,
+    xi: vec4<f32>
+) -> MaterialScatter {
+    let unit_dir = normalize(ray_direction);
+    let wo = -unit_dir;
+    let albedo = material_texture(m.texture, m.color, uv);
+
+    if (m.kind == MATERIAL_LAMBERTIAN) {
+        var direction = normal + material_sphere(xi.yz);
+        if (length(direction) < 0.00000001) {
+            direction = normal;
+        }
+        return MaterialScatter(direction, albedo, true);
+    }
+    if (m.kind == MATERIAL_ISOTROPIC) {
+        return MaterialScatter(material_sphere(xi.yz), albedo, true);
+    }
+    if (m.kind == MATERIAL_METAL || m.kind == MATERIAL_CONDUCTOR) {
+        let mirror = reflect(unit_dir, normal);
+        var direction = mirror + m.params.x * material_sphere(xi.yz);
+        let below = dot(direction, normal);
+        if (m.kind == MATERIAL_METAL) {
+            if (below <= 0.0) {
+                return absorbed();
+            }
+            return MaterialScatter(direction, albedo, true);
+        }
+        // Folded back out like the CPU conductor, rather than absorbed
+        if (below <= 0.0) {
+            direction = direction - 2.0 * below * normal;
+            if (length(direction) < 0.00000001) {
+                direction = mirror;
+            }
+        }
+        let cos_theta = clamp(dot(wo, normal), 0.0, 1.0);
+        let color = vec3<f32>(
+            conductor_reflectance(cos_theta, m.color.x, m.extra.x),
+            conductor_reflectance(cos_theta, m.color.y, m.extra.y),
+            conductor_reflectance(cos_theta, m.color.z, m.extra.z)
+        );
+        return MaterialScatter(direction, color, true);
+    }
+    if (m.kind == MATERIAL_DIELECTRIC) {
+        var ratio = m.params.x;
+        if (front_face) {
+            ratio = 1.0 / m.params.x;
+        }
+        let cos_theta = min(dot(wo, normal), 1.0);
+        let sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+        var direction = refract(unit_dir, normal, ratio);
+        if (ratio * sin_theta > 1.0 || schlick_ior(cos_theta, ratio) > xi.w) {
+            direction = reflect(unit_dir, normal);
+        }
+        return MaterialScatter(direction, vec3<f32>(1.0), true);
+    }
+    if (m.kind == MATERIAL_ROUGH_METAL) {
+        return ggx_reflect(albedo, m.params.y, wo, normal, xi.yz);
+    }
+    if (m.kind == MATERIAL_ROUGH_DIELECTRIC) {
+        var eta = 1.0 / m.params.w;
+        if (front_face) {
+            eta = m.params.w;
+        }
+        return ggx_transmit(eta, m.params.y, wo, normal, xi.yzw);
+    }
+    if (m.kind == MATERIAL_PRINCIPLED) {
+        let metal = clamp(m.params.x, 0.0, 1.0);
+        let glass = (1.0 - metal) * clamp(m.extra.x, 0.0, 1.0);
+        if (xi.x < metal) {
+            return ggx_reflect(albedo, m.params.y, wo, normal, xi.yz);
+        }
+        if (xi.x < metal + glass) {
+            var eta = 1.0 / m.params.w;
+            if (front_face) {
+                eta = m.params.w;
+            }
+            var result = ggx_transmit(eta, m.params.y, wo, normal, xi.yzw);
+            // Only light passing through the surface picks up the base color
+            if (dot(result.direction, normal) < 0.0) {
+                result.attenuation = result.attenuation * albedo;
+            }
+            return result;
+        }
+
+        let f0 = clamp(0.08 * m.params.z, 0.0, 1.0);
+        let coat = f0 + (1.0 - f0) * pow(1.0 - clamp(dot(wo, normal), 0.0, 1.0), 5.0);
+        // Reuse the lobe choice, rescaled to [0, 1) over the coated base
+        let choice = (xi.x - metal - glass) / max(1.0 - metal - glass, 0.000001);
+        if (choice < coat) {
+            var result = ggx_reflect(vec3<f32>(f0), m.params.y, wo, normal, xi.yz);
+            result.attenuation = result.attenuation / coat;
+            return result;
+        }
+        var direction = normal + material_sphere(xi.yz);
+        if (length(direction) < 0.00000001) {
+            direction = normal;
+        }
+        return MaterialScatter(direction, albedo, true);
+    }
+
+    // Lights and anything unknown end the path
+    return absorbed();
+}
@@ -0,0 +1,57 @@
+use crate::flatten::{
+    gpu_struct, BvhNode, PrimitiveRecord, BVH_INTERIOR, BVH_MAX_DEPTH, PRIMITIVE_SPHERE,
+    PRIMITIVE_TRIANGLE,
+};
+
+// The camera's image plane, matching `Camera::get_ray`. The lens radius is in
+// origin.w and w.w is 1 for equirectangular projection.
+gpu_struct! {
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct GpuCamera as GpuCamera {
+        pub origin: [f32; 4],
+        // Top left of the image plane
+        pub corner: [f32; 4],
+        pub horizontal: [f32; 4],
+        pub vertical: [f32; 4],
+        pub u: [f32; 4],
+        pub v: [f32; 4],
+        pub w: [f32; 4],
+    }
+}
+
+impl GpuCamera {
+    pub fn bytes(&self) -> Vec<u8> {
+        [
+            self.origin,
+            self.corner,
+            self.horizontal,
+            self.vertical,
+            self.u,
+            self.v,
+            self.w,
+        ]
+        .iter()
+        .flatten()
+        .flat_map(|v| v.to_ne_bytes())
+        .collect()
+    }
+}
+
+// Constants, structs, the scene bindings and `trace_scene`, including the texture
+// atlas that `material_wgsl` samples. Put this first.
+pub fn scene_wgsl() -> String {
+    let mut source = String::new();
+    source += &format!("let PRIMITIVE_SPHERE: u32 = {}u;\n", PRIMITIVE_SPHERE);
+    source += &format!("let PRIMITIVE_TRIANGLE: u32 = {}u;\n", PRIMITIVE_TRIANGLE);
+    source += &format!("let BVH_INTERIOR: u32 = {}u;\n", BVH_INTERIOR);
+    source += &format!("let BVH_STACK_SIZE: u32 = {}u;\n", BVH_MAX_DEPTH);
+    source += "\n";
+    source += &BvhNode::wgsl();
+    source += "\n";
+    source += &PrimitiveRecord::wgsl();
+    source += "\n";
+    source += &GpuCamera::wgsl();
+    source += "\n";
+    source += include_str!("gpu_scene.wgsl");
+    source
+}
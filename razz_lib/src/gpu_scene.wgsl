@@ -0,0 +1,186 @@
+// Scene data and ray traversal mirroring World::closest_hit, for the structures built by
+// World::flatten. Expects the material bindings to follow at binding 4.
+
+struct AtlasRegion {
+    offset: vec2<u32>;
+    size: vec2<u32>;
+};
+
+[[block]]
+struct AtlasRegions {
+    regions: array<AtlasRegion>;
+};
+
+[[block]]
+struct BvhNodes {
+    nodes: array<GpuBvhNode>;
+};
+
+[[block]]
+struct Primitives {
+    primitives: array<GpuPrimitive>;
+};
+
+[[group(0), binding(2)]]
+var atlas: [[access(read)]] texture_storage_2d<rgba32float>;
+[[group(0), binding(3)]]
+var<storage> atlas_regions: [[access(read)]] AtlasRegions;
+[[group(0), binding(5)]]
+var<storage> scene_nodes: [[access(read)]] BvhNodes;
+[[group(0), binding(6)]]
+var<storage> scene_primitives: [[access(read)]] Primitives;
+
+let SCENE_PI: f32 = 3.14159265;
+
+// Nearest texel of a scene image texture, matching Image::sample
+fn sample_scene_texture(index: u32, uv: vec2<f32>) -> vec4<f32> {
+    let region = atlas_regions.regions[index];
+    let st = clamp(vec2<f32>(uv.x, 1.0 - uv.y), vec2<f32>(0.0), vec2<f32>(1.0));
+    let texel = min(vec2<u32>(st * vec2<f32>(region.size)), region.size - vec2<u32>(1u));
+    return textureLoad(atlas, vec2<i32>(region.offset + texel));
+}
+
+struct SceneHit {
+    t: f32;
+    point: vec3<f32>;
+    // Faces against the ray
+    normal: vec3<f32>;
+    uv: vec2<f32>;
+    material: u32;
+    front_face: bool;
+    hit: bool;
+};
+
+// Whether the ray passes through the node's box between `t_min` and `t_max`
+fn hit_bounds(node: GpuBvhNode, origin: vec3<f32>, inverse: vec3<f32>, t_min: f32, t_max: f32) -> bool {
+    let t0 = (node.min - origin) * inverse;
+    let t1 = (node.max - origin) * inverse;
+    let near = min(t0, t1);
+    let far = max(t0, t1);
+    let enter = max(max(near.x, near.y), max(near.z, t_min));
+    let exit = min(min(far.x, far.y), min(far.z, t_max));
+    return enter <= exit;
+}
+
+// Distance to the primitive, or -1 on a miss. Writes uv of triangles to `hit_uv`.
+var<private> hit_uv: vec2<f32>;
+
+fn hit_primitive(p: GpuPrimitive, origin: vec3<f32>, direction: vec3<f32>, t_min: f32, t_max: f32) -> f32 {
+    if (p.kind == PRIMITIVE_SPHERE) {
+        let oc = origin - p.a.xyz;
+        let a = dot(direction, direction);
+        let half_b = dot(oc, direction);
+        let c = dot(oc, oc) - p.a.w * p.a.w;
+        let disc = half_b * half_b - a * c;
+        if (disc < 0.0) {
+            return -1.0;
+        }
+        let sqrtd = sqrt(disc);
+        var root = (-half_b - sqrtd) / a;
+        if (root < t_min || t_max < root) {
+            root = (-half_b + sqrtd) / a;
+            if (root < t_min || t_max < root) {
+                return -1.0;
+            }
+        }
+        return root;
+    }
+
+    let e1 = p.b.xyz - p.a.xyz;
+    let e2 = p.c.xyz - p.a.xyz;
+    let pvec = cross(direction, e2);
+    let det = dot(e1, pvec);
+    if (abs(det) <= 0.000001 * length(cross(e1, e2)) * length(direction)) {
+        return -1.0;
+    }
+    let inv_det = 1.0 / det;
+    let tvec = origin - p.a.xyz;
+    let u = dot(tvec, pvec) * inv_det;
+    if (u < 0.0 || u > 1.0) {
+        return -1.0;
+    }
+    let qvec = cross(tvec, e1);
+    let v = dot(direction, qvec) * inv_det;
+    if (v < 0.0 || u + v > 1.0) {
+        return -1.0;
+    }
+    let t = dot(e2, qvec) * inv_det;
+    if (t < t_min || t_max < t) {
+        return -1.0;
+    }
+    hit_uv = vec2<f32>(u, v);
+    return t;
+}
+
+fn trace_scene(origin: vec3<f32>, direction: vec3<f32>, t_min: f32) -> SceneHit {
+    var result: SceneHit;
+    result.hit = false;
+    result.t = 1.0e30;
+    var closest: u32 = 0u;
+    var closest_uv = vec2<f32>(0.0);
+    let inverse = 1.0 / direction;
+
+    var stack: array<u32, BVH_STACK_SIZE>;
+    var top: u32 = 1u;
+    stack[0] = 0u;
+    loop {
+        if (top == 0u) {
+            break;
+        }
+        top = top - 1u;
+        let index = stack[top];
+        let node = scene_nodes.nodes[index];
+        if (!hit_bounds(node, origin, inverse, t_min, result.t)) {
+            continue;
+        }
+        if (node.count == BVH_INTERIOR) {
+            if (top + 2u <= BVH_STACK_SIZE) {
+                stack[top] = node.first;
+                stack[top + 1u] = index + 1u;
+                top = top + 2u;
+            }
+            continue;
+        }
+
+        var i: u32 = node.first;
+        loop {
+            if (i >= node.first + node.count) {
+                break;
+            }
+            let t = hit_primitive(scene_primitives.primitives[i], origin, direction, t_min, result.t);
+            if (t >= 0.0) {
+                result.hit = true;
+                result.t = t;
+                closest = i;
+                closest_uv = hit_uv;
+            }
+            i = i + 1u;
+        }
+    }
+
+    if (!result.hit) {
+        return result;
+    }
+
+    let p = scene_primitives.primitives[closest];
+    result.point = origin + result.t * direction;
+    result.material = p.material;
+    var outward = normalize(cross(p.b.xyz - p.a.xyz, p.c.xyz - p.a.xyz));
+    if (p.kind == PRIMITIVE_SPHERE) {
+        outward = (result.point - p.a.xyz) / p.a.w;
+    }
+    result.front_face = dot(direction, outward) < 0.0;
+    result.normal = outward;
+    if (!result.front_face) {
+        result.normal = -outward;
+    }
+
+    result.uv = closest_uv;
+    // Spheres take uv from the normal facing the ray, as Sphere::ray_hit does
+    if (p.kind == PRIMITIVE_SPHERE) {
+        let theta = -acos(result.normal.y);
+        let phi = -atan2(result.normal.z, result.normal.x) + SCENE_PI;
+        result.uv = vec2<f32>(phi / (2.0 * SCENE_PI), theta / SCENE_PI);
+    }
+    return result;
+}
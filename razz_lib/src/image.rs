@@ -3,6 +3,7 @@ use crate::Float;
 use std::ops::{Add, Mul};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Rgba(glam::Vec4);
 
 impl Rgba {
@@ -29,6 +30,10 @@ impl Rgba {
         Self(glam::Vec4::splat(v))
     }
 
+    pub fn max_channel(&self) -> Float {
+        self.0.x.max(self.0.y).max(self.0.z)
+    }
+
     pub fn to_array(&self) -> [f32; 4] {
         self.0.into()
     }
@@ -59,6 +64,7 @@ impl Mul<f32> for Rgba {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Image {
     pub width: usize,
     pub height: usize,
@@ -102,6 +108,16 @@ impl Image {
         )
     }
 
+    pub fn sample(&self, u: Float, v: Float) -> Rgba {
+        if self.width == 0 || self.height == 0 {
+            return Rgba::new(1.0, 0.0, 1.0, 1.0);
+        }
+
+        let x = ((u * self.width as Float) as usize).min(self.width - 1);
+        let y = (((1.0 - v) * self.height as Float) as usize).min(self.height - 1);
+        self.get_pixel_color(x, y)
+    }
+
     pub fn as_bytes(&self) -> &[u8] {
         unsafe { std::slice::from_raw_parts(self.data.as_ptr() as *const u8, self.data.len() * 4) }
     }
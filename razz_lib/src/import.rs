@@ -0,0 +1,326 @@
+use crate::image::{Image, Rgba};
+use crate::report::json_string;
+use crate::{
+    AssetResolver, Dispersion, Float, Material, MaterialKey, Texture, TextureKey, WorldBuilder,
+};
+
+use std::fmt::Write;
+use std::io;
+use std::path::Path;
+
+// How one source material was brought over, for checking a conversion without
+// comparing renders
+#[derive(Debug, Clone)]
+pub struct MaterialConversion {
+    pub source: String,
+    pub key: MaterialKey,
+    // The razz material it became
+    pub material: &'static str,
+    // Parameters carried over only roughly, with how
+    pub approximated: Vec<String>,
+    // Parameters with nothing to map to, with why
+    pub dropped: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ConversionReport {
+    pub materials: Vec<MaterialConversion>,
+}
+
+impl ConversionReport {
+    pub fn key(&self, source: &str) -> Option<MaterialKey> {
+        self.materials
+            .iter()
+            .find(|m| m.source == source)
+            .map(|m| m.key)
+    }
+
+    // Conversions that lost or changed anything
+    pub fn lossy(&self) -> impl Iterator<Item = &MaterialConversion> {
+        self.materials
+            .iter()
+            .filter(|m| !m.approximated.is_empty() || !m.dropped.is_empty())
+    }
+
+    pub fn to_json(&self) -> String {
+        let list = |notes: &[String]| {
+            let notes: Vec<String> = notes.iter().map(|n| json_string(n)).collect();
+            format!("[{}]", notes.join(", "))
+        };
+        let mut json = String::new();
+        let _ = writeln!(json, "{{");
+        let _ = writeln!(json, "  \"materials\": [");
+        for (index, conversion) in self.materials.iter().enumerate() {
+            let _ = writeln!(json, "    {{");
+            let _ = writeln!(
+                json,
+                "      \"source\": {},",
+                json_string(&conversion.source)
+            );
+            let _ = writeln!(json, "      \"material\": \"{}\",", conversion.material);
+            let _ = writeln!(
+                json,
+                "      \"approximated\": {},",
+                list(&conversion.approximated)
+            );
+            let _ = writeln!(json, "      \"dropped\": {}", list(&conversion.dropped));
+            let separator = if index + 1 < self.materials.len() {
+                ","
+            } else {
+                ""
+            };
+            let _ = writeln!(json, "    }}{}", separator);
+        }
+        let _ = writeln!(json, "  ]");
+        let _ = write!(json, "}}");
+        json
+    }
+}
+
+// Converts every material in an MTL file, read through `assets`, and pushes them to
+// `world_builder`. Texture maps are found relative to the file and decoded by
+// `load_texture`, which can return `None` for any it can't read. Returns a report that
+// also maps each source material's name to its key.
+pub fn import_mtl(
+    path: impl AsRef<Path>,
+    assets: &AssetResolver,
+    world_builder: &mut WorldBuilder,
+    mut load_texture: impl FnMut(&Path) -> Option<Image>,
+) -> io::Result<ConversionReport> {
+    let path = path.as_ref();
+    let source = assets.read(path)?;
+    let (materials, _) = tobj::load_mtl_buf(&mut source.as_slice())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut report = ConversionReport::default();
+    for material in &materials {
+        let conversion = convert(material, world_builder, &mut |map| {
+            load_texture(&directory.join(map))
+        });
+        report.materials.push(conversion);
+    }
+    Ok(report)
+}
+
+fn convert(
+    source: &tobj::Material,
+    world_builder: &mut WorldBuilder,
+    load_texture: &mut dyn FnMut(&str) -> Option<Image>,
+) -> MaterialConversion {
+    let mut approximated = Vec::new();
+    let mut dropped = Vec::new();
+    let param = |name: &str| -> Option<Vec<Float>> {
+        let values = source.unknown_param.get(name)?;
+        values.split_whitespace().map(|v| v.parse().ok()).collect()
+    };
+    let color = |c: [Float; 3]| Rgba::new(c[0], c[1], c[2], 1.0);
+    let rgb = |values: Option<Vec<Float>>| match values.as_deref() {
+        Some([r, g, b, ..]) => Some([*r, *g, *b]),
+        Some([v]) => Some([*v; 3]),
+        _ => None,
+    };
+    let scalar = |values: Option<Vec<Float>>| values.and_then(|v| v.first().copied());
+
+    let emission = rgb(param("Ke")).filter(|e| e.iter().any(|c| *c > 0.0));
+    let metallic = scalar(param("Pm"));
+    let roughness = scalar(param("Pr"));
+    let illum = source.illumination_model.unwrap_or(2);
+    let ior = match source.optical_density > 1.0 {
+        true => source.optical_density,
+        false => 1.5,
+    };
+    // Export writes `Ns` as 1000 for a mirror and 0 for fully rough
+    let shininess_roughness = (1.0 - source.shininess / 1000.0).clamp(0.0, 1.0);
+    let transparent = source.dissolve < 1.0 || matches!(illum, 4 | 6 | 7 | 9);
+    let mirror = matches!(illum, 3 | 5) && source.diffuse.iter().all(|c| *c <= 0.0);
+
+    // The diffuse map, or the diffuse color without one
+    let mut diffuse = |world_builder: &mut WorldBuilder, dropped: &mut Vec<String>| {
+        if !source.diffuse_texture.is_empty() {
+            match load_texture(&source.diffuse_texture) {
+                Some(image) => {
+                    return world_builder.push_texture(Texture::Image {
+                        image,
+                        flipbook: None,
+                    })
+                }
+                None => dropped.push(format!(
+                    "map_Kd {}: could not be loaded, Kd used instead",
+                    source.diffuse_texture
+                )),
+            }
+        }
+        world_builder.push_texture(Texture::Solid {
+            color: color(source.diffuse),
+        })
+    };
+    let solid = |world_builder: &mut WorldBuilder, c: [Float; 3]| -> TextureKey {
+        world_builder.push_texture(Texture::Solid { color: color(c) })
+    };
+
+    let (name, material) = if let Some(emit) = emission {
+        if source
+            .diffuse
+            .iter()
+            .chain(&source.specular)
+            .any(|c| *c > 0.0)
+        {
+            dropped.push("Kd and Ks: lights only emit".into());
+        }
+        let emit = solid(world_builder, emit);
+        (
+            "DiffuseLight",
+            Material::DiffuseLight {
+                emit,
+                intensity: 1.0,
+            },
+        )
+    } else if metallic.is_some() || roughness.is_some() {
+        if source.shininess > 0.0 && roughness.is_none() {
+            approximated.push(format!(
+                "Ns {} as roughness {:.2}",
+                source.shininess, shininess_roughness
+            ));
+        }
+        let base_color = diffuse(world_builder, &mut dropped);
+        (
+            "Principled",
+            Material::Principled {
+                base_color,
+                metallic: metallic.unwrap_or(0.0).clamp(0.0, 1.0),
+                roughness: roughness.unwrap_or(shininess_roughness).clamp(0.0, 1.0),
+                specular: 0.5,
+                ior,
+                transmission: (1.0 - source.dissolve).clamp(0.0, 1.0),
+                emission: None,
+                emission_strength: 0.0,
+                detail: None,
+            },
+        )
+    } else if transparent {
+        if source.dissolve > 0.0 && source.dissolve < 1.0 {
+            approximated.push(format!(
+                "d {}: partial transparency made fully transmissive",
+                source.dissolve
+            ));
+        }
+        if rgb(param("Tf")).is_some_and(|tf| tf.iter().any(|c| *c < 1.0)) {
+            dropped.push("Tf: dielectrics are clear".into());
+        }
+        if source.diffuse.iter().any(|c| *c > 0.0) {
+            dropped.push("Kd: dielectrics have no diffuse layer".into());
+        }
+        match shininess_roughness {
+            r if source.shininess > 0.0 && r > 0.0 => {
+                approximated.push(format!("Ns {} as roughness {:.2}", source.shininess, r));
+                (
+                    "Dielectric",
+                    Material::Dielectric {
+                        ir: Dispersion::Constant(ior),
+                        roughness: r,
+                    },
+                )
+            }
+            _ => ("Dielectric", Material::dielectric(ior)),
+        }
+    } else if mirror {
+        let fuzz = match source.shininess > 0.0 {
+            true => {
+                approximated.push(format!(
+                    "Ns {} as fuzz {:.2}",
+                    source.shininess, shininess_roughness
+                ));
+                shininess_roughness
+            }
+            false => 0.0,
+        };
+        let albedo = solid(world_builder, source.specular);
+        ("Metal", Material::Metal { albedo, fuzz })
+    } else {
+        if source.specular.iter().any(|c| *c > 0.0) && illum != 1 {
+            dropped.push("Ks: Lambertian surfaces have no highlight".into());
+        }
+        let albedo = diffuse(world_builder, &mut dropped);
+        ("Lambertian", Material::Lambertian { albedo })
+    };
+
+    if source.ambient.iter().any(|c| *c > 0.0) {
+        dropped.push("Ka: ambient light comes from the scene".into());
+    }
+    let unused_maps = [
+        ("map_Ka", &source.ambient_texture),
+        ("map_Ks", &source.specular_texture),
+        ("map_Ns", &source.shininess_texture),
+        ("map_d", &source.dissolve_texture),
+        ("norm", &source.normal_texture),
+    ];
+    for (map, file) in unused_maps {
+        if !file.is_empty() {
+            dropped.push(format!("{} {}: only diffuse maps are used", map, file));
+        }
+    }
+    let mut unknown: Vec<&String> = source
+        .unknown_param
+        .keys()
+        .filter(|k| !matches!(k.as_str(), "Ke" | "Pm" | "Pr" | "Tf"))
+        .collect();
+    unknown.sort();
+    for key in unknown {
+        dropped.push(format!("{}: not supported", key));
+    }
+
+    MaterialConversion {
+        source: source.name.clone(),
+        key: world_builder.push_material(material),
+        material: name,
+        approximated,
+        dropped,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mtl_materials_are_mapped_and_reported() {
+        let mtl = "newmtl wall\nKd 0.8 0.8 0.8\nKa 0.1 0.1 0.1\nmap_Kd wall.png\n\n\
+                   newmtl glass\nNi 1.45\nd 0.5\nTf 0.9 1 0.9\nillum 7\n\n\
+                   newmtl chrome\nKd 0 0 0\nKs 0.9 0.9 0.9\nNs 900\nillum 3\n\n\
+                   newmtl lamp\nKe 4 4 3\n\n\
+                   newmtl painted\nKd 1 0 0\nPm 0.2\nPr 0.4\nPc 1\n";
+        let path = std::env::temp_dir().join(format!("razz_import_{}.mtl", std::process::id()));
+        std::fs::write(&path, mtl).unwrap();
+        let mut world_builder = WorldBuilder::default();
+        let report = import_mtl(&path, &AssetResolver::new(), &mut world_builder, |_| None);
+        std::fs::remove_file(&path).unwrap();
+        let report = report.unwrap();
+
+        let kinds: Vec<&str> = report.materials.iter().map(|m| m.material).collect();
+        assert_eq!(
+            kinds,
+            [
+                "Lambertian",
+                "Dielectric",
+                "Metal",
+                "DiffuseLight",
+                "Principled"
+            ]
+        );
+        let by_name = |name| report.materials.iter().find(|m| m.source == name).unwrap();
+        assert_eq!(by_name("wall").dropped.len(), 2);
+        assert!(by_name("glass").approximated[0].starts_with("d 0.5"));
+        assert!(by_name("glass").dropped[0].starts_with("Tf"));
+        assert!(by_name("chrome").approximated[0].contains("fuzz 0.10"));
+        assert_eq!(by_name("painted").dropped, ["Pc: not supported"]);
+        assert_eq!(report.lossy().count(), 4);
+
+        let world: crate::World = world_builder.into();
+        match world.materials()[report.key("glass").unwrap()] {
+            Material::Dielectric { ir, .. } => assert_eq!(ir, Dispersion::Constant(1.45)),
+            _ => unreachable!(),
+        }
+        assert!(report.to_json().contains("\"source\": \"lamp\""));
+    }
+}
@@ -0,0 +1,361 @@
+use crate::spectral::{sample_wavelength, wavelength_weight};
+use crate::technique::PathContributions;
+use crate::{Float, HitRecord, Ray3A, RenderMode, Rgba, SampleDimension, Sampler, Scene};
+
+use rand::Rng;
+
+use std::fmt::Debug;
+
+// Light transport for camera rays. Every renderer hands its camera rays to one,
+// `PathIntegrator` unless told otherwise, without knowing how it's computed. `sampler`
+// is already set up for the pixel and sample, and has drawn the camera ray. Every ray
+// traced is added to `rays`, for progress and reports.
+pub trait Integrator: Debug + Send + Sync {
+    fn li(&self, scene: &Scene, ray: &Ray3A, sampler: &mut Sampler, rays: &mut u64) -> Rgba;
+
+    // Whether `li_from_hit` makes use of camera rays' first hits, which renderers then
+    // trace in packets with their neighbours'
+    fn uses_first_hits(&self) -> bool {
+        false
+    }
+
+    // `li` for a camera ray whose first hit the renderer already found. `contributions`,
+    // when given, receives each light's and technique's share of the result for the AOVs
+    // that split the image by them. Integrators that can't tell leave it empty.
+    fn li_from_hit(
+        &self,
+        scene: &Scene,
+        ray: &Ray3A,
+        _first_hit: Option<HitRecord>,
+        sampler: &mut Sampler,
+        rays: &mut u64,
+        _contributions: Option<&mut PathContributions<'_>>,
+    ) -> Rgba {
+        self.li(scene, ray, sampler, rays)
+    }
+
+    // The bounce limit, when `li` is the path tracer run through `World`'s stages one
+    // bounce at a time. `WavefrontRenderer` then moves whole streams of paths through
+    // the stages, and calls `li` pixel by pixel otherwise.
+    fn path_depth(&self) -> Option<usize> {
+        None
+    }
+}
+
+// The path tracer, with light sampling and the camera's polarizer
+#[derive(Debug, Clone, Copy)]
+pub struct PathIntegrator {
+    pub max_depth: usize,
+}
+
+impl PathIntegrator {
+    // Follows the path a bounce at a time through `World`'s stages, starting from
+    // `first_hit` when it's already known
+    fn follow(
+        &self,
+        scene: &Scene,
+        ray: &Ray3A,
+        mut first_hit: Option<Option<HitRecord>>,
+        sampler: &mut Sampler,
+        rays: &mut u64,
+        mut contributions: Option<&mut PathContributions<'_>>,
+    ) -> Rgba {
+        let world = &scene.world;
+        let mut path = world.start_path(ray, scene.sampler.polarizer(), false);
+        while !path.done && path.bounce < self.max_depth {
+            *rays += 1;
+            let hit = match first_hit.take() {
+                Some(hit) => hit,
+                None => world.intersect_path(&mut path, sampler),
+            };
+            world.shade_path(&mut path, hit, sampler, rays, contributions.as_deref_mut());
+        }
+        path.radiance
+    }
+}
+
+impl Integrator for PathIntegrator {
+    fn li(&self, scene: &Scene, ray: &Ray3A, sampler: &mut Sampler, rays: &mut u64) -> Rgba {
+        self.follow(scene, ray, None, sampler, rays, None)
+    }
+
+    fn uses_first_hits(&self) -> bool {
+        true
+    }
+
+    fn li_from_hit(
+        &self,
+        scene: &Scene,
+        ray: &Ray3A,
+        first_hit: Option<HitRecord>,
+        sampler: &mut Sampler,
+        rays: &mut u64,
+        contributions: Option<&mut PathContributions<'_>>,
+    ) -> Rgba {
+        self.follow(scene, ray, Some(first_hit), sampler, rays, contributions)
+    }
+
+    fn path_depth(&self) -> Option<usize> {
+        Some(self.max_depth)
+    }
+}
+
+// Any of the `RenderMode` views, such as ambient occlusion, with paths ending after
+// `max_depth` bounces. `RenderMode::Path` is `PathIntegrator`.
+#[derive(Debug, Clone, Copy)]
+pub struct ModeIntegrator {
+    pub mode: RenderMode,
+    pub max_depth: usize,
+}
+
+impl ModeIntegrator {
+    fn path(&self) -> PathIntegrator {
+        PathIntegrator {
+            max_depth: self.max_depth,
+        }
+    }
+}
+
+impl Integrator for ModeIntegrator {
+    fn li(&self, scene: &Scene, ray: &Ray3A, sampler: &mut Sampler, rays: &mut u64) -> Rgba {
+        match self.mode {
+            RenderMode::Path => self.path().li(scene, ray, sampler, rays),
+            _ => {
+                let media = &mut sampler.medium_rng(0, ray);
+                let first_hit = scene.world.closest_hit(ray, media).map(|(_, rec)| rec);
+                self.li_from_hit(scene, ray, first_hit, sampler, rays, None)
+            }
+        }
+    }
+
+    fn uses_first_hits(&self) -> bool {
+        true
+    }
+
+    fn li_from_hit(
+        &self,
+        scene: &Scene,
+        ray: &Ray3A,
+        first_hit: Option<HitRecord>,
+        sampler: &mut Sampler,
+        rays: &mut u64,
+        contributions: Option<&mut PathContributions<'_>>,
+    ) -> Rgba {
+        match self.mode {
+            RenderMode::Path => {
+                self.path()
+                    .li_from_hit(scene, ray, first_hit, sampler, rays, contributions)
+            }
+            mode => scene
+                .world
+                .mode_color(mode, ray, first_hit, sampler, self.max_depth, rays),
+        }
+    }
+
+    fn path_depth(&self) -> Option<usize> {
+        match self.mode {
+            RenderMode::Path => Some(self.max_depth),
+            _ => None,
+        }
+    }
+}
+
+// The path tracer with each path carrying one wavelength, drawn uniformly over the
+// visible range and weighted by the CIE color matching functions as it's added to the
+// image. A `Material::Dielectric` with a dispersive IOR bends each wavelength by its
+// own IOR, so white light fans out into colors. Noisier than `PathIntegrator`
+// elsewhere, as every sample only sees part of the spectrum.
+#[derive(Debug, Clone, Copy)]
+pub struct SpectralIntegrator {
+    pub max_depth: usize,
+}
+
+impl Integrator for SpectralIntegrator {
+    fn li(&self, scene: &Scene, ray: &Ray3A, sampler: &mut Sampler, rays: &mut u64) -> Rgba {
+        sampler.start(0, SampleDimension::Wavelength);
+        let wavelength = sample_wavelength(sampler.gen::<Float>());
+
+        let world = &scene.world;
+        let mut path = world.start_path(ray, scene.sampler.polarizer(), false);
+        path.wavelength = Some(wavelength);
+        while !path.done && path.bounce < self.max_depth {
+            *rays += 1;
+            let hit = world.intersect_path(&mut path, sampler);
+            world.shade_path(&mut path, hit, sampler, rays, None);
+        }
+        path.radiance * wavelength_weight(wavelength)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        BandRenderer, Camera, DetailNormal, Dispersion, Face, Image, Material, MissShader,
+        ParallelRenderer, Point3, Primative, ProgressiveRenderer, Texture, Vec3A,
+        WavefrontRenderer, World, WorldBuilder,
+    };
+
+    // Shows the ray direction, so it's easy to tell apart from any real transport, and
+    // claims two rays for each
+    #[derive(Debug)]
+    struct DirectionIntegrator;
+
+    impl Integrator for DirectionIntegrator {
+        fn li(&self, _scene: &Scene, ray: &Ray3A, _sampler: &mut Sampler, rays: &mut u64) -> Rgba {
+            *rays += 2;
+            let d = ray.direction.normalize().abs();
+            Rgba::new(d.x, d.y, d.z, 1.0)
+        }
+    }
+
+    #[test]
+    fn renderers_defer_to_their_integrator() {
+        let scene = crate::scenes::cornell_box(1.0);
+        let (width, height) = (8, 6);
+        let pixels = (width * height) as u64;
+
+        let mut renderer = ParallelRenderer::new(width, height, 4);
+        renderer.set_integrator(DirectionIntegrator);
+        renderer.render(&scene);
+        let direction = renderer.image().clone();
+        assert_eq!(renderer.report(&scene).rays_traced, 2 * pixels);
+        // The camera looks down +z, so the middle is mostly blue
+        let middle = direction.get_pixel_color(width / 2, height / 2).to_array();
+        assert!(middle[2] > 0.9 && middle[0] < 0.2);
+
+        let bands = BandRenderer::new(width, height, 2, 4, 1).with_integrator(DirectionIntegrator);
+        let mut banded = Image::new(width, height);
+        bands.render(&scene, |row_start, band| {
+            let start = row_start * width * 4;
+            banded.data[start..start + band.data.len()].copy_from_slice(&band.data);
+        });
+        assert_eq!(banded.data, direction.data);
+
+        let mut wavefront = WavefrontRenderer::new(width, height, 4);
+        wavefront.set_integrator(DirectionIntegrator);
+        wavefront.render(&scene);
+        assert_eq!(wavefront.image().data, direction.data);
+        assert_eq!(wavefront.report(&scene).rays_traced, 2 * pixels);
+
+        let mut progressive = ProgressiveRenderer::new(width, height, 4);
+        progressive.set_integrator(DirectionIntegrator);
+        progressive.render(&scene, &mut rand::thread_rng());
+        let middle = progressive.image().get_pixel_color(width / 2, height / 2);
+        assert!(middle.to_array()[2] > 0.9);
+        assert_eq!(progressive.report(&scene).rays_traced, 2 * pixels);
+
+        // Render modes are integrators too, with `Path` the default path tracer
+        renderer.set_render_mode(RenderMode::Normals);
+        renderer.render(&scene);
+        assert_ne!(renderer.image().data, direction.data);
+        let mut path = ParallelRenderer::new(width, height, 4);
+        path.render(&scene);
+        renderer.set_render_mode(RenderMode::Path);
+        renderer.render(&scene);
+        assert_eq!(renderer.image().data, path.image().data);
+        assert_eq!(
+            renderer.report(&scene).rays_traced,
+            path.report(&scene).rays_traced
+        );
+        // Paths bounce, so they count more than one ray per pixel
+        assert!(path.report(&scene).rays_traced > pixels);
+    }
+
+    #[test]
+    fn detail_normals_fade_with_distance_from_the_camera() {
+        let mut world_builder = WorldBuilder::default();
+        let grey = world_builder.push_texture(Texture::Solid {
+            color: Rgba::splat(0.5),
+        });
+        let mut bumpy = Material::principled(grey);
+        if let Material::Principled { detail, .. } = &mut bumpy {
+            *detail = Some(DetailNormal {
+                strength: 1.0,
+                scale: 5.0,
+                fade_distance: 1.0,
+            });
+        }
+        let bumpy = world_builder.push_material(bumpy);
+        world_builder.push_hittable(Primative::plane(Point3::ZERO, Vec3A::Y, bumpy));
+        let world: World = world_builder.into();
+        let material = world.materials.get(bumpy).unwrap();
+
+        // How far the normals lean over a patch of the floor, seen from `eye`
+        let lean = |eye: Point3| -> Float {
+            let mut lean = 0.0;
+            for i in 0..8 {
+                for j in 0..8 {
+                    let hit_rec = HitRecord {
+                        point: Point3::new(i as Float * 0.13, 0.0, j as Float * 0.17),
+                        normal: Vec3A::Y,
+                        u: 0.0,
+                        v: 0.0,
+                        face: Face::Front,
+                        material_key: bumpy,
+                    };
+                    lean += 1.0 - world.shading_normal(material, &hit_rec, eye).y;
+                }
+            }
+            lean
+        };
+        assert!(lean(Point3::new(0.0, 0.1, 0.0)) > 0.0);
+        assert!(lean(Point3::new(0.0, 1000.0, 0.0)) < 0.01 * lean(Point3::new(0.0, 0.1, 0.0)));
+
+        // Bounces move the ray but keep the camera's position for the fade
+        let camera_ray = Ray3A {
+            origin: Point3::new(0.0, 1000.0, 0.0),
+            direction: -Vec3A::Y,
+        };
+        let mut path = world.start_path(&camera_ray, None, false);
+        let mut sampler = Sampler::new(0, 0, 0, 0);
+        let hit = world.intersect_path(&mut path, &mut sampler);
+        world.shade_path(&mut path, hit, &mut sampler, &mut 0, None);
+        assert!(!path.done);
+        assert!(path.ray.origin.y < 1.0);
+        assert_eq!(path.eye, camera_ray.origin);
+    }
+
+    #[test]
+    fn dispersive_glass_splits_white_light_by_wavelength() {
+        let mut world_builder = WorldBuilder::default();
+        let glass = world_builder.push_material(Material::dispersive_preset("sf11").unwrap());
+        world_builder.push_hittable(Primative::sphere(Point3::ZERO, 1.0, glass));
+        let camera = Camera::new(-5.0 * Vec3A::Z, Vec3A::ZERO, 40.0, 1.0, 0.0, 1.0);
+        let mut scene = Scene::new(world_builder.into(), camera);
+
+        // A ray half way up passes straight through bent down by 2 (θi - θr), further for
+        // shorter wavelengths. Only directions bent more than green's see a white sky.
+        let incidence = (0.5 as Float).asin();
+        let green = Dispersion::preset("sf11").unwrap().ior(550.0);
+        let deviation = 2.0 * (incidence - (incidence.sin() / green).asin());
+        scene
+            .world
+            .set_miss_shader(Some(MissShader::new(move |ray: &Ray3A| {
+                match ray.direction.normalize().y < -deviation.sin() {
+                    true => Rgba::ONE,
+                    false => Rgba::ZERO,
+                }
+            })));
+
+        let ray = Ray3A {
+            origin: Point3::new(0.0, 0.5, -5.0),
+            direction: Vec3A::Z,
+        };
+        let average = |integrator: &dyn Integrator| {
+            let samples = 4000;
+            let mut sum = Rgba::ZERO;
+            for s in 0..samples {
+                let mut sampler = Sampler::new(0, 0, s, 0);
+                sum = sum + integrator.li(&scene, &ray, &mut sampler, &mut 0);
+            }
+            (sum * (1.0 / samples as Float)).to_array()
+        };
+
+        let spectral = average(&SpectralIntegrator { max_depth: 8 });
+        assert!(spectral[2] > 2.0 * spectral[0] && spectral[2] > 0.2);
+        // Every channel refracts alike without wavelengths
+        let path = average(&PathIntegrator { max_depth: 8 });
+        assert_eq!(path[0], path[2]);
+    }
+}
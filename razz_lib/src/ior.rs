@@ -0,0 +1,168 @@
+use crate::material::Material;
+use crate::Float;
+
+// Wavelength of the sodium d-line, used where a single IOR is needed
+pub const D_LINE_NM: Float = 589.3;
+
+// Saved untagged, so a constant IOR is a plain number
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(untagged))]
+pub enum Dispersion {
+    Constant(Float),
+    // n = a + b / λ², with λ in micrometers
+    Cauchy { a: Float, b: Float },
+    // n² = 1 + Σ bᵢλ² / (λ² - cᵢ), with λ in micrometers
+    Sellmeier { b: [Float; 3], c: [Float; 3] },
+}
+
+impl Dispersion {
+    pub fn ior(&self, wavelength_nm: Float) -> Float {
+        let l = wavelength_nm / 1000.0;
+        let l2 = l * l;
+        match self {
+            Self::Constant(ior) => *ior,
+            Self::Cauchy { a, b } => a + b / l2,
+            Self::Sellmeier { b, c } => {
+                let n2 = 1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<Float>();
+                n2.max(1.0).sqrt()
+            }
+        }
+    }
+
+    pub fn preset(name: &str) -> Option<Self> {
+        IOR_PRESETS
+            .iter()
+            .find(|(preset, _)| preset.eq_ignore_ascii_case(name))
+            .map(|(_, dispersion)| *dispersion)
+    }
+}
+
+pub const IOR_PRESETS: [(&str, Dispersion); 9] = [
+    ("air", Dispersion::Constant(1.000293)),
+    (
+        "water",
+        Dispersion::Cauchy {
+            a: 1.3246,
+            b: 0.00313,
+        },
+    ),
+    ("ice", Dispersion::Constant(1.309)),
+    (
+        "fused_silica",
+        Dispersion::Sellmeier {
+            b: [0.6961663, 0.4079426, 0.8974794],
+            c: [0.004679148, 0.01351206, 97.934003],
+        },
+    ),
+    (
+        "bk7",
+        Dispersion::Sellmeier {
+            b: [1.0396122, 0.23179235, 1.0104694],
+            c: [0.0060006985, 0.020017914, 103.56065],
+        },
+    ),
+    (
+        "sf11",
+        Dispersion::Sellmeier {
+            b: [1.737597, 0.31374735, 1.8987811],
+            c: [0.013188707, 0.062306814, 155.2363],
+        },
+    ),
+    (
+        "sapphire",
+        Dispersion::Sellmeier {
+            b: [1.4313493, 0.65054713, 5.341402],
+            c: [0.005279926, 0.014238264, 325.01782],
+        },
+    ),
+    (
+        "diamond",
+        Dispersion::Sellmeier {
+            b: [0.3306, 4.3356, 0.0],
+            c: [0.030625, 0.011236, 0.0],
+        },
+    ),
+    (
+        "cubic_zirconia",
+        Dispersion::Cauchy {
+            a: 2.1236,
+            b: 0.0245,
+        },
+    ),
+];
+
+// Complex IOR (eta, k) sampled at roughly 650, 550 and 450 nm
+pub const CONDUCTOR_PRESETS: [(&str, [Float; 3], [Float; 3]); 5] = [
+    ("gold", [0.143, 0.374, 1.442], [3.983, 2.385, 1.603]),
+    ("silver", [0.155, 0.117, 0.138], [4.828, 3.122, 2.147]),
+    ("copper", [0.200, 0.924, 1.102], [3.912, 2.452, 2.142]),
+    ("aluminum", [1.657, 0.880, 0.521], [9.224, 6.270, 4.837]),
+    ("iron", [2.911, 2.950, 2.585], [3.089, 2.932, 2.767]),
+];
+
+impl Material {
+    pub fn dielectric_preset(name: &str) -> Option<Self> {
+        Dispersion::preset(name).map(|dispersion| Self::dielectric(dispersion.ior(D_LINE_NM)))
+    }
+
+    pub fn dispersive_preset(name: &str) -> Option<Self> {
+        Dispersion::preset(name).map(|ir| Self::Dielectric { ir, roughness: 0.0 })
+    }
+
+    pub fn conductor_preset(name: &str, fuzz: Float) -> Option<Self> {
+        CONDUCTOR_PRESETS
+            .iter()
+            .find(|(preset, _, _)| preset.eq_ignore_ascii_case(name))
+            .map(|(_, eta, k)| Self::Conductor {
+                eta: *eta,
+                k: *k,
+                fuzz,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_match_measured_d_line() {
+        let expected = [
+            ("bk7", 1.5168),
+            ("water", 1.333),
+            ("diamond", 2.417),
+            ("sapphire", 1.768),
+            ("fused_silica", 1.4585),
+        ];
+        for (name, ior) in expected.iter() {
+            let n = Dispersion::preset(name).unwrap().ior(D_LINE_NM);
+            assert!((n - ior).abs() < 0.005, "{}: {}", name, n);
+        }
+    }
+
+    #[test]
+    fn conductors_brighten_towards_grazing() {
+        use crate::material::conductor_reflectance;
+
+        let (eta, k) = (0.143, 3.983);
+        let normal = ((eta - 1.0) * (eta - 1.0) + k * k) / ((eta + 1.0) * (eta + 1.0) + k * k);
+        assert!((conductor_reflectance(1.0, eta, k) - normal).abs() < 1e-4);
+        assert!(conductor_reflectance(0.01, eta, k) > 0.99);
+
+        match Material::conductor_preset("Gold", 0.0) {
+            Some(Material::Conductor { eta, k, .. }) => {
+                let red = conductor_reflectance(1.0, eta[0], k[0]);
+                let blue = conductor_reflectance(1.0, eta[2], k[2]);
+                assert!(red > blue);
+            }
+            _ => panic!("missing gold preset"),
+        }
+    }
+
+    #[test]
+    fn glass_disperses_blue_more_than_red() {
+        let bk7 = Dispersion::preset("BK7").unwrap();
+        assert!(bk7.ior(450.0) > bk7.ior(650.0));
+    }
+}
@@ -0,0 +1,354 @@
+use crate::arena::with_arena;
+use crate::image::Rgba;
+use crate::material::Material;
+use crate::{Float, MediumRng, Point3, Ray3A, Sampler, Scene, Vec3A, World};
+
+use boxtree::Bounded;
+use rand::Rng;
+use std::collections::HashMap;
+
+const PI: Float = std::f64::consts::PI as Float;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IrradianceSettings {
+    // Ward's accuracy `a`, smaller values place records closer together
+    pub error: Float,
+    // Closest and furthest a record may reach, as fractions of the scene's diagonal
+    pub min_spacing: Float,
+    pub max_spacing: Float,
+    pub theta_samples: usize,
+    pub phi_samples: usize,
+    pub max_depth: usize,
+}
+
+impl Default for IrradianceSettings {
+    fn default() -> Self {
+        Self {
+            error: 0.2,
+            min_spacing: 0.002,
+            max_spacing: 0.05,
+            theta_samples: 16,
+            phi_samples: 32,
+            max_depth: 5,
+        }
+    }
+}
+
+// Indirect irradiance at one surface point, with its rotation and translation
+// gradients per color channel (Ward and Heckbert 1992)
+#[derive(Debug, Clone)]
+struct IrradianceRecord {
+    point: Point3,
+    normal: Vec3A,
+    irradiance: [Float; 3],
+    rotation: [Vec3A; 3],
+    translation: [Vec3A; 3],
+    // Harmonic mean distance to the surroundings, clamped to the spacing limits
+    radius: Float,
+}
+
+// Sparse cache of diffuse indirect lighting (Ward et al. 1988). Records are placed by
+// `populate` and interpolated wherever their error estimate allows, so slowly varying
+// lighting costs a handful of records instead of a path per pixel. Direct light from
+// registered area lights isn't cached, it's still sampled per path.
+#[derive(Debug, Clone)]
+pub struct IrradianceCache {
+    settings: IrradianceSettings,
+    records: Vec<IrradianceRecord>,
+    cells: HashMap<(i32, i32, i32), Vec<usize>>,
+    cell_size: Float,
+    min_radius: Float,
+    max_radius: Float,
+}
+
+impl IrradianceCache {
+    pub fn new(settings: IrradianceSettings) -> Self {
+        Self {
+            settings,
+            records: Vec::new(),
+            cells: HashMap::new(),
+            cell_size: 1.0,
+            min_radius: 0.0,
+            max_radius: Float::INFINITY,
+        }
+    }
+
+    pub fn settings(&self) -> IrradianceSettings {
+        self.settings
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    // Places records over the diffuse surfaces seen directly by the camera, traced at
+    // `width` by `height`. A lower resolution than the final image is usually enough.
+    pub fn populate(&mut self, scene: &Scene, width: usize, height: usize, rng: &mut impl Rng) {
+        let bounds = scene.world.bvh.bounds();
+        let diagonal = (bounds.max - bounds.min).length();
+        let error = self.settings.error.clamp(Float::EPSILON, 1.0);
+        self.min_radius = self.settings.min_spacing * diagonal / error;
+        self.max_radius = (self.settings.max_spacing * diagonal / error).max(self.min_radius);
+        // No record reaches further than `error * max_radius`
+        let cell_size = (error * self.max_radius).max(Float::EPSILON);
+        if cell_size != self.cell_size {
+            self.cell_size = cell_size;
+            self.rebuild_cells();
+        }
+
+        let seed = rng.gen();
+        for y in 0..height {
+            for x in 0..width {
+                let mut sampler = Sampler::new(x, y, 0, seed);
+                let ray = scene.sampler.get_ray(x, y, width, height, &mut sampler);
+                let rec = match scene
+                    .world
+                    .closest_hit(&ray, &mut sampler.medium_rng(0, &ray))
+                {
+                    Some((_, rec)) => rec,
+                    None => continue,
+                };
+                match scene.world.materials.get(rec.material_key) {
+                    Some(Material::Lambertian { .. }) => {}
+                    _ => continue,
+                }
+                if self.irradiance(rec.point, rec.normal).is_none() {
+                    let record = self.gather(&scene.world, rec.point, rec.normal, rng);
+                    self.insert(record);
+                }
+            }
+        }
+    }
+
+    // Interpolated indirect irradiance, or `None` where no record is close enough
+    pub fn irradiance(&self, point: Point3, normal: Vec3A) -> Option<Rgba> {
+        let inv_error = 1.0 / self.settings.error.clamp(Float::EPSILON, 1.0);
+        let (cx, cy, cz) = self.cell(point);
+
+        let mut total = [0.0; 3];
+        let mut total_weight = 0.0;
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let cell = match self.cells.get(&(cx + dx, cy + dy, cz + dz)) {
+                        Some(cell) => cell,
+                        None => continue,
+                    };
+                    for record in cell.iter().map(|i| &self.records[*i]) {
+                        let offset = point - record.point;
+                        // Records in front of the point see a different neighbourhood
+                        if offset.dot(0.5 * (normal + record.normal)) < -0.05 * record.radius {
+                            continue;
+                        }
+                        let error = offset.length() / record.radius
+                            + (1.0 - normal.dot(record.normal)).max(0.0).sqrt();
+                        if error * inv_error >= 1.0 {
+                            continue;
+                        }
+
+                        let weight = 1.0 / error.max(1e-6);
+                        let rotation = record.normal.cross(normal);
+                        for (c, total) in total.iter_mut().enumerate() {
+                            let estimate = record.irradiance[c]
+                                + rotation.dot(record.rotation[c])
+                                + offset.dot(record.translation[c]);
+                            *total += weight * estimate.max(0.0);
+                        }
+                        total_weight += weight;
+                    }
+                }
+            }
+        }
+
+        match total_weight > 0.0 {
+            true => Some(Rgba::new(
+                total[0] / total_weight,
+                total[1] / total_weight,
+                total[2] / total_weight,
+                1.0,
+            )),
+            false => None,
+        }
+    }
+
+    fn gather(
+        &self,
+        world: &World,
+        point: Point3,
+        normal: Vec3A,
+        rng: &mut impl Rng,
+    ) -> IrradianceRecord {
+        let (m, n) = (
+            self.settings.theta_samples.max(2),
+            self.settings.phi_samples.max(3),
+        );
+        let tangent = normal.any_orthonormal_vector();
+        let bitangent = normal.cross(tangent);
+        let around = |phi: Float| phi.cos() * tangent + phi.sin() * bitangent;
+
+        // Stratified cosine weighted directions, j over elevation and k over azimuth
+        with_arena(|arena| {
+            let radiance = arena.alloc_slice_fill_copy(m * n, [0.0; 3]);
+            let distance = arena.alloc_slice_fill_copy(m * n, Float::INFINITY);
+            let mut inv_distance_sum = 0.0;
+            for j in 0..m {
+                for k in 0..n {
+                    let theta = ((j as Float + rng.gen::<Float>()) / m as Float)
+                        .sqrt()
+                        .asin();
+                    let phi = 2.0 * PI * (k as Float + rng.gen::<Float>()) / n as Float;
+                    let direction = theta.sin() * around(phi) + theta.cos() * normal;
+                    let ray = Ray3A {
+                        origin: point,
+                        direction,
+                    };
+
+                    let media = &mut MediumRng::new(&ray, rng.gen());
+                    if let Some((t, _)) = world.closest_hit(&ray, media) {
+                        distance[j * n + k] = t;
+                        inv_distance_sum += 1.0 / t;
+                    }
+                    let mut sampler = Sampler::new(j, k, 0, rng.gen());
+                    let color = world.trace(
+                        &ray,
+                        None,
+                        &mut sampler,
+                        self.settings.max_depth,
+                        &mut 0,
+                        None,
+                        true,
+                    );
+                    let [r, g, b, _] = color.to_array();
+                    radiance[j * n + k] = [r, g, b];
+                }
+            }
+
+            let scale = PI / (m * n) as Float;
+            let mut irradiance = [0.0; 3];
+            let mut rotation = [Vec3A::ZERO; 3];
+            let mut translation = [Vec3A::ZERO; 3];
+            let theta_edge = |j: usize| (j as Float / m as Float).sqrt().asin();
+            for k in 0..n {
+                let phi = 2.0 * PI * (k as Float + 0.5) / n as Float;
+                let phi_edge = 2.0 * PI * k as Float / n as Float;
+                let u = around(phi);
+                let v = around(phi + 0.5 * PI);
+                let v_edge = around(phi_edge + 0.5 * PI);
+                let previous_k = (k + n - 1) % n;
+
+                for j in 0..m {
+                    let theta_mid = ((j as Float + 0.5) / m as Float).sqrt().asin();
+                    let (lower, upper) = (theta_edge(j), theta_edge(j + 1));
+                    let l = radiance[j * n + k];
+                    let l_phi = radiance[j * n + previous_k];
+                    let r_phi = distance[j * n + k].min(distance[j * n + previous_k]);
+
+                    for c in 0..3 {
+                        irradiance[c] += scale * l[c];
+                        rotation[c] += -scale * theta_mid.tan() * l[c] * v;
+                        // Change across the azimuthal cell wall
+                        translation[c] += v_edge * (lower.cos() - upper.cos())
+                            / (theta_mid.sin() * r_phi)
+                            * (l[c] - l_phi[c]);
+                    }
+
+                    if j > 0 {
+                        // Change across the elevation cell wall
+                        let l_theta = radiance[(j - 1) * n + k];
+                        let r_theta = distance[j * n + k].min(distance[(j - 1) * n + k]);
+                        for c in 0..3 {
+                            translation[c] += u
+                                * (2.0 * PI / n as Float)
+                                * lower.sin()
+                                * lower.cos()
+                                * lower.cos()
+                                / r_theta
+                                * (l[c] - l_theta[c]);
+                        }
+                    }
+                }
+            }
+
+            let radius = match inv_distance_sum > 0.0 {
+                true => (m * n) as Float / inv_distance_sum,
+                false => self.max_radius,
+            };
+            IrradianceRecord {
+                point,
+                normal,
+                irradiance,
+                rotation,
+                translation,
+                radius: radius.clamp(self.min_radius, self.max_radius),
+            }
+        })
+    }
+
+    fn insert(&mut self, record: IrradianceRecord) {
+        let cell = self.cell(record.point);
+        self.cells.entry(cell).or_default().push(self.records.len());
+        self.records.push(record);
+    }
+
+    fn rebuild_cells(&mut self) {
+        self.cells.clear();
+        for (index, record) in self.records.iter().enumerate() {
+            let cell = self.cell(record.point);
+            self.cells.entry(cell).or_default().push(index);
+        }
+    }
+
+    fn cell(&self, point: Point3) -> (i32, i32, i32) {
+        let cell = (point / self.cell_size).floor();
+        (cell.x as i32, cell.y as i32, cell.z as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Camera, Primative, Texture, WorldBuilder};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn interpolates_within_error_bound() {
+        // A diffuse floor under a glowing ceiling that isn't a registered light
+        let mut world_builder = WorldBuilder::default();
+        let grey = world_builder.push_texture(Texture::Solid {
+            color: Rgba::splat(0.5),
+        });
+        let white = world_builder.push_texture(Texture::Solid { color: Rgba::ONE });
+        let floor = world_builder.push_material(Material::Lambertian { albedo: grey });
+        let glow = world_builder.push_material(Material::DiffuseLight {
+            emit: white,
+            intensity: 1.0,
+        });
+        world_builder.push_hittable(Primative::sphere(
+            Vec3A::new(0.0, -1000.0, 0.0),
+            1000.0,
+            floor,
+        ));
+        world_builder.push_hittable(Primative::sphere(Vec3A::ZERO, 50.0, glow));
+        let camera = Camera::new(Vec3A::new(0.0, 5.0, 5.0), Vec3A::ZERO, 60.0, 1.0, 0.0, 1.0);
+        let scene = Scene::new(world_builder.into(), camera);
+
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut cache = IrradianceCache::new(IrradianceSettings {
+            max_depth: 1,
+            ..Default::default()
+        });
+        cache.populate(&scene, 8, 8, &mut rng);
+        assert!(!cache.is_empty() && cache.len() < 64);
+
+        // Inside the glowing sphere the floor sees radiance one over its upper hemisphere
+        let irradiance = cache.irradiance(Vec3A::ZERO, Vec3A::Y).unwrap();
+        assert!((irradiance.to_array()[0] - PI).abs() < 0.05 * PI);
+        assert!(cache
+            .irradiance(Vec3A::new(0.0, 40.0, 0.0), Vec3A::Y)
+            .is_none());
+    }
+}
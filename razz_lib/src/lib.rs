@@ -1,50 +1,178 @@
+mod arena;
+mod assets;
+mod atlas;
+mod auto;
+mod background;
+mod binding;
+mod blue_noise;
 mod camera;
+mod checkpoint;
+pub mod demo;
+mod denoise;
+mod diff;
+mod export;
+pub mod flatten;
+#[cfg(test)]
+mod fuzz;
+mod gpu_material;
+mod gpu_scene;
 mod image;
+mod import;
+mod integrator;
+mod ior;
+mod irradiance;
+mod light;
+mod macros;
 mod material;
+mod microfacet;
 mod noise;
+mod pack;
+mod packet;
+mod polarization;
+mod post;
+mod progress;
 mod render;
+mod render_mode;
+mod report;
+mod sampler;
+pub mod scenes;
+#[cfg(feature = "serde")]
+mod serialize;
 mod shape;
+mod spectral;
+mod sun;
+mod technique;
 mod texture;
+pub mod thumbnails;
+mod tiled;
 mod traits;
+mod traversal;
+mod wavefront;
 
 pub use boxtree::Ray3A;
-use boxtree::{Bvh3A, RayHittable};
-use rand::Rng;
-use slotmap::{new_key_type, SlotMap};
+use packet::{RayPacket, PACKET_WIDTH};
+use polarization::PolarizationState;
+use rand::{Rng, SeedableRng};
+use slotmap::{new_key_type, SecondaryMap};
+use std::sync::Arc;
+use traversal::SceneBvh;
 
+// For implementing `CustomShape` and `Bsdf`
+pub use boxtree::{Bounded, Bounds3A, RayHittable};
+pub use rand::RngCore;
+pub use slotmap::SlotMap;
+
+pub use assets::*;
+pub use atlas::*;
+pub use auto::*;
+pub use background::*;
+pub use binding::*;
 pub use camera::*;
+pub use checkpoint::*;
+pub use denoise::*;
+pub use diff::*;
+pub use export::*;
+pub use gpu_material::*;
+pub use gpu_scene::*;
 pub use image::*;
+pub use import::*;
+pub use integrator::*;
+pub use ior::*;
+pub use irradiance::*;
+pub use light::*;
 pub use material::*;
+pub use noise::*;
+pub use pack::*;
+pub use polarization::Polarizer;
+pub use post::*;
+pub use progress::*;
 pub use render::*;
+pub use render_mode::*;
+pub use report::*;
+pub use sampler::*;
 pub use shape::*;
+pub use spectral::*;
+pub use sun::*;
+pub use technique::*;
 pub use texture::*;
+pub use tiled::*;
 pub use traits::*;
+pub use wavefront::*;
 
 pub use glam::Vec3A;
 pub type Point3 = Vec3A;
 
 pub type Float = f32;
 
+const PI: Float = std::f64::consts::PI as Float;
+
 new_key_type! { pub struct PrimativeKey; }
 new_key_type! { pub struct MaterialKey; }
 new_key_type! { pub struct TextureKey; }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Scene {
     pub world: World,
     pub sampler: Camera,
+    pub post: PostPipeline,
 }
 
 impl Scene {
     pub fn new(world: World, sampler: Camera) -> Self {
-        Self { world, sampler }
+        Self {
+            world,
+            sampler,
+            post: PostPipeline::new(),
+        }
+    }
+
+    // Focuses the camera on the first surface toward `look_at`, or toward the image
+    // point (`u`, `v`) in [0, 1] from the top left. Returns the new focus distance,
+    // or `None` if the ray misses everything and the focus is left alone.
+    pub fn autofocus(&mut self, screen_point: Option<(Float, Float)>) -> Option<Float> {
+        let camera = &self.sampler;
+        let ray = match screen_point {
+            Some((u, v)) => camera.pinhole_ray(u, v),
+            None => Ray3A {
+                origin: camera.look_from(),
+                direction: camera.look_at() - camera.look_from(),
+            },
+        };
+        // A single ray with nothing to average, so media scatter wherever the ray says
+        let (_, rec) = self.world.closest_hit(&ray, &mut MediumRng::new(&ray, 0))?;
+
+        // Measured along the view axis, as the focus plane is parallel to the image
+        let axis = (camera.look_at() - camera.look_from()).normalize();
+        let distance = (rec.point - camera.look_from()).dot(axis);
+        if distance <= 0.0 {
+            return None;
+        }
+        self.sampler.set_focus_distance(distance);
+        Some(distance)
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DetailCulling {
+    pub eye: Point3,
+    pub min_projected_size: Float,
+    pub keep_probability: Float,
+}
+
 #[derive(Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WorldBuilder {
     textures: SlotMap<TextureKey, Texture>,
     materials: SlotMap<MaterialKey, Material>,
     hittables: Vec<Primative>,
+    lights: Vec<AreaLight>,
+    detail_culling: Option<DetailCulling>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    background: Background,
+    // Indices into `hittables` of instances that can be moved once built
+    #[cfg_attr(feature = "serde", serde(default))]
+    instances: SlotMap<PrimativeKey, usize>,
 }
 
 impl WorldBuilder {
@@ -53,9 +181,24 @@ impl WorldBuilder {
             textures: SlotMap::default(),
             materials: SlotMap::default(),
             hittables: Vec::new(),
+            lights: Vec::new(),
+            detail_culling: None,
+            background: Background::default(),
+            instances: SlotMap::default(),
         }
     }
 
+    pub fn set_background(&mut self, background: Background) {
+        self.background = background;
+    }
+
+    // Primitives whose bounding sphere subtends less than `min_projected_size` radians from
+    // `eye` are only intersected by secondary rays with probability `keep_probability`.
+    // The rest end where they meet one, so the image stays unbiased but noisier.
+    pub fn set_detail_culling(&mut self, detail_culling: DetailCulling) {
+        self.detail_culling = Some(detail_culling);
+    }
+
     pub fn push_texture(&mut self, texture: Texture) -> TextureKey {
         self.textures.insert(texture)
     }
@@ -67,47 +210,840 @@ impl WorldBuilder {
     pub fn push_hittable(&mut self, primative: Primative) {
         self.hittables.push(primative)
     }
+
+    // Places a shared object like `Primative::instance`, with a key to move it by
+    // through `World::move_instances` once the world is built
+    pub fn push_instance(&mut self, object: Arc<Primative>, transform: Transform) -> PrimativeKey {
+        let key = self.instances.insert(self.hittables.len());
+        self.hittables.push(Primative::instance(object, transform));
+        key
+    }
+
+    // Traces `splits` shadow rays, averaged, each time the light at `index`, as returned
+    // by `push_area_light`, is picked. Small bright lights are noisy to sample with one,
+    // and splitting quiets them without the bias of clamping.
+    pub fn set_light_splits(&mut self, index: usize, splits: usize) {
+        if let Some(light) = self.lights.get_mut(index) {
+            light.set_splits(splits);
+        }
+    }
+
+    // Checks what can't be caught as pieces are pushed, like textures that reference
+    // each other in a loop, or a light's material also used by other geometry. Worlds
+    // built anyway render the loop magenta and take that geometry for the light.
+    pub fn validate(&self) -> std::io::Result<()> {
+        if let Some(cycle) = texture_cycle(&self.textures) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{} textures reference each other in a cycle", cycle.len()),
+            ));
+        }
+        match uv_cached_textures(&self.textures) {
+            0 => {}
+            count => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("{} cached textures wrap textures that read uv", count),
+                ))
+            }
+        }
+
+        // A path is matched to the light it hits by material, which the light's own
+        // mesh accounts for once
+        let used: Vec<_> = self
+            .hittables
+            .iter()
+            .flat_map(|primative| primative.material_keys())
+            .collect();
+        let shared = self.lights.iter().filter(|light| {
+            used.iter()
+                .filter(|key| **key == light.material_key())
+                .count()
+                > 1
+        });
+        match shared.count() {
+            0 => Ok(()),
+            count => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{} lights share their material with other geometry", count),
+            )),
+        }
+    }
+
+    // Validates, then builds. Converting with `into` skips validation, leaving the
+    // texture depth cap as the only guard against cycles.
+    pub fn build(self) -> std::io::Result<World> {
+        self.validate()?;
+        Ok(self.into())
+    }
+
+    // The mesh's material should be emissive and used by nothing else, which `validate`
+    // checks. Meshes without a finite, positive area can't be sampled and are only hittable.
+    // Returns the light's index in `World::lights`, or `None` for those that are only hittable.
+    pub fn push_area_light(&mut self, mesh: Arc<Mesh>, two_sided: bool) -> Option<usize> {
+        let light = AreaLight::new(mesh.triangles().collect(), mesh.material_key(), two_sided);
+        self.hittables.push(Primative::Mesh(mesh));
+        if light.area() > 0.0 && light.area().is_finite() {
+            self.lights.push(light);
+            return Some(self.lights.len() - 1);
+        }
+        None
+    }
 }
 
 #[derive(Debug)]
 pub struct World {
     textures: SlotMap<TextureKey, Texture>,
     materials: SlotMap<MaterialKey, Material>,
-    bvh: Bvh3A<Primative>,
+    // Fixed, so the bumps stay put between renders
+    detail_noise: PerlinData,
+    bvh: SceneBvh,
+    // Tested one by one after the BVH
+    unbounded: Vec<Primative>,
+    detail: Option<(SceneBvh, Float)>,
+    lights: Vec<AreaLight>,
+    // The light of each emissive material, found when a path hits it. Validation keeps
+    // light materials off every other primitive.
+    light_indices: SecondaryMap<MaterialKey, usize>,
+    light_area: Float,
+    epsilon: Float,
+    irradiance_cache: Option<IrradianceCache>,
+    background: Background,
+    miss_shader: Option<MissShader>,
+    instances: SecondaryMap<PrimativeKey, Placement>,
+
+    // Kept so the world can be written back out in builder form
+    #[cfg(feature = "serde")]
+    hittables: Vec<Primative>,
+    #[cfg(feature = "serde")]
+    detail_culling: Option<DetailCulling>,
+    #[cfg(feature = "serde")]
+    instance_keys: SlotMap<PrimativeKey, usize>,
+}
+
+// Where a top-level primitive ended up, and its index there
+#[derive(Debug, Clone, Copy)]
+enum Placement {
+    Scene(usize),
+    Detail(usize),
+    Unbounded(usize),
 }
 
 impl World {
-    fn ray_color(&self, ray_in: &Ray3A, rng: &mut impl Rng, depth: usize) -> Rgba {
-        if depth <= 0 {
+    pub fn set_time(&mut self, time: Float) {
+        for texture in self.textures.values_mut() {
+            if let Texture::Image {
+                flipbook: Some(flipbook),
+                ..
+            } = texture
+            {
+                flipbook.set_time(time);
+            }
+        }
+
+        // Water is rebuilt for the new time, then the hierarchies holding it
+        let bvhs = std::iter::once(&mut self.bvh).chain(self.detail.as_mut().map(|(bvh, _)| bvh));
+        for bvh in bvhs {
+            let mut moved = false;
+            for index in 0..bvh.primatives().count() {
+                if let Primative::Water(water) = bvh.primative(index) {
+                    let mut water = water.clone();
+                    water.set_time(time);
+                    bvh.replace(index, Primative::Water(water));
+                    moved = true;
+                }
+            }
+            if moved {
+                bvh.rebuild();
+            }
+        }
+    }
+
+    // Minimum ray distance, scaled to the scene so neither huge nor tiny scenes self-intersect
+    pub fn epsilon(&self) -> Float {
+        self.epsilon
+    }
+
+    pub fn textures(&self) -> &SlotMap<TextureKey, Texture> {
+        &self.textures
+    }
+
+    pub fn materials(&self) -> &SlotMap<MaterialKey, Material> {
+        &self.materials
+    }
+
+    // Top-level primitives, whichever structure they ended up in
+    pub fn primitives(&self) -> impl Iterator<Item = &Primative> + '_ {
+        let detail = self
+            .detail
+            .iter()
+            .flat_map(|(detail_bvh, _)| detail_bvh.primatives());
+        self.bvh
+            .primatives()
+            .chain(detail)
+            .chain(self.unbounded.iter())
+    }
+
+    pub fn primitive_count(&self) -> usize {
+        self.primitives().count()
+    }
+
+    // Around everything but unbounded primitives like infinite planes
+    pub fn bounds(&self) -> Bounds3A {
+        let bounds = self.bvh.bounds();
+        match &self.detail {
+            Some((detail_bvh, _)) => {
+                let detail = detail_bvh.bounds();
+                Bounds3A::new(bounds.min.min(detail.min), bounds.max.max(detail.max))
+            }
+            None => bounds,
+        }
+    }
+
+    pub fn missing_textures(&self) -> usize {
+        let mut keys = Vec::new();
+        for material in self.materials.values() {
+            match material {
+                Material::Lambertian { albedo }
+                | Material::Metal { albedo, .. }
+                | Material::RoughMetal { albedo, .. }
+                | Material::Isotropic { albedo } => keys.push(*albedo),
+                Material::DiffuseLight { emit, .. } => keys.push(*emit),
+                Material::Principled {
+                    base_color,
+                    emission,
+                    ..
+                } => keys.extend(std::iter::once(*base_color).chain(*emission)),
+                Material::Dielectric { .. }
+                | Material::Water { .. }
+                | Material::Conductor { .. }
+                | Material::Custom(_) => {}
+            }
+        }
+        for texture in self.textures.values() {
+            match texture {
+                Texture::Checker { odd, even, .. } => {
+                    keys.push(*odd);
+                    keys.push(*even);
+                }
+                Texture::Cached { texture, .. } | Texture::Transformed { texture, .. } => {
+                    keys.push(*texture)
+                }
+                _ => {}
+            }
+        }
+
+        keys.iter()
+            .filter(|key| !self.textures.contains_key(**key))
+            .count()
+    }
+
+    pub fn background(&self) -> &Background {
+        &self.background
+    }
+
+    pub fn set_background(&mut self, background: Background) {
+        self.background = background;
+    }
+
+    // Rays that miss everything take their color from `shader` instead of the
+    // background. Saved scenes and the GPU only know the background.
+    pub fn set_miss_shader(&mut self, shader: Option<MissShader>) {
+        self.miss_shader = shader;
+    }
+
+    fn miss_color(&self, ray: &Ray3A) -> Rgba {
+        match &self.miss_shader {
+            Some(shader) => shader.color(ray),
+            None => self.background.color(ray.direction),
+        }
+    }
+
+    // Diffuse surfaces first reached by a camera path take their indirect lighting from
+    // the cache wherever it has a close enough record. Populate it before attaching it.
+    pub fn set_irradiance_cache(&mut self, cache: Option<IrradianceCache>) {
+        self.irradiance_cache = cache;
+    }
+
+    pub fn irradiance_cache(&self) -> Option<&IrradianceCache> {
+        self.irradiance_cache.as_ref()
+    }
+
+    // Albedo and normal at the first surface along `ray`, or zero on a miss. Media
+    // draw from `sampler` as the path's first bounce would.
+    pub fn first_hit(&self, ray: &Ray3A, sampler: &mut Sampler) -> (Rgba, Vec3A) {
+        match self.closest_hit(ray, &mut sampler.medium_rng(0, ray)) {
+            Some((_, rec)) => match self.materials.get(rec.material_key) {
+                Some(material) => (
+                    material.albedo(rec.u, rec.v, rec.point, &self.textures),
+                    rec.normal,
+                ),
+                None => (Rgba::ZERO, rec.normal),
+            },
+            None => (Rgba::ZERO, Vec3A::ZERO),
+        }
+    }
+
+    // Opt-in for static scenes rendered over many passes. Counts which primitives the
+    // first `ray_budget` rays hit, then orders the BVH to visit the most hit first for
+    // every later ray, which lets the traversal skip what's behind them.
+    pub fn enable_traversal_profile(&mut self, ray_budget: u64) {
+        self.bvh.enable_profile(ray_budget);
+    }
+
+    // Moves instances pushed with `WorldBuilder::push_instance`, skipping keys that
+    // aren't in this world. The objects keep the hierarchies they were built with, so
+    // only the top-level BVH over them is rebuilt, once per call. Instances stay in
+    // the structure they were first sorted into, detail culling included.
+    pub fn move_instances(&mut self, moves: impl IntoIterator<Item = (PrimativeKey, Transform)>) {
+        let moved = |primative: &Primative, transform| match primative {
+            Primative::Instance(instance) => {
+                let mut instance = instance.clone();
+                instance.set_transform(transform);
+                Some(Primative::Instance(instance))
+            }
+            _ => None,
+        };
+
+        let (mut scene, mut detail) = (false, false);
+        for (key, transform) in moves {
+            let placement = match self.instances.get(key) {
+                Some(placement) => *placement,
+                None => continue,
+            };
+            let primative = match placement {
+                Placement::Scene(index) => {
+                    let primative = moved(self.bvh.primative(index), transform);
+                    if let Some(primative) = &primative {
+                        self.bvh.replace(index, primative.clone());
+                        scene = true;
+                    }
+                    primative
+                }
+                Placement::Detail(index) => {
+                    let (detail_bvh, _) = self.detail.as_mut().expect("placed in detail");
+                    let primative = moved(detail_bvh.primative(index), transform);
+                    if let Some(primative) = &primative {
+                        detail_bvh.replace(index, primative.clone());
+                        detail = true;
+                    }
+                    primative
+                }
+                Placement::Unbounded(index) => {
+                    let primative = moved(&self.unbounded[index], transform);
+                    if let Some(primative) = &primative {
+                        self.unbounded[index] = primative.clone();
+                    }
+                    primative
+                }
+            };
+            #[cfg(feature = "serde")]
+            if let (Some(primative), Some(index)) = (primative, self.instance_keys.get(key)) {
+                self.hittables[*index] = primative;
+            }
+            #[cfg(not(feature = "serde"))]
+            let _ = primative;
+        }
+
+        if scene {
+            self.bvh.rebuild();
+        }
+        if let (true, Some((detail_bvh, _))) = (detail, &mut self.detail) {
+            detail_bvh.rebuild();
+        }
+    }
+
+    // Nearest hit in the BVH or among the unbounded primitives, before `t_max`
+    fn scene_hit(
+        &self,
+        ray: &Ray3A,
+        t_max: Float,
+        media: &mut MediumRng,
+    ) -> Option<(Float, HitRecord)> {
+        let mut closest = self.bvh.ray_hit(ray, self.epsilon, t_max, media);
+        for primative in self.unbounded.iter() {
+            let t_max = closest.as_ref().map_or(t_max, |(t, _)| *t);
+            if let Some(hit) = primative.media_hit(ray, self.epsilon, t_max, media) {
+                closest = Some(hit);
+            }
+        }
+        closest
+    }
+
+    // Nearest hit including detail geometry, which is always kept. Media along the ray
+    // draw from `media`.
+    fn closest_hit(&self, ray: &Ray3A, media: &mut MediumRng) -> Option<(Float, HitRecord)> {
+        let mut closest = self.scene_hit(ray, Float::INFINITY, media);
+        if let Some((detail_bvh, _)) = &self.detail {
+            let t_max = closest.as_ref().map_or(Float::INFINITY, |(t, _)| *t);
+            if let Some(detail_hit) = detail_bvh.ray_hit(ray, self.epsilon, t_max, media) {
+                closest = Some(detail_hit);
+            }
+        }
+        closest
+    }
+
+    // Whether anything, detail geometry included, lies along the ray before `t_max`.
+    // Cheaper than a closest hit as it stops at the first thing in the way.
+    pub fn ray_occluded(&self, ray: &Ray3A, t_max: Float, media: &mut MediumRng) -> bool {
+        self.bvh.occluded(ray, self.epsilon, t_max, media)
+            || self
+                .unbounded
+                .iter()
+                .any(|primative| primative.occluded(ray, self.epsilon, t_max, media))
+            || self
+                .detail
+                .as_ref()
+                .is_some_and(|(detail_bvh, _)| detail_bvh.occluded(ray, self.epsilon, t_max, media))
+    }
+
+    pub fn lights(&self) -> &[AreaLight] {
+        &self.lights
+    }
+
+    // Chooses a light with probability proportional to its area
+    fn pick_light(&self, rng: &mut impl Rng) -> usize {
+        let target = rng.gen::<Float>() * self.light_area;
+        let mut accumulated = 0.0;
+        for (index, light) in self.lights.iter().enumerate() {
+            accumulated += light.area();
+            if accumulated >= target {
+                return index;
+            }
+        }
+        self.lights.len() - 1
+    }
+
+    // Direct lighting from one light at a Lambertian vertex, excluding the albedo
+    fn sample_light(
+        &self,
+        light: &AreaLight,
+        rec: &HitRecord,
+        sampler: &mut Sampler,
+        rays: &mut u64,
+    ) -> Rgba {
+        let sample = light.sample(sampler);
+        let to_light = sample.point - rec.point;
+        let distance_squared = to_light.length_squared();
+        let distance = distance_squared.sqrt();
+        let wi = to_light / distance;
+
+        let cos_surface = rec.normal.dot(wi);
+        let cos_light = match light.two_sided() {
+            true => sample.normal.dot(-wi).abs(),
+            false => sample.normal.dot(-wi),
+        };
+        if cos_surface <= 0.0 || cos_light <= 0.0 {
             return Rgba::ZERO;
         }
 
-        match self.bvh.ray_hit(ray_in, 0.001, Float::INFINITY) {
-            Some((_, hit_rec)) => {
-                let material = self
-                    .materials
-                    .get(hit_rec.material_key)
-                    .expect("No material found!");
-                let emitted = material.emit(hit_rec.u, hit_rec.v, hit_rec.point, &self.textures);
+        *rays += 1;
+        let shadow_ray = Ray3A {
+            origin: rec.point,
+            direction: wi,
+        };
+        let mut media = MediumRng::new(&shadow_ray, sampler.gen()).at_time(sampler.time());
+        if self.ray_occluded(&shadow_ray, distance - self.epsilon, &mut media) {
+            return Rgba::ZERO;
+        }
+
+        let emitted = match self.materials.get(light.material_key()) {
+            Some(material) => material.emit(sample.u, sample.v, sample.point, &self.textures),
+            None => return Rgba::ZERO,
+        };
+
+        // Lambertian brdf (1 / PI) over the area pdf (1 / total light area)
+        emitted * (cos_surface * cos_light * self.light_area / (PI * distance_squared))
+    }
+
+    // First hits of up to `PACKET_WIDTH` camera rays, traced together. Rays from
+    // neighbouring pixels mostly visit the same nodes, so each node is tested once for
+    // all of them. Bounces after the first scatter too far apart to share much and
+    // go through `intersect_path` a ray at a time. Detail geometry is always kept for
+    // camera rays, and each ray's media draw from its sampler as the path's first
+    // bounce would, so this matches `intersect_path` at the first bounce.
+    pub(crate) fn primary_hits(
+        &self,
+        rays: &[Ray3A],
+        samplers: &mut [Sampler],
+    ) -> [Option<HitRecord>; PACKET_WIDTH] {
+        let mut media: [MediumRng; PACKET_WIDTH] = Default::default();
+        for ((lane, ray), sampler) in media.iter_mut().zip(rays).zip(samplers) {
+            *lane = sampler.medium_rng(0, ray);
+        }
+        let mut packet = RayPacket::new(rays, self.epsilon, Float::INFINITY);
+        let mut hits = [None; PACKET_WIDTH];
+        self.bvh.packet_hit(&mut packet, &mut hits, &mut media);
+        if let Some((detail_bvh, _)) = &self.detail {
+            detail_bvh.packet_hit(&mut packet, &mut hits, &mut media);
+        }
+        for (lane, (ray, hit)) in rays.iter().zip(hits.iter_mut()).enumerate() {
+            for primative in self.unbounded.iter() {
+                let t_max = packet.t_max(lane);
+                let found = primative.media_hit(ray, self.epsilon, t_max, &mut media[lane]);
+                if let Some((t, rec)) = found {
+                    packet.set_t_max(lane, t);
+                    *hit = Some(rec);
+                }
+            }
+        }
+        hits
+    }
+
+    // With `indirect_only` set, registered lights hit straight away are skipped and the
+    // irradiance cache is ignored, which is what filling the cache needs
+    #[allow(clippy::too_many_arguments)]
+    fn trace(
+        &self,
+        ray_in: &Ray3A,
+        polarizer: Option<Polarizer>,
+        sampler: &mut Sampler,
+        depth: usize,
+        rays: &mut u64,
+        mut contributions: Option<&mut PathContributions<'_>>,
+        indirect_only: bool,
+    ) -> Rgba {
+        let mut path = self.start_path(ray_in, polarizer, indirect_only);
+        while !path.done && path.bounce < depth {
+            *rays += 1;
+            let hit = self.intersect_path(&mut path, sampler);
+            self.shade_path(&mut path, hit, sampler, rays, contributions.as_deref_mut());
+        }
+        path.radiance
+    }
+
+    pub(crate) fn start_path(
+        &self,
+        ray: &Ray3A,
+        polarizer: Option<Polarizer>,
+        indirect_only: bool,
+    ) -> PathState {
+        PathState {
+            ray: Ray3A {
+                origin: ray.origin,
+                direction: ray.direction,
+            },
+            throughput: Rgba::ONE,
+            radiance: Rgba::ZERO,
+            bounce: 0,
+            done: false,
+            eye: ray.origin,
+            sampled_lights: indirect_only,
+            read_cache: !indirect_only,
+            polarization: polarizer.map(|p| PolarizationState::new(p, ray.direction)),
+            clay: false,
+            wavelength: None,
+        }
+    }
+
+    // The first half of a bounce: the closest hit along the path's ray, with detail
+    // geometry culled as set up. Secondary rays that keep detail carry the share of those
+    // that culled it, which end at the detail with a cheaper any-hit test. Passing
+    // through would count what's behind it on top.
+    pub(crate) fn intersect_path(
+        &self,
+        path: &mut PathState,
+        sampler: &mut Sampler,
+    ) -> Option<HitRecord> {
+        let ray = &path.ray;
+        let mut media = sampler.medium_rng(path.bounce, ray);
+        let mut closest = self.scene_hit(ray, Float::INFINITY, &mut media);
+
+        if let Some((detail_bvh, keep_probability)) = &self.detail {
+            sampler.start(path.bounce, SampleDimension::Detail);
+            let keep = path.bounce == 0 || sampler.gen::<Float>() < *keep_probability;
+            let t_max = closest.as_ref().map_or(Float::INFINITY, |(t, _)| *t);
+            if keep {
+                if let Some(detail_hit) = detail_bvh.ray_hit(ray, self.epsilon, t_max, &mut media) {
+                    if path.bounce > 0 {
+                        path.throughput = path.throughput * (1.0 / keep_probability);
+                    }
+                    closest = Some(detail_hit);
+                }
+            } else if detail_bvh.occluded(ray, self.epsilon, t_max, &mut media) {
+                path.done = true;
+                return None;
+            }
+        }
+        closest.map(|(_, hit_rec)| hit_rec)
+    }
+
+    // The hit's normal bumped by the material's detail normal, if it has one. Detail
+    // fades with distance from `eye`, the camera, not the path's previous vertex.
+    fn shading_normal(&self, material: &Material, hit_rec: &HitRecord, eye: Point3) -> Vec3A {
+        match material.detail_normal() {
+            Some(detail) => detail.perturb(
+                &self.detail_noise,
+                hit_rec.normal,
+                hit_rec.point,
+                (hit_rec.point - eye).length(),
+            ),
+            None => hit_rec.normal,
+        }
+    }
+
+    // The second half: adds what the path picks up at `hit`, or from the background
+    // on a miss, and scatters it onward or ends it
+    pub(crate) fn shade_path(
+        &self,
+        path: &mut PathState,
+        hit: Option<HitRecord>,
+        sampler: &mut Sampler,
+        rays: &mut u64,
+        mut contributions: Option<&mut PathContributions<'_>>,
+    ) {
+        const MIN_ROULETTE_BOUNCES: usize = 3;
+
+        // Ended on culled detail
+        if path.done {
+            return;
+        }
+        let bounce = path.bounce;
+        let ray = &path.ray;
+        let mut hit_rec = match hit {
+            Some(hit_rec) => hit_rec,
+            None => {
+                let weight = path.polarization.as_ref().map_or(1.0, |p| p.weight());
+                let contribution = path.throughput * self.miss_color(ray) * weight;
+                path.radiance = path.radiance + contribution;
+                if let Some(contributions) = contributions.as_deref_mut() {
+                    contributions.add(self.lights.len(), Technique::Environment, contribution);
+                }
+                path.done = true;
+                return;
+            }
+        };
+        let material = match self.materials.get(hit_rec.material_key) {
+            Some(material) => material,
+            // Shown magenta, like a missing texture
+            None => {
+                path.radiance = path.radiance + path.throughput * Rgba::new(1.0, 0.0, 1.0, 1.0);
+                path.done = true;
+                return;
+            }
+        };
+        hit_rec.normal = self.shading_normal(material, &hit_rec, path.eye);
+        let light_index = self.light_indices.get(hit_rec.material_key).copied();
+        let counts_emission = match light_index.map(|i| &self.lights[i]) {
+            // Already accounted for by light sampling at the previous vertex
+            Some(_) if path.sampled_lights => false,
+            Some(light) => light.two_sided() || hit_rec.face == Face::Front,
+            None => true,
+        };
+        // Emitters are unpolarized, so the filter reduces to a scalar weight
+        let weight = path.polarization.as_ref().map_or(1.0, |p| p.weight());
+        if counts_emission {
+            let emitted = material.emit(hit_rec.u, hit_rec.v, hit_rec.point, &self.textures);
+            let contribution = path.throughput * emitted * weight;
+            path.radiance = path.radiance + contribution;
+            if let Some(contributions) = contributions.as_deref_mut() {
+                let index = light_index.unwrap_or(self.lights.len());
+                contributions.add(index, Technique::Bsdf, contribution);
+            }
+        }
+
+        // Clay leaves emitters as they are, so the lighting still shows
+        let clay = path.clay
+            && material
+                .emit(hit_rec.u, hit_rec.v, hit_rec.point, &self.textures)
+                .max_channel()
+                <= 0.0;
+        sampler.start(bounce, SampleDimension::Bsdf);
+        let scattered = match clay {
+            true => diffuse_scatter(
+                Rgba::new(CLAY_ALBEDO, CLAY_ALBEDO, CLAY_ALBEDO, 1.0),
+                &hit_rec,
+                sampler,
+            ),
+            false => match path.wavelength {
+                Some(wavelength) => {
+                    material.scatter_wavelength(wavelength, ray, &hit_rec, &self.textures, sampler)
+                }
+                None => material.scatter(ray, &hit_rec, &self.textures, sampler),
+            },
+        };
+        match scattered {
+            ScatterResult::Scattered { ray_out, color } => {
+                path.sampled_lights = false;
+                if clay || matches!(material, Material::Lambertian { .. }) {
+                    if !self.lights.is_empty() {
+                        sampler.start(bounce, SampleDimension::LightPick);
+                        let index = self.pick_light(sampler);
+                        sampler.start(bounce, SampleDimension::Light);
+                        let light = &self.lights[index];
+                        let direct = (0..light.splits()).fold(Rgba::ZERO, |sum, _| {
+                            sum + self.sample_light(light, &hit_rec, sampler, rays)
+                        }) * (1.0 / light.splits() as Float);
+                        let contribution = path.throughput * color * direct * weight;
+                        path.radiance = path.radiance + contribution;
+                        if let Some(contributions) = contributions.as_deref_mut() {
+                            contributions.add(index, Technique::Light, contribution);
+                        }
+                        path.sampled_lights = true;
+                    }
 
-                match material.scatter(ray_in, &hit_rec, &self.textures, rng) {
-                    ScatterResult::Scattered { ray_out, color } => {
-                        emitted + color * self.ray_color(&ray_out, rng, depth - 1)
+                    // Only the first diffuse vertex reads the cache
+                    let cache = match std::mem::take(&mut path.read_cache) {
+                        true => self.irradiance_cache.as_ref(),
+                        false => None,
+                    };
+                    if let Some(irradiance) =
+                        cache.and_then(|cache| cache.irradiance(hit_rec.point, hit_rec.normal))
+                    {
+                        let contribution = path.throughput * color * irradiance * (weight / PI);
+                        path.radiance = path.radiance + contribution;
+                        if let Some(contributions) = contributions {
+                            contributions.add(self.lights.len(), Technique::Cache, contribution);
+                        }
+                        path.done = true;
+                        return;
                     }
-                    ScatterResult::Absorbed => emitted,
                 }
+
+                if let Some(polarization) = &mut path.polarization {
+                    polarization.scatter(material, ray, &hit_rec, &ray_out);
+                }
+
+                path.throughput = path.throughput * color;
+                path.ray = ray_out;
+            }
+            ScatterResult::Absorbed => {
+                path.done = true;
+                return;
             }
-            None => Rgba::ZERO,
         }
+
+        // Russian roulette, reweighted so the estimate stays unbiased
+        if bounce >= MIN_ROULETTE_BOUNCES {
+            let survival = path.throughput.max_channel().min(1.0);
+            sampler.start(bounce, SampleDimension::Roulette);
+            if survival <= 0.0 || sampler.gen::<Float>() >= survival {
+                path.done = true;
+                return;
+            }
+            path.throughput = path.throughput * (1.0 / survival);
+        }
+        path.bounce += 1;
     }
 }
 
+// One path between bounces, so integrators can advance many paths a stage at a time
+pub(crate) struct PathState {
+    pub ray: Ray3A,
+    pub throughput: Rgba,
+    pub radiance: Rgba,
+    pub bounce: usize,
+    pub done: bool,
+    // Where the path started, which detail normals fade with distance from
+    eye: Point3,
+    sampled_lights: bool,
+    read_cache: bool,
+    polarization: Option<PolarizationState>,
+    // Shades everything but emitters as `RenderMode::Clay`
+    clay: bool,
+    // The single wavelength carried by a `SpectralIntegrator` path, in nanometers
+    wavelength: Option<Float>,
+}
+
 impl From<WorldBuilder> for World {
     fn from(builder: WorldBuilder) -> Self {
+        #[cfg(feature = "serde")]
+        let hittables = builder.hittables.clone();
+        let light_area = builder.lights.iter().map(|l| l.area()).sum();
+        let mut light_indices = SecondaryMap::new();
+        for (index, light) in builder.lights.iter().enumerate().rev() {
+            light_indices.insert(light.material_key(), index);
+        }
+        let culling = builder.detail_culling;
+        let is_detail = |primative: &Primative| match culling {
+            Some(culling) => {
+                let bounds = primative.bounds();
+                let radius = 0.5 * (bounds.max - bounds.min).length();
+                let distance = (0.5 * (bounds.max + bounds.min) - culling.eye).length();
+                distance > radius && radius / distance < culling.min_projected_size
+            }
+            None => false,
+        };
+
+        let (mut primatives, mut details, mut unbounded) = (Vec::new(), Vec::new(), Vec::new());
+        let placements: Vec<_> = builder
+            .hittables
+            .into_iter()
+            .map(|primative| match primative.is_bounded() {
+                false => {
+                    unbounded.push(primative);
+                    Placement::Unbounded(unbounded.len() - 1)
+                }
+                true if is_detail(&primative) => {
+                    details.push(primative);
+                    Placement::Detail(details.len() - 1)
+                }
+                true => {
+                    primatives.push(primative);
+                    Placement::Scene(primatives.len() - 1)
+                }
+            })
+            .collect();
+        let instances = builder
+            .instances
+            .iter()
+            .filter_map(|(key, index)| Some((key, *placements.get(*index)?)))
+            .collect();
+        let epsilon = scene_epsilon(primatives.iter().chain(&details));
+        let detail_noise = PerlinData::new(&mut rand::rngs::StdRng::seed_from_u64(0));
+
+        let keep_probability = culling.map_or(1.0, |culling| {
+            culling.keep_probability.clamp(Float::EPSILON, 1.0)
+        });
         Self {
             textures: builder.textures,
             materials: builder.materials,
-            bvh: Bvh3A::build(builder.hittables),
+            detail_noise,
+            bvh: SceneBvh::build(primatives),
+            unbounded,
+            detail: match details.is_empty() {
+                true => None,
+                false => Some((SceneBvh::build(details), keep_probability)),
+            },
+            lights: builder.lights,
+            light_indices,
+            light_area,
+            epsilon,
+            irradiance_cache: None,
+            background: builder.background,
+            miss_shader: None,
+            instances,
+            #[cfg(feature = "serde")]
+            hittables,
+            #[cfg(feature = "serde")]
+            detail_culling: culling,
+            #[cfg(feature = "serde")]
+            instance_keys: builder.instances,
         }
     }
 }
+
+// Float precision near a point degrades with its magnitude, so the offset scales with both the
+// scene's extent and how far it sits from the origin
+fn scene_epsilon<'a>(primatives: impl Iterator<Item = &'a Primative>) -> Float {
+    const RELATIVE_EPSILON: Float = 1e-5;
+    const DEFAULT_EPSILON: Float = 0.001;
+
+    let mut bounds = primatives.map(|p| p.bounds());
+    let first = match bounds.next() {
+        Some(first) => first,
+        None => return DEFAULT_EPSILON,
+    };
+    let (min, max) = bounds.fold((first.min, first.max), |(min, max), b| {
+        (min.min(b.min), max.max(b.max))
+    });
+
+    let extent = (max - min)
+        .length()
+        .max(min.abs().max_element())
+        .max(max.abs().max_element());
+    match extent.is_finite() && extent > 0.0 {
+        true => extent * RELATIVE_EPSILON,
+        false => DEFAULT_EPSILON,
+    }
+}
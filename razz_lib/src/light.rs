@@ -0,0 +1,229 @@
+use crate::image::{Image, Rgba};
+use crate::{Float, MaterialKey, Point3, Vec3A};
+
+use rand::Rng;
+
+#[derive(Debug, Clone, Copy)]
+pub struct LightSample {
+    pub point: Point3,
+    pub normal: Vec3A,
+    pub u: Float,
+    pub v: Float,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AreaLight {
+    material_key: MaterialKey,
+    two_sided: bool,
+    triangles: Vec<[Point3; 3]>,
+    cdf: Vec<Float>,
+    area: Float,
+    // Shadow rays traced each time the light is picked
+    #[cfg_attr(feature = "serde", serde(default = "default_splits"))]
+    splits: usize,
+}
+
+fn default_splits() -> usize {
+    1
+}
+
+impl AreaLight {
+    pub fn new(triangles: Vec<[Point3; 3]>, material_key: MaterialKey, two_sided: bool) -> Self {
+        let mut area = 0.0;
+        let cdf = triangles
+            .iter()
+            .map(|[v0, v1, v2]| {
+                area += 0.5 * (*v1 - *v0).cross(*v2 - *v0).length();
+                area
+            })
+            .collect();
+
+        Self {
+            material_key,
+            two_sided,
+            triangles,
+            cdf,
+            area,
+            splits: default_splits(),
+        }
+    }
+
+    pub fn material_key(&self) -> MaterialKey {
+        self.material_key
+    }
+
+    pub fn two_sided(&self) -> bool {
+        self.two_sided
+    }
+
+    pub fn area(&self) -> Float {
+        self.area
+    }
+
+    pub fn splits(&self) -> usize {
+        self.splits
+    }
+
+    pub fn set_splits(&mut self, splits: usize) {
+        self.splits = splits.max(1);
+    }
+
+    // Uniform over the light's surface, so the area pdf is 1 / area
+    pub fn sample(&self, rng: &mut impl Rng) -> LightSample {
+        let target = rng.gen::<Float>() * self.area;
+        let index = self
+            .cdf
+            .iter()
+            .position(|c| *c >= target)
+            .unwrap_or(self.triangles.len() - 1);
+        let [v0, v1, v2] = self.triangles[index];
+
+        let (mut u, mut v) = (rng.gen::<Float>(), rng.gen::<Float>());
+        if u + v > 1.0 {
+            u = 1.0 - u;
+            v = 1.0 - v;
+        }
+
+        LightSample {
+            point: v0 + u * (v1 - v0) + v * (v2 - v0),
+            normal: (v1 - v0).cross(v2 - v0).normalize(),
+            u,
+            v,
+        }
+    }
+}
+
+// False color view of which buffer contributes most to each pixel. Each light gets its own hue,
+// which is brighter the more that light dominates.
+pub fn dominant_light_map(contributions: &[Image]) -> Image {
+    let (width, height) = match contributions.first() {
+        Some(first) => (first.width, first.height),
+        None => return Image::new(0, 0),
+    };
+
+    let mut map = Image::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let mut total = 0.0;
+            let mut dominant = (0, 0.0);
+            for (index, buffer) in contributions.iter().enumerate() {
+                let [r, g, b, _] = buffer.get_pixel_color(x, y).to_array();
+                let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+                total += luminance;
+                if luminance > dominant.1 {
+                    dominant = (index, luminance);
+                }
+            }
+
+            if total > 0.0 {
+                map.set_pixel_color(x, y, light_hue(dominant.0) * (dominant.1 / total));
+            }
+        }
+    }
+    map
+}
+
+// Golden angle steps keep neighbouring indices visually distinct
+fn light_hue(index: usize) -> Rgba {
+    let hue = (index as Float * 0.618034).fract() * 6.0;
+    let channel = |offset: Float| (((hue + offset) % 6.0 - 3.0).abs() - 1.0).clamp(0.0, 1.0);
+    Rgba::new(channel(0.0), channel(4.0), channel(2.0), 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Background, Camera, Material, Mesh, ParallelRenderer, Primative, Scene, Technique, Texture,
+        WorldBuilder,
+    };
+
+    fn lamp_scene(splits: usize) -> Scene {
+        let mut world_builder = WorldBuilder::default();
+        let grey = world_builder.push_texture(Texture::Solid {
+            color: Rgba::splat(0.5),
+        });
+        let grey = world_builder.push_material(Material::Lambertian { albedo: grey });
+        let white = world_builder.push_texture(Texture::Solid { color: Rgba::ONE });
+        let light = world_builder.push_material(Material::DiffuseLight {
+            emit: white,
+            intensity: 4.0,
+        });
+        world_builder.push_hittable(Primative::sphere(Vec3A::ZERO, 1.0, grey));
+        let lamp = Mesh::new(
+            vec![
+                Vec3A::new(-1.0, 1.5, -1.0),
+                Vec3A::new(1.0, 1.5, -1.0),
+                Vec3A::new(1.0, 1.5, 1.0),
+                Vec3A::new(-1.0, 1.5, 1.0),
+            ],
+            vec![(0, 2, 1), (0, 3, 2)],
+            light,
+        )
+        .unwrap();
+        let lamp = world_builder.push_area_light(lamp, true).unwrap();
+        world_builder.set_light_splits(lamp, splits);
+        world_builder.set_background(Background::Solid { color: Rgba::ZERO });
+        let camera = Camera::new(4.0 * Vec3A::Z, Vec3A::ZERO, 40.0, 1.0, 0.0, 1.0);
+        Scene::new(world_builder.into(), camera)
+    }
+
+    #[test]
+    fn split_lights_are_less_noisy_with_the_same_mean() {
+        let light_estimate = |splits| {
+            let mut renderer = ParallelRenderer::new(16, 16, 2);
+            renderer.enable_technique_aovs();
+            for _ in 0..16 {
+                renderer.render(&lamp_scene(splits));
+            }
+            let summary = renderer.technique_aovs().unwrap().summary();
+            let (_, mean, variance) = summary
+                .into_iter()
+                .find(|(technique, _, _)| *technique == Technique::Light)
+                .unwrap();
+            (mean, variance)
+        };
+        // Close and wide, so where on the lamp a shadow ray lands matters
+        let (mean, variance) = light_estimate(1);
+        let (split_mean, split_variance) = light_estimate(16);
+        assert!(mean > 0.0);
+        assert!(
+            (split_mean - mean).abs() < 0.1 * mean,
+            "{} {}",
+            split_mean,
+            mean
+        );
+        assert!(
+            split_variance < 0.5 * variance,
+            "{} {}",
+            split_variance,
+            variance
+        );
+    }
+
+    #[test]
+    fn light_materials_shared_with_other_geometry_fail_validation() {
+        let mut world_builder = WorldBuilder::default();
+        let white = world_builder.push_texture(Texture::Solid { color: Rgba::ONE });
+        let light = world_builder.push_material(Material::DiffuseLight {
+            emit: white,
+            intensity: 4.0,
+        });
+        let lamp = Mesh::new(
+            vec![
+                Vec3A::new(-1.0, 1.5, -1.0),
+                Vec3A::new(1.0, 1.5, -1.0),
+                Vec3A::new(1.0, 1.5, 1.0),
+            ],
+            vec![(0, 2, 1)],
+            light,
+        )
+        .unwrap();
+        world_builder.push_area_light(lamp, true);
+        assert!(world_builder.validate().is_ok());
+
+        world_builder.push_hittable(Primative::sphere(Vec3A::ZERO, 1.0, light));
+        assert!(world_builder.validate().is_err());
+    }
+}
@@ -0,0 +1,106 @@
+// Declares a scene in one place. Textures and materials are named, and later entries
+// refer to them by name, so a misspelt or missing reference fails to compile rather
+// than turning up as a missing key at render time. Every section but `camera` is
+// optional, and they come in this order:
+//
+//     let scene = scene! {
+//         camera: Camera::new(eye, target, 40.0, 1.0, 0.0, 10.0),
+//         background: Background::rtiow_sky(),
+//         textures: {
+//             grey: Texture::Solid { color: Rgba::splat(0.5) },
+//             lamp: Texture::Solid { color: Rgba::ONE },
+//         },
+//         materials: {
+//             matte: Material::Lambertian { albedo: grey },
+//             light: Material::DiffuseLight { emit: lamp, intensity: 4.0 },
+//         },
+//         primitives: [Primative::sphere(Vec3A::ZERO, 1.0, matte)],
+//         lights: [Mesh::new(vertices, indices, light)? => true],
+//     };
+//
+// `lights` are meshes and whether they're two sided, as for `push_area_light`. A
+// leading `world: builder,` adds to an existing `WorldBuilder` instead of a new one.
+#[macro_export]
+macro_rules! scene {
+    (
+        $(world: $world:expr,)?
+        camera: $camera:expr
+        $(, background: $background:expr)?
+        $(, textures: { $($texture:ident: $texture_value:expr),* $(,)? })?
+        $(, materials: { $($material:ident: $material_value:expr),* $(,)? })?
+        $(, primitives: [ $($primitive:expr),* $(,)? ])?
+        $(, lights: [ $($light:expr => $two_sided:expr),* $(,)? ])?
+        $(,)?
+    ) => {{
+        #[allow(unused_mut)]
+        let mut world_builder: $crate::WorldBuilder = $crate::scene!(@world $($world)?);
+        $(world_builder.set_background($background);)?
+        $($(let $texture = world_builder.push_texture($texture_value);)*)?
+        $($(let $material = world_builder.push_material($material_value);)*)?
+        $($(world_builder.push_hittable($primitive);)*)?
+        $($(world_builder.push_area_light($light, $two_sided);)*)?
+        $crate::Scene::new(world_builder.into(), $camera)
+    }};
+    (@world) => {
+        $crate::WorldBuilder::default()
+    };
+    (@world $world:expr) => {
+        $world
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Background, Camera, Material, Mesh, Primative, Rgba, Texture, TextureSpace, Vec3A,
+    };
+
+    #[test]
+    fn names_resolve_to_the_keys_pushed() {
+        let scene = scene! {
+            camera: Camera::new(4.0 * Vec3A::Z, Vec3A::ZERO, 40.0, 1.0, 0.0, 1.0),
+            background: Background::Solid { color: Rgba::ZERO },
+            textures: {
+                grey: Texture::Solid { color: Rgba::splat(0.5) },
+                white: Texture::Solid { color: Rgba::ONE },
+                checker: Texture::Checker { odd: grey, even: white, scale: 1.0, space: TextureSpace::World },
+            },
+            materials: {
+                matte: Material::Lambertian { albedo: checker },
+                light: Material::DiffuseLight { emit: white, intensity: 4.0 },
+            },
+            primitives: [
+                Primative::sphere(Vec3A::ZERO, 1.0, matte),
+                Primative::sphere(Vec3A::X, 0.5, matte),
+            ],
+            lights: [
+                Mesh::new(
+                    vec![
+                        Vec3A::new(-1.0, 2.0, 0.0),
+                        Vec3A::new(1.0, 2.0, 0.0),
+                        Vec3A::new(0.0, 2.0, 1.0),
+                    ],
+                    vec![(0, 2, 1)],
+                    light,
+                )
+                .unwrap() => true,
+            ],
+        };
+
+        let world = &scene.world;
+        assert_eq!(world.textures().len(), 3);
+        assert_eq!(world.materials().len(), 2);
+        assert_eq!(world.primitive_count(), 3);
+        assert_eq!(world.lights().len(), 1);
+        assert!(matches!(
+            world.materials().get(world.lights()[0].material_key()),
+            Some(Material::DiffuseLight { .. })
+        ));
+
+        // Only the camera is needed
+        let empty = scene! {
+            camera: Camera::new(Vec3A::Z, Vec3A::ZERO, 40.0, 1.0, 0.0, 1.0),
+        };
+        assert_eq!(empty.world.primitive_count(), 0);
+    }
+}
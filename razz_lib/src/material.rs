@@ -1,22 +1,187 @@
 use crate::image::Rgba;
+use crate::microfacet::{fresnel_dielectric, Ggx};
 use crate::shape::{Face, HitRecord};
 use crate::texture::Texture;
-use crate::{Float, Point3, Ray3A, TextureKey, Vec3A};
+use crate::{Dispersion, Float, PerlinData, Point3, Ray3A, TextureKey, Vec3A, D_LINE_NM};
 
-use rand::Rng;
+use rand::{Rng, RngCore};
 use slotmap::SlotMap;
+use std::fmt::Debug;
+
+const PI: Float = std::f64::consts::PI as Float;
+
+// High frequency procedural bumps on the shading normal, for surface detail without
+// textures or displacement. Set as the `detail` of a principled material.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DetailNormal {
+    // How far the normal leans, 0 leaves it untouched
+    pub strength: Float,
+    // Spatial frequency of the bumps in world units
+    pub scale: Float,
+    // Distance from the camera at which the bumps are half as strong, so detail finer
+    // than a pixel fades instead of sparkling
+    pub fade_distance: Float,
+}
+
+impl Default for DetailNormal {
+    fn default() -> Self {
+        Self {
+            strength: 0.3,
+            scale: 50.0,
+            fade_distance: 10.0,
+        }
+    }
+}
+
+impl DetailNormal {
+    // `normal` leaned along the noise gradient at `p`, seen from `distance` away
+    pub fn perturb(&self, noise: &PerlinData, normal: Vec3A, p: Point3, distance: Float) -> Vec3A {
+        let strength = self.strength / (1.0 + distance / self.fade_distance.max(Float::EPSILON));
+        if strength <= 0.0 {
+            return normal;
+        }
+
+        const H: Float = 1e-2;
+        let q = self.scale * p;
+        let gradient = Vec3A::new(
+            noise.noise(q + H * Vec3A::X) - noise.noise(q - H * Vec3A::X),
+            noise.noise(q + H * Vec3A::Y) - noise.noise(q - H * Vec3A::Y),
+            noise.noise(q + H * Vec3A::Z) - noise.noise(q - H * Vec3A::Z),
+        ) / (2.0 * H);
+        let tangential = gradient - gradient.dot(normal) * normal;
+
+        // Never lean past the surface
+        match (normal - strength * tangential).try_normalize() {
+            Some(bumped) if bumped.dot(normal) > 0.0 => bumped,
+            _ => normal,
+        }
+    }
+}
 
 pub enum ScatterResult {
     Scattered { ray_out: Ray3A, color: Rgba },
     Absorbed,
 }
 
+// Surfaces defined outside razz_lib, added to a world with `Material::custom`.
+// Directions follow the built-in materials: `ray_in` arrives at `rec`, whose normal
+// faces it, and `direction` leaves the surface towards where light comes from.
+// Only Lambertian surfaces sample lights directly, so a custom BSDF is reached by
+// `sample` alone. Custom materials are saved by name and load as a magenta
+// placeholder, and the GPU renders them as a diffuse surface of their `albedo`.
+pub trait Bsdf: Debug + Send + Sync {
+    // Picks the next direction, with `color` the ratio of `eval` to `pdf`
+    fn sample(
+        &self,
+        ray_in: &Ray3A,
+        rec: &HitRecord,
+        texture_map: &SlotMap<TextureKey, Texture>,
+        rng: &mut dyn RngCore,
+    ) -> ScatterResult;
+
+    // BSDF times the cosine term for light arriving from `direction`
+    fn eval(
+        &self,
+        ray_in: &Ray3A,
+        rec: &HitRecord,
+        direction: Vec3A,
+        texture_map: &SlotMap<TextureKey, Texture>,
+    ) -> Rgba;
+
+    // Solid angle density of `sample` choosing `direction`, zero for delta lobes
+    fn pdf(&self, ray_in: &Ray3A, rec: &HitRecord, direction: Vec3A) -> Float;
+
+    fn emit(
+        &self,
+        _u: Float,
+        _v: Float,
+        _p: Point3,
+        _texture_map: &SlotMap<TextureKey, Texture>,
+    ) -> Rgba {
+        Rgba::ZERO
+    }
+
+    // Reflectance at normal incidence, for denoising and the GPU stand-in
+    fn albedo(
+        &self,
+        _u: Float,
+        _v: Float,
+        _p: Point3,
+        _texture_map: &SlotMap<TextureKey, Texture>,
+    ) -> Rgba {
+        Rgba::ONE
+    }
+
+    // What a saved scene remembers the material by
+    fn name(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Material {
-    Lambertian { albedo: TextureKey },
-    Metal { albedo: TextureKey, fuzz: Float },
-    Dielectric { ir: Float },
-    DiffuseLight { emit: TextureKey },
+    Lambertian {
+        albedo: TextureKey,
+    },
+    Metal {
+        albedo: TextureKey,
+        fuzz: Float,
+    },
+    // Glass, smooth at zero roughness and with GGX microfacet reflection and
+    // refraction above it. An IOR that depends on the wavelength splits white light
+    // into colors under `SpectralIntegrator`, other integrators see it at the sodium
+    // d-line.
+    Dielectric {
+        ir: Dispersion,
+        #[cfg_attr(feature = "serde", serde(default))]
+        roughness: Float,
+    },
+    // GGX microfacet reflection, with `albedo` as the Schlick reflectance at normal incidence
+    RoughMetal {
+        albedo: TextureKey,
+        roughness: Float,
+    },
+    // Complex index of refraction n + ik per RGB channel
+    Conductor {
+        eta: [Float; 3],
+        k: [Float; 3],
+        fuzz: Float,
+    },
+    // A dielectric that tints light refracting through it as if each crossing passed
+    // through `depth` of water, absorbing `absorption` per unit length in each channel.
+    // A depth of zero leaves it clear.
+    Water {
+        ir: Float,
+        absorption: [Float; 3],
+        depth: Float,
+    },
+    DiffuseLight {
+        emit: TextureKey,
+        intensity: Float,
+    },
+    // Phase function for participating media, scatters uniformly in all directions
+    Isotropic {
+        albedo: TextureKey,
+    },
+    // Metallic/roughness surface in the style of Blender's Principled BSDF and glTF.
+    // Metals reflect tinted by `base_color`, everything else is a specular coat over a
+    // diffuse base, or over rough glass in proportion to `transmission`.
+    Principled {
+        base_color: TextureKey,
+        metallic: Float,
+        roughness: Float,
+        // Scales the coat's reflectance at normal incidence, 0.5 is the usual 4%
+        specular: Float,
+        ior: Float,
+        transmission: Float,
+        emission: Option<TextureKey>,
+        emission_strength: Float,
+        #[cfg_attr(feature = "serde", serde(default))]
+        detail: Option<DetailNormal>,
+    },
+    Custom(#[cfg_attr(feature = "serde", serde(with = "custom_bsdf"))] Box<dyn Bsdf>),
 }
 
 impl Material {
@@ -33,8 +198,90 @@ impl Material {
             Self::Metal { albedo, fuzz } => {
                 metal_scatter(albedo, *fuzz, ray_in, rec, texture_map, rng)
             }
-            Self::Dielectric { ir } => dielectric_scatter(*ir, ray_in, rec, rng),
+            Self::Dielectric { ir, roughness } => {
+                glass_scatter(ir.ior(D_LINE_NM), *roughness, ray_in, rec, rng)
+            }
+            Self::RoughMetal { albedo, roughness } => {
+                rough_metal_scatter(albedo, *roughness, ray_in, rec, texture_map, rng)
+            }
+            Self::Conductor { eta, k, fuzz } => conductor_scatter(eta, k, *fuzz, ray_in, rec, rng),
+            Self::Water {
+                ir,
+                absorption,
+                depth,
+            } => water_scatter(*ir, absorption, *depth, ray_in, rec, rng),
             Self::DiffuseLight { .. } => ScatterResult::Absorbed,
+            Self::Isotropic { albedo } => isotropic_scatter(albedo, rec, texture_map, rng),
+            Self::Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                ior,
+                transmission,
+                ..
+            } => {
+                let lobes = principled_lobes(*metallic, *transmission);
+                let choice = rng.gen::<Float>();
+                if choice < lobes[0] {
+                    let base = self.albedo(rec.u, rec.v, rec.point, texture_map);
+                    return ggx_metal_scatter(base, *roughness, ray_in, rec, rng);
+                }
+                if choice < lobes[0] + lobes[1] {
+                    // Only light passing through the surface picks up the base color
+                    return match rough_dielectric_scatter(*ior, *roughness, ray_in, rec, rng) {
+                        ScatterResult::Scattered { ray_out, color }
+                            if ray_out.direction.dot(rec.normal) < 0.0 =>
+                        {
+                            let base = self.albedo(rec.u, rec.v, rec.point, texture_map);
+                            ScatterResult::Scattered {
+                                ray_out,
+                                color: color * base,
+                            }
+                        }
+                        scattered => scattered,
+                    };
+                }
+
+                let cos_o = -ray_in.direction.normalize().dot(rec.normal);
+                let coat = principled_coat(*specular, cos_o);
+                match rng.gen::<Float>() < coat {
+                    true => match ggx_reflect_scatter(
+                        Rgba::splat(0.08 * specular),
+                        *roughness,
+                        ray_in,
+                        rec,
+                        rng,
+                    ) {
+                        ScatterResult::Scattered { ray_out, color } => ScatterResult::Scattered {
+                            ray_out,
+                            color: color * (1.0 / coat),
+                        },
+                        absorbed => absorbed,
+                    },
+                    // The base only sees what the coat lets through, which cancels
+                    // against the chance of getting here
+                    false => lambertian_scatter(base_color, rec, texture_map, rng),
+                }
+            }
+            Self::Custom(bsdf) => bsdf.sample(ray_in, rec, texture_map, rng),
+        }
+    }
+
+    // `scatter` for light of a single wavelength, which only dispersive glass tells apart
+    pub(crate) fn scatter_wavelength(
+        &self,
+        wavelength: Float,
+        ray_in: &Ray3A,
+        rec: &HitRecord,
+        texture_map: &SlotMap<TextureKey, Texture>,
+        rng: &mut impl Rng,
+    ) -> ScatterResult {
+        match self {
+            Self::Dielectric { ir, roughness } => {
+                glass_scatter(ir.ior(wavelength), *roughness, ray_in, rec, rng)
+            }
+            _ => self.scatter(ray_in, rec, texture_map, rng),
         }
     }
 
@@ -50,12 +297,264 @@ impl Material {
             Self::Lambertian { .. } => Rgba::ZERO,
             Self::Metal { .. } => Rgba::ZERO,
             Self::Dielectric { .. } => Rgba::ZERO,
-            Self::DiffuseLight { emit } => match texture_map.get(*emit) {
+            Self::RoughMetal { .. } => Rgba::ZERO,
+            Self::Conductor { .. } => Rgba::ZERO,
+            Self::Water { .. } => Rgba::ZERO,
+            Self::Isotropic { .. } => Rgba::ZERO,
+            Self::DiffuseLight { emit, intensity }
+            | Self::Principled {
+                emission: Some(emit),
+                emission_strength: intensity,
+                ..
+            } => match texture_map.get(*emit) {
+                Some(texture) => texture.value(u, v, p, texture_map) * *intensity,
+                None => Rgba::new(1.0, 0.0, 1.0, 1.0),
+            },
+            Self::Principled { emission: None, .. } => Rgba::ZERO,
+            Self::Custom(bsdf) => bsdf.emit(u, v, p, texture_map),
+        }
+    }
+}
+
+impl Material {
+    // Reflectance at normal incidence, used as a denoising feature
+    pub fn albedo(
+        &self,
+        u: Float,
+        v: Float,
+        p: Point3,
+        texture_map: &SlotMap<TextureKey, Texture>,
+    ) -> Rgba {
+        match self {
+            Self::Lambertian { albedo }
+            | Self::Metal { albedo, .. }
+            | Self::RoughMetal { albedo, .. }
+            | Self::Principled {
+                base_color: albedo, ..
+            }
+            | Self::Isotropic { albedo } => match texture_map.get(*albedo) {
                 Some(texture) => texture.value(u, v, p, texture_map),
                 None => Rgba::new(1.0, 0.0, 1.0, 1.0),
             },
+            Self::Conductor { eta, k, .. } => Rgba::new(
+                conductor_reflectance(1.0, eta[0], k[0]),
+                conductor_reflectance(1.0, eta[1], k[1]),
+                conductor_reflectance(1.0, eta[2], k[2]),
+                1.0,
+            ),
+            Self::Dielectric { .. } | Self::Water { .. } | Self::DiffuseLight { .. } => Rgba::ONE,
+            Self::Custom(bsdf) => bsdf.albedo(u, v, p, texture_map),
         }
     }
+
+    // Solid angle density of `scatter` choosing `direction`. Delta lobes and the
+    // fuzzed reflections have no density to evaluate and return zero.
+    pub fn pdf(&self, ray_in: &Ray3A, rec: &HitRecord, direction: Vec3A) -> Float {
+        let wo = -ray_in.direction.normalize();
+        let wi = direction.normalize();
+        let cos_i = wi.dot(rec.normal);
+
+        match self {
+            Self::Lambertian { .. } => cos_i.max(0.0) / PI,
+            Self::Isotropic { .. } => 1.0 / (4.0 * PI),
+            Self::RoughMetal { roughness, .. } => {
+                ggx_metal_eval(&Ggx::new(*roughness), Rgba::ONE, wo, wi, rec.normal).1
+            }
+            Self::Dielectric { ir, roughness } if *roughness > 0.0 => {
+                let eta = relative_ior(ir.ior(D_LINE_NM), rec);
+                rough_dielectric_eval(&Ggx::new(*roughness), eta, wo, wi, rec.normal).1
+            }
+            Self::Principled {
+                metallic,
+                roughness,
+                specular,
+                ior,
+                transmission,
+                ..
+            } => {
+                let ggx = Ggx::new(*roughness);
+                let lobes = principled_lobes(*metallic, *transmission);
+                let metal = ggx_metal_eval(&ggx, Rgba::ONE, wo, wi, rec.normal).1;
+                let reflect = ggx_reflect_eval(&ggx, Rgba::ONE, wo, wi, rec.normal).1;
+                let glass =
+                    rough_dielectric_eval(&ggx, relative_ior(*ior, rec), wo, wi, rec.normal);
+                let coat = principled_coat(*specular, wo.dot(rec.normal));
+                lobes[0] * metal
+                    + lobes[1] * glass.1
+                    + lobes[2] * (coat * reflect + (1.0 - coat) * cos_i.max(0.0) / PI)
+            }
+            Self::Custom(bsdf) => bsdf.pdf(ray_in, rec, direction),
+            _ => 0.0,
+        }
+    }
+
+    // BSDF times the cosine term for light arriving from `direction`. Together with
+    // `pdf` this gives the weight `scatter` would have returned.
+    pub fn eval(
+        &self,
+        ray_in: &Ray3A,
+        rec: &HitRecord,
+        direction: Vec3A,
+        texture_map: &SlotMap<TextureKey, Texture>,
+    ) -> Rgba {
+        let wo = -ray_in.direction.normalize();
+        let wi = direction.normalize();
+        let cos_o = wo.dot(rec.normal);
+        let cos_i = wi.dot(rec.normal);
+
+        match self {
+            Self::Lambertian { .. } => {
+                self.albedo(rec.u, rec.v, rec.point, texture_map) * (cos_i.max(0.0) / PI)
+            }
+            Self::Isotropic { .. } => {
+                self.albedo(rec.u, rec.v, rec.point, texture_map) * (1.0 / (4.0 * PI))
+            }
+            Self::RoughMetal { roughness, .. } => {
+                let f0 = self.albedo(rec.u, rec.v, rec.point, texture_map);
+                ggx_metal_eval(&Ggx::new(*roughness), f0, wo, wi, rec.normal).0
+            }
+            Self::Dielectric { ir, roughness } if *roughness > 0.0 => {
+                let eta = relative_ior(ir.ior(D_LINE_NM), rec);
+                Rgba::splat(rough_dielectric_eval(&Ggx::new(*roughness), eta, wo, wi, rec.normal).0)
+            }
+            Self::Principled {
+                metallic,
+                roughness,
+                specular,
+                ior,
+                transmission,
+                ..
+            } => {
+                let ggx = Ggx::new(*roughness);
+                let base = self.albedo(rec.u, rec.v, rec.point, texture_map);
+                let lobes = principled_lobes(*metallic, *transmission);
+
+                let metal = ggx_metal_eval(&ggx, base, wo, wi, rec.normal).0;
+                let glass =
+                    rough_dielectric_eval(&ggx, relative_ior(*ior, rec), wo, wi, rec.normal);
+                let glass = match cos_i < 0.0 {
+                    true => base * glass.0,
+                    false => Rgba::splat(glass.0),
+                };
+                let f0 = Rgba::splat(0.08 * specular);
+                let coat = ggx_reflect_eval(&ggx, f0, wo, wi, rec.normal).0;
+                let diffuse =
+                    base * ((1.0 - principled_coat(*specular, cos_o)) * cos_i.max(0.0) / PI);
+
+                metal * lobes[0] + glass * lobes[1] + (coat + diffuse) * lobes[2]
+            }
+            Self::Custom(bsdf) => bsdf.eval(ray_in, rec, direction, texture_map),
+            _ => Rgba::ZERO,
+        }
+    }
+}
+
+impl Material {
+    // Smooth glass
+    pub fn dielectric(ir: Float) -> Self {
+        Self::Dielectric {
+            ir: Dispersion::Constant(ir),
+            roughness: 0.0,
+        }
+    }
+
+    // Blender's defaults, a slightly glossy plastic
+    pub fn principled(base_color: TextureKey) -> Self {
+        Self::Principled {
+            base_color,
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            ior: 1.45,
+            transmission: 0.0,
+            emission: None,
+            emission_strength: 0.0,
+            detail: None,
+        }
+    }
+
+    // Procedural bumps on the shading normal, if the material has any
+    pub fn detail_normal(&self) -> Option<&DetailNormal> {
+        match self {
+            Self::Principled { detail, .. } => detail.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn custom(bsdf: impl Bsdf + 'static) -> Self {
+        Self::Custom(Box::new(bsdf))
+    }
+}
+
+// Stands in for a custom material read back from a saved scene, which only kept
+// its name. Glows magenta like a missing texture and stops paths.
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+struct MissingBsdf {
+    name: String,
+}
+
+#[cfg(feature = "serde")]
+impl Bsdf for MissingBsdf {
+    fn sample(
+        &self,
+        _ray_in: &Ray3A,
+        _rec: &HitRecord,
+        _texture_map: &SlotMap<TextureKey, Texture>,
+        _rng: &mut dyn RngCore,
+    ) -> ScatterResult {
+        ScatterResult::Absorbed
+    }
+
+    fn eval(
+        &self,
+        _ray_in: &Ray3A,
+        _rec: &HitRecord,
+        _direction: Vec3A,
+        _texture_map: &SlotMap<TextureKey, Texture>,
+    ) -> Rgba {
+        Rgba::ZERO
+    }
+
+    fn pdf(&self, _ray_in: &Ray3A, _rec: &HitRecord, _direction: Vec3A) -> Float {
+        0.0
+    }
+
+    fn emit(
+        &self,
+        _u: Float,
+        _v: Float,
+        _p: Point3,
+        _texture_map: &SlotMap<TextureKey, Texture>,
+    ) -> Rgba {
+        Rgba::new(1.0, 0.0, 1.0, 1.0)
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+// Custom materials are written as their name
+#[cfg(feature = "serde")]
+mod custom_bsdf {
+    use super::*;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[allow(clippy::borrowed_box)]
+    pub fn serialize<S: Serializer>(
+        bsdf: &Box<dyn Bsdf>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&bsdf.name())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Box<dyn Bsdf>, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(Box::new(MissingBsdf { name }))
+    }
 }
 
 impl Default for Material {
@@ -73,6 +572,15 @@ fn lambertian_scatter(
     texture_map: &SlotMap<TextureKey, Texture>,
     rng: &mut impl Rng,
 ) -> ScatterResult {
+    let color = match texture_map.get(*albedo) {
+        Some(texture) => texture.value(rec.u, rec.v, rec.point, texture_map),
+        None => Rgba::new(1.0, 0.0, 1.0, 1.0),
+    };
+    diffuse_scatter(color, rec, rng)
+}
+
+// Cosine weighted about the normal, for Lambertian surfaces of a known color
+pub(crate) fn diffuse_scatter(color: Rgba, rec: &HitRecord, rng: &mut impl Rng) -> ScatterResult {
     let mut scatter_dir = rec.normal + sample_unit_sphere(rng);
 
     if near_zero(scatter_dir) {
@@ -84,6 +592,22 @@ fn lambertian_scatter(
             origin: rec.point,
             direction: scatter_dir,
         },
+        color,
+    }
+}
+
+#[inline]
+fn isotropic_scatter(
+    albedo: &TextureKey,
+    rec: &HitRecord,
+    texture_map: &SlotMap<TextureKey, Texture>,
+    rng: &mut impl Rng,
+) -> ScatterResult {
+    ScatterResult::Scattered {
+        ray_out: Ray3A {
+            origin: rec.point,
+            direction: sample_unit_sphere(rng),
+        },
         color: match texture_map.get(*albedo) {
             Some(texture) => texture.value(rec.u, rec.v, rec.point, texture_map),
             None => Rgba::new(1.0, 0.0, 1.0, 1.0),
@@ -120,6 +644,44 @@ fn metal_scatter(
     };
 }
 
+#[inline]
+fn conductor_scatter(
+    eta: &[Float; 3],
+    k: &[Float; 3],
+    fuzz: Float,
+    ray_in: &Ray3A,
+    rec: &HitRecord,
+    rng: &mut impl Rng,
+) -> ScatterResult {
+    let unit_dir = ray_in.direction.normalize();
+    let cos_theta = Vec3A::dot(-unit_dir, rec.normal).clamp(0.0, 1.0);
+
+    let mirror = reflect(unit_dir, rec.normal);
+    let mut direction = mirror + fuzz * sample_unit_sphere(rng);
+    // Fuzz that points into the surface is folded back out rather than absorbed, so
+    // rough conductors reflect all the Fresnel term lets through, as smooth ones do
+    let below = Vec3A::dot(direction, rec.normal);
+    if below <= 0.0 {
+        direction -= 2.0 * below * rec.normal;
+        if near_zero(direction) {
+            direction = mirror;
+        }
+    }
+
+    ScatterResult::Scattered {
+        ray_out: Ray3A {
+            origin: rec.point,
+            direction,
+        },
+        color: Rgba::new(
+            conductor_reflectance(cos_theta, eta[0], k[0]),
+            conductor_reflectance(cos_theta, eta[1], k[1]),
+            conductor_reflectance(cos_theta, eta[2], k[2]),
+            1.0,
+        ),
+    }
+}
+
 #[inline]
 fn dielectric_scatter(
     ir: Float,
@@ -153,6 +715,300 @@ fn dielectric_scatter(
     }
 }
 
+// Light that refracts in is tinted for the leg below the surface, going down after
+// entering and coming up before leaving
+fn water_scatter(
+    ir: Float,
+    absorption: &[Float; 3],
+    depth: Float,
+    ray_in: &Ray3A,
+    rec: &HitRecord,
+    rng: &mut impl Rng,
+) -> ScatterResult {
+    match dielectric_scatter(ir, ray_in, rec, rng) {
+        ScatterResult::Scattered { ray_out, .. }
+            if depth > 0.0 && ray_out.direction.dot(rec.normal) < 0.0 =>
+        {
+            let underwater = match rec.face {
+                Face::Front => ray_out.direction,
+                Face::Back => ray_in.direction.normalize(),
+            };
+            // Grazing legs are capped rather than running off to infinity
+            let length = depth / underwater.dot(rec.normal).abs().max(0.05);
+            let tint = |a: Float| (-a * length).exp();
+            ScatterResult::Scattered {
+                ray_out,
+                color: Rgba::new(
+                    tint(absorption[0]),
+                    tint(absorption[1]),
+                    tint(absorption[2]),
+                    1.0,
+                ),
+            }
+        }
+        scattered => scattered,
+    }
+}
+
+#[inline]
+fn rough_metal_scatter(
+    albedo: &TextureKey,
+    roughness: Float,
+    ray_in: &Ray3A,
+    rec: &HitRecord,
+    texture_map: &SlotMap<TextureKey, Texture>,
+    rng: &mut impl Rng,
+) -> ScatterResult {
+    let f0 = match texture_map.get(*albedo) {
+        Some(texture) => texture.value(rec.u, rec.v, rec.point, texture_map),
+        None => Rgba::new(1.0, 0.0, 1.0, 1.0),
+    };
+    ggx_metal_scatter(f0, roughness, ray_in, rec, rng)
+}
+
+// GGX reflection with the energy lost between microfacets added back as a second
+// lobe (Kulla-Conty), so rough metals don't darken. Picks between the two lobes by
+// the fraction single scattering reflects.
+#[inline]
+fn ggx_metal_scatter(
+    f0: Rgba,
+    roughness: Float,
+    ray_in: &Ray3A,
+    rec: &HitRecord,
+    rng: &mut impl Rng,
+) -> ScatterResult {
+    let ggx = Ggx::new(roughness);
+    let cos_o = -ray_in.direction.normalize().dot(rec.normal);
+    let single = ggx.albedo(cos_o).max(Float::EPSILON);
+    if rng.gen::<Float>() < single {
+        return match ggx_reflect_scatter(f0, roughness, ray_in, rec, rng) {
+            ScatterResult::Scattered { ray_out, color } => ScatterResult::Scattered {
+                ray_out,
+                color: color * (1.0 / single),
+            },
+            absorbed => absorbed,
+        };
+    }
+
+    // Cosine weighted, which leaves only the albedo terms of the lobe
+    let direction = (rec.normal + sample_unit_sphere(rng)).normalize_or_zero();
+    let cos_i = direction.dot(rec.normal);
+    if cos_o <= 0.0 || cos_i <= 0.0 {
+        return ScatterResult::Absorbed;
+    }
+    let average = ggx.average_albedo();
+    ScatterResult::Scattered {
+        ray_out: Ray3A {
+            origin: rec.point,
+            direction,
+        },
+        color: multiscatter_fresnel(f0, average)
+            * ((1.0 - ggx.albedo(cos_i)) / (1.0 - average).max(Float::EPSILON)),
+    }
+}
+
+#[inline]
+fn ggx_reflect_scatter(
+    f0: Rgba,
+    roughness: Float,
+    ray_in: &Ray3A,
+    rec: &HitRecord,
+    rng: &mut impl Rng,
+) -> ScatterResult {
+    let wo = -ray_in.direction.normalize();
+    let ggx = Ggx::new(roughness);
+    let h = ggx.sample_visible(wo, rec.normal, rng);
+    let wi = reflect(-wo, h);
+
+    let (cos_o, cos_i) = (wo.dot(rec.normal), wi.dot(rec.normal));
+    if cos_o <= 0.0 || cos_i <= 0.0 {
+        return ScatterResult::Absorbed;
+    }
+
+    ScatterResult::Scattered {
+        ray_out: Ray3A {
+            origin: rec.point,
+            direction: wi,
+        },
+        // Visible normal sampling leaves only Fresnel and the masking ratio
+        color: schlick(f0, wo.dot(h)) * (ggx.g2(cos_o, cos_i) / ggx.g1(cos_o)),
+    }
+}
+
+// Smooth glass has a single reflected and refracted direction, rough glass spreads them
+fn glass_scatter(
+    ir: Float,
+    roughness: Float,
+    ray_in: &Ray3A,
+    rec: &HitRecord,
+    rng: &mut impl Rng,
+) -> ScatterResult {
+    match roughness > 0.0 {
+        true => rough_dielectric_scatter(ir, roughness, ray_in, rec, rng),
+        false => dielectric_scatter(ir, ray_in, rec, rng),
+    }
+}
+
+#[inline]
+fn rough_dielectric_scatter(
+    ir: Float,
+    roughness: Float,
+    ray_in: &Ray3A,
+    rec: &HitRecord,
+    rng: &mut impl Rng,
+) -> ScatterResult {
+    let wo = -ray_in.direction.normalize();
+    let cos_o = wo.dot(rec.normal);
+    if cos_o <= 0.0 {
+        return ScatterResult::Absorbed;
+    }
+
+    let eta = relative_ior(ir, rec);
+    let ggx = Ggx::new(roughness);
+    let h = ggx.sample_visible(wo, rec.normal, rng);
+    let reflected = fresnel_dielectric(wo.dot(h), eta) > rng.gen();
+    let wi = match reflected {
+        true => reflect(-wo, h),
+        false => refract(-wo, h, 1.0 / eta),
+    };
+
+    // Reflections must stay above the surface and refractions below it
+    let cos_i = wi.dot(rec.normal);
+    if (cos_i > 0.0) != reflected || cos_i == 0.0 {
+        return ScatterResult::Absorbed;
+    }
+
+    ScatterResult::Scattered {
+        ray_out: Ray3A {
+            origin: rec.point,
+            direction: wi,
+        },
+        color: Rgba::splat(ggx.g2(cos_o, cos_i.abs()) / ggx.g1(cos_o)),
+    }
+}
+
+// Ratio of the index on the far side of the surface to the near side
+#[inline]
+fn relative_ior(ir: Float, rec: &HitRecord) -> Float {
+    match rec.face {
+        Face::Front => ir,
+        Face::Back => 1.0 / ir,
+    }
+}
+
+// BSDF times cosine, and sampling density, of a rough dielectric (Walter et al. 2007)
+fn rough_dielectric_eval(
+    ggx: &Ggx,
+    eta: Float,
+    wo: Vec3A,
+    wi: Vec3A,
+    normal: Vec3A,
+) -> (Float, Float) {
+    let (cos_o, cos_i) = (wo.dot(normal), wi.dot(normal));
+    if cos_o <= 0.0 || cos_i == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let reflected = cos_i > 0.0;
+    let h = match reflected {
+        true => wo + wi,
+        false => wo + eta * wi,
+    };
+    let h = match h.try_normalize() {
+        Some(h) if h.dot(normal) < 0.0 => -h,
+        Some(h) => h,
+        None => return (0.0, 0.0),
+    };
+    let (cos_oh, cos_ih) = (wo.dot(h), wi.dot(h));
+    if cos_oh <= 0.0 || (!reflected && cos_ih >= 0.0) {
+        return (0.0, 0.0);
+    }
+
+    let fresnel = fresnel_dielectric(cos_oh, eta);
+    let d = ggx.d(h.dot(normal));
+    let g2 = ggx.g2(cos_o, cos_i.abs());
+    let visible = ggx.visible_pdf(wo, normal, h);
+
+    match reflected {
+        true => (
+            fresnel * d * g2 / (4.0 * cos_o),
+            fresnel * visible / (4.0 * cos_oh),
+        ),
+        false => {
+            let denom = cos_oh + eta * cos_ih;
+            let jacobian = eta * eta * -cos_ih / (denom * denom);
+            (
+                (1.0 - fresnel) * d * g2 * cos_oh * jacobian / cos_o,
+                (1.0 - fresnel) * visible * jacobian,
+            )
+        }
+    }
+}
+
+// BSDF times cosine, and sampling density, of GGX reflection with Schlick Fresnel
+fn ggx_reflect_eval(ggx: &Ggx, f0: Rgba, wo: Vec3A, wi: Vec3A, normal: Vec3A) -> (Rgba, Float) {
+    let (cos_o, cos_i) = (wo.dot(normal), wi.dot(normal));
+    let h = (wo + wi).normalize();
+    if cos_o <= 0.0 || cos_i <= 0.0 || wo.dot(h) <= 0.0 {
+        return (Rgba::ZERO, 0.0);
+    }
+    (
+        schlick(f0, wo.dot(h)) * (ggx.d(h.dot(normal)) * ggx.g2(cos_o, cos_i) / (4.0 * cos_o)),
+        ggx.visible_pdf(wo, normal, h) / (4.0 * wo.dot(h)),
+    )
+}
+
+// Both lobes of `ggx_metal_scatter`, with its pdf for choosing between them
+#[inline]
+fn ggx_metal_eval(ggx: &Ggx, f0: Rgba, wo: Vec3A, wi: Vec3A, normal: Vec3A) -> (Rgba, Float) {
+    let (cos_o, cos_i) = (wo.dot(normal), wi.dot(normal));
+    if cos_o <= 0.0 || cos_i <= 0.0 {
+        return (Rgba::ZERO, 0.0);
+    }
+    let (single, single_pdf) = ggx_reflect_eval(ggx, f0, wo, wi, normal);
+    let (e_o, e_i, average) = (ggx.albedo(cos_o), ggx.albedo(cos_i), ggx.average_albedo());
+    let multiple = multiscatter_fresnel(f0, average)
+        * ((1.0 - e_o) * (1.0 - e_i) * cos_i / (PI * (1.0 - average).max(Float::EPSILON)));
+    (
+        single + multiple,
+        e_o * single_pdf + (1.0 - e_o) * cos_i / PI,
+    )
+}
+
+// Fresnel for light that bounced several times between microfacets, from the
+// hemispherical average of Schlick's approximation
+#[inline]
+fn multiscatter_fresnel(f0: Rgba, average_albedo: Float) -> Rgba {
+    let f0 = f0.to_array();
+    let f = |c: usize| {
+        let f_avg = (20.0 * f0[c] + 1.0) / 21.0;
+        f_avg * f_avg * average_albedo / (1.0 - f_avg * (1.0 - average_albedo))
+    };
+    Rgba::new(f(0), f(1), f(2), 1.0)
+}
+
+// Chance of picking the metal, glass and coated diffuse lobes, which are also their
+// weights in the mix
+#[inline]
+fn principled_lobes(metallic: Float, transmission: Float) -> [Float; 3] {
+    let metal = metallic.clamp(0.0, 1.0);
+    let glass = (1.0 - metal) * transmission.clamp(0.0, 1.0);
+    [metal, glass, 1.0 - metal - glass]
+}
+
+// Fraction of light the coat reflects, as seen from the outgoing direction
+#[inline]
+fn principled_coat(specular: Float, cos_o: Float) -> Float {
+    let f0 = (0.08 * specular).clamp(0.0, 1.0);
+    f0 + (1.0 - f0) * (1.0 - cos_o.clamp(0.0, 1.0)).powi(5)
+}
+
+#[inline]
+fn schlick(f0: Rgba, cosine: Float) -> Rgba {
+    let w = (1.0 - cosine.clamp(0.0, 1.0)).powi(5);
+    f0 * (1.0 - w) + Rgba::splat(w)
+}
+
 #[inline]
 fn sample_unit_sphere<R: Rng>(rng: &mut R) -> Vec3A {
     (rng.gen::<Vec3A>() - 0.5 * Vec3A::ONE).normalize()
@@ -178,8 +1034,203 @@ fn reflectance(cosine: Float, ref_idx: Float) -> Float {
     r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
 }
 
+// Exact unpolarized Fresnel reflectance for an interface from air into a conductor
+#[inline]
+pub(crate) fn conductor_reflectance(cos_theta: Float, eta: Float, k: Float) -> Float {
+    let cos2 = cos_theta * cos_theta;
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_theta * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    (0.5 * (rs + rp)).clamp(0.0, 1.0)
+}
+
 #[inline]
 pub fn near_zero(v: Vec3A) -> bool {
     const ETA: Float = 1e-8;
     (v.x.abs() < ETA) && (v.y.abs() < ETA) && (v.z.abs() < ETA)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn rough_metal_passes_the_white_furnace() {
+        // A perfect reflector under uniform white light must reflect all of it
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut textures = SlotMap::with_key();
+        let white = textures.insert(Texture::Solid { color: Rgba::ONE });
+        let normal = Vec3A::Y;
+        for roughness in [0.2, 0.6, 1.0].iter() {
+            let metal = Material::RoughMetal {
+                albedo: white,
+                roughness: *roughness,
+            };
+            for cos_o in [0.2 as Float, 0.6, 1.0].iter() {
+                let wo = Vec3A::new((1.0 - cos_o * cos_o).sqrt(), *cos_o, 0.0);
+                let ray_in = Ray3A {
+                    origin: wo,
+                    direction: -wo,
+                };
+                let rec = HitRecord {
+                    point: Point3::ZERO,
+                    normal,
+                    u: 0.0,
+                    v: 0.0,
+                    face: Face::Front,
+                    material_key: Default::default(),
+                };
+
+                let samples = 50_000;
+                let reflected: Float = (0..samples)
+                    .map(
+                        |_| match metal.scatter(&ray_in, &rec, &textures, &mut rng) {
+                            ScatterResult::Scattered { color, .. } => color.to_array()[0],
+                            ScatterResult::Absorbed => 0.0,
+                        },
+                    )
+                    .sum::<Float>()
+                    / samples as Float;
+                assert!(
+                    (reflected - 1.0).abs() < 0.03,
+                    "roughness {} cos {}: {}",
+                    roughness,
+                    cos_o,
+                    reflected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fuzzed_conductors_reflect_their_whole_fresnel_term() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let textures = SlotMap::with_key();
+        let gold = Material::conductor_preset("gold", 1.0).unwrap();
+        let (eta, k) = match &gold {
+            Material::Conductor { eta, k, .. } => (eta[0], k[0]),
+            _ => unreachable!(),
+        };
+        let cos_o: Float = 0.2;
+        let wo = Vec3A::new((1.0 - cos_o * cos_o).sqrt(), cos_o, 0.0);
+        let ray_in = Ray3A {
+            origin: wo,
+            direction: -wo,
+        };
+        let rec = HitRecord {
+            point: Point3::ZERO,
+            normal: Vec3A::Y,
+            u: 0.0,
+            v: 0.0,
+            face: Face::Front,
+            material_key: Default::default(),
+        };
+
+        // Near grazing most of the fuzz ball lies below the surface
+        for _ in 0..10_000 {
+            match gold.scatter(&ray_in, &rec, &textures, &mut rng) {
+                ScatterResult::Scattered { ray_out, color } => {
+                    assert!(ray_out.direction.dot(Vec3A::Y) >= 0.0);
+                    assert_eq!(color.to_array()[0], conductor_reflectance(cos_o, eta, k));
+                }
+                ScatterResult::Absorbed => panic!("fuzzed gold absorbed a ray"),
+            }
+        }
+    }
+
+    // Half the light bounces back uniformly over the hemisphere
+    #[derive(Debug)]
+    struct GreyHemisphere;
+
+    impl Bsdf for GreyHemisphere {
+        fn sample(
+            &self,
+            _ray_in: &Ray3A,
+            rec: &HitRecord,
+            _texture_map: &SlotMap<TextureKey, Texture>,
+            mut rng: &mut dyn RngCore,
+        ) -> ScatterResult {
+            let direction = sample_unit_sphere(&mut rng);
+            let direction = match direction.dot(rec.normal) < 0.0 {
+                true => -direction,
+                false => direction,
+            };
+            ScatterResult::Scattered {
+                ray_out: Ray3A {
+                    origin: rec.point,
+                    direction,
+                },
+                color: Rgba::splat(0.5 * 2.0 * direction.normalize().dot(rec.normal)),
+            }
+        }
+
+        fn eval(
+            &self,
+            _ray_in: &Ray3A,
+            rec: &HitRecord,
+            direction: Vec3A,
+            _texture_map: &SlotMap<TextureKey, Texture>,
+        ) -> Rgba {
+            Rgba::splat(0.5 / PI * direction.normalize().dot(rec.normal).max(0.0))
+        }
+
+        fn pdf(&self, _ray_in: &Ray3A, rec: &HitRecord, direction: Vec3A) -> Float {
+            match direction.dot(rec.normal) > 0.0 {
+                true => 1.0 / (2.0 * PI),
+                false => 0.0,
+            }
+        }
+    }
+
+    #[test]
+    fn custom_bsdfs_are_called_through_the_material() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let textures = SlotMap::with_key();
+        let material = Material::custom(GreyHemisphere);
+        let ray_in = Ray3A {
+            origin: Vec3A::Y,
+            direction: -Vec3A::Y,
+        };
+        let rec = HitRecord {
+            point: Point3::ZERO,
+            normal: Vec3A::Y,
+            u: 0.0,
+            v: 0.0,
+            face: Face::Front,
+            material_key: Default::default(),
+        };
+
+        match material.scatter(&ray_in, &rec, &textures, &mut rng) {
+            ScatterResult::Scattered { ray_out, color } => {
+                let d = ray_out.direction;
+                let weight = material.eval(&ray_in, &rec, d, &textures).to_array()[0]
+                    / material.pdf(&ray_in, &rec, d);
+                assert!((color.to_array()[0] - weight).abs() < 1e-4);
+            }
+            ScatterResult::Absorbed => panic!("custom material absorbed the ray"),
+        }
+        assert_eq!(
+            material.emit(0.0, 0.0, Point3::ZERO, &textures).to_array(),
+            Rgba::ZERO.to_array()
+        );
+        assert_eq!(
+            material
+                .albedo(0.0, 0.0, Point3::ZERO, &textures)
+                .to_array(),
+            Rgba::ONE.to_array()
+        );
+    }
+}
@@ -0,0 +1,244 @@
+use crate::{Float, Vec3A};
+
+use rand::Rng;
+use std::sync::OnceLock;
+
+const PI: Float = std::f64::consts::PI as Float;
+
+// Roughness and cosine steps in the directional albedo table
+const ALBEDO_RESOLUTION: usize = 32;
+// Stratified samples per side for each table entry
+const ALBEDO_STRATA: usize = 24;
+
+static ALBEDO_TABLE: OnceLock<AlbedoTable> = OnceLock::new();
+
+// Fraction of light single scattering GGX reflects with perfect Fresnel reflectance,
+// indexed by roughness and then the outgoing cosine, with its cosine weighted
+// average per roughness. The rest is lost to paths that bounce between
+// microfacets, which Kulla-Conty compensation adds back.
+struct AlbedoTable {
+    albedo: Vec<[Float; ALBEDO_RESOLUTION]>,
+    average: [Float; ALBEDO_RESOLUTION],
+}
+
+impl AlbedoTable {
+    fn get() -> &'static Self {
+        ALBEDO_TABLE.get_or_init(Self::compute)
+    }
+
+    fn compute() -> Self {
+        let step = 1.0 / (ALBEDO_RESOLUTION - 1) as Float;
+        let albedo: Vec<_> = (0..ALBEDO_RESOLUTION)
+            .map(|r| {
+                let ggx = Ggx::new(r as Float * step);
+                let mut row = [0.0; ALBEDO_RESOLUTION];
+                for (c, e) in row.iter_mut().enumerate() {
+                    let cos_o = (c as Float * step).max(1e-3);
+                    *e = ggx.single_scatter_albedo(cos_o);
+                }
+                row
+            })
+            .collect();
+
+        let mut average = [0.0; ALBEDO_RESOLUTION];
+        for (avg, row) in average.iter_mut().zip(albedo.iter()) {
+            // Trapezoid rule for 2 * integral of E(mu) mu over [0, 1]
+            *avg = (0..ALBEDO_RESOLUTION)
+                .map(|c| {
+                    let mu = c as Float * step;
+                    let weight = if c == 0 || c == ALBEDO_RESOLUTION - 1 {
+                        0.5
+                    } else {
+                        1.0
+                    };
+                    weight * row[c] * mu
+                })
+                .sum::<Float>()
+                * 2.0
+                * step;
+        }
+
+        Self { albedo, average }
+    }
+
+    // Bilinear in roughness and cosine
+    fn albedo(&self, roughness: Float, cos: Float) -> Float {
+        let (r0, r1, tr) = Self::cell(roughness);
+        let (c0, c1, tc) = Self::cell(cos);
+        let row = |r: usize| self.albedo[r][c0] + tc * (self.albedo[r][c1] - self.albedo[r][c0]);
+        row(r0) + tr * (row(r1) - row(r0))
+    }
+
+    fn average(&self, roughness: Float) -> Float {
+        let (r0, r1, t) = Self::cell(roughness);
+        self.average[r0] + t * (self.average[r1] - self.average[r0])
+    }
+
+    fn cell(x: Float) -> (usize, usize, Float) {
+        let x = x.clamp(0.0, 1.0) * (ALBEDO_RESOLUTION - 1) as Float;
+        let i = (x as usize).min(ALBEDO_RESOLUTION - 2);
+        (i, i + 1, x - i as Float)
+    }
+}
+
+// Trowbridge-Reitz (GGX) normal distribution with Smith height-correlated masking.
+// Directions point away from the surface and cosines are taken against the
+// macro surface normal.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Ggx {
+    alpha: Float,
+}
+
+impl Ggx {
+    // Perceptually linear roughness, squared to get alpha. Clamped away from a
+    // perfect mirror where the distribution degenerates to a delta.
+    pub(crate) fn new(roughness: Float) -> Self {
+        Self {
+            alpha: (roughness * roughness).max(1e-3),
+        }
+    }
+
+    pub(crate) fn d(&self, cos_h: Float) -> Float {
+        if cos_h <= 0.0 {
+            return 0.0;
+        }
+        let a2 = self.alpha * self.alpha;
+        let t = cos_h * cos_h * (a2 - 1.0) + 1.0;
+        a2 / (PI * t * t)
+    }
+
+    fn lambda(&self, cos: Float) -> Float {
+        let cos2 = (cos * cos).max(Float::EPSILON);
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        0.5 * (-1.0 + (1.0 + self.alpha * self.alpha * tan2).sqrt())
+    }
+
+    pub(crate) fn g1(&self, cos: Float) -> Float {
+        1.0 / (1.0 + self.lambda(cos))
+    }
+
+    pub(crate) fn g2(&self, cos_o: Float, cos_i: Float) -> Float {
+        1.0 / (1.0 + self.lambda(cos_o) + self.lambda(cos_i))
+    }
+
+    // Density of `sample_visible` choosing micro normal `h`
+    pub(crate) fn visible_pdf(&self, wo: Vec3A, normal: Vec3A, h: Vec3A) -> Float {
+        let cos_o = wo.dot(normal);
+        if cos_o <= 0.0 {
+            return 0.0;
+        }
+        self.g1(cos_o) * wo.dot(h).max(0.0) * self.d(h.dot(normal)) / cos_o
+    }
+
+    // Samples micro normals visible from `wo` (Heitz 2018)
+    pub(crate) fn sample_visible(&self, wo: Vec3A, normal: Vec3A, rng: &mut impl Rng) -> Vec3A {
+        self.visible_normal(wo, normal, rng.gen(), rng.gen())
+    }
+
+    // The micro normal `sample_visible` picks for the uniform numbers `u1` and `u2`
+    fn visible_normal(&self, wo: Vec3A, normal: Vec3A, u1: Float, u2: Float) -> Vec3A {
+        let tangent = normal.any_orthonormal_vector();
+        let bitangent = normal.cross(tangent);
+        let local = Vec3A::new(wo.dot(tangent), wo.dot(bitangent), wo.dot(normal));
+
+        // Stretch to the hemisphere configuration
+        let vh = Vec3A::new(self.alpha * local.x, self.alpha * local.y, local.z).normalize();
+        let length2 = vh.x * vh.x + vh.y * vh.y;
+        let t1 = match length2 > 0.0 {
+            true => Vec3A::new(-vh.y, vh.x, 0.0) / length2.sqrt(),
+            false => Vec3A::X,
+        };
+        let t2 = vh.cross(t1);
+
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+        // Unstretch back to the ellipsoid
+        let h = Vec3A::new(self.alpha * nh.x, self.alpha * nh.y, nh.z.max(0.0)).normalize();
+        h.x * tangent + h.y * bitangent + h.z * normal
+    }
+
+    fn roughness(&self) -> Float {
+        self.alpha.sqrt()
+    }
+
+    // Reflected fraction for light leaving at `cos_o`, from a precomputed table
+    pub(crate) fn albedo(&self, cos_o: Float) -> Float {
+        AlbedoTable::get().albedo(self.roughness(), cos_o)
+    }
+
+    // `albedo` averaged over the hemisphere weighted by cosine
+    pub(crate) fn average_albedo(&self) -> Float {
+        AlbedoTable::get().average(self.roughness())
+    }
+
+    // Stratified estimate of the reflected fraction, for building the table
+    fn single_scatter_albedo(&self, cos_o: Float) -> Float {
+        let normal = Vec3A::Z;
+        let wo = Vec3A::new((1.0 - cos_o * cos_o).sqrt(), 0.0, cos_o);
+        let mut total = 0.0;
+        for i in 0..ALBEDO_STRATA {
+            for j in 0..ALBEDO_STRATA {
+                let u1 = (i as Float + 0.5) / ALBEDO_STRATA as Float;
+                let u2 = (j as Float + 0.5) / ALBEDO_STRATA as Float;
+                let h = self.visible_normal(wo, normal, u1, u2);
+                let cos_i = (2.0 * wo.dot(h) * h - wo).z;
+                if cos_i > 0.0 {
+                    total += self.g2(cos_o, cos_i) / self.g1(cos_o);
+                }
+            }
+        }
+        total / (ALBEDO_STRATA * ALBEDO_STRATA) as Float
+    }
+}
+
+// Exact unpolarized Fresnel reflectance for a dielectric interface, where `eta` is the
+// ratio of the transmitted to incident index. Returns one under total internal reflection.
+pub(crate) fn fresnel_dielectric(cos_i: Float, eta: Float) -> Float {
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn visible_normals_match_their_pdf() {
+        // Integrate the pdf over the sphere by uniform sampling, it should come to one
+        let mut rng = StdRng::seed_from_u64(7);
+        let normal = Vec3A::Y;
+        let wo = Vec3A::new(0.6, 0.8, 0.0);
+        for roughness in [0.3, 0.7].iter() {
+            let ggx = Ggx::new(*roughness);
+            let samples = 200_000;
+            let mut total = 0.0;
+            for _ in 0..samples {
+                let z = 2.0 * rng.gen::<Float>() - 1.0;
+                let phi = 2.0 * PI * rng.gen::<Float>();
+                let r = (1.0 - z * z).sqrt();
+                let h = Vec3A::new(r * phi.cos(), z, r * phi.sin());
+                total += ggx.visible_pdf(wo, normal, h) * 4.0 * PI;
+            }
+            let integral = total / samples as Float;
+            assert!((integral - 1.0).abs() < 0.05, "{}: {}", roughness, integral);
+
+            for _ in 0..100 {
+                let h = ggx.sample_visible(wo, normal, &mut rng);
+                assert!((h.length() - 1.0).abs() < 1e-4 && h.dot(normal) >= 0.0);
+                assert!(wo.dot(h) >= -1e-4);
+            }
+        }
+    }
+}
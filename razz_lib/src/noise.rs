@@ -2,7 +2,8 @@ use crate::{Float, Point3, Vec3A};
 
 use rand::{distributions::Uniform, Rng};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Noise {
     Perlin(PerlinData),
     Turbulent(PerlinData, usize),
@@ -26,6 +27,11 @@ impl Noise {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "PerlinTables", try_from = "PerlinTables")
+)]
 pub struct PerlinData {
     ranvec: [Vec3A; Self::POINT_COUNT],
     perm_x: [usize; Self::POINT_COUNT],
@@ -137,6 +143,50 @@ impl PerlinData {
 
         accum.abs()
     }
+
+    // Signed fractal sum of `octaves` layers, roughly in [-1, 1]
+    #[inline]
+    pub fn fbm(&self, p: Point3, octaves: usize) -> Float {
+        let mut accum = 0.0;
+        let mut norm = 0.0;
+        let mut temp_p = p;
+        let mut weight = 1.0;
+
+        for _ in 0..octaves {
+            accum += weight * self.noise(temp_p);
+            norm += weight;
+            weight *= 0.5;
+            temp_p *= 2.0;
+        }
+
+        match norm > 0.0 {
+            true => accum / norm,
+            false => 0.0,
+        }
+    }
+
+    // Distance to the nearest feature point, with one point jittered inside each
+    // unit cell. Reuses the permutation tables as the cell hash.
+    #[inline]
+    pub fn worley(&self, p: Point3) -> Float {
+        let cell = p.floor();
+        let mut nearest = Float::INFINITY;
+
+        for di in -1..=1 {
+            for dj in -1..=1 {
+                for dk in -1..=1 {
+                    let c = cell + Vec3A::new(di as Float, dj as Float, dk as Float);
+                    let hash = self.perm_x[(c.x as isize & 255) as usize]
+                        ^ self.perm_y[(c.y as isize & 255) as usize]
+                        ^ self.perm_z[(c.z as isize & 255) as usize];
+                    let feature = c + 0.5 * (Vec3A::ONE + self.ranvec[hash]);
+                    nearest = nearest.min((feature - p).length_squared());
+                }
+            }
+        }
+
+        nearest.sqrt()
+    }
 }
 
 #[cfg(test)]
@@ -151,3 +201,50 @@ mod tests {
         let _perlin = Noise::perlin(&mut rng);
     }
 }
+
+// Serde only derives fixed size arrays up to 32 elements
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct PerlinTables {
+    ranvec: Vec<Vec3A>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+#[cfg(feature = "serde")]
+impl From<PerlinData> for PerlinTables {
+    fn from(data: PerlinData) -> Self {
+        Self {
+            ranvec: data.ranvec.to_vec(),
+            perm_x: data.perm_x.to_vec(),
+            perm_y: data.perm_y.to_vec(),
+            perm_z: data.perm_z.to_vec(),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<PerlinTables> for PerlinData {
+    type Error = String;
+
+    fn try_from(tables: PerlinTables) -> Result<Self, Self::Error> {
+        use std::convert::TryInto;
+
+        let count = PerlinData::POINT_COUNT;
+        let error = |name: &str| format!("perlin table `{}` must have {} entries", name, count);
+        let perm = |table: Vec<usize>, name: &str| -> Result<[usize; PerlinData::POINT_COUNT], _> {
+            if table.iter().any(|i| *i >= count) {
+                return Err(format!("perlin table `{}` indexes out of range", name));
+            }
+            table.try_into().map_err(|_| error(name))
+        };
+
+        Ok(Self {
+            ranvec: tables.ranvec.try_into().map_err(|_| error("ranvec"))?,
+            perm_x: perm(tables.perm_x, "perm_x")?,
+            perm_y: perm(tables.perm_y, "perm_y")?,
+            perm_z: perm(tables.perm_z, "perm_z")?,
+        })
+    }
+}
@@ -0,0 +1,176 @@
+use crate::AssetResolver;
+
+use std::io::{self, Read, Write};
+use std::path::{Component, Path};
+
+pub const PACK_EXTENSION: &str = "razzpack";
+
+const PACK_MAGIC: &[u8; 8] = b"RAZZPACK";
+const PACK_VERSION: u32 = 1;
+
+// A scene file and every asset it references in one file, for sharing scenes and
+// attaching exact reproductions to bug reports. The layout is uncompressed:
+//   magic "RAZZPACK", version u32, entry count u32, then per entry
+//   name length u32, utf-8 name, data length u64, data
+// Integers are little endian. The first entry is always the scene file and the
+// rest are keyed by the relative path the scene asked for them with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScenePack {
+    entries: Vec<(String, Vec<u8>)>,
+}
+
+impl ScenePack {
+    pub fn new(scene_name: &str, scene: Vec<u8>) -> Self {
+        Self {
+            entries: vec![(pack_name(scene_name), scene)],
+        }
+    }
+
+    // Packs `scene_file` with every asset `assets` has read so far, so load the
+    // scene through `assets` first
+    pub fn package(scene_file: impl AsRef<Path>, assets: &AssetResolver) -> io::Result<Self> {
+        let scene_file = scene_file.as_ref();
+        let scene_name = scene_file
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut pack = Self::new(&scene_name, std::fs::read(scene_file)?);
+        for path in assets.requested() {
+            pack.insert(&pack_name(&path), assets.read(&path)?);
+        }
+        Ok(pack)
+    }
+
+    pub fn scene_name(&self) -> &str {
+        &self.entries[0].0
+    }
+
+    pub fn scene(&self) -> &[u8] {
+        &self.entries[0].1
+    }
+
+    // Replaces any entry already stored under `name`
+    pub fn insert(&mut self, name: &str, data: Vec<u8>) {
+        let name = pack_name(name);
+        match self.entries.iter_mut().find(|(n, _)| *n == name) {
+            Some(entry) => entry.1 = data,
+            None => self.entries.push((name, data)),
+        }
+    }
+
+    pub fn get(&self, name: impl AsRef<Path>) -> Option<&[u8]> {
+        let name = pack_name(name);
+        self.entries
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, data)| data.as_slice())
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(PACK_MAGIC)?;
+        writer.write_all(&PACK_VERSION.to_le_bytes())?;
+        writer.write_all(&(self.entries.len() as u32).to_le_bytes())?;
+        for (name, data) in self.entries.iter() {
+            writer.write_all(&(name.len() as u32).to_le_bytes())?;
+            writer.write_all(name.as_bytes())?;
+            writer.write_all(&(data.len() as u64).to_le_bytes())?;
+            writer.write_all(data)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from(mut reader: impl Read) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != PACK_MAGIC {
+            return Err(invalid("not a razzpack file"));
+        }
+        if read_u32(&mut reader)? != PACK_VERSION {
+            return Err(invalid("unsupported razzpack version"));
+        }
+
+        let count = read_u32(&mut reader)?;
+        if count == 0 {
+            return Err(invalid("razzpack has no scene"));
+        }
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            let mut name = vec![0; read_u32(&mut reader)? as usize];
+            reader.read_exact(&mut name)?;
+            let name = String::from_utf8(name).map_err(|_| invalid("entry name isn't utf-8"))?;
+
+            let mut length = [0; 8];
+            reader.read_exact(&mut length)?;
+            let mut data = Vec::new();
+            (&mut reader)
+                .take(u64::from_le_bytes(length))
+                .read_to_end(&mut data)?;
+            if data.len() as u64 != u64::from_le_bytes(length) {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            entries.push((name, data));
+        }
+
+        Ok(Self { entries })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.write_to(io::BufWriter::new(std::fs::File::create(path)?))
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read_from(io::BufReader::new(std::fs::File::open(path)?))
+    }
+}
+
+// Entries are named with forward slashes and no leading root or `.` parts
+fn pack_name(path: impl AsRef<Path>) -> String {
+    path.as_ref()
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            Component::ParentDir => Some("..".to_string()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_round_trip_and_serve_assets() {
+        let mut pack = ScenePack::new("scene.json", b"{}".to_vec());
+        pack.insert("./obj/mesh.obj", b"v 0 0 0".to_vec());
+        pack.insert("./textures/tex.png", vec![1, 2, 3]);
+        pack.insert("obj/mesh.obj", b"v 1 1 1".to_vec());
+
+        let mut bytes = Vec::new();
+        pack.write_to(&mut bytes).unwrap();
+        let loaded = ScenePack::read_from(bytes.as_slice()).unwrap();
+        assert_eq!(loaded, pack);
+        assert_eq!(loaded.scene_name(), "scene.json");
+        assert_eq!(loaded.names().count(), 3);
+
+        let assets = AssetResolver::from_pack(loaded);
+        assert_eq!(assets.read("obj/mesh.obj").unwrap(), b"v 1 1 1");
+        assert!(assets.read("obj/missing.obj").is_err());
+
+        bytes.truncate(bytes.len() - 1);
+        assert!(ScenePack::read_from(bytes.as_slice()).is_err());
+    }
+}
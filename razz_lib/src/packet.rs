@@ -0,0 +1,401 @@
+use crate::{Float, Ray3A, Vec3A};
+
+use boxtree::Bounds3A;
+use glam::{BVec4A, Vec4};
+
+// Rays traced together. Four fill one SSE register, which glam's `Vec4` uses.
+pub(crate) const PACKET_WIDTH: usize = 4;
+const LEAF_SIZE: usize = 4;
+// Balanced trees over anything that fits in memory are shallower than this
+const MAX_DEPTH: usize = 64;
+
+// Up to four rays laid out by component, so each step of a test runs on all of them
+// at once. Lanes without a ray never reach anything.
+#[derive(Debug)]
+pub(crate) struct RayPacket {
+    origin: [Vec4; 3],
+    direction: [Vec4; 3],
+    inverse_direction: [Vec4; 3],
+    t_min: Vec4,
+    // Where each ray ends, brought in as closer hits are found
+    t_max: Vec4,
+    rays: usize,
+}
+
+impl RayPacket {
+    pub fn new(rays: &[Ray3A], t_min: Float, t_max: Float) -> Self {
+        assert!(rays.len() <= PACKET_WIDTH);
+        let lane = |f: &dyn Fn(&Ray3A) -> Float, fill: Float| {
+            let mut lanes = [fill; PACKET_WIDTH];
+            for (lane, ray) in lanes.iter_mut().zip(rays) {
+                *lane = f(ray);
+            }
+            Vec4::from(lanes)
+        };
+        let direction = [
+            lane(&|r| r.direction.x, 1.0),
+            lane(&|r| r.direction.y, 1.0),
+            lane(&|r| r.direction.z, 1.0),
+        ];
+        Self {
+            origin: [
+                lane(&|r| r.origin.x, 0.0),
+                lane(&|r| r.origin.y, 0.0),
+                lane(&|r| r.origin.z, 0.0),
+            ],
+            // Finite, so a ray along a box's face gives zero rather than NaN
+            inverse_direction: [
+                direction[0]
+                    .recip()
+                    .clamp(Vec4::splat(-1e30), Vec4::splat(1e30)),
+                direction[1]
+                    .recip()
+                    .clamp(Vec4::splat(-1e30), Vec4::splat(1e30)),
+                direction[2]
+                    .recip()
+                    .clamp(Vec4::splat(-1e30), Vec4::splat(1e30)),
+            ],
+            direction,
+            t_min: Vec4::splat(t_min),
+            t_max: lane(&|_| t_max, Float::NEG_INFINITY),
+            rays: rays.len(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.rays
+    }
+
+    pub fn ray(&self, lane: usize) -> Ray3A {
+        Ray3A {
+            origin: Vec3A::new(
+                self.origin[0].to_array()[lane],
+                self.origin[1].to_array()[lane],
+                self.origin[2].to_array()[lane],
+            ),
+            direction: Vec3A::new(
+                self.direction[0].to_array()[lane],
+                self.direction[1].to_array()[lane],
+                self.direction[2].to_array()[lane],
+            ),
+        }
+    }
+
+    pub fn t_min(&self) -> Float {
+        self.t_min.x
+    }
+
+    pub fn t_max(&self, lane: usize) -> Float {
+        self.t_max.to_array()[lane]
+    }
+
+    pub fn set_t_max(&mut self, lane: usize, t: Float) {
+        let mut t_max = self.t_max.to_array();
+        t_max[lane] = t;
+        self.t_max = Vec4::from(t_max);
+    }
+
+    // Stops the ray, for any-hit queries that only need to know something is in the way
+    pub fn end(&mut self, lane: usize) {
+        self.set_t_max(lane, Float::NEG_INFINITY);
+    }
+
+    fn ended(&self) -> bool {
+        self.t_max.cmpge(self.t_min).bitmask() & ((1 << self.rays) - 1) == 0
+    }
+
+    // Lanes whose ray passes through the box before it ends
+    fn hits_bounds(&self, min: Vec3A, max: Vec3A) -> BVec4A {
+        let (min, max) = (min.to_array(), max.to_array());
+        let mut near = self.t_min;
+        let mut far = self.t_max;
+        for axis in 0..3 {
+            let t0 = (Vec4::splat(min[axis]) - self.origin[axis]) * self.inverse_direction[axis];
+            let t1 = (Vec4::splat(max[axis]) - self.origin[axis]) * self.inverse_direction[axis];
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+        }
+        near.cmple(far)
+    }
+
+    // Lanes that may hit the triangle, by Möller-Trumbore on every lane at once. The
+    // bounds are loosened a little, so lanes left out are certain misses and the
+    // single ray test has the final say on the rest.
+    pub fn may_hit_triangle(&self, v0: Vec3A, v1: Vec3A, v2: Vec3A) -> u32 {
+        const SLACK: Float = 1e-4;
+        let splat = |v: Vec3A| [Vec4::splat(v.x), Vec4::splat(v.y), Vec4::splat(v.z)];
+        let cross = |a: &[Vec4; 3], b: &[Vec4; 3]| {
+            [
+                a[1] * b[2] - a[2] * b[1],
+                a[2] * b[0] - a[0] * b[2],
+                a[0] * b[1] - a[1] * b[0],
+            ]
+        };
+        let dot = |a: &[Vec4; 3], b: &[Vec4; 3]| a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+
+        let (e1, e2) = (splat(v1 - v0), splat(v2 - v0));
+        let pvec = cross(&self.direction, &e2);
+        let inv_det = dot(&e1, &pvec).recip();
+        let origin = splat(v0);
+        let tvec = [
+            self.origin[0] - origin[0],
+            self.origin[1] - origin[1],
+            self.origin[2] - origin[2],
+        ];
+        let u = dot(&tvec, &pvec) * inv_det;
+        let qvec = cross(&tvec, &e1);
+        let v = dot(&self.direction, &qvec) * inv_det;
+        let t = dot(&e2, &qvec) * inv_det;
+
+        let low = Vec4::splat(-SLACK);
+        let high = Vec4::splat(1.0 + SLACK);
+        let slack_t = Vec4::splat(SLACK) * t.abs().max(Vec4::ONE);
+        let mask = u.cmpge(low)
+            & u.cmple(high)
+            & v.cmpge(low)
+            & (u + v).cmple(high)
+            & t.cmpge(self.t_min - slack_t)
+            & t.cmple(self.t_max + slack_t);
+        mask.bitmask() & ((1 << self.rays) - 1)
+    }
+}
+
+// Which child of an interior node a traversal goes into first
+#[derive(Debug, Clone, Copy)]
+enum Visit {
+    Nearer,
+    First,
+    Second,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    min: Vec3A,
+    max: Vec3A,
+    // Leaves cover `count` entries of `order` from `start`. Interior nodes have a
+    // count of zero, their first child right after them and the second at `start`.
+    start: u32,
+    count: u32,
+    axis: u8,
+    visit: Visit,
+}
+
+// A bounding volume hierarchy over item bounds, traversed a packet at a time. Boxtree
+// only takes single rays, so meshes and the top level use these instead, with single
+// rays going through as packets of one.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PacketBvh {
+    nodes: Vec<Node>,
+    // Item indices in leaf order
+    order: Vec<u32>,
+}
+
+impl PacketBvh {
+    pub fn build(bounds: impl Iterator<Item = Bounds3A>) -> Self {
+        let mut items: Vec<_> = bounds
+            .enumerate()
+            .map(|(index, b)| (index as u32, b.min, b.max))
+            .collect();
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(2 * items.len() / LEAF_SIZE + 1),
+            order: Vec::with_capacity(items.len()),
+        };
+        if !items.is_empty() {
+            bvh.build_node(&mut items, 0);
+        }
+        bvh
+    }
+
+    // Around every item, or empty without any
+    pub fn bounds(&self) -> Bounds3A {
+        match self.nodes.first() {
+            Some(root) => Bounds3A::new(root.min, root.max),
+            None => Bounds3A::new(
+                Vec3A::splat(Float::INFINITY),
+                Vec3A::splat(Float::NEG_INFINITY),
+            ),
+        }
+    }
+
+    // Splits at the median centroid along the widest axis, which keeps the tree
+    // balanced without sorting everything at every level
+    fn build_node(&mut self, items: &mut [(u32, Vec3A, Vec3A)], depth: usize) -> usize {
+        let (mut min, mut max) = (items[0].1, items[0].2);
+        let (mut low, mut high) = (0.5 * (min + max), 0.5 * (min + max));
+        for (_, item_min, item_max) in items.iter() {
+            min = min.min(*item_min);
+            max = max.max(*item_max);
+            let centroid = 0.5 * (*item_min + *item_max);
+            low = low.min(centroid);
+            high = high.max(centroid);
+        }
+
+        let index = self.nodes.len();
+        if items.len() <= LEAF_SIZE || depth + 1 >= MAX_DEPTH {
+            self.nodes.push(Node {
+                min,
+                max,
+                start: self.order.len() as u32,
+                count: items.len() as u32,
+                axis: 0,
+                visit: Visit::Nearer,
+            });
+            self.order.extend(items.iter().map(|(i, _, _)| *i));
+            return index;
+        }
+
+        let extent = (high - low).to_array();
+        let axis = (0..3)
+            .max_by(|a, b| extent[*a].total_cmp(&extent[*b]))
+            .unwrap_or(0);
+        let centroid = |item: &(u32, Vec3A, Vec3A)| (item.1 + item.2).to_array()[axis];
+        let middle = items.len() / 2;
+        items.select_nth_unstable_by(middle, |a, b| {
+            centroid(a)
+                .partial_cmp(&centroid(b))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        self.nodes.push(Node {
+            min,
+            max,
+            start: 0,
+            count: 0,
+            axis: axis as u8,
+            visit: Visit::Nearer,
+        });
+        let (first, second) = items.split_at_mut(middle);
+        self.build_node(first, depth + 1);
+        let second = self.build_node(second, depth + 1);
+        self.nodes[index].start = second as u32;
+        index
+    }
+
+    // Orders the tree by `hits`, a count per item of rays found to hit it. Leaves try
+    // their most hit items first, and an interior node goes first into whichever child
+    // holds more hits once that's at least `min_hits`. Elsewhere the nearer child still
+    // goes first.
+    pub fn prioritize(&mut self, hits: &[u32], min_hits: u64) {
+        if !self.nodes.is_empty() {
+            self.prioritize_node(0, hits, min_hits);
+        }
+    }
+
+    // Hits counted under the node at `index`
+    fn prioritize_node(&mut self, index: usize, hits: &[u32], min_hits: u64) -> u64 {
+        let (start, count) = (self.nodes[index].start, self.nodes[index].count);
+        if count > 0 {
+            let items = &mut self.order[start as usize..(start + count) as usize];
+            items.sort_by_key(|&item| std::cmp::Reverse(hits[item as usize]));
+            return items.iter().map(|&item| hits[item as usize] as u64).sum();
+        }
+
+        let first = self.prioritize_node(index + 1, hits, min_hits);
+        let second = self.prioritize_node(start as usize, hits, min_hits);
+        self.nodes[index].visit = if first > second && first >= min_hits {
+            Visit::First
+        } else if second > first && second >= min_hits {
+            Visit::Second
+        } else {
+            Visit::Nearer
+        };
+        first + second
+    }
+
+    // Calls `leaf` with each item whose bounds a ray of the packet reaches, and a
+    // bitmask of those rays. `leaf` shortens the rays as it finds hits, which culls
+    // later subtrees, so the nearer child is visited first unless `prioritize` picked
+    // one. Once `leaf` has ended every ray the traversal stops.
+    pub fn traverse(
+        &self,
+        packet: &mut RayPacket,
+        mut leaf: impl FnMut(usize, u32, &mut RayPacket),
+    ) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = [0u32; MAX_DEPTH + 1];
+        let mut top = 1;
+        while top > 0 {
+            top -= 1;
+            let node = &self.nodes[stack[top] as usize];
+            let lanes = packet.hits_bounds(node.min, node.max).bitmask();
+            if lanes == 0 {
+                continue;
+            }
+
+            if node.count > 0 {
+                let items = &self.order[node.start as usize..(node.start + node.count) as usize];
+                for item in items {
+                    leaf(*item as usize, lanes, packet);
+                    if packet.ended() {
+                        return;
+                    }
+                }
+                continue;
+            }
+
+            let first = stack[top] + 1;
+            let (near, far) = match node.visit {
+                Visit::First => (first, node.start),
+                Visit::Second => (node.start, first),
+                Visit::Nearer => {
+                    // Rays of a packet mostly head the same way, so their sum picks the side
+                    let axis = node.axis as usize;
+                    let heading: Float = packet.direction[axis].to_array().iter().sum();
+                    match heading < 0.0 {
+                        true => (node.start, first),
+                        false => (first, node.start),
+                    }
+                }
+            };
+            stack[top] = far;
+            stack[top + 1] = near;
+            top += 2;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenes;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn packets_find_the_same_first_hits_as_single_rays() {
+        let scene = scenes::cornell_box(1.0);
+        let world = &scene.world;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        for _ in 0..64 {
+            // Camera like bundles, fanned a little from one origin
+            let (width, height) = (32, 32);
+            let (i, j) = (rng.gen_range(0..width - 1), rng.gen_range(0..height - 1));
+            let mut sampler = crate::Sampler::new(i, j, 0, 0);
+            let rays: Vec<_> = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                .iter()
+                .map(|(x, y)| {
+                    scene
+                        .sampler
+                        .get_ray(i + x, j + y, width, height, &mut sampler)
+                })
+                .collect();
+            for len in [PACKET_WIDTH, 3] {
+                let mut samplers = vec![sampler.clone(); len];
+                let hits = world.primary_hits(&rays[..len], &mut samplers);
+                for ((ray, hit), sampler) in rays.iter().zip(&hits[..len]).zip(&mut samplers) {
+                    let media = &mut sampler.medium_rng(0, ray);
+                    let single = world.closest_hit(ray, media).map(|(_, rec)| rec);
+                    match (hit, single) {
+                        (Some(a), Some(b)) => {
+                            assert!((a.point - b.point).length() < 1e-3);
+                            assert_eq!(a.material_key, b.material_key);
+                        }
+                        (None, None) => {}
+                        (a, b) => panic!("{:?} {:?}", a, b),
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use crate::material::Material;
+use crate::shape::{Face, HitRecord};
+use crate::{Float, Ray3A, Vec3A, D_LINE_NM};
+
+use std::ops::{Add, Div, Mul, Sub};
+
+type Stokes = [Float; 4];
+type Mueller = [[Float; 4]; 4];
+
+// A linear polarizing filter in front of the camera. Like a real filter it
+// passes half of any unpolarized light.
+#[derive(Debug, Clone, Copy)]
+pub struct Polarizer {
+    frame_x: Vec3A,
+    analyzer: Stokes,
+}
+
+impl Polarizer {
+    // `angle` is the transmission axis in radians, measured from `frame_x`
+    pub fn linear(angle: Float, frame_x: Vec3A) -> Self {
+        let (s, c) = (2.0 * angle).sin_cos();
+        Self {
+            frame_x,
+            analyzer: [0.5, 0.5 * c, 0.5 * s, 0.0],
+        }
+    }
+}
+
+// Tracks the first row of the accumulated Mueller matrix from the camera
+// back along the path. Emitters are unpolarized, so only its first entry
+// scales their contribution.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PolarizationState {
+    frame_x: Vec3A,
+    row: Stokes,
+}
+
+impl PolarizationState {
+    pub(crate) fn new(polarizer: Polarizer, direction: Vec3A) -> Self {
+        Self {
+            frame_x: perpendicular_frame(polarizer.frame_x, direction.normalize()),
+            row: polarizer.analyzer,
+        }
+    }
+
+    pub(crate) fn weight(&self) -> Float {
+        self.row[0]
+    }
+
+    // The color throughput already carries the unpolarized reflectance, so
+    // each Mueller matrix is normalized by its first entry
+    pub(crate) fn scatter(
+        &mut self,
+        material: &Material,
+        ray_in: &Ray3A,
+        rec: &HitRecord,
+        ray_out: &Ray3A,
+    ) {
+        let direction = ray_in.direction.normalize();
+        let cos_i = direction.dot(-rec.normal).clamp(0.0, 1.0);
+        let reflected = ray_out.direction.dot(rec.normal) > 0.0;
+
+        let mueller = match material {
+            Material::Dielectric { roughness, .. } if *roughness > 0.0 => {
+                return self.depolarize(ray_out)
+            }
+            Material::Dielectric { ir, .. } => {
+                smooth_dielectric(ir.ior(D_LINE_NM), rec.face, cos_i, reflected)
+            }
+            Material::Water { ir, .. } => smooth_dielectric(*ir, rec.face, cos_i, reflected),
+            Material::Conductor { eta, k, .. } => {
+                fresnel_reflection(cos_i, Complex::new(eta[1], k[1]))
+            }
+            Material::Metal { .. } => diagonal([1.0, 1.0, -1.0, -1.0]),
+            _ => return self.depolarize(ray_out),
+        };
+
+        // Rotate into the s/p basis of the interaction, which stays
+        // perpendicular to the outgoing ray for specular events
+        let s_axis = match direction.cross(rec.normal).try_normalize() {
+            Some(s_axis) => s_axis,
+            None => self.frame_x,
+        };
+        let theta = self
+            .frame_x
+            .cross(s_axis)
+            .dot(direction)
+            .atan2(self.frame_x.dot(s_axis));
+
+        self.row = multiply(multiply(self.row, &rotation(-theta)), &mueller);
+        self.frame_x = perpendicular_frame(s_axis, ray_out.direction.normalize());
+    }
+
+    // Rough and diffuse scattering mixes every polarization, leaving only intensity
+    fn depolarize(&mut self, ray_out: &Ray3A) {
+        self.row = [self.row[0], 0.0, 0.0, 0.0];
+        self.frame_x = perpendicular_frame(self.frame_x, ray_out.direction.normalize());
+    }
+}
+
+// Fresnel reflection or transmission at smooth glass of IOR `ir`
+fn smooth_dielectric(ir: Float, face: Face, cos_i: Float, reflected: bool) -> Mueller {
+    let eta = match face {
+        Face::Front => ir,
+        Face::Back => 1.0 / ir,
+    };
+    match reflected {
+        true => fresnel_reflection(cos_i, Complex::new(eta, 0.0)),
+        false => fresnel_transmission(cos_i, eta),
+    }
+}
+
+fn perpendicular_frame(frame_x: Vec3A, direction: Vec3A) -> Vec3A {
+    match (frame_x - frame_x.dot(direction) * direction).try_normalize() {
+        Some(frame_x) => frame_x,
+        None => direction.any_orthonormal_vector(),
+    }
+}
+
+fn multiply(row: Stokes, m: &Mueller) -> Stokes {
+    let mut out = [0.0; 4];
+    for (j, value) in out.iter_mut().enumerate() {
+        *value = (0..4).map(|i| row[i] * m[i][j]).sum();
+    }
+    out
+}
+
+fn diagonal(d: [Float; 4]) -> Mueller {
+    let mut m = [[0.0; 4]; 4];
+    for i in 0..4 {
+        m[i][i] = d[i];
+    }
+    m
+}
+
+fn rotation(theta: Float) -> Mueller {
+    let (s, c) = (2.0 * theta).sin_cos();
+    [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, c, s, 0.0],
+        [0.0, -s, c, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+// Reflection off an interface with relative index `eta`, which may be complex
+fn fresnel_reflection(cos_i: Float, eta: Complex) -> Mueller {
+    let cos_i_c = Complex::new(cos_i, 0.0);
+    let sin2_i = Complex::new(1.0 - cos_i * cos_i, 0.0);
+    let cos_t = (Complex::new(1.0, 0.0) - sin2_i / (eta * eta)).sqrt();
+
+    let rs = (cos_i_c - eta * cos_t) / (cos_i_c + eta * cos_t);
+    let rp = (eta * cos_i_c - cos_t) / (eta * cos_i_c + cos_t);
+    let (r_s, r_p) = (rs.norm_sqr(), rp.norm_sqr());
+    let cross = rs * rp.conj();
+
+    normalize([
+        [r_s + r_p, r_s - r_p, 0.0, 0.0],
+        [r_s - r_p, r_s + r_p, 0.0, 0.0],
+        [0.0, 0.0, 2.0 * cross.re, 2.0 * cross.im],
+        [0.0, 0.0, -2.0 * cross.im, 2.0 * cross.re],
+    ])
+}
+
+fn fresnel_transmission(cos_i: Float, eta: Float) -> Mueller {
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    let cos_t = (1.0 - sin2_t).max(0.0).sqrt();
+
+    let ts = 2.0 * cos_i / (cos_i + eta * cos_t);
+    let tp = 2.0 * cos_i / (eta * cos_i + cos_t);
+    let (t_s, t_p) = (ts * ts, tp * tp);
+
+    normalize([
+        [t_s + t_p, t_s - t_p, 0.0, 0.0],
+        [t_s - t_p, t_s + t_p, 0.0, 0.0],
+        [0.0, 0.0, 2.0 * ts * tp, 0.0],
+        [0.0, 0.0, 0.0, 2.0 * ts * tp],
+    ])
+}
+
+fn normalize(mut m: Mueller) -> Mueller {
+    let scale = match m[0][0] > 0.0 {
+        true => 1.0 / m[0][0],
+        false => return diagonal([1.0, 0.0, 0.0, 0.0]),
+    };
+    m.iter_mut().flatten().for_each(|v| *v *= scale);
+    m
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Complex {
+    re: Float,
+    im: Float,
+}
+
+impl Complex {
+    fn new(re: Float, im: Float) -> Self {
+        Self { re, im }
+    }
+
+    fn conj(self) -> Self {
+        Self::new(self.re, -self.im)
+    }
+
+    fn norm_sqr(self) -> Float {
+        self.re * self.re + self.im * self.im
+    }
+
+    // Principal root
+    fn sqrt(self) -> Self {
+        let r = self.norm_sqr().sqrt();
+        let re = (0.5 * (r + self.re)).max(0.0).sqrt();
+        let im = (0.5 * (r - self.re)).max(0.0).sqrt();
+        Self::new(re, if self.im < 0.0 { -im } else { im })
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self {
+        let d = rhs.norm_sqr();
+        Self::new(
+            (self.re * rhs.re + self.im * rhs.im) / d,
+            (self.im * rhs.re - self.re * rhs.im) / d,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MaterialKey;
+
+    fn glass_reflection(polarizer_angle: Float) -> Float {
+        // Glass floor in the xz plane viewed at Brewster's angle, so the
+        // plane of incidence is yz and s-polarization lies along x
+        let ir = 1.5;
+        let brewster = (ir as Float).atan();
+        let direction = Vec3A::new(0.0, -brewster.cos(), brewster.sin());
+        let normal = Vec3A::new(0.0, 1.0, 0.0);
+
+        let mut state = PolarizationState::new(
+            Polarizer::linear(polarizer_angle, Vec3A::new(1.0, 0.0, 0.0)),
+            direction,
+        );
+        let rec = HitRecord {
+            point: Vec3A::ZERO,
+            normal,
+            u: 0.0,
+            v: 0.0,
+            face: Face::Front,
+            material_key: MaterialKey::default(),
+        };
+        let ray_in = Ray3A {
+            origin: -direction,
+            direction,
+        };
+        let ray_out = Ray3A {
+            origin: Vec3A::ZERO,
+            direction: direction - 2.0 * direction.dot(normal) * normal,
+        };
+        state.scatter(&Material::dielectric(ir), &ray_in, &rec, &ray_out);
+        state.weight()
+    }
+
+    #[test]
+    fn crossed_polarizer_removes_brewster_glare() {
+        let pi = std::f64::consts::PI as Float;
+        assert!((glass_reflection(0.0) - 1.0).abs() < 1e-4);
+        assert!(glass_reflection(0.5 * pi).abs() < 1e-4);
+    }
+
+    #[test]
+    fn normal_incidence_reflects_like_a_mirror() {
+        let m = fresnel_reflection(1.0, Complex::new(1.5, 0.0));
+        assert!((m[0][1]).abs() < 1e-5);
+        assert!((m[2][2] + 1.0).abs() < 1e-5);
+        assert!((m[3][3] + 1.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,673 @@
+use crate::blue_noise::{blue_noise, BLUE_NOISE_SIZE};
+use crate::image::{Image, Rgba};
+use crate::sampler::mix;
+use crate::Float;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ColorGrade {
+    pub lift: [Float; 3],
+    pub gamma: [Float; 3],
+    pub gain: [Float; 3],
+    pub saturation: Float,
+    pub contrast: Float,
+}
+
+impl Default for ColorGrade {
+    fn default() -> Self {
+        Self {
+            lift: [0.0; 3],
+            gamma: [1.0; 3],
+            gain: [1.0; 3],
+            saturation: 1.0,
+            contrast: 1.0,
+        }
+    }
+}
+
+impl ColorGrade {
+    pub fn apply(&self, color: Rgba) -> Rgba {
+        let c = color.to_array();
+        let mut out = [0.0; 3];
+        for i in 0..3 {
+            let x = c[i].max(0.0);
+            let lifted = x + self.lift[i] * (1.0 - x);
+            let v = self.gain[i] * lifted.max(0.0).powf(1.0 / self.gamma[i].max(1e-4));
+            out[i] = (v - 0.5) * self.contrast + 0.5;
+        }
+
+        let luma = 0.2126 * out[0] + 0.7152 * out[1] + 0.0722 * out[2];
+        Rgba::new(
+            (luma + (out[0] - luma) * self.saturation).max(0.0),
+            (luma + (out[1] - luma) * self.saturation).max(0.0),
+            (luma + (out[2] - luma) * self.saturation).max(0.0),
+            c[3],
+        )
+    }
+}
+
+// How light brighter than the display can show is brought into range
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Tonemap {
+    // Each channel cut off at 1
+    #[default]
+    Clamp,
+    // Extended Reinhard on luminance, with `white` the luminance that maps to 1
+    Reinhard {
+        white: Float,
+    },
+    // Narkowicz's fit of the ACES filmic curve, per channel
+    Aces,
+}
+
+impl Tonemap {
+    pub fn apply(&self, color: Rgba) -> Rgba {
+        let c = color.to_array();
+        let mapped = match self {
+            Tonemap::Clamp => [c[0], c[1], c[2]],
+            Tonemap::Reinhard { white } => {
+                let luma = 0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2];
+                if luma <= 0.0 {
+                    [0.0; 3]
+                } else {
+                    let white = white.max(1e-4);
+                    let scale = (1.0 + luma / (white * white)) / (1.0 + luma);
+                    [c[0] * scale, c[1] * scale, c[2] * scale]
+                }
+            }
+            Tonemap::Aces => [aces(c[0]), aces(c[1]), aces(c[2])],
+        };
+        Rgba::new(
+            mapped[0].clamp(0.0, 1.0),
+            mapped[1].clamp(0.0, 1.0),
+            mapped[2].clamp(0.0, 1.0),
+            c[3],
+        )
+    }
+}
+
+fn aces(x: Float) -> Float {
+    let x = x.max(0.0);
+    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+}
+
+// How display values are stored, relative to linear light
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Encoding {
+    // The piecewise sRGB curve, what PNGs and most displays expect
+    #[default]
+    Srgb,
+    Gamma(Float),
+    // For outputs that encode on their own, like sRGB swap chains
+    Linear,
+}
+
+impl Encoding {
+    pub fn encode(&self, v: Float) -> Float {
+        let v = v.max(0.0);
+        match self {
+            Encoding::Srgb if v <= 0.0031308 => 12.92 * v,
+            Encoding::Srgb => 1.055 * v.powf(1.0 / 2.4) - 0.055,
+            Encoding::Gamma(gamma) => v.powf(1.0 / gamma.max(1e-4)),
+            Encoding::Linear => v,
+        }
+    }
+
+    pub fn decode(&self, v: Float) -> Float {
+        let v = v.max(0.0);
+        match self {
+            Encoding::Srgb if v <= 0.04045 => v / 12.92,
+            Encoding::Srgb => ((v + 0.055) / 1.055).powf(2.4),
+            Encoding::Gamma(gamma) => v.powf(*gamma),
+            Encoding::Linear => v,
+        }
+    }
+}
+
+// Noise added when display values are rounded to 8 bits, so smooth gradients break
+// up into fine grain instead of visible bands
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Dither {
+    #[default]
+    None,
+    // Two white noise values summed, spanning a step either side
+    Triangular,
+    // A tiling blue-noise texture, shifted per channel, spanning half a step either
+    // side. Its grain is finer than white noise's at the same strength.
+    BlueNoise,
+}
+
+impl Dither {
+    // `value` of `channel` at pixel `(x, y)`, rounded to 0..=255
+    pub fn quantize(&self, value: Float, x: usize, y: usize, channel: usize) -> u8 {
+        let noise = match self {
+            Dither::None => 0.0,
+            Dither::Triangular => {
+                let hash = mix(((x as u64) << 40) ^ ((y as u64) << 8) ^ channel as u64);
+                let a = (hash & 0xffff_ffff) as Float / 2f64.powi(32) as Float;
+                let b = (hash >> 32) as Float / 2f64.powi(32) as Float;
+                a + b - 1.0
+            }
+            Dither::BlueNoise => {
+                let n = BLUE_NOISE_SIZE;
+                // Channels read tiles apart so their noise doesn't line up into grey
+                let (x, y) = (x + 17 * channel, y + 31 * channel);
+                blue_noise()[(y % n) * n + x % n] - 0.5
+            }
+        };
+        (value.clamp(0.0, 1.0) * 255.0 + noise)
+            .round()
+            .clamp(0.0, 255.0) as u8
+    }
+}
+
+// Turns the linear light the renderers accumulate into display values: scaled by
+// `exposure` stops, brought into range by the tonemapper, then encoded
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DisplayTransform {
+    pub exposure: Float,
+    pub tonemap: Tonemap,
+    pub encoding: Encoding,
+}
+
+impl DisplayTransform {
+    pub fn apply(&self, color: Rgba) -> Rgba {
+        let scale = (2.0 as Float).powf(self.exposure);
+        let c = color.to_array();
+        let exposed = Rgba::new(c[0] * scale, c[1] * scale, c[2] * scale, c[3]);
+        let c = self.tonemap.apply(exposed).to_array();
+        let encode = |v| self.encoding.encode(v);
+        Rgba::new(encode(c[0]), encode(c[1]), encode(c[2]), c[3])
+    }
+
+    pub fn apply_to(&self, image: &mut Image) {
+        map_pixels(image, |c| self.apply(c));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PostStage {
+    Grade(ColorGrade),
+    Glare {
+        threshold: Float,
+        intensity: Float,
+        streaks: usize,
+        length: usize,
+    },
+    // Light above `threshold` spread into a soft glow. `radius` is the blur's standard
+    // deviation as a share of the image height, so the glow looks alike at any size.
+    Bloom {
+        threshold: Float,
+        intensity: Float,
+        radius: Float,
+    },
+    // Darkens toward the corners, by `strength` at the corners themselves, starting
+    // `radius` of the way out from the center
+    Vignette {
+        strength: Float,
+        radius: Float,
+    },
+}
+
+impl PostStage {
+    pub fn apply(&self, image: &mut Image) {
+        match self {
+            Self::Grade(grade) => map_pixels(image, |c| grade.apply(c)),
+            Self::Glare {
+                threshold,
+                intensity,
+                streaks,
+                length,
+            } => glare(image, *threshold, *intensity, *streaks, *length),
+            Self::Bloom {
+                threshold,
+                intensity,
+                radius,
+            } => bloom(image, *threshold, *intensity, *radius),
+            Self::Vignette { strength, radius } => vignette(image, *strength, *radius),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PostPipeline {
+    // Run in order on the linear image
+    pub stages: Vec<PostStage>,
+    // Applied after the stages
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub display: DisplayTransform,
+}
+
+impl PostPipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, stage: PostStage) {
+        self.stages.push(stage);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    pub fn apply(&self, image: &Image) -> Image {
+        let mut image = image.clone();
+        for stage in &self.stages {
+            stage.apply(&mut image);
+        }
+        self.display.apply_to(&mut image);
+        image
+    }
+
+    // Runs `edit` on the first grading stage, appending a neutral one if there is none
+    pub fn edit_grade<R>(&mut self, edit: impl FnOnce(&mut ColorGrade) -> R) -> R {
+        let first = self.stages.iter_mut().find_map(|stage| match stage {
+            PostStage::Grade(grade) => Some(grade),
+            _ => None,
+        });
+        match first {
+            Some(grade) => edit(grade),
+            None => {
+                let mut grade = ColorGrade::default();
+                let result = edit(&mut grade);
+                self.stages.push(PostStage::Grade(grade));
+                result
+            }
+        }
+    }
+}
+
+// Starburst streaks splatted from every pixel brighter than the threshold
+fn glare(image: &mut Image, threshold: Float, intensity: Float, streaks: usize, length: usize) {
+    let (width, height) = (image.width as isize, image.height as isize);
+    let directions: Vec<(Float, Float)> = (0..streaks)
+        .map(|i| {
+            let angle = i as Float * std::f64::consts::PI as Float * 2.0 / streaks as Float;
+            (angle.cos(), angle.sin())
+        })
+        .collect();
+
+    let mut glow = vec![0.0; image.data.len()];
+    for j in 0..height {
+        for i in 0..width {
+            let c = image.get_pixel_color(i as usize, j as usize).to_array();
+            let excess = [
+                (c[0] - threshold).max(0.0),
+                (c[1] - threshold).max(0.0),
+                (c[2] - threshold).max(0.0),
+            ];
+            if excess == [0.0; 3] {
+                continue;
+            }
+
+            for (dx, dy) in &directions {
+                for step in 1..=length {
+                    let x = i + (dx * step as Float).round() as isize;
+                    let y = j + (dy * step as Float).round() as isize;
+                    if x < 0 || y < 0 || x >= width || y >= height {
+                        break;
+                    }
+
+                    let falloff = intensity * (1.0 - step as Float / (length + 1) as Float).powi(2);
+                    let index = (y * width + x) as usize * 4;
+                    for k in 0..3 {
+                        glow[index + k] += excess[k] * falloff;
+                    }
+                }
+            }
+        }
+    }
+
+    image.data.iter_mut().zip(glow).for_each(|(v, g)| *v += g);
+}
+
+fn bloom(image: &mut Image, threshold: Float, intensity: Float, radius: Float) {
+    let (width, height) = (image.width, image.height);
+    // Nothing to blur, and a box blur needs at least one pixel to clamp to
+    if width == 0 || height == 0 {
+        return;
+    }
+    let mut glow: Vec<Float> = image
+        .data
+        .chunks(4)
+        .flat_map(|c| {
+            let excess = |v: Float| (v - threshold).max(0.0);
+            [excess(c[0]), excess(c[1]), excess(c[2]), 0.0]
+        })
+        .collect();
+
+    // Three box blurs come close to a Gaussian, at a cost independent of the radius
+    let sigma = radius * height as Float;
+    // Each box of width w adds (w^2 - 1) / 12 to the variance
+    let half = (((4.0 * sigma * sigma + 1.0).sqrt() - 1.0) / 2.0).round() as usize;
+    for _ in 0..3 {
+        box_blur(&mut glow, width, height, half, 4, width * 4);
+        box_blur(&mut glow, height, width, half, width * 4, 4);
+    }
+
+    image
+        .data
+        .iter_mut()
+        .zip(glow)
+        .for_each(|(v, g)| *v += intensity * g);
+}
+
+// Blurs `lines` lines of `len` pixels each, with neighbouring pixels `step` values
+// apart and neighbouring lines `stride` apart. Edges are extended.
+fn box_blur(data: &mut [Float], len: usize, lines: usize, half: usize, step: usize, stride: usize) {
+    let window = (2 * half + 1) as Float;
+    let mut line = vec![[0.0; 3]; len];
+    for l in 0..lines {
+        let at = |i: isize| l * stride + i.clamp(0, len as isize - 1) as usize * step;
+        let mut sum = [0.0; 3];
+        for i in -(half as isize)..=half as isize {
+            for c in 0..3 {
+                sum[c] += data[at(i) + c];
+            }
+        }
+        for (i, out) in line.iter_mut().enumerate() {
+            *out = sum.map(|s| s / window);
+            let (leaving, entering) = (at(i as isize - half as isize), at((i + half + 1) as isize));
+            for c in 0..3 {
+                sum[c] += data[entering + c] - data[leaving + c];
+            }
+        }
+        for (i, value) in line.iter().enumerate() {
+            data[at(i as isize)..at(i as isize) + 3].copy_from_slice(value);
+        }
+    }
+}
+
+fn vignette(image: &mut Image, strength: Float, radius: Float) {
+    let (width, height) = (image.width as Float, image.height as Float);
+    let half_diagonal = 0.5 * (width * width + height * height).sqrt();
+    for (index, pixel) in image.data.chunks_mut(4).enumerate() {
+        let x = (index % image.width) as Float + 0.5 - 0.5 * width;
+        let y = (index / image.width) as Float + 0.5 - 0.5 * height;
+        let r = (x * x + y * y).sqrt() / half_diagonal.max(1e-4);
+        let t = ((r - radius) / (1.0 - radius).max(1e-4)).clamp(0.0, 1.0);
+        let scale = 1.0 - strength * t * t * (3.0 - 2.0 * t);
+        for v in &mut pixel[..3] {
+            *v *= scale.max(0.0);
+        }
+    }
+}
+
+fn map_pixels(image: &mut Image, f: impl Fn(Rgba) -> Rgba) {
+    for pixel in image.data.chunks_mut(4) {
+        let color = f(Rgba::new(pixel[0], pixel[1], pixel[2], pixel[3]));
+        pixel.copy_from_slice(&color.to_array());
+    }
+}
+
+impl Image {
+    // A display-referred copy of this linear image
+    pub fn display(&self, transform: &DisplayTransform) -> Image {
+        let mut image = self.clone();
+        transform.apply_to(&mut image);
+        image
+    }
+
+    // A linear copy of this image of `encoding` values, such as a loaded texture
+    pub fn decode(&self, encoding: Encoding) -> Image {
+        let data = self
+            .data
+            .chunks(4)
+            .flat_map(|c| {
+                let d = |v| encoding.decode(v);
+                [d(c[0]), d(c[1]), d(c[2]), c[3]]
+            })
+            .collect();
+        Image::from_vec(self.width, self.height, data)
+    }
+
+    pub fn exposure(&self, ev: Float) -> Image {
+        let scale = (2.0 as Float).powf(ev);
+        let data = self
+            .data
+            .chunks(4)
+            .flat_map(|c| [c[0] * scale, c[1] * scale, c[2] * scale, c[3]])
+            .collect();
+
+        Image::from_vec(self.width, self.height, data)
+    }
+
+    pub fn bracket(&self, evs: &[Float]) -> Vec<Image> {
+        evs.iter().map(|ev| self.exposure(*ev)).collect()
+    }
+}
+
+// Mertens-style exposure fusion, blended per pixel. `None` without any exposures, or
+// if they aren't all the same size.
+pub fn fuse_exposures(exposures: &[Image]) -> Option<Image> {
+    let (width, height) = exposures.first().map(|e| (e.width, e.height))?;
+    if exposures
+        .iter()
+        .any(|e| (e.width, e.height) != (width, height))
+    {
+        return None;
+    }
+
+    let weights: Vec<Vec<Float>> = exposures.iter().map(fusion_weights).collect();
+
+    let mut fused = Image::new(width, height);
+    for j in 0..height {
+        for i in 0..width {
+            let index = j * width + i;
+            let total: Float = weights.iter().map(|w| w[index]).sum();
+
+            let mut color = Rgba::ZERO;
+            for (image, w) in exposures.iter().zip(&weights) {
+                let weight = match total > 0.0 {
+                    true => w[index] / total,
+                    false => 1.0 / exposures.len() as Float,
+                };
+                color = color + clamp_ldr(image.get_pixel_color(i, j)) * weight;
+            }
+            fused.set_pixel_color(i, j, color);
+        }
+    }
+
+    Some(fused)
+}
+
+fn fusion_weights(image: &Image) -> Vec<Float> {
+    let (width, height) = (image.width, image.height);
+    let luma = |i: usize, j: usize| {
+        let c = clamp_ldr(image.get_pixel_color(i, j)).to_array();
+        0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2]
+    };
+
+    let mut weights = Vec::with_capacity(width * height);
+    for j in 0..height {
+        for i in 0..width {
+            let c = clamp_ldr(image.get_pixel_color(i, j)).to_array();
+
+            let center = luma(i, j);
+            let laplacian = luma(i.saturating_sub(1), j)
+                + luma((i + 1).min(width - 1), j)
+                + luma(i, j.saturating_sub(1))
+                + luma(i, (j + 1).min(height - 1))
+                - 4.0 * center;
+            let contrast = laplacian.abs();
+
+            let mean = (c[0] + c[1] + c[2]) / 3.0;
+            let saturation =
+                (((c[0] - mean).powi(2) + (c[1] - mean).powi(2) + (c[2] - mean).powi(2)) / 3.0)
+                    .sqrt();
+
+            let well_exposed = c[..3]
+                .iter()
+                .map(|v| (-(v - 0.5).powi(2) / (2.0 * 0.2 * 0.2)).exp())
+                .product::<Float>();
+
+            weights.push((contrast + 1e-3) * (saturation + 1e-3) * well_exposed);
+        }
+    }
+
+    weights
+}
+
+fn clamp_ldr(color: Rgba) -> Rgba {
+    let c = color.to_array();
+    Rgba::new(
+        c[0].clamp(0.0, 1.0),
+        c[1].clamp(0.0, 1.0),
+        c[2].clamp(0.0, 1.0),
+        1.0,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tonemappers_keep_highlights_in_range() {
+        let bright = Rgba::new(8.0, 2.0, 0.5, 1.0);
+        for tonemap in [Tonemap::Reinhard { white: 4.0 }, Tonemap::Aces] {
+            let [r, g, b, a] = tonemap.apply(bright).to_array();
+            assert!(r <= 1.0 && r > g && g > b && b > 0.0, "{:?}", tonemap);
+            assert_eq!(a, 1.0);
+        }
+        // Black stays black, and the white point maps to 1
+        assert_eq!(Tonemap::Aces.apply(Rgba::ZERO), Rgba::ZERO);
+        let white = Tonemap::Reinhard { white: 4.0 }.apply(Rgba::new(4.0, 4.0, 4.0, 1.0));
+        assert!((white.to_array()[0] - 1.0).abs() < 1e-5);
+
+        let display = DisplayTransform {
+            exposure: 1.0,
+            ..DisplayTransform::default()
+        };
+        let [r, ..] = display.apply(Rgba::new(0.25, 0.0, 0.0, 1.0)).to_array();
+        assert!((r - 0.735357).abs() < 1e-5);
+        for v in [0.001, 0.2, 0.9] {
+            assert!((Encoding::Srgb.decode(Encoding::Srgb.encode(v)) - v).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn dithering_keeps_gradients_unbanded_on_average() {
+        // A value a third of the way between two 8 bit steps
+        let value = 100.333 / 255.0;
+        for dither in [Dither::Triangular, Dither::BlueNoise] {
+            let mean = (0..64 * 64)
+                .map(|i| dither.quantize(value, i % 64, i / 64, 0) as Float)
+                .sum::<Float>()
+                / (64 * 64) as Float;
+            assert!((mean - 100.333).abs() < 0.05, "{:?} {}", dither, mean);
+        }
+        assert_eq!(Dither::None.quantize(value, 3, 4, 0), 100);
+        assert_eq!(Dither::Triangular.quantize(2.0, 3, 4, 0), 255);
+    }
+
+    #[test]
+    fn bloom_spreads_bright_pixels_and_vignette_darkens_corners() {
+        let mut image = Image::new(41, 21);
+        image.data.iter_mut().for_each(|v| *v = 0.5);
+        image.set_pixel_color(20, 10, Rgba::new(100.0, 100.0, 100.0, 1.0));
+
+        let mut bloomed = image.clone();
+        PostStage::Bloom {
+            threshold: 1.0,
+            intensity: 1.0,
+            radius: 0.1,
+        }
+        .apply(&mut bloomed);
+        let at = |image: &Image, x, y| image.get_pixel_color(x, y).to_array()[0];
+        assert!(at(&bloomed, 22, 10) > at(&bloomed, 25, 10));
+        assert!(at(&bloomed, 25, 10) > 0.5);
+        assert_eq!(at(&bloomed, 0, 0), 0.5);
+        // The glow carries the energy above the threshold, blurring doesn't add any
+        let total = |image: &Image| image.data.chunks(4).map(|c| c[0]).sum::<Float>();
+        assert!((total(&bloomed) - total(&image) - 99.0).abs() < 0.5);
+
+        let mut vignetted = image;
+        PostStage::Vignette {
+            strength: 0.5,
+            radius: 0.3,
+        }
+        .apply(&mut vignetted);
+        assert_eq!(at(&vignetted, 21, 10), 0.5);
+        assert!((at(&vignetted, 0, 0) - 0.25).abs() < 0.02);
+    }
+
+    #[test]
+    fn bloom_leaves_empty_images_alone() {
+        for (width, height) in [(0, 4), (4, 0)] {
+            let mut image = Image::new(width, height);
+            PostStage::Bloom {
+                threshold: 1.0,
+                intensity: 1.0,
+                radius: 0.1,
+            }
+            .apply(&mut image);
+            assert!(image.data.is_empty());
+        }
+    }
+
+    #[test]
+    fn glare_streaks_out_from_bright_pixels() {
+        let mut image = Image::new(21, 21);
+        image.set_pixel_color(10, 10, Rgba::new(11.0, 11.0, 11.0, 1.0));
+
+        let mut glared = image.clone();
+        PostStage::Glare {
+            threshold: 1.0,
+            intensity: 0.5,
+            streaks: 4,
+            length: 5,
+        }
+        .apply(&mut glared);
+        let at = |image: &Image, x, y| image.get_pixel_color(x, y).to_array()[0];
+        // Fading along each streak and stopping at its length, with nothing between
+        assert!(at(&glared, 11, 10) > at(&glared, 13, 10));
+        assert!(at(&glared, 13, 10) > at(&glared, 15, 10));
+        assert!(at(&glared, 15, 10) > 0.0);
+        assert_eq!(at(&glared, 16, 10), 0.0);
+        assert_eq!(at(&glared, 12, 12), 0.0);
+        for (x, y) in [(9, 10), (10, 11), (10, 9)] {
+            assert!((at(&glared, x, y) - at(&glared, 11, 10)).abs() < 1e-4);
+        }
+        assert_eq!(at(&glared, 10, 10), 11.0);
+        // Each streak carries the excess over the threshold, weighted by its falloff
+        let total = |image: &Image| image.data.chunks(4).map(|c| c[0]).sum::<Float>();
+        let falloff: Float = (1..=5).map(|s| (1.0 - s as Float / 6.0).powi(2)).sum();
+        let expected = 4.0 * 10.0 * 0.5 * falloff;
+        assert!((total(&glared) - total(&image) - expected).abs() < 1e-3);
+    }
+
+    #[test]
+    fn grades_are_edited_in_place() {
+        let mut post = PostPipeline::new();
+        post.push(PostStage::Vignette {
+            strength: 0.5,
+            radius: 0.3,
+        });
+        post.edit_grade(|grade| grade.saturation = 0.5);
+        post.edit_grade(|grade| grade.contrast = 2.0);
+        assert_eq!(post.stages.len(), 2);
+        let expected = ColorGrade {
+            saturation: 0.5,
+            contrast: 2.0,
+            ..ColorGrade::default()
+        };
+        assert_eq!(post.stages[1], PostStage::Grade(expected));
+    }
+
+    #[test]
+    fn fusion_needs_exposures_of_one_size() {
+        let image = Image::new(4, 3);
+        assert!(fuse_exposures(&[]).is_none());
+        assert!(fuse_exposures(&[image.clone(), Image::new(3, 4)]).is_none());
+        let fused = fuse_exposures(&image.bracket(&[-1.0, 0.0, 1.0])).unwrap();
+        assert_eq!((fused.width, fused.height), (4, 3));
+    }
+}
@@ -0,0 +1,122 @@
+use crate::{Float, Image};
+
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// A snapshot of how far a render has got, handed to progress callbacks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderProgress {
+    // Passes over the image, fractional while a pass is part way through
+    pub samples_completed: Float,
+    pub target_samples: Option<usize>,
+    pub rays_traced: u64,
+    pub elapsed: Duration,
+    // Only known with a target, extrapolated from the time taken so far
+    pub remaining: Option<Duration>,
+}
+
+impl RenderProgress {
+    pub fn new(
+        samples_completed: Float,
+        target_samples: Option<usize>,
+        rays_traced: u64,
+        elapsed: Duration,
+    ) -> Self {
+        let remaining = target_samples
+            .filter(|_| samples_completed > 0.0)
+            .map(|target| {
+                let left = (target as Float - samples_completed).max(0.0);
+                elapsed.mul_f64((left / samples_completed) as f64)
+            });
+        Self {
+            samples_completed,
+            target_samples,
+            rays_traced,
+            elapsed,
+            remaining,
+        }
+    }
+
+    // Between 0 and 1, if there's a target to measure against
+    pub fn fraction(&self) -> Option<Float> {
+        self.target_samples
+            .map(|target| (self.samples_completed / target.max(1) as Float).min(1.0))
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        self.rays_traced as f64 / self.elapsed.as_secs_f64().max(1e-6)
+    }
+}
+
+type Callback = Box<dyn FnMut(&RenderProgress) + Send>;
+
+// Locked so renderers holding one can still be shared with their worker threads
+pub(crate) struct ProgressCallback(Mutex<Callback>);
+
+impl ProgressCallback {
+    pub fn new(callback: impl FnMut(&RenderProgress) + Send + 'static) -> Self {
+        Self(Mutex::new(Box::new(callback)))
+    }
+
+    pub fn call(&self, progress: &RenderProgress) {
+        (self.0.lock().unwrap())(progress)
+    }
+}
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+// The accumulation as a pass over the image finishes, borrowed rather than copied
+#[derive(Debug, Clone, Copy)]
+pub struct RenderPass<'a> {
+    // Mean of every sample so far, in linear light before any post processing
+    pub image: &'a Image,
+    // Full passes completed, which stays at zero through the previews
+    pub samples_per_pixel: usize,
+    pub preview: bool,
+}
+
+type PassFn = Box<dyn FnMut(&RenderPass<'_>) + Send>;
+
+pub(crate) struct PassCallback(Mutex<PassFn>);
+
+impl PassCallback {
+    pub fn new(callback: impl FnMut(&RenderPass<'_>) + Send + 'static) -> Self {
+        Self(Mutex::new(Box::new(callback)))
+    }
+
+    pub fn call(&self, pass: &RenderPass<'_>) {
+        (self.0.lock().unwrap())(pass)
+    }
+}
+
+impl fmt::Debug for PassCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PassCallback")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remaining_time_scales_with_the_samples_left() {
+        let progress = RenderProgress::new(2.5, Some(10), 1000, Duration::from_secs(5));
+        assert_eq!(progress.fraction(), Some(0.25));
+        assert_eq!(progress.remaining, Some(Duration::from_secs(15)));
+        assert_eq!(progress.rays_per_second(), 200.0);
+
+        let done = RenderProgress::new(12.0, Some(10), 1000, Duration::from_secs(5));
+        assert_eq!(done.fraction(), Some(1.0));
+        assert_eq!(done.remaining, Some(Duration::ZERO));
+
+        let open_ended = RenderProgress::new(0.0, None, 0, Duration::ZERO);
+        assert_eq!(open_ended.fraction(), None);
+        assert_eq!(open_ended.remaining, None);
+    }
+}
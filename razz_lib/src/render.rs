@@ -1,8 +1,20 @@
-use crate::image::Image;
-use crate::{Float, Scene};
+use crate::arena::with_arena;
+use crate::checkpoint::hash_tiles;
+use crate::image::{Image, Rgba};
+use crate::packet::PACKET_WIDTH;
+use crate::progress::{PassCallback, ProgressCallback};
+use crate::technique::PathContributions;
+use crate::{
+    Aovs, Float, Integrator, ModeIntegrator, PathIntegrator, RenderMode, RenderPass,
+    RenderProgress, RenderReport, SampleSequence, Sampler, Scene, Technique, TechniqueAovs,
+    TileHash, TiledImage,
+};
 
 use rand::Rng;
 use rayon::prelude::*;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 pub struct ProgressiveRenderer {
@@ -11,6 +23,9 @@ pub struct ProgressiveRenderer {
     max_ray_depth: usize,
     image: Image,
     num_samples: usize,
+    rays_traced: u64,
+    render_time: Duration,
+    integrator: Box<dyn Integrator>,
 }
 
 impl ProgressiveRenderer {
@@ -21,17 +36,35 @@ impl ProgressiveRenderer {
             max_ray_depth,
             image: Image::new(width, height),
             num_samples: 0,
+            rays_traced: 0,
+            render_time: Duration::ZERO,
+            integrator: Box::new(PathIntegrator {
+                max_depth: max_ray_depth,
+            }),
         }
     }
 
+    // Starts over, with every sample from `integrator`
+    pub fn set_integrator(&mut self, integrator: impl Integrator + 'static) {
+        self.integrator = Box::new(integrator);
+        self.reset();
+    }
+
     pub fn render(&mut self, scene: &Scene, rng: &mut impl Rng) -> &Image {
+        let start = Instant::now();
+        let seed = rng.gen();
         // Render 1 passes over the image
         for j in 0..self.height {
             for i in 0..self.width {
-                let sample_ray = scene.sampler.get_ray(i, j, self.width, self.height, rng);
-                let sample_color = scene.world.ray_color(&sample_ray, rng, self.max_ray_depth);
+                let mut sampler = Sampler::new(i, j, self.num_samples as u64, seed);
+                let sample_ray = scene
+                    .sampler
+                    .get_ray(i, j, self.width, self.height, &mut sampler);
+                let sample_color =
+                    self.integrator
+                        .li(scene, &sample_ray, &mut sampler, &mut self.rays_traced);
 
-                let pixel_rgb = sample_color.gamma_correct(1, 2.0).to_rgba();
+                let pixel_rgb = sample_color.to_rgba();
 
                 if self.num_samples == 0 {
                     self.image.set_pixel_color(i, j, pixel_rgb);
@@ -44,8 +77,37 @@ impl ProgressiveRenderer {
             }
         }
         self.num_samples += 1;
+        self.render_time += start.elapsed();
+        &self.image
+    }
+
+    // The accumulated linear light
+    pub fn image(&self) -> &Image {
         &self.image
     }
+
+    // The image through the scene's post stages and display transform, ready to show
+    // or save
+    pub fn display_image(&self, scene: &Scene) -> Image {
+        scene.post.apply(&self.image)
+    }
+
+    pub fn reset(&mut self) {
+        self.num_samples = 0;
+        self.rays_traced = 0;
+        self.render_time = Duration::ZERO;
+    }
+
+    pub fn report(&self, scene: &Scene) -> RenderReport {
+        RenderReport::new(
+            scene,
+            &self.image,
+            self.num_samples,
+            self.max_ray_depth,
+            self.rays_traced,
+            self.render_time,
+        )
+    }
 }
 
 #[derive(Debug)]
@@ -54,7 +116,21 @@ pub struct ParallelRenderer {
     height: usize,
     max_ray_depth: usize,
     image: Image,
-    num_samples: usize,
+    row_samples: Vec<usize>,
+    rays_traced: u64,
+    render_time: Duration,
+    aovs: Option<Aovs>,
+    light_aovs: Option<Vec<Image>>,
+    technique_aovs: Option<TechniqueAovs>,
+    next_row: usize,
+    rows_per_second: f64,
+    target_samples: Option<usize>,
+    on_progress: Option<ProgressCallback>,
+    on_pass: Option<PassCallback>,
+    sequence: SampleSequence,
+    preview_stride: usize,
+    next_preview_stride: usize,
+    integrator: Box<dyn Integrator>,
 }
 
 impl ParallelRenderer {
@@ -64,51 +140,792 @@ impl ParallelRenderer {
             height,
             max_ray_depth,
             image: Image::new(width, height),
-            num_samples: 0,
+            row_samples: vec![0; height],
+            rays_traced: 0,
+            render_time: Duration::ZERO,
+            aovs: None,
+            light_aovs: None,
+            technique_aovs: None,
+            next_row: 0,
+            rows_per_second: 0.0,
+            target_samples: None,
+            on_progress: None,
+            on_pass: None,
+            sequence: SampleSequence::default(),
+            preview_stride: 1,
+            next_preview_stride: 1,
+            integrator: Box::new(PathIntegrator {
+                max_depth: max_ray_depth,
+            }),
+        }
+    }
+
+    // Starts over at a new size, keeping the enabled buffers, and matches the scene's
+    // camera to it so pixels stay square
+    pub fn resize(&mut self, width: usize, height: usize, scene: &mut Scene) {
+        self.width = width;
+        self.height = height;
+        self.image = Image::new(width, height);
+        self.row_samples = vec![0; height];
+        if self.aovs.is_some() {
+            self.aovs = Some(Aovs::new(width, height));
+        }
+        if let Some(buffers) = &mut self.light_aovs {
+            // Reallocated at the new size by the next render
+            buffers.clear();
+        }
+        if self.technique_aovs.is_some() {
+            self.technique_aovs = Some(TechniqueAovs::new(width, height));
+        }
+        // Throughput changes with the pixel count
+        self.rows_per_second = 0.0;
+        self.reset();
+
+        if width > 0 && height > 0 {
+            scene.sampler.set_aspect(width as Float / height as Float);
+        }
+    }
+
+    // Also accumulate first-hit albedo and normal buffers for denoising
+    pub fn enable_aovs(&mut self) {
+        if self.aovs.is_none() {
+            self.aovs = Some(Aovs::new(self.width, self.height));
+            self.reset();
+        }
+    }
+
+    pub fn aovs(&self) -> Option<&Aovs> {
+        self.aovs.as_ref()
+    }
+
+    // Also accumulate each light's linear contribution into its own buffer, followed by one
+    // for emitters that aren't registered lights. The buffers sum to the image before gamma.
+    pub fn enable_light_aovs(&mut self) {
+        if self.light_aovs.is_none() {
+            self.light_aovs = Some(Vec::new());
+            self.reset();
+        }
+    }
+
+    pub fn light_aovs(&self) -> Option<&[Image]> {
+        self.light_aovs.as_deref()
+    }
+
+    // Also accumulate the mean and variance of each sampling technique's contribution,
+    // for comparing how much noise each adds
+    pub fn enable_technique_aovs(&mut self) {
+        if self.technique_aovs.is_none() {
+            self.technique_aovs = Some(TechniqueAovs::new(self.width, self.height));
+            self.reset();
+        }
+    }
+
+    pub fn technique_aovs(&self) -> Option<&TechniqueAovs> {
+        self.technique_aovs.as_ref()
+    }
+
+    // Starts over, as samples from different sequences don't mix well
+    pub fn set_sample_sequence(&mut self, sequence: SampleSequence) {
+        self.sequence = sequence;
+        self.reset();
+    }
+
+    // Before the first full pass, render previews that trace only every `stride`th pixel
+    // of every `stride`th row and fill in the rest, halving the stride each pass. The
+    // full passes after replace them. A stride of 1 turns previews off.
+    pub fn set_preview_stride(&mut self, stride: usize) {
+        self.preview_stride = stride.max(1);
+        self.reset();
+    }
+
+    // Starts over, showing the scene as `mode` from then on through `ModeIntegrator`
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.set_integrator(ModeIntegrator {
+            mode,
+            max_depth: self.max_ray_depth,
+        });
+    }
+
+    // Starts over, with every sample from `integrator`
+    pub fn set_integrator(&mut self, integrator: impl Integrator + 'static) {
+        self.integrator = Box::new(integrator);
+        self.reset();
+    }
+
+    // Samples per pixel the caller means to stop at, only used to estimate the time left
+    pub fn set_target_samples(&mut self, target_samples: Option<usize>) {
+        self.target_samples = target_samples;
+    }
+
+    // Called with the progress at the end of every `render` and `render_for`
+    pub fn on_progress(&mut self, callback: impl FnMut(&RenderProgress) + Send + 'static) {
+        self.on_progress = Some(ProgressCallback::new(callback));
+    }
+
+    // Called as each pass over the image finishes, previews included, with the
+    // accumulated image. `render_for` may finish several passes in one call, or none.
+    pub fn on_pass(&mut self, callback: impl FnMut(&RenderPass<'_>) + Send + 'static) {
+        self.on_pass = Some(PassCallback::new(callback));
+    }
+
+    pub fn progress(&self) -> RenderProgress {
+        let rows = self.row_samples.iter().sum::<usize>() as Float;
+        RenderProgress::new(
+            rows / self.height.max(1) as Float,
+            self.target_samples,
+            self.rays_traced,
+            self.render_time,
+        )
+    }
+
+    fn report_progress(&self) {
+        if let Some(callback) = &self.on_progress {
+            callback.call(&self.progress());
+        }
+    }
+
+    fn report_pass(&self, preview: bool) {
+        if let Some(callback) = &self.on_pass {
+            callback.call(&RenderPass {
+                image: &self.image,
+                samples_per_pixel: self.samples_per_pixel(),
+                preview,
+            });
         }
     }
 
     pub fn render(&mut self, scene: &Scene) -> &Image {
+        let start = Instant::now();
+
         // Render 1 passes over the image
-        let img_data: Vec<f32> = (0..self.height)
-            .into_par_iter()
-            .flat_map(|j| {
-                let mut rng = rand::thread_rng();
+        let preview = self.next_preview_stride > 1;
+        if preview {
+            self.render_preview(scene);
+        } else {
+            let rows: Vec<usize> = (0..self.height).collect();
+            self.render_rows(scene, &rows);
+        }
+        self.report_pass(preview);
+
+        self.render_time += start.elapsed();
+        self.report_progress();
+        &self.image
+    }
+
+    // Renders as many rows as are expected to fit in `budget`, continuing from where the
+    // previous call stopped, so interactive frame rates hold regardless of scene cost.
+    // At least one batch of rows is always rendered.
+    pub fn render_for(&mut self, scene: &Scene, budget: Duration) -> &Image {
+        let start = Instant::now();
+        if self.height == 0 {
+            return &self.image;
+        }
+        // Previews are quick enough to ignore the budget
+        if self.next_preview_stride > 1 {
+            self.render_preview(scene);
+            self.render_time += start.elapsed();
+            self.report_pass(true);
+            self.report_progress();
+            return &self.image;
+        }
+
+        let mut passes = self.samples_per_pixel();
+        let mut first_batch = true;
+        loop {
+            let remaining = budget.saturating_sub(start.elapsed()).as_secs_f64();
+            // Leave some headroom for thread scheduling jitter
+            let batch = match self.rows_per_second > 0.0 {
+                true => (0.9 * remaining * self.rows_per_second) as usize,
+                false => rayon::current_num_threads(),
+            };
+            if batch == 0 && !first_batch {
+                break;
+            }
+            first_batch = false;
+
+            let batch = batch.clamp(1, self.height);
+            let rows: Vec<usize> = (0..batch)
+                .map(|k| (self.next_row + k) % self.height)
+                .collect();
+
+            let batch_start = Instant::now();
+            self.render_rows(scene, &rows);
+            let rate = batch as f64 / batch_start.elapsed().as_secs_f64().max(1e-6);
+            self.rows_per_second = match self.rows_per_second > 0.0 {
+                true => 0.5 * (self.rows_per_second + rate),
+                false => rate,
+            };
+            self.next_row = (self.next_row + batch) % self.height;
+            if self.samples_per_pixel() > passes {
+                passes = self.samples_per_pixel();
+                self.report_pass(false);
+            }
+        }
+
+        self.render_time += start.elapsed();
+        self.report_progress();
+        &self.image
+    }
+
+    fn render_rows(&mut self, scene: &Scene, rows: &[usize]) {
+        let integrator = &*self.integrator;
+        let with_aovs = self.aovs.is_some();
 
-                (0..self.width)
-                    .into_iter()
-                    .flat_map(|i| {
-                        let sample_ray =
+        let light_count = scene.world.lights().len() + 1;
+        if let Some(buffers) = &mut self.light_aovs {
+            if buffers.len() != light_count {
+                *buffers = vec![Image::new(self.width, self.height); light_count];
+            }
+        }
+        let with_light_aovs = self.light_aovs.is_some();
+        let with_technique_aovs = self.technique_aovs.is_some();
+        let tally = with_light_aovs || with_technique_aovs;
+
+        let rendered: Vec<_> = rows
+            .par_iter()
+            .map(|&j| {
+                with_arena(|arena| {
+                    let sample_index = self.row_samples[j] as u64;
+                    let mut rays = 0;
+                    let mut albedo: Vec<Float> = Vec::new();
+                    let mut normal: Vec<Float> = Vec::new();
+                    let mut contributions = PathContributions::new(light_count, arena);
+                    let mut light_rows: Vec<Vec<Float>> = match with_light_aovs {
+                        true => vec![Vec::with_capacity(self.width * 4); light_count],
+                        false => Vec::new(),
+                    };
+                    let mut technique_rows: Vec<Vec<Float>> = match with_technique_aovs {
+                        true => vec![Vec::with_capacity(self.width * 4); Technique::ALL.len()],
+                        false => Vec::new(),
+                    };
+
+                    let samplers =
+                        arena.alloc_slice_fill_iter((0..self.width).map(|i| {
+                            Sampler::new(i, j, sample_index, 0).with_sequence(self.sequence)
+                        }));
+                    let sample_rays = arena.alloc_slice_fill_iter(
+                        samplers.iter_mut().enumerate().map(|(i, sampler)| {
                             scene
                                 .sampler
-                                .get_ray(i, j, self.width, self.height, &mut rng);
-                        let sample_color =
-                            scene
-                                .world
-                                .ray_color(&sample_ray, &mut rng, self.max_ray_depth);
+                                .get_ray(i, j, self.width, self.height, sampler)
+                        }),
+                    );
+                    // Neighbouring camera rays find their first hits together
+                    let first_hits = integrator.uses_first_hits().then(|| {
+                        let hits = arena.alloc_slice_fill_copy(self.width, None);
+                        for ((rays, samplers), hits) in sample_rays
+                            .chunks(PACKET_WIDTH)
+                            .zip(samplers.chunks_mut(PACKET_WIDTH))
+                            .zip(hits.chunks_mut(PACKET_WIDTH))
+                        {
+                            let found = scene.world.primary_hits(rays, samplers);
+                            hits.copy_from_slice(&found[..rays.len()]);
+                        }
+                        &*hits
+                    });
+
+                    let row = (0..self.width)
+                        .into_iter()
+                        .flat_map(|i| {
+                            let sample_ray = &sample_rays[i];
+                            contributions.clear();
+                            let sample_color = match &first_hits {
+                                Some(first_hits) => integrator.li_from_hit(
+                                    scene,
+                                    sample_ray,
+                                    first_hits[i],
+                                    &mut samplers[i],
+                                    &mut rays,
+                                    match tally {
+                                        true => Some(&mut contributions),
+                                        false => None,
+                                    },
+                                ),
+                                None => {
+                                    integrator.li(scene, sample_ray, &mut samplers[i], &mut rays)
+                                }
+                            };
 
-                        let pixel_rgb = sample_color.gamma_correct(1, 2.0).to_rgba();
-                        pixel_rgb.to_array()
-                    })
-                    .collect::<Vec<f32>>()
+                            for (light_row, contribution) in
+                                light_rows.iter_mut().zip(contributions.per_light.iter())
+                            {
+                                light_row.extend(contribution.to_array().iter());
+                            }
+                            for (technique_row, contribution) in
+                                technique_rows.iter_mut().zip(&contributions.per_technique)
+                            {
+                                technique_row.extend(contribution.to_array().iter());
+                            }
+                            if with_aovs {
+                                let (a, n) = scene.world.first_hit(sample_ray, &mut samplers[i]);
+                                albedo.extend(a.to_array().iter());
+                                normal.extend([n.x, n.y, n.z, 0.0].iter());
+                            }
+
+                            sample_color.to_array()
+                        })
+                        .collect::<Vec<f32>>();
+                    (j, row, albedo, normal, light_rows, technique_rows, rays)
+                })
             })
             .collect();
 
-        if self.num_samples == 0 {
-            self.image.data = img_data;
-        } else {
-            let num_samples_float = self.num_samples as Float;
+        let stride = self.width * 4;
+        for (j, row, albedo, normal, light_rows, technique_rows, rays) in rendered {
+            let samples = self.row_samples[j];
+            let span = j * stride..(j + 1) * stride;
+
+            accumulate(&mut self.image.data[span.clone()], &row, samples);
+            if let Some(aovs) = &mut self.aovs {
+                accumulate(&mut aovs.albedo.data[span.clone()], &albedo, samples);
+                accumulate(&mut aovs.normal.data[span.clone()], &normal, samples);
+            }
+            if let Some(buffers) = &mut self.light_aovs {
+                for (buffer, light_row) in buffers.iter_mut().zip(&light_rows) {
+                    accumulate(&mut buffer.data[span.clone()], light_row, samples);
+                }
+            }
+            if let Some(techniques) = &mut self.technique_aovs {
+                techniques.accumulate(span.clone(), &technique_rows, samples);
+            }
+
+            self.row_samples[j] += 1;
+            self.rays_traced += rays;
+        }
+    }
 
-            self.image
-                .data
-                .iter_mut()
-                .zip(img_data)
-                .for_each(|(old, new)| {
-                    *old = (*old * num_samples_float + new) * (1.0 / (num_samples_float + 1.0))
-                });
+    // Traces the pixels on a grid `next_preview_stride` apart, then fills each pixel
+    // from the four grid samples around it, weighted by distance and by how well their
+    // first hits match the pixel's own. Only first hits are traced for the rest, which
+    // keeps edges between objects and materials sharp.
+    fn render_preview(&mut self, scene: &Scene) {
+        let stride = self.next_preview_stride;
+        let (width, height) = (self.width, self.height);
+        if width == 0 || height == 0 {
+            return;
         }
 
-        self.num_samples += 1;
+        let first_hit = |i, j| {
+            let mut sampler = Sampler::new(i, j, 0, 0).with_sequence(self.sequence);
+            let ray = scene.sampler.get_ray(i, j, width, height, &mut sampler);
+            let (albedo, normal) = scene.world.first_hit(&ray, &mut sampler);
+            (ray, sampler, albedo, normal)
+        };
+
+        let grid_width = (width - 1) / stride + 1;
+        let grid_height = (height - 1) / stride + 1;
+        let grid: Vec<_> = (0..grid_width * grid_height)
+            .into_par_iter()
+            .map(|k| {
+                let (i, j) = ((k % grid_width) * stride, (k / grid_width) * stride);
+                let (ray, mut sampler, albedo, normal) = first_hit(i, j);
+                let mut rays = 0;
+                let color = self.integrator.li(scene, &ray, &mut sampler, &mut rays);
+                (color, albedo, normal, rays)
+            })
+            .collect();
+
+        let rows: Vec<Vec<Float>> = (0..height)
+            .into_par_iter()
+            .map(|j| {
+                let (gy, fy) = (j / stride, (j % stride) as Float / stride as Float);
+                let mut row = Vec::with_capacity(width * 4);
+                for i in 0..width {
+                    let (gx, fx) = (i / stride, (i % stride) as Float / stride as Float);
+                    if fx == 0.0 && fy == 0.0 {
+                        row.extend(grid[gy * grid_width + gx].0.to_array().iter());
+                        continue;
+                    }
+
+                    let (_, _, albedo, normal) = first_hit(i, j);
+                    let mut sum = Rgba::ZERO;
+                    let mut total = 0.0;
+                    let mut nearest = (Rgba::ZERO, Float::NEG_INFINITY);
+                    for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                        let (x, y) = (gx + dx, gy + dy);
+                        if x >= grid_width || y >= grid_height {
+                            continue;
+                        }
+                        let (color, grid_albedo, grid_normal, _) = grid[y * grid_width + x];
+                        let distance = match (dx, dy) {
+                            (0, 0) => (1.0 - fx) * (1.0 - fy),
+                            (1, 0) => fx * (1.0 - fy),
+                            (0, _) => (1.0 - fx) * fy,
+                            _ => fx * fy,
+                        };
+                        let (a, b) = (albedo.to_array(), grid_albedo.to_array());
+                        let albedo_difference =
+                            (0..3).map(|c| (a[c] - b[c]).abs()).fold(0.0, Float::max);
+                        let similarity = (-(normal - grid_normal).length_squared() / 0.1
+                            - albedo_difference / 0.1)
+                            .exp();
+                        let weight = distance * similarity;
+                        sum = sum + color * weight;
+                        total += weight;
+                        if distance > nearest.1 {
+                            nearest = (color, distance);
+                        }
+                    }
+                    // No neighbour on the same surface, so take the closest
+                    let color = match total > 1e-4 {
+                        true => sum * (1.0 / total),
+                        false => nearest.0,
+                    };
+                    row.extend(color.to_array().iter());
+                }
+                row
+            })
+            .collect();
+
+        for (j, row) in rows.into_iter().enumerate() {
+            self.image.data[j * width * 4..(j + 1) * width * 4].copy_from_slice(&row);
+        }
+        // A first hit for every pixel, grid pixels included, and the grid's paths
+        self.rays_traced += (width * height) as u64 + grid.iter().map(|g| g.3).sum::<u64>();
+        self.next_preview_stride /= 2;
+    }
+
+    // The accumulated linear light
+    pub fn image(&self) -> &Image {
         &self.image
     }
+
+    // The image through the scene's post stages and display transform, ready to show
+    // or save
+    pub fn display_image(&self, scene: &Scene) -> Image {
+        scene.post.apply(&self.image)
+    }
+
+    // Full passes completed over every row
+    pub fn samples_per_pixel(&self) -> usize {
+        self.row_samples.iter().copied().min().unwrap_or(0)
+    }
+
+    // Hashes of the accumulated image and its per-row sample counts, for checking one
+    // worker's tiles against another's
+    pub fn tile_hashes(&self, tile_size: usize) -> Vec<TileHash> {
+        hash_tiles(&self.image, tile_size, Some(&self.row_samples))
+    }
+
+    pub fn reset(&mut self) {
+        self.row_samples.iter_mut().for_each(|s| *s = 0);
+        self.rays_traced = 0;
+        self.render_time = Duration::ZERO;
+        self.next_row = 0;
+        self.next_preview_stride = self.preview_stride;
+    }
+
+    pub fn report(&self, scene: &Scene) -> RenderReport {
+        RenderReport::new(
+            scene,
+            &self.image,
+            self.samples_per_pixel(),
+            self.max_ray_depth,
+            self.rays_traced,
+            self.render_time,
+        )
+    }
+}
+
+pub(crate) fn accumulate(old: &mut [Float], new: &[Float], num_samples: usize) {
+    if num_samples == 0 {
+        old.copy_from_slice(new);
+    } else {
+        let num_samples_float = num_samples as Float;
+        old.iter_mut().zip(new).for_each(|(old, new)| {
+            *old = (*old * num_samples_float + new) * (1.0 / (num_samples_float + 1.0))
+        });
+    }
+}
+
+#[derive(Debug)]
+pub struct BandRenderer {
+    width: usize,
+    height: usize,
+    band_height: usize,
+    samples_per_pixel: usize,
+    on_progress: Option<ProgressCallback>,
+    sequence: SampleSequence,
+    integrator: Box<dyn Integrator>,
+}
+
+impl BandRenderer {
+    pub fn new(
+        width: usize,
+        height: usize,
+        band_height: usize,
+        max_ray_depth: usize,
+        samples_per_pixel: usize,
+    ) -> Self {
+        Self {
+            width,
+            height,
+            band_height: band_height.max(1),
+            samples_per_pixel: samples_per_pixel.max(1),
+            on_progress: None,
+            sequence: SampleSequence::default(),
+            integrator: Box::new(PathIntegrator {
+                max_depth: max_ray_depth,
+            }),
+        }
+    }
+
+    // In place of `PathIntegrator`
+    pub fn with_integrator(mut self, integrator: impl Integrator + 'static) -> Self {
+        self.integrator = Box::new(integrator);
+        self
+    }
+
+    pub fn with_sample_sequence(mut self, sequence: SampleSequence) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    // Called after every band, with the samples completed averaged over the image
+    pub fn on_progress(mut self, callback: impl FnMut(&RenderProgress) + Send + 'static) -> Self {
+        self.on_progress = Some(ProgressCallback::new(callback));
+        self
+    }
+
+    // Renders the image one band of rows at a time so only a single band is held in memory.
+    // `on_band` receives the first row of the band and the finished band image, in
+    // linear light.
+    pub fn render(&self, scene: &Scene, mut on_band: impl FnMut(usize, &Image)) {
+        let start = Instant::now();
+        let rays_traced = AtomicU64::new(0);
+        for row_start in (0..self.height).step_by(self.band_height) {
+            let row_end = (row_start + self.band_height).min(self.height);
+
+            let band_data: Vec<f32> = (row_start..row_end)
+                .into_par_iter()
+                .flat_map(|j| {
+                    let mut rays = 0;
+
+                    let row = (0..self.width)
+                        .flat_map(|i| {
+                            let mut pixel_color = Rgba::ZERO;
+                            for s in 0..self.samples_per_pixel {
+                                let mut sampler =
+                                    Sampler::new(i, j, s as u64, 0).with_sequence(self.sequence);
+                                let sample_ray = scene.sampler.get_ray(
+                                    i,
+                                    j,
+                                    self.width,
+                                    self.height,
+                                    &mut sampler,
+                                );
+                                pixel_color = pixel_color
+                                    + self.integrator.li(
+                                        scene,
+                                        &sample_ray,
+                                        &mut sampler,
+                                        &mut rays,
+                                    );
+                            }
+
+                            (pixel_color * (1.0 / self.samples_per_pixel as Float)).to_array()
+                        })
+                        .collect::<Vec<f32>>();
+                    rays_traced.fetch_add(rays, Ordering::Relaxed);
+                    row
+                })
+                .collect();
+
+            let band = Image::from_vec(self.width, row_end - row_start, band_data);
+            on_band(row_start, &band);
+
+            if let Some(callback) = &self.on_progress {
+                let progress = RenderProgress::new(
+                    (self.samples_per_pixel * row_end) as Float / self.height as Float,
+                    Some(self.samples_per_pixel),
+                    rays_traced.load(Ordering::Relaxed),
+                    start.elapsed(),
+                );
+                callback.call(&progress);
+            }
+        }
+    }
+
+    // Renders straight into a disk backed image, for outputs too large to fit in memory.
+    // Bands matching the tile size keep only one row of tiles in use at a time. They're
+    // stored through the scene's display transform, as the whole image is never at hand
+    // for its post stages.
+    pub fn render_to(&self, scene: &Scene, target: &mut TiledImage) -> io::Result<()> {
+        target.check_size(self.width, self.height)?;
+        let mut result = Ok(());
+        self.render(scene, |row_start, band| {
+            if result.is_ok() {
+                let mut band = band.clone();
+                scene.post.display.apply_to(&mut band);
+                result = target.write_region(0, row_start, &band);
+            }
+        });
+        result.and_then(|_| target.flush())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Background, Camera, DetailCulling, Material, MissShader, Primative, Projection, Ray3A,
+        Texture, Vec3A, World, WorldBuilder,
+    };
+
+    #[test]
+    fn previews_trace_fewer_pixels_then_give_way_to_full_passes() {
+        let mut world_builder = WorldBuilder::default();
+        let grey = world_builder.push_texture(Texture::Solid {
+            color: Rgba::splat(0.5),
+        });
+        let grey = world_builder.push_material(Material::Lambertian { albedo: grey });
+        world_builder.push_hittable(Primative::sphere(Vec3A::ZERO, 1.0, grey));
+        world_builder.set_background(Background::rtiow_sky());
+        let camera = Camera::new(3.0 * Vec3A::Z, Vec3A::ZERO, 40.0, 1.0, 0.0, 1.0);
+        let scene = Scene::new(world_builder.into(), camera);
+
+        let mut full = ParallelRenderer::new(32, 32, 4);
+        full.render(&scene);
+
+        let mut renderer = ParallelRenderer::new(32, 32, 4);
+        renderer.set_preview_stride(4);
+        renderer.render(&scene);
+        // Every pixel's first hit is traced, but only the grid's paths
+        let preview_rays = renderer.progress().rays_traced;
+        assert!(preview_rays >= 32 * 32);
+        assert!((preview_rays - 32 * 32) * 8 < full.progress().rays_traced);
+        assert_eq!(renderer.samples_per_pixel(), 0);
+        // Sky fills the corners and the sphere the middle, with nothing left black
+        let (corner, middle) = (
+            renderer.image().get_pixel_color(1, 1),
+            renderer.image().get_pixel_color(15, 15),
+        );
+        assert!(corner.to_array()[2] > 0.5 && middle.to_array()[2] > 0.0);
+        assert!(corner != middle);
+
+        renderer.render(&scene);
+        assert_eq!(renderer.samples_per_pixel(), 0);
+        renderer.render(&scene);
+        assert_eq!(renderer.samples_per_pixel(), 1);
+        assert_eq!(renderer.image().data, full.image().data);
+    }
+
+    #[test]
+    fn pass_callbacks_see_each_finished_pass() {
+        use std::sync::{Arc, Mutex};
+
+        let scene = crate::scenes::cornell_box(1.0);
+        let passes = Arc::new(Mutex::new(Vec::new()));
+        let mut renderer = ParallelRenderer::new(8, 8, 2);
+        renderer.set_preview_stride(2);
+        let seen = Arc::clone(&passes);
+        renderer.on_pass(move |pass| {
+            let middle = pass.image.get_pixel_color(4, 4);
+            seen.lock()
+                .unwrap()
+                .push((pass.samples_per_pixel, pass.preview, middle));
+        });
+
+        renderer.render(&scene);
+        renderer.render(&scene);
+        // Enough time for a few passes over so small an image
+        renderer.render_for(&scene, Duration::from_millis(20));
+        // Rows past the last full pass have moved on, so end on one
+        renderer.render(&scene);
+        let passes = passes.lock().unwrap();
+        assert_eq!(passes[0].0, 0);
+        assert!(passes[0].1);
+        assert_eq!((passes[1].0, passes[1].1), (1, false));
+        // One call per pass, in order, each seeing the image as it stood then
+        assert_eq!(passes.len(), renderer.samples_per_pixel() + 1);
+        for (i, (samples, _, _)) in passes.iter().enumerate().skip(1) {
+            assert_eq!(*samples, i);
+        }
+        assert_eq!(
+            passes.last().unwrap().2,
+            renderer.image().get_pixel_color(4, 4)
+        );
+    }
+
+    #[test]
+    fn panoramas_converge_at_the_poles_and_wrap_at_the_seam() {
+        let mut world = World::from(WorldBuilder::default());
+        // The sky shows where each ray points
+        world.set_miss_shader(Some(MissShader::new(|ray: &Ray3A| {
+            let d = 0.5 * ray.direction.normalize() + Vec3A::splat(0.5);
+            Rgba::new(d.x, d.y, d.z, 1.0)
+        })));
+        let camera = Camera::new(Vec3A::ZERO, Vec3A::Z, 90.0, 2.0, 0.0, 1.0)
+            .with_projection(Projection::Equirectangular);
+        let scene = Scene::new(world, camera);
+
+        let (width, height) = (32, 32);
+        let mut image = Image::new(width, height);
+        BandRenderer::new(width, height, 5, 4, 4).render(&scene, |row_start, band| {
+            let start = row_start * width * 4;
+            image.data[start..start + band.data.len()].copy_from_slice(&band.data);
+        });
+        let at = |i, j| image.get_pixel_color(i, j).to_array();
+
+        // Every pixel of the top row looks almost straight up, and the bottom straight down
+        for i in 0..width {
+            let (top, bottom) = (at(i, 0), at(i, height - 1));
+            assert!(top[1] > 0.99 && bottom[1] < 0.01);
+            for c in [0, 2] {
+                assert!((top[c] - 0.5).abs() < 0.06 && (bottom[c] - 0.5).abs() < 0.06);
+            }
+        }
+
+        // The left and right edges are neighbours, no further apart than any others
+        let step =
+            |a: [f32; 4], b: [f32; 4]| (0..3).map(|c| (a[c] - b[c]).abs()).fold(0.0, f32::max);
+        let j = height / 2;
+        let widest = (1..width)
+            .map(|i| step(at(i - 1, j), at(i, j)))
+            .fold(0.0, f32::max);
+        assert!(step(at(width - 1, j), at(0, j)) < 1.5 * widest);
+    }
+
+    #[test]
+    fn culling_detail_keeps_the_mean_brightness() {
+        let mean = |keep_probability: Float| {
+            let mut world_builder = WorldBuilder::default();
+            let white = world_builder.push_texture(Texture::Solid {
+                color: Rgba::splat(0.8),
+            });
+            let white = world_builder.push_material(Material::Lambertian { albedo: white });
+            let black = world_builder.push_texture(Texture::Solid { color: Rgba::ZERO });
+            let black = world_builder.push_material(Material::Lambertian { albedo: black });
+            world_builder.push_hittable(Primative::sphere(Vec3A::ZERO, 1.0, white));
+            // A shell of tiny absorbers that shades about half of the sky from the sphere
+            let golden = std::f64::consts::PI as Float * (3.0 - (5.0 as Float).sqrt());
+            for i in 0..400 {
+                let y = 1.0 - 2.0 * (i as Float + 0.5) / 400.0;
+                let r = (1.0 - y * y).sqrt();
+                let (sin, cos) = (golden * i as Float).sin_cos();
+                let center = 1.5 * Vec3A::new(r * cos, y, r * sin);
+                world_builder.push_hittable(Primative::sphere(center, 0.12, black));
+            }
+            world_builder.set_background(Background::Solid { color: Rgba::ONE });
+            let eye = Vec3A::new(0.0, 0.0, -6.0);
+            world_builder.set_detail_culling(DetailCulling {
+                eye,
+                min_projected_size: 0.05,
+                keep_probability,
+            });
+            let camera = Camera::new(eye, Vec3A::ZERO, 30.0, 1.0, 0.0, 1.0);
+            let scene = Scene::new(world_builder.into(), camera);
+
+            let mut renderer = ParallelRenderer::new(16, 16, 8);
+            renderer.set_target_samples(Some(64));
+            while renderer.samples_per_pixel() < 64 {
+                renderer.render(&scene);
+            }
+            let data = &renderer.image().data;
+            data.chunks(4).map(|c| c[0]).sum::<Float>() / (data.len() / 4) as Float
+        };
+
+        let (all, culled) = (mean(1.0), mean(0.25));
+        assert!((all - culled).abs() < 0.02 * all, "{} {}", all, culled);
+    }
 }
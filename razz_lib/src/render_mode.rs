@@ -0,0 +1,152 @@
+use crate::material::diffuse_scatter;
+use crate::sampler::{SampleDimension, Sampler};
+use crate::shape::HitRecord;
+use crate::{Float, Ray3A, Rgba, ScatterResult, World};
+
+// Reflectance of every surface in `RenderMode::Clay`
+pub(crate) const CLAY_ALBEDO: Float = 0.8;
+
+// What the renderer shows. Everything but `Path` is a quick look at the scene's
+// geometry or lighting setup rather than the finished image.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum RenderMode {
+    #[default]
+    Path,
+    // White where nothing lies within `radius` over the hemisphere of the first hit,
+    // and for rays that miss
+    AmbientOcclusion {
+        radius: Float,
+    },
+    // Normals at the first hit, mapped from [-1, 1] to [0, 1]
+    Normals,
+    // Distance to the first hit over `max_distance`, white at and beyond it
+    Depth {
+        max_distance: Float,
+    },
+    // Path traced with every surface but the emitters swapped for grey Lambertian
+    Clay,
+}
+
+impl World {
+    // One sample of a camera ray in any mode but `Path`, from its first hit as
+    // `primary_hits` found it
+    pub(crate) fn mode_color(
+        &self,
+        mode: RenderMode,
+        ray: &Ray3A,
+        first_hit: Option<HitRecord>,
+        sampler: &mut Sampler,
+        depth: usize,
+        rays: &mut u64,
+    ) -> Rgba {
+        let grey = |value: Float| Rgba::new(value, value, value, 1.0);
+        match (mode, first_hit) {
+            (RenderMode::Clay, first_hit) => {
+                let mut path = self.start_path(ray, None, false);
+                path.clay = true;
+                // The cache holds light bounced off the real materials
+                path.read_cache = false;
+                let mut first_hit = Some(first_hit);
+                while !path.done && path.bounce < depth {
+                    *rays += 1;
+                    let hit = match first_hit.take() {
+                        Some(hit) => hit,
+                        None => self.intersect_path(&mut path, sampler),
+                    };
+                    self.shade_path(&mut path, hit, sampler, rays, None);
+                }
+                path.radiance
+            }
+            (RenderMode::AmbientOcclusion { radius }, Some(rec)) => {
+                sampler.start(0, SampleDimension::Bsdf);
+                let direction = match diffuse_scatter(Rgba::ONE, &rec, sampler) {
+                    ScatterResult::Scattered { ray_out, .. } => ray_out.direction.normalize(),
+                    ScatterResult::Absorbed => return grey(0.0),
+                };
+                *rays += 1;
+                let ao_ray = Ray3A {
+                    origin: rec.point,
+                    direction,
+                };
+                // The occlusion ray stands in for the path's second bounce
+                match self.ray_occluded(&ao_ray, radius, &mut sampler.medium_rng(1, &ao_ray)) {
+                    true => grey(0.0),
+                    false => grey(1.0),
+                }
+            }
+            (RenderMode::Normals, Some(rec)) => {
+                let n = 0.5 * (rec.normal + 1.0);
+                Rgba::new(n.x, n.y, n.z, 1.0)
+            }
+            (RenderMode::Depth { max_distance }, Some(rec)) => {
+                let distance = (rec.point - ray.origin).length();
+                grey((distance / max_distance.max(Float::EPSILON)).min(1.0))
+            }
+            (RenderMode::AmbientOcclusion { .. } | RenderMode::Depth { .. }, None) => grey(1.0),
+            (RenderMode::Normals, None) => Rgba::ZERO,
+            // Handled by `PathIntegrator`
+            (RenderMode::Path, _) => Rgba::ZERO,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{scenes, Integrator, PathIntegrator, Point3};
+
+    #[test]
+    fn inspection_modes_shade_the_first_hit() {
+        let scene = scenes::cornell_box(1.0);
+        let world = &scene.world;
+        // From the camera to the red wall, clear of both blocks
+        let ray = Ray3A {
+            origin: Point3::new(278.0, 278.0, -800.0),
+            direction: Point3::new(277.0, 0.0, 1200.0).normalize(),
+        };
+        let average = |mode: RenderMode| {
+            let (mut sum, passes) = (Rgba::ZERO, 256);
+            for pass in 0..passes {
+                let mut sampler = Sampler::new(0, 0, pass, 0);
+                let media = &mut sampler.medium_rng(0, &ray);
+                let hit = world.closest_hit(&ray, media).map(|(_, rec)| rec);
+                let mut rays = 0;
+                sum = sum
+                    + match mode {
+                        RenderMode::Path => PathIntegrator { max_depth: 5 }.li(
+                            &scene,
+                            &ray,
+                            &mut sampler,
+                            &mut rays,
+                        ),
+                        mode => world.mode_color(mode, &ray, hit, &mut sampler, 5, &mut rays),
+                    };
+            }
+            (sum * (1.0 / passes as Float)).to_array()
+        };
+
+        // The red wall faces -x
+        let normal = average(RenderMode::Normals);
+        assert!(normal[0] < 0.01 && (normal[1] - 0.5).abs() < 0.01);
+
+        let distance = 277.0f32.hypot(1200.0);
+        let depth = average(RenderMode::Depth {
+            max_distance: 10.0 * distance,
+        });
+        assert!((depth[0] - 0.1).abs() < 1e-3);
+        assert_eq!(average(RenderMode::Depth { max_distance: 1.0 })[0], 1.0);
+
+        // Open over the flat wall with a short radius, mostly blocked by the box with a
+        // long one
+        let open = average(RenderMode::AmbientOcclusion { radius: 1.0 });
+        let closed = average(RenderMode::AmbientOcclusion { radius: 1e4 });
+        assert_eq!(open[0], 1.0);
+        assert!(closed[0] < 0.5);
+
+        // Still lit by the lamp, but grey where the path traced wall is red
+        let path = average(RenderMode::Path);
+        let clay = average(RenderMode::Clay);
+        assert!(path[0] > 4.0 * path[2]);
+        assert!(clay[0] > 0.0 && (clay[0] - clay[2]).abs() < 0.2 * clay[0]);
+    }
+}
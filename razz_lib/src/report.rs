@@ -0,0 +1,155 @@
+use crate::image::Image;
+use crate::Scene;
+
+use std::fmt::Write;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct RenderReport {
+    pub width: usize,
+    pub height: usize,
+    pub samples_per_pixel: usize,
+    pub max_ray_depth: usize,
+    pub rays_traced: u64,
+    pub render_time: Duration,
+    pub image_bytes: usize,
+    pub peak_memory_bytes: Option<u64>,
+    pub nan_pixels: usize,
+    pub missing_textures: usize,
+    pub warnings: Vec<String>,
+}
+
+impl RenderReport {
+    pub fn new(
+        scene: &Scene,
+        image: &Image,
+        samples_per_pixel: usize,
+        max_ray_depth: usize,
+        rays_traced: u64,
+        render_time: Duration,
+    ) -> Self {
+        let nan_pixels = image
+            .data
+            .chunks(4)
+            .filter(|c| c.iter().any(|v| !v.is_finite()))
+            .count();
+        let missing_textures = scene.world.missing_textures();
+
+        let mut warnings = Vec::new();
+        if nan_pixels > 0 {
+            warnings.push(format!(
+                "{} pixels contain NaN or infinite values",
+                nan_pixels
+            ));
+        }
+        if missing_textures > 0 {
+            warnings.push(format!(
+                "{} texture references are missing and render as magenta",
+                missing_textures
+            ));
+        }
+
+        Self {
+            width: image.width,
+            height: image.height,
+            samples_per_pixel,
+            max_ray_depth,
+            rays_traced,
+            render_time,
+            image_bytes: image.data.len() * std::mem::size_of::<f32>(),
+            peak_memory_bytes: None,
+            nan_pixels,
+            missing_textures,
+            warnings,
+        }
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        match self.render_time.as_secs_f64() {
+            t if t > 0.0 => self.rays_traced as f64 / t,
+            _ => 0.0,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        let _ = writeln!(json, "{{");
+        let _ = writeln!(json, "  \"width\": {},", self.width);
+        let _ = writeln!(json, "  \"height\": {},", self.height);
+        let _ = writeln!(json, "  \"samples_per_pixel\": {},", self.samples_per_pixel);
+        let _ = writeln!(json, "  \"max_ray_depth\": {},", self.max_ray_depth);
+        let _ = writeln!(json, "  \"rays_traced\": {},", self.rays_traced);
+        let _ = writeln!(
+            json,
+            "  \"rays_per_second\": {:.1},",
+            self.rays_per_second()
+        );
+        let _ = writeln!(
+            json,
+            "  \"render_time_seconds\": {:.6},",
+            self.render_time.as_secs_f64()
+        );
+        let _ = writeln!(json, "  \"image_bytes\": {},", self.image_bytes);
+        match self.peak_memory_bytes {
+            Some(bytes) => {
+                let _ = writeln!(json, "  \"peak_memory_bytes\": {},", bytes);
+            }
+            None => {
+                let _ = writeln!(json, "  \"peak_memory_bytes\": null,");
+            }
+        }
+        let _ = writeln!(json, "  \"nan_pixels\": {},", self.nan_pixels);
+        let _ = writeln!(json, "  \"missing_textures\": {},", self.missing_textures);
+        let warnings: Vec<String> = self.warnings.iter().map(|w| json_string(w)).collect();
+        let _ = writeln!(json, "  \"warnings\": [{}]", warnings.join(", "));
+        let _ = write!(json, "}}");
+        json
+    }
+}
+
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ProgressiveRenderer;
+
+    #[test]
+    fn reports_count_rays_and_write_valid_json() {
+        let scene = crate::scenes::cornell_box(1.0);
+        let mut renderer = ProgressiveRenderer::new(6, 4, 4);
+        renderer.render(&scene, &mut rand::thread_rng());
+        renderer.render(&scene, &mut rand::thread_rng());
+        let mut report = renderer.report(&scene);
+        // At least the camera ray of every sample
+        assert!(report.rays_traced >= 2 * 6 * 4);
+        assert_eq!(report.samples_per_pixel, 2);
+
+        let warning = "a \"quoted\" C:\\path\nover\tlines\u{1}";
+        report.warnings = vec![warning.to_string()];
+        report.peak_memory_bytes = Some(1024);
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["warnings"][0], warning);
+        assert_eq!(json["rays_traced"], report.rays_traced);
+        assert_eq!(json["peak_memory_bytes"], 1024);
+        assert_eq!(json["width"], 6);
+
+        renderer.reset();
+        assert_eq!(renderer.report(&scene).rays_traced, 0);
+    }
+}
@@ -0,0 +1,505 @@
+use crate::blue_noise::{blue_noise, BLUE_NOISE_SIZE};
+use crate::{Float, Ray3A};
+
+use rand::RngCore;
+
+// Values each decision is expected to draw. Draws past these still get values of
+// their own, just not from the dimensions a sequence would lay out for them.
+const CAMERA_DIMENSIONS: u32 = 6;
+const BOUNCE_DIMENSIONS: u32 = 8;
+const OVERFLOW: u32 = 1 << 31;
+// Stepping by the golden ratio's fractional part spreads values evenly for any count
+const GOLDEN_RATIO_FRACT: f64 = 0.618_033_988_749_894_8;
+
+// Halton bases, one per dimension. Dimensions past these fall back to random values.
+const PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+// How the values in each dimension are spread over the samples of a pixel. Dimensions
+// are used in pairs, so a decision drawing two values gets a well spread 2D pattern.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SampleSequence {
+    // Independent uniform values
+    #[default]
+    Random,
+    // Jittered cells of a square grid, visited in a shuffled order. `samples` is the
+    // pixel's sample count, rounded down to a square, after which the grid repeats.
+    Stratified {
+        samples: usize,
+    },
+    // The Halton sequence, shifted by a random offset per pixel
+    Halton,
+    // Sobol's first two dimensions with hash-based Owen scrambling per pixel, and the
+    // sample order shuffled per pair of dimensions to keep the pairs independent
+    Sobol,
+    // A tiling blue-noise texture shifted per dimension, so neighbouring pixels get
+    // values far apart and few samples leave fine grain rather than blotches. Each
+    // sample moves every value on by the golden ratio.
+    BlueNoise,
+}
+
+// What a random value is being drawn for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleDimension {
+    // Camera decisions, made once per path
+    PixelJitter,
+    Lens,
+    Wavelength,
+    Time,
+    // Decisions made again at every bounce
+    Detail,
+    Bsdf,
+    LightPick,
+    Light,
+    Roulette,
+    // Seeds the `MediumRng` for the media along the bounce's ray
+    Medium,
+}
+
+impl SampleDimension {
+    // First dimension and number of dimensions reserved for the decision
+    fn span(self, bounce: u32) -> (u32, u32) {
+        let per_bounce =
+            |offset, len| (CAMERA_DIMENSIONS + bounce * BOUNCE_DIMENSIONS + offset, len);
+        match self {
+            SampleDimension::PixelJitter => (0, 2),
+            SampleDimension::Lens => (2, 2),
+            SampleDimension::Wavelength => (4, 1),
+            SampleDimension::Time => (5, 1),
+            SampleDimension::Bsdf => per_bounce(0, 2),
+            SampleDimension::Light => per_bounce(2, 2),
+            SampleDimension::LightPick => per_bounce(4, 1),
+            SampleDimension::Roulette => per_bounce(5, 1),
+            SampleDimension::Detail => per_bounce(6, 1),
+            SampleDimension::Medium => per_bounce(7, 1),
+        }
+    }
+}
+
+// The random numbers for one path. Every value comes from the pixel, the sample index
+// and a dimension fixed by the bounce and decision it's drawn for, so the same sample
+// always traces the same path and a material drawing an extra value can't shift the
+// numbers seen by the light sampling after it. Implements `RngCore`, so materials and
+// lights draw from it as from any other generator. Media draw from a `MediumRng`, seeded
+// from it at each bounce.
+#[derive(Debug, Clone)]
+pub struct Sampler {
+    pixel: (usize, usize),
+    seed: u64,
+    pixel_seed: u64,
+    sample_index: u64,
+    sequence: SampleSequence,
+    bounce: u32,
+    dimension: SampleDimension,
+    draw: u32,
+    // When the path's camera ray was sent, within the shutter
+    time: Float,
+}
+
+impl Sampler {
+    pub fn new(pixel_x: usize, pixel_y: usize, sample_index: u64, seed: u64) -> Self {
+        Self {
+            pixel: (pixel_x, pixel_y),
+            seed,
+            pixel_seed: mix(seed ^ mix(((pixel_x as u64) << 32) | pixel_y as u64)),
+            sample_index,
+            sequence: SampleSequence::Random,
+            bounce: 0,
+            dimension: SampleDimension::PixelJitter,
+            draw: 0,
+            time: 0.0,
+        }
+    }
+
+    pub fn with_sequence(mut self, sequence: SampleSequence) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    // Directs the following draws to `dimension` at `bounce`, counted from the camera
+    pub fn start(&mut self, bounce: usize, dimension: SampleDimension) {
+        self.bounce = bounce as u32;
+        self.dimension = dimension;
+        self.draw = 0;
+    }
+
+    pub fn sample_index(&self) -> u64 {
+        self.sample_index
+    }
+
+    pub fn time(&self) -> Float {
+        self.time
+    }
+
+    // Set by the camera as it sends the path's ray
+    pub fn set_time(&mut self, time: Float) {
+        self.time = time;
+    }
+
+    // Values for the media along the path's ray at `bounce`, at the path's time
+    pub fn medium_rng(&mut self, bounce: usize, ray: &Ray3A) -> MediumRng {
+        self.start(bounce, SampleDimension::Medium);
+        MediumRng::new(ray, self.next_u64()).at_time(self.time)
+    }
+
+    // The dimension the next draw comes from
+    pub fn dimension(&self) -> u32 {
+        let (first, len) = self.dimension.span(self.bounce);
+        match self.draw < len {
+            true => first + self.draw,
+            false => OVERFLOW | (first << 8) | self.draw.min(0xff),
+        }
+    }
+}
+
+impl RngCore for Sampler {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let dimension = self.dimension();
+        self.draw = self.draw.wrapping_add(1);
+        let key = ((dimension as u64) << 32) | self.draw as u64;
+        let random = mix(self.pixel_seed ^ mix(self.sample_index ^ mix(key)));
+        if dimension & OVERFLOW != 0 {
+            return random;
+        }
+
+        // Floats are made from the high bits, the low ones just stay random
+        let value = match self.sequence {
+            SampleSequence::Random => return random,
+            SampleSequence::Stratified { samples } => self.stratified(dimension, samples, random),
+            SampleSequence::Halton => match PRIMES.get(dimension as usize) {
+                Some(&base) => {
+                    let offset = self.dimension_seed(dimension) as f64 / 2f64.powi(32);
+                    let x = radical_inverse(self.sample_index, base) + offset;
+                    ((x - x.floor()) * 2f64.powi(32)) as u32
+                }
+                None => return random,
+            },
+            SampleSequence::Sobol => {
+                let pair_seed = self.dimension_seed(OVERFLOW | (dimension / 2));
+                let index = owen_scramble(self.sample_index as u32, pair_seed);
+                let x = match dimension % 2 {
+                    0 => index.reverse_bits(),
+                    _ => sobol_second_dimension(index),
+                };
+                owen_scramble(x, self.dimension_seed(dimension))
+            }
+            SampleSequence::BlueNoise => self.blue_noise(dimension),
+        };
+        ((value as u64) << 32) | (random & 0xffff_ffff)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl Sampler {
+    fn dimension_seed(&self, dimension: u32) -> u32 {
+        mix(self.pixel_seed ^ mix(dimension as u64)) as u32
+    }
+
+    fn stratified(&self, dimension: u32, samples: usize, random: u64) -> u32 {
+        let side = ((samples.max(1) as f64).sqrt() as u32).max(1);
+        let cells = side * side;
+        let stratum = (self.sample_index % cells as u64) as u32;
+        let cell = shuffle(
+            stratum,
+            cells,
+            self.dimension_seed(OVERFLOW | (dimension / 2)),
+        );
+        let column = match dimension % 2 {
+            0 => cell % side,
+            _ => cell / side,
+        };
+        let jitter = (random >> 32) as f64 / 2f64.powi(32);
+        ((column as f64 + jitter) / side as f64 * 2f64.powi(32)) as u32
+    }
+
+    fn blue_noise(&self, dimension: u32) -> u32 {
+        // The shift can't depend on the pixel, or neighbours would read unrelated texels
+        let shift = mix(self.seed ^ mix(dimension as u64));
+        let n = BLUE_NOISE_SIZE;
+        let x = (self.pixel.0 + shift as usize) % n;
+        let y = (self.pixel.1 + (shift >> 32) as usize) % n;
+        let rotation = (self.sample_index as f64 * GOLDEN_RATIO_FRACT).fract();
+        let value = (blue_noise()[y * n + x] as f64 + rotation).fract();
+        (value * 2f64.powi(32)) as u32
+    }
+}
+
+// `index` mirrored around the radix point in `base`
+fn radical_inverse(mut index: u64, base: u32) -> f64 {
+    let base = base as u64;
+    let inverse_base = 1.0 / base as f64;
+    let (mut digits, mut scale) = (0, 1.0);
+    while index > 0 {
+        digits = digits * base + index % base;
+        scale *= inverse_base;
+        index /= base;
+    }
+    (digits as f64 * scale).min(1.0 - f64::EPSILON)
+}
+
+// The second Sobol dimension, the first being the bit reversed index
+fn sobol_second_dimension(mut index: u32) -> u32 {
+    let (mut x, mut direction) = (0, 1 << 31);
+    while index != 0 {
+        if index & 1 != 0 {
+            x ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    x
+}
+
+// Nested uniform scrambling, after Burley's "Practical Hash-based Owen Scrambling"
+fn owen_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x.reverse_bits()
+}
+
+// A permutation of 0..len picked by `seed`, from Kensler's "Correlated Multi-Jittered
+// Sampling"
+fn shuffle(mut i: u32, len: u32, seed: u32) -> u32 {
+    let mut mask = len - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & mask) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & mask) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & mask) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= mask;
+        i ^= i >> 5;
+        if i < len {
+            return i.wrapping_add(seed) % len;
+        }
+    }
+}
+
+// Values for the media and clouds a ray meets while the world is intersected. The BVH
+// has no room for a sampler, so intersections take one of these alongside the ray,
+// seeded from the path's medium dimension. Queries made outside a path seed it with
+// whatever fixed value suits them, leaving the values a function of the ray. It also
+// carries the time the ray was sent, for instances that move while the shutter is open.
+#[derive(Debug, Default, Clone)]
+pub struct MediumRng {
+    state: u64,
+    time: Float,
+}
+
+impl MediumRng {
+    pub fn new(ray: &Ray3A, seed: u64) -> Self {
+        let [ox, oy, oz] = ray.origin.to_array();
+        let [dx, dy, dz] = ray.direction.to_array();
+        let state = [ox, oy, oz, dx, dy, dz]
+            .iter()
+            .fold(seed, |h, v| mix(h ^ v.to_bits() as u64));
+        Self { state, time: 0.0 }
+    }
+
+    // Sent at `time` rather than 0
+    pub fn at_time(mut self, time: Float) -> Self {
+        self.time = time;
+        self
+    }
+
+    pub fn time(&self) -> Float {
+        self.time
+    }
+}
+
+impl RngCore for MediumRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        mix(self.state)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+// SplitMix64's finalizer
+pub(crate) fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn decisions_draw_from_their_own_dimensions() {
+        let draw = |extra_bsdf_draws: usize| {
+            let mut sampler = Sampler::new(3, 7, 5, 0);
+            sampler.start(1, SampleDimension::Bsdf);
+            for _ in 0..extra_bsdf_draws {
+                sampler.gen::<f32>();
+            }
+            sampler.start(1, SampleDimension::Light);
+            sampler.gen::<f32>()
+        };
+        // However many values the material used, the light sees the same one
+        assert_eq!(draw(1), draw(10));
+
+        let mut sampler = Sampler::new(3, 7, 5, 0);
+        sampler.start(1, SampleDimension::Light);
+        let first_bounce = sampler.gen::<f32>();
+        sampler.start(2, SampleDimension::Light);
+        assert_ne!(first_bounce, sampler.gen::<f32>());
+        assert_ne!(
+            Sampler::new(3, 7, 5, 0).next_u64(),
+            Sampler::new(3, 7, 6, 0).next_u64()
+        );
+    }
+
+    #[test]
+    fn sequences_cover_every_stratum() {
+        let sequences = [
+            SampleSequence::Stratified { samples: 16 },
+            SampleSequence::Halton,
+            SampleSequence::Sobol,
+        ];
+        for sequence in sequences {
+            for dimension in [SampleDimension::PixelJitter, SampleDimension::Light] {
+                // Halton's bases grow with the dimension, only its first one splits in 16
+                if sequence == SampleSequence::Halton && dimension != SampleDimension::PixelJitter {
+                    continue;
+                }
+                let mut cells = [false; 16];
+                for index in 0..16 {
+                    let mut sampler = Sampler::new(3, 7, index, 0).with_sequence(sequence);
+                    sampler.start(2, dimension);
+                    let (x, y) = (sampler.gen::<f32>(), sampler.gen::<f32>());
+                    match sequence {
+                        SampleSequence::Halton => cells[(16.0 * x) as usize] = true,
+                        _ => cells[(4.0 * y) as usize * 4 + (4.0 * x) as usize] = true,
+                    }
+                }
+                // With 16 samples each of the 4x4 cells gets exactly one
+                assert!(cells.iter().all(|c| *c), "{:?} {:?}", sequence, dimension);
+            }
+        }
+    }
+
+    #[test]
+    fn blue_noise_separates_neighbouring_pixels() {
+        let value = |x, y, index| {
+            let mut sampler = Sampler::new(x, y, index, 0).with_sequence(SampleSequence::BlueNoise);
+            sampler.start(0, SampleDimension::PixelJitter);
+            sampler.gen::<f32>()
+        };
+        let pixels = 32 * 32;
+        let difference: f32 = (0..pixels)
+            .map(|i| {
+                let d = (value(i % 32, i / 32, 3) - value(i % 32 + 1, i / 32, 3)).abs();
+                // Values wrap around after each sample's shift
+                d.min(1.0 - d)
+            })
+            .sum::<f32>()
+            / pixels as f32;
+        // Independent values would be a quarter apart on average
+        assert!(difference > 0.3, "{}", difference);
+        assert_ne!(value(3, 7, 0), value(3, 7, 1));
+    }
+
+    #[test]
+    fn volumes_render_the_same_on_any_thread() {
+        use crate::{
+            CloudSettings, Material, ParallelRenderer, Point3, Primative, Rgba, Texture, Vec3A,
+            WorldBuilder,
+        };
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let render = || {
+            let mut world_builder = WorldBuilder::default();
+            let white = world_builder.push_texture(Texture::Solid {
+                color: Rgba::splat(0.8),
+            });
+            let phase = world_builder.push_material(Material::Isotropic { albedo: white });
+            let fog = Primative::sphere(Point3::new(-0.6, 0.0, 0.0), 0.6, phase);
+            world_builder.push_hittable(Primative::constant_medium(fog, 2.0, phase));
+            let layer = Primative::sphere(Point3::new(0.6, 0.0, 0.0), 0.6, phase);
+            let settings = CloudSettings {
+                coverage: 1.0,
+                base: -0.6,
+                top: 0.6,
+                scale: 3.0,
+                ..Default::default()
+            };
+            let mut rng = StdRng::seed_from_u64(2);
+            world_builder.push_hittable(Primative::clouds(layer, settings, phase, &mut rng));
+            world_builder.set_background(crate::Background::rtiow_sky());
+            let camera = crate::Camera::new(-4.0 * Vec3A::Z, Vec3A::ZERO, 40.0, 1.0, 0.0, 1.0);
+            let scene = crate::Scene::new(world_builder.into(), camera);
+
+            let mut renderer = ParallelRenderer::new(16, 16, 6);
+            renderer.render(&scene);
+            renderer.render(&scene);
+            (renderer.image().clone(), renderer.tile_hashes(8))
+        };
+
+        // Rows land on whichever threads are free, so this only holds if media draw from
+        // the path's sampler rather than a thread's generator
+        let (image, hashes) = render();
+        for _ in 0..3 {
+            let (again, again_hashes) = render();
+            assert_eq!(image.data, again.data);
+            assert_eq!(hashes, again_hashes);
+        }
+    }
+}
@@ -0,0 +1,389 @@
+// Canonical scenes shared by the viewer, tests and benchmarks
+
+use crate::{
+    Background, Camera, CloudSettings, Float, Material, MaterialKey, Mesh, Point3, Primative, Rgba,
+    Scene, Texture, Transform, Vec3A, WorldBuilder,
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::sync::Arc;
+
+// The classic box with a tall and a short block
+pub fn cornell_box(aspect_ratio: Float) -> Scene {
+    let mut world_builder = WorldBuilder::default();
+    let camera = cornell_box_room(&mut world_builder, aspect_ratio);
+    let block = |size, degrees: Float, corner, material| {
+        Primative::instance(
+            Arc::new(Primative::cuboid(Vec3A::ZERO, size, material)),
+            Transform::new(
+                corner,
+                glam::Quat::from_rotation_y(degrees.to_radians()),
+                1.0,
+            ),
+        )
+    };
+
+    crate::scene! {
+        world: world_builder,
+        camera: camera,
+        textures: {
+            white: Texture::Solid { color: Rgba::new(0.73, 0.73, 0.73, 1.0) },
+        },
+        materials: {
+            white_material: Material::Lambertian { albedo: white },
+        },
+        primitives: [
+            block(
+                Vec3A::new(165.0, 330.0, 165.0),
+                15.0,
+                Vec3A::new(265.0, 0.0, 295.0),
+                white_material,
+            ),
+            block(
+                Vec3A::new(165.0, 165.0, 165.0),
+                -18.0,
+                Vec3A::new(130.0, 0.0, 65.0),
+                white_material,
+            ),
+        ],
+    }
+}
+
+// The empty box, in its original 555 unit coordinates, with a registered area light
+pub fn cornell_box_room(world_builder: &mut WorldBuilder, aspect_ratio: Float) -> Camera {
+    let camera = Camera::new(
+        Vec3A::new(278.0, 278.0, -800.0),
+        Vec3A::new(278.0, 278.0, 0.0),
+        40.0,
+        aspect_ratio,
+        0.0,
+        10.0,
+    );
+
+    let red_texture = world_builder.push_texture(Texture::Solid {
+        color: Rgba::new(0.65, 0.05, 0.05, 1.0),
+    });
+    let white_texture = world_builder.push_texture(Texture::Solid {
+        color: Rgba::new(0.73, 0.73, 0.73, 1.0),
+    });
+    let green_texture = world_builder.push_texture(Texture::Solid {
+        color: Rgba::new(0.12, 0.45, 0.15, 1.0),
+    });
+    let light_texture = world_builder.push_texture(Texture::Solid {
+        color: Rgba::new(5.0, 5.0, 5.0, 1.0),
+    });
+
+    let red_material = world_builder.push_material(Material::Lambertian {
+        albedo: red_texture,
+    });
+    let white_material = world_builder.push_material(Material::Lambertian {
+        albedo: white_texture,
+    });
+    let green_material = world_builder.push_material(Material::Lambertian {
+        albedo: green_texture,
+    });
+    let light_material = world_builder.push_material(Material::DiffuseLight {
+        emit: light_texture,
+        intensity: 1.0,
+    });
+
+    let x = Vec3A::new(555.0, 0.0, 0.0);
+    let y = Vec3A::new(0.0, 555.0, 0.0);
+    let z = Vec3A::new(0.0, 0.0, 555.0);
+    let walls = [
+        (x, y, z, red_material),
+        (Vec3A::ZERO, z, y, green_material),
+        (z, x, y, white_material),
+        (Vec3A::ZERO, x, z, white_material),
+        (y, x, z, white_material),
+    ];
+
+    let light = Mesh::build(
+        vec![
+            [213.0, 554.0, 227.0].into(),
+            [343.0, 554.0, 227.0].into(),
+            [343.0, 554.0, 332.0].into(),
+            [343.0, 554.0, 332.0].into(),
+            [213.0, 554.0, 332.0].into(),
+            [213.0, 554.0001, 227.0].into(),
+        ],
+        None,
+        vec![(0, 1, 2), (3, 4, 5)],
+        None,
+        light_material,
+    );
+
+    for (corner, u, v, material) in walls.iter() {
+        world_builder.push_hittable(Primative::quad(*corner, *u, *v, *material));
+    }
+    world_builder.push_area_light(light, false);
+
+    camera
+}
+
+// Four glossy plates of increasing roughness lit by four spheres of equal power but
+// increasing size (Veach 1997). Each light and plate pair favours a different sampling
+// strategy, which makes it the standard check for multiple importance sampling.
+pub fn veach_mis(aspect_ratio: Float) -> Scene {
+    const RADII: [Float; 4] = [0.03, 0.1, 0.3, 0.9];
+    const ROUGHNESS: [Float; 4] = [0.1, 0.2, 0.3, 0.45];
+
+    let mut world_builder = WorldBuilder::default();
+    let eye = Vec3A::new(0.0, 2.0, 15.0);
+    let camera = Camera::new(
+        eye,
+        Vec3A::new(0.0, -2.0, 2.5),
+        28.0,
+        aspect_ratio,
+        0.0,
+        1.0,
+    );
+
+    let light_center = Vec3A::new(0.0, 0.0, -1.0);
+    let white = world_builder.push_texture(Texture::Solid { color: Rgba::ONE });
+    for (i, radius) in RADII.iter().enumerate() {
+        let material = world_builder.push_material(Material::DiffuseLight {
+            emit: white,
+            intensity: 0.8 / (radius * radius),
+        });
+        let center = light_center + Vec3A::new(-3.75 + 2.5 * i as Float, 0.0, 0.0);
+        world_builder.push_hittable(Primative::sphere(center, *radius, material));
+    }
+
+    let grey = world_builder.push_texture(Texture::Solid {
+        color: Rgba::new(0.3, 0.3, 0.3, 1.0),
+    });
+    for (i, roughness) in ROUGHNESS.iter().enumerate() {
+        let material = world_builder.push_material(Material::RoughMetal {
+            albedo: grey,
+            roughness: *roughness,
+        });
+        // Tilted so the middle of each plate mirrors the lights towards the eye
+        let center = Vec3A::new(0.0, -2.0 - 0.4 * i as Float, 0.5 + 1.1 * i as Float);
+        let normal = ((eye - center).normalize() + (light_center - center).normalize()).normalize();
+        let across = 4.0 * Vec3A::X;
+        let along = 0.5 * normal.cross(Vec3A::X).normalize();
+        world_builder.push_hittable(Primative::Mesh(quad(
+            [
+                center - across - along,
+                center + across - along,
+                center + across + along,
+                center - across + along,
+            ],
+            material,
+        )));
+    }
+
+    let floor = world_builder.push_texture(Texture::Solid {
+        color: Rgba::new(0.4, 0.4, 0.4, 1.0),
+    });
+    let floor = world_builder.push_material(Material::Lambertian { albedo: floor });
+    world_builder.push_hittable(Primative::Mesh(quad(
+        [
+            Vec3A::new(-10.0, -4.5, -4.0),
+            Vec3A::new(10.0, -4.5, -4.0),
+            Vec3A::new(10.0, -4.5, 10.0),
+            Vec3A::new(-10.0, -4.5, 10.0),
+        ],
+        floor,
+    )));
+
+    Scene::new(world_builder.into(), camera)
+}
+
+// A unit sphere inside a uniformly white emitting sphere. Any material that conserves
+// energy and doesn't absorb should vanish against the background.
+pub fn furnace(material: impl FnOnce(&mut WorldBuilder) -> MaterialKey) -> Scene {
+    let mut world_builder = WorldBuilder::default();
+    let material = material(&mut world_builder);
+    world_builder.push_hittable(Primative::sphere(Vec3A::ZERO, 1.0, material));
+
+    let white = world_builder.push_texture(Texture::Solid { color: Rgba::ONE });
+    let environment = world_builder.push_material(Material::DiffuseLight {
+        emit: white,
+        intensity: 1.0,
+    });
+    world_builder.push_hittable(Primative::sphere(Vec3A::ZERO, 100.0, environment));
+
+    let camera = Camera::new(Vec3A::new(0.0, 0.0, 4.0), Vec3A::ZERO, 40.0, 1.0, 0.0, 1.0);
+    Scene::new(world_builder.into(), camera)
+}
+
+// The final scene from Ray Tracing in One Weekend. The ground is a large plane rather
+// than a huge sphere.
+pub fn rtiow_final(seed: u64, aspect_ratio: Float) -> Scene {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut world_builder = WorldBuilder::default();
+    let solid = |world_builder: &mut WorldBuilder, color: Rgba| {
+        world_builder.push_texture(Texture::Solid { color })
+    };
+
+    let ground = solid(&mut world_builder, Rgba::new(0.5, 0.5, 0.5, 1.0));
+    let ground = world_builder.push_material(Material::Lambertian { albedo: ground });
+    world_builder.push_hittable(Primative::Mesh(quad(
+        [
+            Vec3A::new(-50.0, 0.0, -50.0),
+            Vec3A::new(-50.0, 0.0, 50.0),
+            Vec3A::new(50.0, 0.0, 50.0),
+            Vec3A::new(50.0, 0.0, -50.0),
+        ],
+        ground,
+    )));
+
+    let glass = world_builder.push_material(Material::dielectric(1.5));
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_material = rng.gen::<Float>();
+            let center = Vec3A::new(
+                a as Float + 0.9 * rng.gen::<Float>(),
+                0.2,
+                b as Float + 0.9 * rng.gen::<Float>(),
+            );
+            if (center - Vec3A::new(4.0, 0.2, 0.0)).length() <= 0.9 {
+                continue;
+            }
+
+            let material = if choose_material < 0.8 {
+                let color = rng.gen::<Vec3A>() * rng.gen::<Vec3A>();
+                let albedo = solid(
+                    &mut world_builder,
+                    Rgba::new(color.x, color.y, color.z, 1.0),
+                );
+                world_builder.push_material(Material::Lambertian { albedo })
+            } else if choose_material < 0.95 {
+                let color = Vec3A::splat(0.5) + 0.5 * rng.gen::<Vec3A>();
+                let albedo = solid(
+                    &mut world_builder,
+                    Rgba::new(color.x, color.y, color.z, 1.0),
+                );
+                let fuzz = 0.5 * rng.gen::<Float>();
+                world_builder.push_material(Material::Metal { albedo, fuzz })
+            } else {
+                glass
+            };
+            world_builder.push_hittable(Primative::sphere(center, 0.2, material));
+        }
+    }
+
+    let brown = solid(&mut world_builder, Rgba::new(0.4, 0.2, 0.1, 1.0));
+    let brown = world_builder.push_material(Material::Lambertian { albedo: brown });
+    let bronze = solid(&mut world_builder, Rgba::new(0.7, 0.6, 0.5, 1.0));
+    let bronze = world_builder.push_material(Material::Metal {
+        albedo: bronze,
+        fuzz: 0.0,
+    });
+    world_builder.push_hittable(Primative::sphere(Vec3A::new(0.0, 1.0, 0.0), 1.0, glass));
+    world_builder.push_hittable(Primative::sphere(Vec3A::new(-4.0, 1.0, 0.0), 1.0, brown));
+    world_builder.push_hittable(Primative::sphere(Vec3A::new(4.0, 1.0, 0.0), 1.0, bronze));
+
+    world_builder.set_background(Background::rtiow_sky());
+
+    let camera = Camera::new(
+        Vec3A::new(13.0, 2.0, 3.0),
+        Vec3A::ZERO,
+        20.0,
+        aspect_ratio,
+        0.1,
+        10.0,
+    );
+    Scene::new(world_builder.into(), camera)
+}
+
+// A layer of cumulus over a flat plain, lit by a low sun and a blue sky
+pub fn skyscape(seed: u64, aspect_ratio: Float) -> Scene {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut world_builder = WorldBuilder::default();
+
+    let ground = world_builder.push_texture(Texture::Solid {
+        color: Rgba::new(0.3, 0.35, 0.2, 1.0),
+    });
+    let ground = world_builder.push_material(Material::Lambertian { albedo: ground });
+    world_builder.push_hittable(Primative::Mesh(quad(
+        [
+            Vec3A::new(-90.0, 0.0, -90.0),
+            Vec3A::new(-90.0, 0.0, 90.0),
+            Vec3A::new(90.0, 0.0, 90.0),
+            Vec3A::new(90.0, 0.0, -90.0),
+        ],
+        ground,
+    )));
+
+    let white = world_builder.push_texture(Texture::Solid { color: Rgba::ONE });
+    let phase = world_builder.push_material(Material::Isotropic { albedo: white });
+    let settings = CloudSettings {
+        density: 0.6,
+        coverage: 0.45,
+        base: 6.0,
+        top: 12.0,
+        scale: 0.08,
+        octaves: 5,
+        step: 0.25,
+    };
+    let layer = Primative::Mesh(cuboid(
+        Vec3A::new(-80.0, settings.base, -80.0),
+        Vec3A::new(80.0, settings.top, 80.0),
+        phase,
+    ));
+    world_builder.push_hittable(Primative::clouds(layer, settings, phase, &mut rng));
+
+    let sun = world_builder.push_material(Material::DiffuseLight {
+        emit: white,
+        intensity: 80.0,
+    });
+    let sun_direction = Vec3A::new(-0.5, 0.5, -1.0).normalize();
+    world_builder.push_hittable(Primative::sphere(90.0 * sun_direction, 8.0, sun));
+
+    let sky = world_builder.push_texture(Texture::Solid {
+        color: Rgba::new(0.45, 0.6, 0.9, 1.0),
+    });
+    let sky = world_builder.push_material(Material::DiffuseLight {
+        emit: sky,
+        intensity: 1.0,
+    });
+    world_builder.push_hittable(Primative::sphere(Vec3A::ZERO, 100.0, sky));
+
+    let camera = Camera::new(
+        Vec3A::new(0.0, 1.5, 20.0),
+        Vec3A::new(0.0, 8.0, 0.0),
+        70.0,
+        aspect_ratio,
+        0.0,
+        1.0,
+    );
+    Scene::new(world_builder.into(), camera)
+}
+
+pub(crate) fn quad(corners: [Point3; 4], material: MaterialKey) -> Arc<Mesh> {
+    Mesh::build(
+        corners.to_vec(),
+        None,
+        vec![(0, 1, 2), (0, 2, 3)],
+        None,
+        material,
+    )
+}
+
+pub(crate) fn cuboid(min: Point3, max: Point3, material: MaterialKey) -> Arc<Mesh> {
+    let vertices = (0..8)
+        .map(|corner| {
+            Vec3A::new(
+                if corner & 1 == 0 { min.x } else { max.x },
+                if corner & 2 == 0 { min.y } else { max.y },
+                if corner & 4 == 0 { min.z } else { max.z },
+            )
+        })
+        .collect();
+    let faces = [
+        (0, 2, 3, 1),
+        (4, 5, 7, 6),
+        (0, 1, 5, 4),
+        (2, 6, 7, 3),
+        (0, 4, 6, 2),
+        (1, 3, 7, 5),
+    ];
+    let indices = faces
+        .iter()
+        .flat_map(|(a, b, c, d)| vec![(*a, *b, *c), (*a, *c, *d)])
+        .collect();
+    Mesh::build(vertices, None, indices, None, material)
+}
@@ -0,0 +1,136 @@
+use crate::{
+    AreaLight, Background, DetailCulling, Material, Primative, Texture, World, WorldBuilder,
+};
+use crate::{MaterialKey, PrimativeKey, TextureKey};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use slotmap::SlotMap;
+
+// Field names must match `WorldBuilder` so a saved world loads back through it
+#[derive(Serialize)]
+struct WorldRef<'a> {
+    textures: &'a SlotMap<TextureKey, Texture>,
+    materials: &'a SlotMap<MaterialKey, Material>,
+    hittables: &'a [Primative],
+    lights: &'a [AreaLight],
+    detail_culling: &'a Option<DetailCulling>,
+    background: &'a Background,
+    instances: &'a SlotMap<PrimativeKey, usize>,
+}
+
+impl Serialize for World {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        WorldRef {
+            textures: &self.textures,
+            materials: &self.materials,
+            hittables: &self.hittables,
+            lights: &self.lights,
+            detail_culling: &self.detail_culling,
+            background: &self.background,
+            instances: &self.instance_keys,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for World {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        WorldBuilder::deserialize(deserializer)?
+            .build()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Camera, Mesh, ParallelRenderer, Point3, Rgba, Scene, TextureSpace, Transform, Vec3A,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn scenes_round_trip_with_their_keys_and_shared_meshes() {
+        let mut world_builder = WorldBuilder::default();
+        let red = world_builder.push_texture(Texture::Solid {
+            color: Rgba::new(0.8, 0.1, 0.1, 1.0),
+        });
+        let white = world_builder.push_texture(Texture::Solid {
+            color: Rgba::splat(0.9),
+        });
+        let checker = world_builder.push_texture(Texture::Checker {
+            odd: red,
+            even: white,
+            scale: 4.0,
+            space: TextureSpace::World,
+        });
+        let floor = world_builder.push_material(Material::Lambertian { albedo: checker });
+        let metal = world_builder.push_material(Material::Metal {
+            albedo: white,
+            fuzz: 0.2,
+        });
+        let light = world_builder.push_material(Material::DiffuseLight {
+            emit: white,
+            intensity: 4.0,
+        });
+
+        let pyramid = Mesh::new(
+            vec![
+                Point3::new(-0.5, 0.0, -0.5),
+                Point3::new(0.5, 0.0, -0.5),
+                Point3::new(0.0, 0.0, 0.5),
+                Point3::new(0.0, 1.0, 0.0),
+            ],
+            vec![(0, 1, 3), (1, 2, 3), (2, 0, 3), (0, 2, 1)],
+            metal,
+        )
+        .unwrap();
+        let shared = Arc::new(Primative::Mesh(pyramid));
+        for x in [-0.8, 0.8] {
+            let transform = Transform::new(Vec3A::new(x, 0.0, 0.0), glam::Quat::IDENTITY, 1.0);
+            world_builder.push_instance(Arc::clone(&shared), transform);
+        }
+        let lamp = Mesh::new(
+            vec![
+                Point3::new(-1.0, 3.0, -1.0),
+                Point3::new(1.0, 3.0, -1.0),
+                Point3::new(1.0, 3.0, 1.0),
+                Point3::new(-1.0, 3.0, 1.0),
+            ],
+            vec![(0, 1, 2), (0, 2, 3)],
+            light,
+        )
+        .unwrap();
+        world_builder.push_area_light(lamp, true);
+        world_builder.push_hittable(Primative::plane(Point3::ZERO, Vec3A::Y, floor));
+
+        let camera = Camera::new(Vec3A::new(0.0, 1.5, -4.0), Vec3A::ZERO, 50.0, 1.0, 0.0, 4.0);
+        let scene = Scene::new(world_builder.into(), camera);
+        let json = serde_json::to_string(&scene).unwrap();
+        let loaded: Scene = serde_json::from_str(&json).unwrap();
+
+        // Keys from before saving still find the same textures and materials
+        let (textures, materials) = (loaded.world.textures(), loaded.world.materials());
+        assert!(matches!(
+            textures.get(checker),
+            Some(Texture::Checker { odd, even, .. }) if (*odd, *even) == (red, white)
+        ));
+        assert!(matches!(
+            materials.get(floor),
+            Some(Material::Lambertian { albedo }) if *albedo == checker
+        ));
+        assert!(matches!(materials.get(metal), Some(Material::Metal { .. })));
+        assert_eq!(loaded.world.lights().len(), 1);
+        assert_eq!(loaded.world.lights()[0].material_key(), light);
+
+        let render = |scene: &Scene| {
+            let mut renderer = ParallelRenderer::new(12, 12, 4);
+            renderer.render(scene);
+            renderer.render(scene);
+            renderer.image().clone()
+        };
+        let (before, after) = (render(&scene), render(&loaded));
+        assert!(before.data.iter().any(|v| *v > 0.0));
+        assert_eq!(before.data, after.data);
+    }
+}
@@ -0,0 +1,215 @@
+use super::medium::{inside_span, phase_hit};
+use super::*;
+use crate::noise::PerlinData;
+use crate::sampler::MediumRng;
+
+use rand::Rng;
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CloudSettings {
+    // Density at the thick core of a cloud, in the same units as `ConstantMedium`
+    pub density: Float,
+    // Fraction of the sky covered, from 0 (clear) to 1 (overcast)
+    pub coverage: Float,
+    // World space heights of the cloud layer's base and top
+    pub base: Float,
+    pub top: Float,
+    // Spatial frequency of the cloud shapes and the fBm octaves that form them
+    pub scale: Float,
+    pub octaves: usize,
+    // Ray march step length in world units
+    pub step: Float,
+}
+
+impl Default for CloudSettings {
+    fn default() -> Self {
+        Self {
+            density: 1.0,
+            coverage: 0.5,
+            base: 0.0,
+            top: 1.0,
+            scale: 1.0,
+            octaves: 5,
+            step: 0.05,
+        }
+    }
+}
+
+// A layer of procedural clouds filling a convex `boundary`. Shapes come from fBm,
+// thresholded by coverage and shaped by height, with Worley noise eroding the
+// edges into billows. Rays march through at a fixed step accumulating optical
+// depth and scatter once it passes an exponentially sampled target.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Clouds {
+    boundary: Box<Primative>,
+    noise: Box<PerlinData>,
+    settings: CloudSettings,
+    phase_material: MaterialKey,
+}
+
+impl Clouds {
+    // What scattering inside the medium is shaded with
+    pub fn phase_material(&self) -> MaterialKey {
+        self.phase_material
+    }
+
+    // `phase_material` is usually `Material::Isotropic`
+    pub fn new<T: Rng>(
+        boundary: Primative,
+        settings: CloudSettings,
+        phase_material: MaterialKey,
+        rng: &mut T,
+    ) -> Self {
+        Self {
+            boundary: Box::new(boundary),
+            noise: Box::new(PerlinData::new(rng)),
+            settings,
+            phase_material,
+        }
+    }
+
+    pub fn settings(&self) -> &CloudSettings {
+        &self.settings
+    }
+
+    pub fn density(&self, p: Point3) -> Float {
+        let s = &self.settings;
+        if s.coverage <= 0.0 || s.top <= s.base {
+            return 0.0;
+        }
+        let height = (p.y - s.base) / (s.top - s.base);
+        if !(0.0..=1.0).contains(&height) {
+            return 0.0;
+        }
+
+        // Rounded bases and thinning tops
+        let profile = smoothstep(0.0, 0.15, height) * (1.0 - smoothstep(0.6, 1.0, height));
+        let q = s.scale * p;
+        // fBm rarely strays beyond [-0.25, 0.25], so stretch it over [0, 1]
+        let shape = profile * (0.5 + 2.0 * self.noise.fbm(q, s.octaves)).clamp(0.0, 1.0);
+        // Only shapes above the coverage threshold form clouds
+        let cover = (shape - (1.0 - s.coverage)) / s.coverage.min(1.0);
+        if cover <= 0.0 {
+            return 0.0;
+        }
+
+        let erosion = 0.3 * self.noise.worley(4.0 * q).min(1.0);
+        s.density * ((cover - erosion) / (1.0 - erosion)).clamp(0.0, 1.0)
+    }
+}
+
+impl Bounded<Bounds3A> for Clouds {
+    fn bounds(&self) -> Bounds3A {
+        self.boundary.bounds()
+    }
+}
+
+impl Clouds {
+    // Where along `ray` it scatters, if anywhere, drawing from `rng`
+    pub(crate) fn scatter(
+        &self,
+        ray: &Ray3A,
+        t_min: Float,
+        t_max: Float,
+        rng: &mut MediumRng,
+    ) -> Option<(Float, HitRecord)> {
+        if self.settings.density <= 0.0 {
+            return None;
+        }
+        let (t_enter, t_exit) = inside_span(&self.boundary, ray, t_min, t_max)?;
+
+        let ray_length = ray.direction.length();
+        let dt = self.settings.step.max(Float::EPSILON) / ray_length;
+        let target = -(1.0 - rng.gen::<Float>()).ln();
+        // Jittering where each step samples turns banding into noise
+        let jitter = rng.gen::<Float>();
+
+        let mut depth = 0.0;
+        let mut time = t_enter;
+        while time < t_exit {
+            let span = dt.min(t_exit - time);
+            let sigma = self.density(ray.at(time + jitter * span)) * ray_length;
+            if depth + sigma * span >= target {
+                let time = time + (target - depth) / sigma;
+                return Some((time, phase_hit(ray, time, self.phase_material)));
+            }
+            depth += sigma * span;
+            time += span;
+        }
+        None
+    }
+}
+
+impl RayHittable<Bounds3A> for Clouds {
+    type Item = HitRecord;
+
+    fn ray_hit(&self, ray: &Ray3A, t_min: f32, t_max: f32) -> Option<(f32, Self::Item)> {
+        self.scatter(ray, t_min, t_max, &mut MediumRng::new(ray, 0))
+    }
+}
+
+fn smoothstep(edge0: Float, edge1: Float, x: Float) -> Float {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn clouds_stay_within_their_layer() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let boundary = Primative::sphere(Point3::ZERO, 10.0, MaterialKey::default());
+        let settings = CloudSettings {
+            coverage: 1.0,
+            base: -1.0,
+            top: 1.0,
+            ..Default::default()
+        };
+        let clouds = Clouds::new(boundary, settings, MaterialKey::default(), &mut rng);
+
+        let inside = (0..100)
+            .map(|i| Point3::new(0.37 * i as Float, 0.0, 0.11 * i as Float))
+            .filter(|p| clouds.density(*p) > 0.0)
+            .count();
+        assert!(inside > 50);
+        assert_eq!(clouds.density(Point3::new(0.0, 1.5, 0.0)), 0.0);
+        assert_eq!(clouds.density(Point3::new(0.0, -1.5, 0.0)), 0.0);
+
+        // A ray passing over the layer never scatters
+        let ray = Ray3A {
+            origin: Point3::new(-9.0, 2.0, 0.0),
+            direction: Vec3A::X,
+        };
+        assert!((0..100).all(|_| clouds.ray_hit(&ray, 0.001, Float::INFINITY).is_none()));
+
+        // One through the layer scatters wherever its seed says, every time
+        let ray = Ray3A {
+            origin: Point3::new(-9.0, 0.0, 0.0),
+            direction: Vec3A::X,
+        };
+        let scatter = |seed| {
+            let mut rng = MediumRng::new(&ray, seed);
+            clouds
+                .scatter(&ray, 0.001, Float::INFINITY, &mut rng)
+                .map(|(t, _)| t)
+        };
+        assert!(scatter(3).is_some());
+        assert_eq!(scatter(3), scatter(3));
+
+        let clear = Clouds::new(
+            Primative::default(),
+            CloudSettings {
+                coverage: 0.0,
+                ..settings
+            },
+            MaterialKey::default(),
+            &mut rng,
+        );
+        assert_eq!(clear.density(Point3::ZERO), 0.0);
+    }
+}
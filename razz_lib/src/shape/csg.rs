@@ -0,0 +1,178 @@
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    // The first shape with the second carved out of it
+    Difference,
+}
+
+// Crossings walked along a ray before giving up on it. Only children with a great many
+// crossings get this far, such as media, which can scatter anywhere inside.
+const MAX_CROSSINGS: usize = 1024;
+
+impl CsgOp {
+    fn contains(self, inside_a: bool, inside_b: bool) -> bool {
+        match self {
+            Self::Union => inside_a || inside_b,
+            Self::Intersection => inside_a && inside_b,
+            Self::Difference => inside_a && !inside_b,
+        }
+    }
+}
+
+// Two solids combined by walking the boundary crossings of each along the ray and
+// tracking whether the ray is inside either. The combined surface is wherever the
+// ray moves in or out of the result. Children should be closed surfaces, or
+// planes, as volumes have no boundary to cross. Surfaces keep the material of the
+// child they came from, so a carved hole shows the material of the second shape.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Csg {
+    op: CsgOp,
+    a: Box<Primative>,
+    b: Box<Primative>,
+}
+
+impl Csg {
+    pub(crate) fn material_keys(&self) -> Vec<MaterialKey> {
+        let mut keys = self.a.material_keys();
+        keys.extend(self.b.material_keys());
+        keys
+    }
+
+    pub fn new(op: CsgOp, a: Primative, b: Primative) -> Self {
+        Self {
+            op,
+            a: Box::new(a),
+            b: Box::new(b),
+        }
+    }
+
+    pub fn op(&self) -> CsgOp {
+        self.op
+    }
+}
+
+impl Bounded<Bounds3A> for Csg {
+    fn bounds(&self) -> Bounds3A {
+        let (a, b) = (self.a.bounds(), self.b.bounds());
+        match self.op {
+            CsgOp::Union => Bounds3A::new(a.min.min(b.min), a.max.max(b.max)),
+            CsgOp::Intersection => {
+                let min = a.min.max(b.min);
+                Bounds3A::new(min, a.max.min(b.max).max(min))
+            }
+            CsgOp::Difference => a,
+        }
+    }
+}
+
+impl RayHittable<Bounds3A> for Csg {
+    type Item = HitRecord;
+
+    fn ray_hit(&self, ray: &Ray3A, t_min: f32, t_max: f32) -> Option<(f32, Self::Item)> {
+        // Start far behind the origin so every crossing before `t_min` is counted
+        let mut next_a = self.a.ray_hit(ray, Float::NEG_INFINITY, Float::INFINITY);
+        let mut next_b = self.b.ray_hit(ray, Float::NEG_INFINITY, Float::INFINITY);
+        // A ray whose first crossing is an exit began inside, as with planes
+        let mut inside_a = matches!(next_a, Some((_, rec)) if rec.face == Face::Back);
+        let mut inside_b = matches!(next_b, Some((_, rec)) if rec.face == Face::Back);
+        let mut inside = self.op.contains(inside_a, inside_b);
+
+        for _ in 0..MAX_CROSSINGS {
+            let from_a = match (next_a, next_b) {
+                (Some((ta, _)), Some((tb, _))) => ta <= tb,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => return None,
+            };
+            let (next, child, inside_child) = match from_a {
+                true => (&mut next_a, &self.a, &mut inside_a),
+                false => (&mut next_b, &self.b, &mut inside_b),
+            };
+            let (t, rec) = next.take()?;
+            if t > t_max {
+                return None;
+            }
+
+            *inside_child = rec.face == Face::Front;
+            let was_inside = inside;
+            inside = self.op.contains(inside_a, inside_b);
+            if inside != was_inside && t >= t_min {
+                // Child normals already face the ray, only the side is the result's
+                let face = match inside {
+                    true => Face::Front,
+                    false => Face::Back,
+                };
+                return Some((t, HitRecord { face, ..rec }));
+            }
+
+            // Step just past the crossing, relative to its distance to stay scale-free
+            let t_skip = t + 1e-5 * t.abs().max(1.0);
+            // Crossings at infinity or NaN can't be stepped past
+            if t.is_nan() || t_skip <= t {
+                return None;
+            }
+            *next = child.ray_hit(ray, t_skip, Float::INFINITY);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csg_hits_follow_the_combined_solid() {
+        let key = MaterialKey::default();
+        let cube = || Primative::cuboid(-Point3::ONE, Point3::ONE, key);
+        let ray = |x: Float| Ray3A {
+            origin: Point3::new(x, 0.0, 5.0),
+            direction: -Vec3A::Z,
+        };
+
+        // A bite out of the front face exposes the inside of the sphere
+        let bitten = Csg::new(
+            CsgOp::Difference,
+            cube(),
+            Primative::sphere(Point3::Z, 0.5, key),
+        );
+        let (t, rec) = bitten.ray_hit(&ray(0.0), 0.001, 10.0).unwrap();
+        assert!((t - 4.5).abs() < 1e-4);
+        assert_eq!((rec.face, rec.normal), (Face::Front, Vec3A::Z));
+        let (t, _) = bitten.ray_hit(&ray(0.9), 0.001, 10.0).unwrap();
+        assert!((t - 4.0).abs() < 1e-4);
+
+        // Rounded corners, the sphere clips the cube
+        let rounded = Csg::new(
+            CsgOp::Intersection,
+            cube(),
+            Primative::sphere(Point3::ZERO, 1.2, key),
+        );
+        let (t, _) = rounded.ray_hit(&ray(0.0), 0.001, 10.0).unwrap();
+        assert!((t - 4.0).abs() < 1e-4);
+        let corner = Ray3A {
+            origin: Point3::new(0.9, 0.9, 5.0),
+            direction: -Vec3A::Z,
+        };
+        assert!(rounded.ray_hit(&corner, 0.001, 10.0).is_none());
+
+        // From inside overlapping spheres the first surface is where both end
+        let merged = Csg::new(
+            CsgOp::Union,
+            Primative::sphere(Point3::ZERO, 1.0, key),
+            Primative::sphere(Point3::X, 1.0, key),
+        );
+        let outward = Ray3A {
+            origin: Point3::ZERO,
+            direction: Vec3A::X,
+        };
+        let (t, rec) = merged.ray_hit(&outward, 0.001, 10.0).unwrap();
+        assert!((t - 2.0).abs() < 1e-4);
+        assert_eq!(rec.face, Face::Back);
+    }
+}
@@ -0,0 +1,351 @@
+use super::*;
+
+// Straight pieces each cubic segment is split into for intersection
+const CURVE_PIECES: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CurveBasis {
+    // Every third point is on the curve, 3n + 1 points make n segments
+    Bezier,
+    // The curve passes through every point, with one segment between each pair
+    CatmullRom,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CurveShape {
+    // A ribbon that always turns to face the ray, cheap and fine for thin hair
+    Flat,
+    // A tube, for strands seen up close
+    Round,
+}
+
+// One hair, blade of grass or fur fiber with a width at each control point
+#[derive(Debug, Clone, PartialEq)]
+pub struct Strand {
+    pub points: Vec<Point3>,
+    pub widths: Vec<Float>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct CurveData {
+    // Cubic Bezier control points, the widths at both ends and the range of u along
+    // the whole strand
+    segments: Vec<([Point3; 4], [Float; 2], [Float; 2])>,
+    shape: CurveShape,
+    material_key: MaterialKey,
+}
+
+#[derive(Debug, Clone)]
+pub struct CurveSegment {
+    data: Arc<CurveData>,
+    index: usize,
+}
+
+impl CurveSegment {
+    fn point(&self, s: Float) -> Point3 {
+        let ([p0, p1, p2, p3], _, _) = self.data.segments[self.index];
+        let r = 1.0 - s;
+        r * r * r * p0 + 3.0 * r * r * s * p1 + 3.0 * r * s * s * p2 + s * s * s * p3
+    }
+
+    fn half_width(&self, s: Float) -> Float {
+        let (_, [w0, w1], _) = self.data.segments[self.index];
+        0.5 * (w0 + s * (w1 - w0))
+    }
+}
+
+impl Bounded<Bounds3A> for CurveSegment {
+    fn bounds(&self) -> Bounds3A {
+        // Bezier curves stay within the hull of their control points
+        let (points, [w0, w1], _) = self.data.segments[self.index];
+        let pad = Vec3A::splat(0.5 * w0.max(w1));
+        let min = points.iter().fold(points[0], |m, p| m.min(*p));
+        let max = points.iter().fold(points[0], |m, p| m.max(*p));
+        Bounds3A::new(min - pad, max + pad)
+    }
+}
+
+impl RayHittable<Bounds3A> for CurveSegment {
+    type Item = HitRecord;
+
+    fn ray_hit(&self, ray: &Ray3A, t_min: f32, t_max: f32) -> Option<(f32, Self::Item)> {
+        let ray_length = ray.direction.length();
+        let direction = ray.direction / ray_length;
+
+        let mut closest: Option<(Float, Float, Point3, Vec3A, Vec3A)> = None;
+        let mut a = self.point(0.0);
+        for piece in 0..CURVE_PIECES {
+            let (s0, s1) = (
+                piece as Float / CURVE_PIECES as Float,
+                (piece + 1) as Float / CURVE_PIECES as Float,
+            );
+            let b = self.point(s1);
+            let radius = self.half_width(0.5 * (s0 + s1));
+            let hit = match self.data.shape {
+                CurveShape::Flat => ribbon_hit(ray.origin, direction, a, b, radius),
+                CurveShape::Round => capsule_hit(ray.origin, direction, a, b, radius),
+            };
+            if let Some((distance, along, axis)) = hit {
+                let t = distance / ray_length;
+                if t_min <= t && t <= closest.map_or(t_max, |c| c.0) {
+                    closest = Some((t, s0 + along * (s1 - s0), axis, a, b));
+                }
+            }
+            a = b;
+        }
+
+        let (t, s, axis, a, b) = closest?;
+        let point = ray.at(t);
+        let tangent = (b - a).normalize_or_zero();
+        let normal = match self.data.shape {
+            // Facing the ray, across the ribbon
+            CurveShape::Flat => -(direction - direction.dot(tangent) * tangent),
+            CurveShape::Round => point - axis,
+        }
+        .try_normalize()
+        .unwrap_or(-direction);
+
+        // v runs across the strand as seen from the ray
+        let across = tangent.cross(direction).normalize_or_zero();
+        let v = 0.5 + 0.5 * across.dot(point - axis) / self.half_width(s).max(Float::EPSILON);
+        let (_, _, [u0, u1]) = self.data.segments[self.index];
+        let (face, normal) = get_face(ray, normal);
+        Some((
+            t,
+            HitRecord {
+                point,
+                normal,
+                u: u0 + s * (u1 - u0),
+                v: v.clamp(0.0, 1.0),
+                face,
+                material_key: self.data.material_key,
+            },
+        ))
+    }
+}
+
+// Distance along the unit `direction` to a capsule of `radius` around `a` to `b`,
+// the fraction along the axis and the nearest point on it
+fn capsule_hit(
+    origin: Point3,
+    direction: Vec3A,
+    a: Point3,
+    b: Point3,
+    radius: Float,
+) -> Option<(Float, Float, Point3)> {
+    let ba = b - a;
+    let oa = origin - a;
+    let baba = ba.dot(ba);
+    let bard = ba.dot(direction);
+    let baoa = ba.dot(oa);
+
+    // The cylinder between the end caps
+    let qa = baba - bard * bard;
+    let qb = baba * direction.dot(oa) - baoa * bard;
+    let qc = baba * oa.dot(oa) - baoa * baoa - radius * radius * baba;
+    let h = qb * qb - qa * qc;
+    if h < 0.0 {
+        return None;
+    }
+    let t = (-qb - h.sqrt()) / qa;
+    let y = baoa + t * bard;
+    if qa > 0.0 && 0.0 < y && y < baba {
+        let along = y / baba;
+        return Some((t, along, a + along * ba));
+    }
+
+    // Otherwise a spherical cap
+    let (center, along) = if y <= 0.0 { (a, 0.0) } else { (b, 1.0) };
+    let oc = origin - center;
+    let half_b = direction.dot(oc);
+    let h = half_b * half_b - (oc.dot(oc) - radius * radius);
+    match h > 0.0 {
+        true => Some((-half_b - h.sqrt(), along, center)),
+        false => None,
+    }
+}
+
+// Distance along the unit `direction` to where it passes within `radius` of the
+// segment `a` to `b`, the fraction along it and the nearest point on it
+fn ribbon_hit(
+    origin: Point3,
+    direction: Vec3A,
+    a: Point3,
+    b: Point3,
+    radius: Float,
+) -> Option<(Float, Float, Point3)> {
+    let ba = b - a;
+    let w = origin - a;
+    let (bd, bb) = (direction.dot(ba), ba.dot(ba));
+    let denom = bb - bd * bd;
+    if denom <= 1e-12 * bb {
+        return None;
+    }
+
+    let along = ((ba.dot(w) - bd * direction.dot(w)) / denom).clamp(0.0, 1.0);
+    let axis = a + along * ba;
+    let t = direction.dot(axis - origin);
+    match (origin + t * direction - axis).length_squared() <= radius * radius {
+        true => Some((t, along, axis)),
+        false => None,
+    }
+}
+
+// Hair, fur and grass as strands of cubic curves with a BVH over their segments,
+// rather than millions of tessellated triangles
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "CurveData", into = "CurveData")
+)]
+pub struct Curves {
+    bvh: Bvh3A<CurveSegment>,
+    data: Arc<CurveData>,
+}
+
+impl Curves {
+    pub fn material_key(&self) -> MaterialKey {
+        self.data.material_key
+    }
+
+    pub fn new(
+        strands: &[Strand],
+        basis: CurveBasis,
+        shape: CurveShape,
+        material_key: MaterialKey,
+    ) -> Self {
+        let mut segments = Vec::new();
+        for strand in strands {
+            assert_eq!(
+                strand.points.len(),
+                strand.widths.len(),
+                "one width per control point"
+            );
+            let (p, w) = (&strand.points, &strand.widths);
+            let strand_segments: Vec<_> = match basis {
+                CurveBasis::Bezier => (0..p.len().saturating_sub(1) / 3)
+                    .map(|i| {
+                        let i = 3 * i;
+                        ([p[i], p[i + 1], p[i + 2], p[i + 3]], [w[i], w[i + 3]])
+                    })
+                    .collect(),
+                // Converted to Bezier, the ends mirrored to give them neighbours
+                CurveBasis::CatmullRom => (0..p.len().saturating_sub(1))
+                    .map(|i| {
+                        let p0 = if i == 0 { 2.0 * p[0] - p[1] } else { p[i - 1] };
+                        let p3 = p.get(i + 2).copied().unwrap_or(2.0 * p[i + 1] - p[i]);
+                        let (p1, p2) = (p[i], p[i + 1]);
+                        let points = [p1, p1 + (p2 - p0) / 6.0, p2 - (p3 - p1) / 6.0, p2];
+                        (points, [w[i], w[i + 1]])
+                    })
+                    .collect(),
+            };
+
+            let count = strand_segments.len() as Float;
+            segments.extend(strand_segments.into_iter().enumerate().map(
+                |(i, (points, widths))| {
+                    (
+                        points,
+                        widths,
+                        [i as Float / count, (i + 1) as Float / count],
+                    )
+                },
+            ));
+        }
+
+        CurveData {
+            segments,
+            shape,
+            material_key,
+        }
+        .into()
+    }
+
+    pub fn segment_count(&self) -> usize {
+        self.data.segments.len()
+    }
+}
+
+impl From<CurveData> for Curves {
+    fn from(data: CurveData) -> Self {
+        let data = Arc::new(data);
+        let segments = (0..data.segments.len())
+            .map(|index| CurveSegment {
+                data: Arc::clone(&data),
+                index,
+            })
+            .collect();
+        Self {
+            bvh: Bvh3A::build(segments),
+            data,
+        }
+    }
+}
+
+impl From<Curves> for CurveData {
+    fn from(curves: Curves) -> Self {
+        (*curves.data).clone()
+    }
+}
+
+impl Bounded<Bounds3A> for Curves {
+    fn bounds(&self) -> Bounds3A {
+        self.bvh.bounds()
+    }
+}
+
+impl RayHittable<Bounds3A> for Curves {
+    type Item = HitRecord;
+
+    fn ray_hit(&self, ray: &Ray3A, t_min: f32, t_max: f32) -> Option<(f32, Self::Item)> {
+        self.bvh.ray_hit(ray, t_min, t_max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strands_are_hit_along_their_length() {
+        let key = MaterialKey::default();
+        let strand = Strand {
+            points: vec![
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(1.0, 0.0, 0.0),
+                Point3::new(2.0, 0.0, 0.0),
+                Point3::new(3.0, 1.0, 0.0),
+            ],
+            widths: vec![0.2, 0.2, 0.2, 0.0],
+        };
+        let down = |x: Float| Ray3A {
+            origin: Point3::new(x, 5.0, 0.0),
+            direction: -Vec3A::Y,
+        };
+
+        let round = Curves::new(
+            std::slice::from_ref(&strand),
+            CurveBasis::CatmullRom,
+            CurveShape::Round,
+            key,
+        );
+        assert_eq!(round.segment_count(), 3);
+        let (t, rec) = round.ray_hit(&down(0.5), 0.001, 10.0).unwrap();
+        assert!((t - 4.9).abs() < 1e-3);
+        assert!((rec.normal - Vec3A::Y).length() < 1e-3);
+        assert!((rec.u - 1.0 / 6.0).abs() < 1e-2);
+        // Catmull-Rom curves pass through their points
+        assert!(round.ray_hit(&down(1.0), 0.001, 10.0).is_some());
+
+        let flat = Curves::new(&[strand], CurveBasis::Bezier, CurveShape::Flat, key);
+        assert_eq!(flat.segment_count(), 1);
+        let (t, rec) = flat.ray_hit(&down(0.2), 0.001, 10.0).unwrap();
+        assert!(t > 4.9 && t < 5.1);
+        assert_eq!(rec.face, Face::Front);
+        assert!(flat.ray_hit(&down(-0.5), 0.001, 10.0).is_none());
+    }
+}
@@ -0,0 +1,292 @@
+use super::*;
+
+// A shared primitive placed with its own transform, so repeated geometry is only
+// stored and built into a BVH once. Each instance can swap the materials the
+// object was built with for its own, to vary copies without duplicating them, and
+// can move over time for motion blur.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Instance {
+    object: Arc<Primative>,
+    transform: Transform,
+    // Pairs of the object's material and the one this instance shows instead
+    #[cfg_attr(feature = "serde", serde(default))]
+    material_overrides: Vec<(MaterialKey, MaterialKey)>,
+    // Where the instance has moved to by time 1, from `transform` at time 0. Rays
+    // sent in between see it part way, those sent before or after at that end.
+    #[cfg_attr(feature = "serde", serde(default))]
+    motion: Option<Transform>,
+}
+
+impl Instance {
+    // The object's materials, with this instance's overrides swapped in
+    pub(crate) fn material_keys(&self) -> Vec<MaterialKey> {
+        self.object
+            .material_keys()
+            .into_iter()
+            .map(|key| {
+                self.material_overrides
+                    .iter()
+                    .find(|(slot, _)| *slot == key)
+                    .map_or(key, |(_, material)| *material)
+            })
+            .collect()
+    }
+
+    pub fn new(object: Arc<Primative>, transform: Transform) -> Self {
+        Self {
+            object,
+            transform,
+            material_overrides: Vec::new(),
+            motion: None,
+        }
+    }
+
+    // Moves from `transform` at time 0 to `to` at time 1
+    pub fn with_motion(mut self, to: Transform) -> Self {
+        self.motion = Some(to);
+        self
+    }
+
+    fn transform_at(&self, time: Float) -> Transform {
+        match &self.motion {
+            Some(to) => self.transform.lerp(to, time.clamp(0.0, 1.0)),
+            None => self.transform,
+        }
+    }
+
+    // Surfaces of the object using `slot` show `material` in this instance
+    pub fn with_material_override(mut self, slot: MaterialKey, material: MaterialKey) -> Self {
+        match self.material_overrides.iter_mut().find(|(s, _)| *s == slot) {
+            Some(entry) => entry.1 = material,
+            None => self.material_overrides.push((slot, material)),
+        }
+        self
+    }
+
+    pub fn material_overrides(&self) -> &[(MaterialKey, MaterialKey)] {
+        &self.material_overrides
+    }
+
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
+    }
+
+    pub(crate) fn surfaces(&self) -> Option<Vec<Surface>> {
+        let mut surfaces = self.object.surfaces()?;
+        for surface in surfaces.iter_mut() {
+            surface
+                .vertices
+                .iter_mut()
+                .for_each(|p| *p = self.transform.apply(*p));
+            if let Some((_, material)) = self
+                .material_overrides
+                .iter()
+                .find(|(slot, _)| *slot == surface.material_key)
+            {
+                surface.material_key = *material;
+            }
+        }
+        Some(surfaces)
+    }
+
+    pub(crate) fn occluded(
+        &self,
+        ray: &Ray3A,
+        t_min: Float,
+        t_max: Float,
+        media: &mut MediumRng,
+    ) -> bool {
+        let transform = self.transform_at(media.time());
+        self.object
+            .occluded(&to_local(&transform, ray), t_min, t_max, media)
+    }
+}
+
+// Direction is scaled along with the origin so `t` is the same in both spaces
+fn to_local(transform: &Transform, ray: &Ray3A) -> Ray3A {
+    let inverse_rotation = transform.rotation.conjugate();
+    let inverse_scale = 1.0 / transform.scale;
+    Ray3A {
+        origin: inverse_rotation * (ray.origin - transform.translation) * inverse_scale,
+        direction: inverse_rotation * ray.direction * inverse_scale,
+    }
+}
+
+impl Bounded<Bounds3A> for Instance {
+    fn bounds(&self) -> Bounds3A {
+        let Bounds3A { min, max } = self.object.bounds();
+        // Turning in between can sweep the box past where it is at either end, but
+        // never past the sphere about the object's origin that holds it. That sphere
+        // only moves and grows linearly, so its extent at the two ends bounds it.
+        if let Some(to) = &self.motion {
+            let radius = min.abs().max(max.abs()).length();
+            let (from, to) = (&self.transform, to);
+            let (from_extent, to_extent) = (from.scale.abs(), to.scale.abs());
+            return Bounds3A::new(
+                (from.translation - from_extent * radius).min(to.translation - to_extent * radius),
+                (from.translation + from_extent * radius).max(to.translation + to_extent * radius),
+            );
+        }
+
+        let mut bounds = Bounds3A::new(
+            Vec3A::splat(Float::INFINITY),
+            Vec3A::splat(Float::NEG_INFINITY),
+        );
+        for corner in 0..8 {
+            let p = Vec3A::new(
+                if corner & 1 == 0 { min.x } else { max.x },
+                if corner & 2 == 0 { min.y } else { max.y },
+                if corner & 4 == 0 { min.z } else { max.z },
+            );
+            let p = self.transform.apply(p);
+            bounds.min = bounds.min.min(p);
+            bounds.max = bounds.max.max(p);
+        }
+        bounds
+    }
+}
+
+impl RayHittable<Bounds3A> for Instance {
+    type Item = HitRecord;
+
+    fn ray_hit(&self, ray: &Ray3A, t_min: f32, t_max: f32) -> Option<(f32, Self::Item)> {
+        self.media_hit(ray, t_min, t_max, &mut MediumRng::new(ray, 0))
+    }
+}
+
+impl Instance {
+    pub(crate) fn media_hit(
+        &self,
+        ray: &Ray3A,
+        t_min: Float,
+        t_max: Float,
+        media: &mut MediumRng,
+    ) -> Option<(Float, HitRecord)> {
+        let transform = self.transform_at(media.time());
+        let local = to_local(&transform, ray);
+        let (t, mut rec) = self.object.media_hit(&local, t_min, t_max, media)?;
+        rec.point = ray.at(t);
+        rec.normal = transform.rotation * rec.normal;
+        if let Some((_, material)) = self
+            .material_overrides
+            .iter()
+            .find(|(slot, _)| *slot == rec.material_key)
+        {
+            rec.material_key = *material;
+        }
+        Some((t, rec))
+    }
+}
+
+impl Transform {
+    pub fn new(translation: Vec3A, rotation: glam::Quat, scale: Float) -> Self {
+        Self {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn apply(&self, p: Point3) -> Point3 {
+        self.translation + self.rotation * (self.scale * p)
+    }
+
+    pub fn affine(&self) -> glam::Affine3A {
+        glam::Affine3A::from_scale_rotation_translation(
+            glam::Vec3::splat(self.scale),
+            self.rotation,
+            self.translation.into(),
+        )
+    }
+
+    // Part way from `self` to `to`, turning along the shortest arc
+    pub fn lerp(&self, to: &Transform, t: Float) -> Self {
+        Self {
+            translation: self.translation.lerp(to.translation, t),
+            rotation: self.rotation.slerp(to.rotation, t),
+            scale: self.scale + (to.scale - self.scale) * t,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instanced_sphere_matches_placed_sphere() {
+        let object = Arc::new(Primative::sphere(Vec3A::ZERO, 1.0, MaterialKey::default()));
+        let transform = Transform::new(
+            Vec3A::new(3.0, 1.0, -2.0),
+            glam::Quat::from_rotation_y(0.7),
+            2.0,
+        );
+        let instance = Instance::new(object, transform);
+        let placed = Sphere::new(Vec3A::new(3.0, 1.0, -2.0), 2.0, MaterialKey::default());
+
+        let ray = Ray3A {
+            origin: Vec3A::new(0.0, 0.5, 5.0),
+            direction: Vec3A::new(0.4, 0.0, -1.0),
+        };
+        let (t_a, a) = instance.ray_hit(&ray, 0.001, Float::INFINITY).unwrap();
+        let (t_b, b) = placed.ray_hit(&ray, 0.001, Float::INFINITY).unwrap();
+        assert!((t_a - t_b).abs() < 1e-4);
+        assert!((a.normal - b.normal).length() < 1e-4);
+        assert!((a.point - b.point).length() < 1e-4);
+
+        // Rotated object bounds are conservative
+        let (bounds, tight) = (instance.bounds(), placed.bounds());
+        assert!(bounds.min.cmple(tight.min + Vec3A::splat(1e-4)).all());
+        assert!(bounds.max.cmpge(tight.max - Vec3A::splat(1e-4)).all());
+    }
+
+    #[test]
+    fn moving_instances_are_hit_where_they_are_at_the_ray_time() {
+        let object = Arc::new(Primative::sphere(Vec3A::ZERO, 1.0, MaterialKey::default()));
+        let at = |x: Float| Transform::new(Vec3A::new(x, 0.0, 0.0), glam::Quat::IDENTITY, 1.0);
+        let instance = Instance::new(object, at(0.0)).with_motion(at(10.0));
+        let hit = |x: Float, time: Float| {
+            let ray = Ray3A {
+                origin: Vec3A::new(x, 0.0, 5.0),
+                direction: -Vec3A::Z,
+            };
+            let media = &mut MediumRng::default().at_time(time);
+            instance
+                .media_hit(&ray, 0.001, Float::INFINITY, media)
+                .is_some()
+        };
+        assert!(hit(0.0, 0.0) && !hit(5.0, 0.0));
+        assert!(hit(5.0, 0.5) && !hit(0.0, 0.5));
+        assert!(hit(10.0, 2.0));
+
+        // Bounds hold it the whole way
+        let bounds = instance.bounds();
+        assert!(bounds.min.x <= -1.0 && bounds.max.x >= 11.0);
+    }
+
+    #[test]
+    fn instances_override_materials_by_slot() {
+        let mut keys = slotmap::SlotMap::<MaterialKey, ()>::with_key();
+        let (bark, leaves, autumn) = (keys.insert(()), keys.insert(()), keys.insert(()));
+        let tree = Arc::new(Primative::sphere(Vec3A::ZERO, 1.0, leaves));
+        let transform = Transform::new(Vec3A::ZERO, glam::Quat::IDENTITY, 1.0);
+        let ray = Ray3A {
+            origin: Vec3A::new(0.0, 0.0, 5.0),
+            direction: -Vec3A::Z,
+        };
+
+        let plain = Instance::new(Arc::clone(&tree), transform);
+        let recolored = Instance::new(tree, transform)
+            .with_material_override(bark, leaves)
+            .with_material_override(leaves, autumn);
+        let (_, a) = plain.ray_hit(&ray, 0.001, Float::INFINITY).unwrap();
+        let (_, b) = recolored.ray_hit(&ray, 0.001, Float::INFINITY).unwrap();
+        assert_eq!(a.material_key, leaves);
+        assert_eq!(b.material_key, autumn);
+    }
+}
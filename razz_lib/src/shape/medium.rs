@@ -0,0 +1,341 @@
+use super::*;
+use crate::noise::Noise;
+use crate::sampler::MediumRng;
+
+use rand::Rng;
+
+// A convex volume of uniform density. Rays that enter the boundary scatter
+// somewhere inside it with a probability that grows with distance travelled.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConstantMedium {
+    boundary: Box<Primative>,
+    neg_inv_density: Float,
+    phase_material: MaterialKey,
+}
+
+impl ConstantMedium {
+    // What scattering inside the medium is shaded with
+    pub fn phase_material(&self) -> MaterialKey {
+        self.phase_material
+    }
+
+    // `phase_material` is usually `Material::Isotropic`
+    pub fn new(boundary: Primative, density: Float, phase_material: MaterialKey) -> Self {
+        Self {
+            boundary: Box::new(boundary),
+            neg_inv_density: -1.0 / density.max(Float::MIN_POSITIVE),
+            phase_material,
+        }
+    }
+}
+
+impl Bounded<Bounds3A> for ConstantMedium {
+    fn bounds(&self) -> Bounds3A {
+        self.boundary.bounds()
+    }
+}
+
+impl ConstantMedium {
+    // Where along `ray` it scatters, if anywhere, drawing from `rng`
+    pub(crate) fn scatter(
+        &self,
+        ray: &Ray3A,
+        t_min: Float,
+        t_max: Float,
+        rng: &mut MediumRng,
+    ) -> Option<(Float, HitRecord)> {
+        let (t_enter, t_exit) = inside_span(&self.boundary, ray, t_min, t_max)?;
+
+        let ray_length = ray.direction.length();
+        let distance_inside = (t_exit - t_enter) * ray_length;
+        let hit_distance = self.neg_inv_density * rng.gen::<Float>().ln();
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let time = t_enter + hit_distance / ray_length;
+        Some((time, phase_hit(ray, time, self.phase_material)))
+    }
+}
+
+// Outside the world's traversal there's no path to draw from, so scattering follows
+// from the ray alone
+impl RayHittable<Bounds3A> for ConstantMedium {
+    type Item = HitRecord;
+
+    fn ray_hit(&self, ray: &Ray3A, t_min: f32, t_max: f32) -> Option<(f32, Self::Item)> {
+        self.scatter(ray, t_min, t_max, &mut MediumRng::new(ray, 0))
+    }
+}
+
+// Spatially varying density, in the same units as `ConstantMedium`
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DensityField {
+    // Noise remapped to [0, 1] and scaled by `density`. `scale` is the
+    // spatial frequency of the noise.
+    Noise {
+        noise: Box<Noise>,
+        scale: Float,
+        density: Float,
+    },
+    // Trilinearly interpolated voxels spanning `min` to `max`, x fastest.
+    // Density is zero outside the grid.
+    Grid {
+        resolution: [usize; 3],
+        values: Vec<Float>,
+        min: Point3,
+        max: Point3,
+    },
+}
+
+impl DensityField {
+    // `None` if an axis has no voxels, or `values` doesn't fill the grid exactly
+    pub fn grid(
+        resolution: [usize; 3],
+        values: Vec<Float>,
+        min: Point3,
+        max: Point3,
+    ) -> Option<Self> {
+        let count = resolution.iter().product::<usize>();
+        if count == 0 || values.len() != count {
+            return None;
+        }
+        Some(Self::Grid {
+            resolution,
+            values,
+            min,
+            max,
+        })
+    }
+
+    pub fn density(&self, p: Point3) -> Float {
+        match self {
+            Self::Noise {
+                noise,
+                scale,
+                density,
+            } => {
+                let n = match noise.as_ref() {
+                    Noise::Perlin(_) => 0.5 * (1.0 + noise.sample(*scale * p)),
+                    Noise::Turbulent(_, _) => noise.sample(*scale * p),
+                };
+                density * n.clamp(0.0, 1.0)
+            }
+            Self::Grid {
+                resolution,
+                values,
+                min,
+                max,
+            } => {
+                let local = (p - *min) / (*max - *min);
+                if local.min_element() < 0.0 || local.max_element() > 1.0 {
+                    return 0.0;
+                }
+                // Grids loaded from a file haven't been through `grid`
+                let count = resolution.iter().product::<usize>();
+                if count == 0 || values.len() != count {
+                    return 0.0;
+                }
+
+                // Voxel centers sit at (i + 0.5) / resolution
+                let mut index = [0; 3];
+                let mut next = [0; 3];
+                let mut frac = [0.0; 3];
+                for axis in 0..3 {
+                    let n = resolution[axis];
+                    let x = (local[axis] * n as Float - 0.5).clamp(0.0, (n - 1) as Float);
+                    index[axis] = x.floor() as usize;
+                    next[axis] = (index[axis] + 1).min(n - 1);
+                    frac[axis] = x - x.floor();
+                }
+
+                let at = |x: usize, y: usize, z: usize| {
+                    values[x + resolution[0] * (y + resolution[1] * z)]
+                };
+                let lerp = |a: Float, b: Float, t: Float| a + t * (b - a);
+                let [x0, y0, z0] = index;
+                let [x1, y1, z1] = next;
+                let [fx, fy, fz] = frac;
+
+                let c00 = lerp(at(x0, y0, z0), at(x1, y0, z0), fx);
+                let c10 = lerp(at(x0, y1, z0), at(x1, y1, z0), fx);
+                let c01 = lerp(at(x0, y0, z1), at(x1, y0, z1), fx);
+                let c11 = lerp(at(x0, y1, z1), at(x1, y1, z1), fx);
+                lerp(lerp(c00, c10, fy), lerp(c01, c11, fy), fz)
+            }
+        }
+    }
+
+    // Upper bound on `density` anywhere in the field
+    pub fn majorant(&self) -> Float {
+        match self {
+            Self::Noise { density, .. } => *density,
+            Self::Grid { values, .. } => values.iter().cloned().fold(0.0, Float::max),
+        }
+    }
+}
+
+// Tentative collisions tested along a ray before letting it through. Only a majorant far
+// above the density almost everywhere, or steps too small to move `time`, get this far.
+const MAX_STEPS: usize = 4096;
+
+// A volume whose density varies through a `DensityField`. Free flight is
+// sampled with delta tracking against the field's majorant: tentative
+// collisions are accepted in proportion to the local density, which keeps
+// both scattering and shadow ray occlusion unbiased.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HeterogeneousMedium {
+    boundary: Box<Primative>,
+    density: DensityField,
+    majorant: Float,
+    phase_material: MaterialKey,
+}
+
+impl HeterogeneousMedium {
+    // What scattering inside the medium is shaded with
+    pub fn phase_material(&self) -> MaterialKey {
+        self.phase_material
+    }
+
+    pub fn new(boundary: Primative, density: DensityField, phase_material: MaterialKey) -> Self {
+        Self {
+            boundary: Box::new(boundary),
+            majorant: density.majorant(),
+            density,
+            phase_material,
+        }
+    }
+
+    pub fn density(&self) -> &DensityField {
+        &self.density
+    }
+}
+
+impl Bounded<Bounds3A> for HeterogeneousMedium {
+    fn bounds(&self) -> Bounds3A {
+        self.boundary.bounds()
+    }
+}
+
+impl HeterogeneousMedium {
+    // Where along `ray` it scatters, if anywhere, drawing from `rng`
+    pub(crate) fn scatter(
+        &self,
+        ray: &Ray3A,
+        t_min: Float,
+        t_max: Float,
+        rng: &mut MediumRng,
+    ) -> Option<(Float, HitRecord)> {
+        if self.majorant <= 0.0 {
+            return None;
+        }
+        let (t_enter, t_exit) = inside_span(&self.boundary, ray, t_min, t_max)?;
+
+        let inv_step = 1.0 / (self.majorant * ray.direction.length());
+        // An infinite majorant or direction would never step forward
+        if inv_step.is_nan() || inv_step <= 0.0 {
+            return None;
+        }
+        let mut time = t_enter;
+        for _ in 0..MAX_STEPS {
+            time -= (1.0 - rng.gen::<Float>()).ln() * inv_step;
+            if time.is_nan() || time >= t_exit {
+                return None;
+            }
+
+            let point = ray.at(time);
+            if rng.gen::<Float>() * self.majorant < self.density.density(point) {
+                return Some((time, phase_hit(ray, time, self.phase_material)));
+            }
+        }
+        None
+    }
+}
+
+impl RayHittable<Bounds3A> for HeterogeneousMedium {
+    type Item = HitRecord;
+
+    fn ray_hit(&self, ray: &Ray3A, t_min: f32, t_max: f32) -> Option<(f32, Self::Item)> {
+        self.scatter(ray, t_min, t_max, &mut MediumRng::new(ray, 0))
+    }
+}
+
+// Parametric span of `ray` inside a convex `boundary`, clipped to [t_min, t_max]
+pub(super) fn inside_span(
+    boundary: &Primative,
+    ray: &Ray3A,
+    t_min: Float,
+    t_max: Float,
+) -> Option<(Float, Float)> {
+    let (t_enter, _) = boundary.ray_hit(ray, Float::NEG_INFINITY, Float::INFINITY)?;
+    // Step just past the entry point, relative to its distance to stay scale-free
+    let t_skip = t_enter + 1e-5 * t_enter.abs().max(1.0);
+    let (t_exit, _) = boundary.ray_hit(ray, t_skip, Float::INFINITY)?;
+
+    let t_enter = t_enter.max(t_min).max(0.0);
+    let t_exit = t_exit.min(t_max);
+    match t_enter < t_exit {
+        true => Some((t_enter, t_exit)),
+        false => None,
+    }
+}
+
+pub(super) fn phase_hit(ray: &Ray3A, time: Float, phase_material: MaterialKey) -> HitRecord {
+    HitRecord {
+        point: ray.at(time),
+        // Arbitrary, the phase function ignores it
+        normal: Vec3A::X,
+        u: 0.0,
+        v: 0.0,
+        face: Face::Front,
+        material_key: phase_material,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_interpolates_between_voxel_centers() {
+        let field = DensityField::grid(
+            [2, 1, 1],
+            vec![1.0, 3.0],
+            Point3::ZERO,
+            Point3::new(2.0, 1.0, 1.0),
+        )
+        .unwrap();
+        assert!((field.density(Point3::new(0.5, 0.5, 0.5)) - 1.0).abs() < 1e-5);
+        assert!((field.density(Point3::new(1.0, 0.5, 0.5)) - 2.0).abs() < 1e-5);
+        assert!((field.density(Point3::new(1.9, 0.2, 0.8)) - 3.0).abs() < 1e-5);
+        assert_eq!(field.density(Point3::new(-0.1, 0.5, 0.5)), 0.0);
+        assert_eq!(field.majorant(), 3.0);
+
+        let (min, max) = (Point3::ZERO, Point3::ONE);
+        assert!(DensityField::grid([0, 0, 0], vec![], min, max).is_none());
+        assert!(DensityField::grid([2, 0, 1], vec![], min, max).is_none());
+        assert!(DensityField::grid([2, 1, 1], vec![1.0], min, max).is_none());
+    }
+
+    #[test]
+    fn media_scatter_where_their_seed_says() {
+        let boundary = Primative::sphere(Point3::ZERO, 1.0, MaterialKey::default());
+        let medium = ConstantMedium::new(boundary, 1.0, MaterialKey::default());
+        let ray = Ray3A {
+            origin: Point3::new(0.0, 0.0, -2.0),
+            direction: Vec3A::Z,
+        };
+        let scatter = |seed| {
+            let mut rng = MediumRng::new(&ray, seed);
+            medium
+                .scatter(&ray, 0.0, Float::INFINITY, &mut rng)
+                .map(|(t, _)| t)
+        };
+        assert_eq!(scatter(1), scatter(1));
+        let times: Vec<_> = (0..16).filter_map(scatter).collect();
+        assert!(times.len() > 4 && times.iter().any(|t| *t != times[0]));
+    }
+}
@@ -1,28 +1,29 @@
 use super::*;
+use crate::packet::{PacketBvh, RayPacket};
 use std::sync::Arc;
 
 use glam::Affine3A;
+use rayon::prelude::*;
+
+// Meshes larger than this are cut into clusters whose hierarchies are built in parallel
+const CLUSTER_TRIANGLES: usize = 1 << 15;
 
 #[derive(Debug, Clone)]
 pub struct Triangle {
-    mesh: Arc<Mesh>,
+    mesh: Arc<MeshData>,
     index: usize,
 }
 
 impl Triangle {
-    fn vertices(&self) -> (Point3, Point3, Point3) {
-        let (i0, i1, i2) = self.mesh.indices[self.index];
-        let v0 = self.mesh.vertices[i0];
-        let v1 = self.mesh.vertices[i1];
-        let v2 = self.mesh.vertices[i2];
-
-        (v0, v1, v2)
+    // The authored face this triangle was cut from
+    pub fn source_face(&self) -> usize {
+        self.mesh.faces[self.index]
     }
 }
 
 impl Bounded<Bounds3A> for Triangle {
     fn bounds(&self) -> Bounds3A {
-        let (v0, v1, v2) = self.vertices();
+        let (v0, v1, v2) = self.mesh.triangle_vertices(self.index);
 
         Bounds3A {
             min: v0.min(v1).min(v2),
@@ -35,14 +36,519 @@ impl RayHittable<Bounds3A> for Triangle {
     type Item = HitRecord;
 
     fn ray_hit(&self, ray: &Ray3A, t_min: f32, t_max: f32) -> Option<(f32, Self::Item)> {
-        let (v0, v1, v2) = self.vertices();
+        self.mesh.triangle_hit(self.index, ray, t_min, t_max)
+    }
+}
+
+// One part of a mesh, with a hierarchy of its own over its triangles
+#[derive(Debug, Clone)]
+struct Cluster {
+    // Mesh triangle of each item in `bvh`
+    triangles: Vec<usize>,
+    bvh: PacketBvh,
+}
+
+// Triangles in clusters, under a hierarchy over the clusters' bounds. Single rays go
+// down it as packets of one, so camera packets, closest hits and shadow rays all
+// share one tree.
+#[derive(Debug, Clone)]
+pub struct Mesh {
+    bvh: PacketBvh,
+    clusters: Vec<Cluster>,
+    data: Arc<MeshData>,
+}
+
+// The vertex data a mesh's triangles share. It's kept apart from the hierarchies so
+// triangles can point at it while they're being built.
+#[derive(Debug)]
+struct MeshData {
+    vertices: Vec<Point3>,
+    normals: Option<Vec<Vec3A>>,
+    indices: Vec<(usize, usize, usize)>,
+    // The source face of each triangle, so quads and n-gons split into several
+    // triangles can still be told apart from their neighbours
+    faces: Vec<usize>,
+
+    material_key: MaterialKey,
+}
+
+// Why the pieces of a mesh don't fit together, if they don't
+fn mismatch(
+    vertices: &[Point3],
+    normals: Option<&[Vec3A]>,
+    indices: &[(usize, usize, usize)],
+    faces: Option<&[usize]>,
+) -> Option<String> {
+    let vertex_count = vertices.len();
+    if let Some((i0, i1, i2)) = indices
+        .iter()
+        .find(|(i0, i1, i2)| *i0.max(i1).max(i2) >= vertex_count)
+    {
+        return Some(format!(
+            "mesh triangle ({}, {}, {}) indexes past {} vertices",
+            i0, i1, i2, vertex_count
+        ));
+    }
+    if let Some(faces) = faces.filter(|f| f.len() != indices.len()) {
+        return Some(format!(
+            "mesh has {} source faces for {} triangles",
+            faces.len(),
+            indices.len()
+        ));
+    }
+    normals.filter(|n| n.len() != vertex_count).map(|normals| {
+        format!(
+            "mesh has {} normals for {} vertices",
+            normals.len(),
+            vertex_count
+        )
+    })
+}
+
+impl Mesh {
+    pub fn new(
+        vertices: Vec<Point3>,
+        indices: Vec<(usize, usize, usize)>,
+        material_key: MaterialKey,
+    ) -> std::io::Result<Arc<Self>> {
+        Self::checked(vertices, None, indices, None, material_key)
+    }
+
+    // Smooth shaded, with one normal per vertex
+    pub fn with_normals(
+        vertices: Vec<Point3>,
+        normals: Vec<Vec3A>,
+        indices: Vec<(usize, usize, usize)>,
+        material_key: MaterialKey,
+    ) -> std::io::Result<Arc<Self>> {
+        Self::checked(vertices, Some(normals), indices, None, material_key)
+    }
+
+    // Triangulated polygons, `faces` giving the source face of each triangle
+    pub fn with_source_faces(
+        vertices: Vec<Point3>,
+        normals: Option<Vec<Vec3A>>,
+        indices: Vec<(usize, usize, usize)>,
+        faces: Vec<usize>,
+        material_key: MaterialKey,
+    ) -> std::io::Result<Arc<Self>> {
+        Self::checked(vertices, normals, indices, Some(faces), material_key)
+    }
+
+    // `build`, once the data is known to fit together
+    fn checked(
+        vertices: Vec<Point3>,
+        normals: Option<Vec<Vec3A>>,
+        indices: Vec<(usize, usize, usize)>,
+        faces: Option<Vec<usize>>,
+        material_key: MaterialKey,
+    ) -> std::io::Result<Arc<Self>> {
+        match mismatch(&vertices, normals.as_deref(), &indices, faces.as_deref()) {
+            Some(mismatch) => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                mismatch,
+            )),
+            None => Ok(Self::build(vertices, normals, indices, faces, material_key)),
+        }
+    }
+
+    // Without `faces` every triangle is its own face. Indices must be in range and
+    // normals and faces, if any, one per vertex and triangle, which `mismatch` checks.
+    // Geometry generated in the crate is built straight from here.
+    pub(crate) fn build(
+        vertices: Vec<Point3>,
+        normals: Option<Vec<Vec3A>>,
+        indices: Vec<(usize, usize, usize)>,
+        faces: Option<Vec<usize>>,
+        material_key: MaterialKey,
+    ) -> Arc<Self> {
+        let faces = faces.unwrap_or_else(|| (0..indices.len()).collect());
+        let bounds: Vec<_> = indices
+            .par_iter()
+            .map(|(i0, i1, i2)| {
+                let (v0, v1, v2) = (vertices[*i0], vertices[*i1], vertices[*i2]);
+                (v0.min(v1).min(v2), v0.max(v1).max(v2))
+            })
+            .collect();
+        let mut clusters = Vec::new();
+        split_clusters((0..bounds.len()).collect(), &bounds, &mut clusters);
+        let clusters: Vec<_> = clusters
+            .into_par_iter()
+            .map(|triangles| Cluster {
+                bvh: PacketBvh::build(
+                    triangles
+                        .iter()
+                        .map(|i| Bounds3A::new(bounds[*i].0, bounds[*i].1)),
+                ),
+                triangles,
+            })
+            .collect();
+
+        Arc::new(Self {
+            bvh: PacketBvh::build(clusters.iter().map(|cluster| cluster.bvh.bounds())),
+            clusters,
+            data: Arc::new(MeshData {
+                vertices,
+                normals,
+                indices,
+                faces,
+                material_key,
+            }),
+        })
+    }
+
+    // `path` and its material library are read through `assets`, then placed and
+    // fixed up as `options` asks. Polygons are split into triangle fans that remember
+    // the face they came from, numbered in file order across all objects. Files that
+    // can't be read or parsed are an error.
+    pub fn from_obj(
+        path: impl AsRef<Path> + Debug,
+        assets: &AssetResolver,
+        material_key: MaterialKey,
+        options: ObjOptions,
+    ) -> std::io::Result<Arc<Self>> {
+        let affine = options.transform.map_or(Affine3A::IDENTITY, |t| t.affine());
+        let source = assets.read(&path)?;
+        let directory = path.as_ref().parent().unwrap_or_else(|| Path::new(""));
+        let obj = tobj::load_obj_buf(
+            &mut source.as_slice(),
+            &tobj::LoadOptions {
+                single_index: true,
+                ignore_points: true,
+                ignore_lines: true,
+                ..Default::default()
+            },
+            |mtl| match assets.read(directory.join(mtl)) {
+                Ok(mtl) => tobj::load_mtl_buf(&mut mtl.as_slice()),
+                Err(_) => Err(tobj::LoadError::OpenFileFailed),
+            },
+        );
+
+        let (models, _) = obj.map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("invalid OBJ file {:?}: {}", path, e),
+            )
+        })?;
+
+        let mut vertices = Vec::new();
+        let mut normals = Vec::new();
+        let mut indices = Vec::new();
+        let mut faces = Vec::new();
+        let mut face_count = 0;
+        for model in models {
+            let mesh = &model.mesh;
+
+            // Indices are relative to each model's own vertices
+            let offset = vertices.len();
+            let mut mesh_indices = Vec::new();
+            // Without arities every face is already a triangle
+            let arities: Vec<usize> = match mesh.face_arities.is_empty() {
+                true => vec![3; mesh.indices.len() / 3],
+                false => mesh.face_arities.iter().map(|a| *a as usize).collect(),
+            };
+            let mut start = 0;
+            for arity in arities {
+                let corner = |i: usize| offset + mesh.indices[start + i] as usize;
+                for i in 1..arity.saturating_sub(1) {
+                    mesh_indices.push((corner(0), corner(i), corner(i + 1)));
+                    faces.push(face_count);
+                }
+                face_count += 1;
+                start += arity;
+            }
+            let mesh_vertices: Vec<_> = mesh
+                .positions
+                .chunks(3)
+                .map(|c| affine.transform_point3a(Point3::new(c[0], c[1], c[2])))
+                .collect();
+            let mesh_normals: Vec<_> = match mesh.normals.len() == mesh.positions.len() {
+                true => mesh
+                    .normals
+                    .chunks(3)
+                    .map(|c| {
+                        affine
+                            .transform_vector3a(Vec3A::new(c[0], c[1], c[2]))
+                            .normalize_or_zero()
+                    })
+                    .collect(),
+                false => vertex_normals(&mesh_vertices, &mesh_indices, offset),
+            };
+
+            indices.extend(mesh_indices);
+            vertices.extend(mesh_vertices);
+            normals.extend(mesh_normals);
+        }
+
+        let mesh = Self::with_source_faces(vertices, Some(normals), indices, faces, material_key)?;
+        Ok(match options.orient_outward {
+            true => mesh.oriented_outward(),
+            false => mesh,
+        })
+    }
+}
+
+// How `Mesh::from_obj` places and fixes up what it reads
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ObjOptions {
+    // Places vertices and normals, which are left as authored without one
+    pub transform: Option<Transform>,
+    // Rewinds every triangle to face out of the solid, for downloaded models with
+    // mixed winding. Only closed meshes have an inside, see `Mesh::oriented_outward`.
+    pub orient_outward: bool,
+}
+
+// Cuts `triangles` in two at the median centroid along the widest axis until each
+// part fits in a cluster. Meshes that already fit stay whole and in order.
+fn split_clusters(
+    mut triangles: Vec<usize>,
+    bounds: &[(Point3, Point3)],
+    clusters: &mut Vec<Vec<usize>>,
+) {
+    if triangles.len() <= CLUSTER_TRIANGLES {
+        clusters.push(triangles);
+        return;
+    }
+
+    // Twice the centroid, which splits the same
+    let centroid = |i: usize| bounds[i].0 + bounds[i].1;
+    let (low, high) = triangles.iter().fold(
+        (
+            Vec3A::splat(Float::INFINITY),
+            Vec3A::splat(Float::NEG_INFINITY),
+        ),
+        |(low, high), i| (low.min(centroid(*i)), high.max(centroid(*i))),
+    );
+    let extent = (high - low).to_array();
+    let axis = (0..3)
+        .max_by(|a, b| extent[*a].total_cmp(&extent[*b]))
+        .unwrap_or(0);
+    let middle = triangles.len() / 2;
+    triangles.select_nth_unstable_by(middle, |a, b| {
+        centroid(*a).to_array()[axis]
+            .partial_cmp(&centroid(*b).to_array()[axis])
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let second = triangles.split_off(middle);
+    split_clusters(triangles, bounds, clusters);
+    split_clusters(second, bounds, clusters);
+}
+
+// Area weighted average of the faces around each vertex. `offset` is subtracted from
+// the indices to address `vertices`.
+fn vertex_normals(
+    vertices: &[Point3],
+    indices: &[(usize, usize, usize)],
+    offset: usize,
+) -> Vec<Vec3A> {
+    let mut normals = vec![Vec3A::ZERO; vertices.len()];
+    for (i0, i1, i2) in indices
+        .iter()
+        .map(|(a, b, c)| (a - offset, b - offset, c - offset))
+    {
+        // Unnormalized, the cross product is already scaled by twice the area
+        let face = (vertices[i1] - vertices[i0]).cross(vertices[i2] - vertices[i0]);
+        normals[i0] += face;
+        normals[i1] += face;
+        normals[i2] += face;
+    }
+    normals.iter().map(|n| n.normalize_or_zero()).collect()
+}
+
+impl Mesh {
+    // A copy with every triangle wound so its normal points out of the solid, for
+    // imported models with mixed winding. Rays are shot off each triangle along its
+    // normal and an odd number of crossings means it faces inward. Vertex normals
+    // are flipped to agree with the corrected faces. Only closed meshes have an
+    // inside, open ones may come out with arbitrary winding.
+    fn oriented_outward(&self) -> Arc<Self> {
+        let bounds = self.bounds();
+        let eps = 1e-4 * (bounds.max - bounds.min).length().max(Float::EPSILON);
+
+        let indices: Vec<_> = (0..self.data.indices.len())
+            .map(|i| {
+                let (i0, i1, i2) = self.data.indices[i];
+                match self.faces_inward(i, eps) {
+                    true => (i0, i2, i1),
+                    false => (i0, i1, i2),
+                }
+            })
+            .collect();
+
+        let normals = self.data.normals.as_ref().map(|normals| {
+            let faces = vertex_normals(&self.data.vertices, &indices, 0);
+            normals
+                .iter()
+                .zip(faces)
+                .map(|(n, face)| match n.dot(face) {
+                    d if d < 0.0 => -*n,
+                    _ if *n == Vec3A::ZERO => face,
+                    _ => *n,
+                })
+                .collect()
+        });
+
+        Self::build(
+            self.data.vertices.clone(),
+            normals,
+            indices,
+            Some(self.data.faces.clone()),
+            self.data.material_key,
+        )
+    }
+
+    // Majority vote over a few rays fanned around the normal, so rays grazing an
+    // edge or slipping through a crack don't decide alone
+    fn faces_inward(&self, index: usize, eps: Float) -> bool {
+        let (v0, v1, v2) = self.data.triangle_vertices(index);
+        let normal = match (v1 - v0).cross(v2 - v0).try_normalize() {
+            Some(normal) => normal,
+            None => return false,
+        };
+        let tangent = normal.any_orthonormal_vector();
+        let bitangent = normal.cross(tangent);
+        let origin = (v0 + v1 + v2) / 3.0;
+
+        let inward = [
+            normal,
+            normal + 0.5 * tangent,
+            normal - 0.5 * tangent,
+            normal + 0.5 * bitangent,
+            normal - 0.5 * bitangent,
+        ]
+        .iter()
+        .filter(|direction| {
+            let ray = Ray3A {
+                origin,
+                direction: direction.normalize(),
+            };
+            let mut crossings = 0;
+            let mut t = eps;
+            while let Some((hit, _)) = self.ray_hit(&ray, t, Float::INFINITY) {
+                crossings += 1;
+                t = hit + eps;
+            }
+            crossings % 2 == 1
+        })
+        .count();
+        inward >= 3
+    }
+
+    pub fn material_key(&self) -> MaterialKey {
+        self.data.material_key
+    }
+
+    // `PacketBvh::traverse` over the triangles, through the clusters
+    fn traverse(&self, packet: &mut RayPacket, mut leaf: impl FnMut(usize, u32, &mut RayPacket)) {
+        self.bvh.traverse(packet, |cluster, _, packet| {
+            let cluster = &self.clusters[cluster];
+            cluster.bvh.traverse(packet, |index, lanes, packet| {
+                leaf(cluster.triangles[index], lanes, packet)
+            });
+        });
+    }
+
+    // Closest hits of each ray in the packet, kept in `hits` only where they're
+    // closer than what the rays have already found
+    pub(crate) fn packet_hit(&self, packet: &mut RayPacket, hits: &mut [Option<HitRecord>]) {
+        self.traverse(packet, |index, lanes, packet| {
+            let (v0, v1, v2) = self.data.triangle_vertices(index);
+            let mut lanes = lanes & packet.may_hit_triangle(v0, v1, v2);
+            while lanes != 0 {
+                let lane = lanes.trailing_zeros() as usize;
+                lanes &= lanes - 1;
+                let ray = packet.ray(lane);
+                if let Some((t, rec)) =
+                    self.data
+                        .triangle_hit(index, &ray, packet.t_min(), packet.t_max(lane))
+                {
+                    packet.set_t_max(lane, t);
+                    hits[lane] = Some(rec);
+                }
+            }
+        });
+    }
+
+    // Whether any triangle crosses the ray between `t_min` and `t_max`. The search
+    // stops at the first one found and never builds a hit record.
+    pub(crate) fn occluded(&self, ray: &Ray3A, t_min: Float, t_max: Float) -> bool {
+        let mut packet = RayPacket::new(std::slice::from_ref(ray), t_min, t_max);
+        let mut occluded = false;
+        self.traverse(&mut packet, |index, _, packet| {
+            if self
+                .data
+                .triangle_intersect(index, ray, t_min, t_max)
+                .is_some()
+            {
+                occluded = true;
+                packet.end(0);
+            }
+        });
+        occluded
+    }
+
+    pub(crate) fn surface(&self) -> Surface {
+        Surface {
+            vertices: self.data.vertices.clone(),
+            indices: self.data.indices.clone(),
+            material_key: self.data.material_key,
+        }
+    }
+
+    pub fn source_face(&self, triangle: usize) -> usize {
+        self.data.faces[triangle]
+    }
+
+    // Triangles cut from `face`, usually adjacent as faces are split in order
+    pub fn face_triangles(&self, face: usize) -> impl Iterator<Item = usize> + '_ {
+        self.data
+            .faces
+            .iter()
+            .enumerate()
+            .filter(move |(_, f)| **f == face)
+            .map(|(triangle, _)| triangle)
+    }
+
+    pub fn triangles(&self) -> impl Iterator<Item = [Point3; 3]> + '_ {
+        let data = &self.data;
+        data.indices
+            .iter()
+            .map(move |(i0, i1, i2)| [data.vertices[*i0], data.vertices[*i1], data.vertices[*i2]])
+    }
+}
+
+impl MeshData {
+    fn triangle_vertices(&self, index: usize) -> (Point3, Point3, Point3) {
+        let (i0, i1, i2) = self.indices[index];
+        (self.vertices[i0], self.vertices[i1], self.vertices[i2])
+    }
+
+    // Vertex normals interpolated across the face, if the mesh has them
+    fn shading_normal(&self, index: usize, u: Float, v: Float) -> Option<Vec3A> {
+        let normals = self.normals.as_ref()?;
+        let (i0, i1, i2) = self.indices[index];
+        ((1.0 - u - v) * normals[i0] + u * normals[i1] + v * normals[i2]).try_normalize()
+    }
+
+    // Where the ray crosses the triangle, as its `t` and the barycentric `u` and `v`
+    fn triangle_intersect(
+        &self,
+        index: usize,
+        ray: &Ray3A,
+        t_min: Float,
+        t_max: Float,
+    ) -> Option<(Float, Float, Float)> {
+        let (v0, v1, v2) = self.triangle_vertices(index);
 
         let v0v1 = v1 - v0;
         let v0v2 = v2 - v0;
         let pvec = ray.direction.cross(v0v2);
         let det = v0v1.dot(pvec);
 
-        if det.abs() < 0.0001 {
+        // Relative to the edge and ray lengths so small triangles aren't rejected
+        let scale = v0v1.cross(v0v2).length() * ray.direction.length();
+        if det.abs() <= 1e-6 * scale {
             return None;
         };
 
@@ -64,10 +570,27 @@ impl RayHittable<Bounds3A> for Triangle {
         if time < t_min || t_max < time {
             return None;
         };
+        Some((time, u, v))
+    }
 
+    fn triangle_hit(
+        &self,
+        index: usize,
+        ray: &Ray3A,
+        t_min: Float,
+        t_max: Float,
+    ) -> Option<(Float, HitRecord)> {
+        let (time, u, v) = self.triangle_intersect(index, ray, t_min, t_max)?;
+        let (v0, v1, v2) = self.triangle_vertices(index);
         let point = ray.at(time);
-        let normal = v0v1.cross(v0v2).normalize();
-        let (face, normal) = get_face(ray, normal);
+        let geometric = (v1 - v0).cross(v2 - v0).normalize();
+        let (face, normal) = get_face(ray, geometric);
+        // Front and back stay decided by the true surface, only shading is smoothed
+        let normal = match self.shading_normal(index, u, v) {
+            Some(shading) if shading.dot(normal) < 0.0 => -shading,
+            Some(shading) => shading,
+            None => normal,
+        };
 
         Some((
             time,
@@ -77,105 +600,236 @@ impl RayHittable<Bounds3A> for Triangle {
                 u,
                 v,
                 face,
-                material_key: self.mesh.material_key,
+                material_key: self.material_key,
             },
         ))
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct Mesh {
-    bvh: Bvh3A<Triangle>,
+impl Bounded<Bounds3A> for Mesh {
+    fn bounds(&self) -> Bounds3A {
+        self.bvh.bounds()
+    }
+}
 
-    vertices: Vec<Point3>,
-    indices: Vec<(usize, usize, usize)>,
+impl RayHittable<Bounds3A> for Mesh {
+    type Item = HitRecord;
 
-    material_key: MaterialKey,
+    fn ray_hit(&self, ray: &Ray3A, t_min: f32, t_max: f32) -> Option<(f32, Self::Item)> {
+        let mut packet = RayPacket::new(std::slice::from_ref(ray), t_min, t_max);
+        let mut hits = [None];
+        self.packet_hit(&mut packet, &mut hits);
+        hits[0].map(|rec| (packet.t_max(0), rec))
+    }
 }
 
-impl Mesh {
-    pub fn new(
+// Meshes are stored as their vertex data and rebuilt on load. Instances that
+// shared an `Arc` are written out once per reference.
+#[cfg(feature = "serde")]
+pub(crate) mod arc_mesh {
+    use super::*;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize)]
+    struct MeshRef<'a> {
+        vertices: &'a [Point3],
+        normals: Option<&'a [Vec3A]>,
+        indices: &'a [(usize, usize, usize)],
+        faces: &'a [usize],
+        material_key: MaterialKey,
+    }
+
+    #[derive(Deserialize)]
+    struct MeshData {
         vertices: Vec<Point3>,
+        #[serde(default)]
+        normals: Option<Vec<Vec3A>>,
         indices: Vec<(usize, usize, usize)>,
+        #[serde(default)]
+        faces: Option<Vec<usize>>,
         material_key: MaterialKey,
-    ) -> Arc<Self> {
-        let mesh = Self {
-            bvh: Bvh3A::build(vec![]),
-            vertices,
-            indices,
-            material_key,
-        };
+    }
+
+    pub fn serialize<S: Serializer>(mesh: &Arc<Mesh>, serializer: S) -> Result<S::Ok, S::Error> {
+        MeshRef {
+            vertices: &mesh.data.vertices,
+            normals: mesh.data.normals.as_deref(),
+            indices: &mesh.data.indices,
+            faces: &mesh.data.faces,
+            material_key: mesh.data.material_key,
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Arc<Mesh>, D::Error> {
+        let data = MeshData::deserialize(deserializer)?;
+        if let Some(mismatch) = mismatch(
+            &data.vertices,
+            data.normals.as_deref(),
+            &data.indices,
+            data.faces.as_deref(),
+        ) {
+            return Err(serde::de::Error::custom(mismatch));
+        }
+        Ok(Mesh::build(
+            data.vertices,
+            data.normals,
+            data.indices,
+            data.faces,
+            data.material_key,
+        ))
+    }
+}
 
-        let mesh = Arc::new(mesh);
-        let triangles = (0..mesh.indices.len())
-            .map(|i| Triangle {
-                mesh: Arc::clone(&mesh),
-                index: i,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn large_meshes_are_split_into_whole_clusters() {
+        // A strip of slivers along x, more than two clusters long
+        let count = 2 * CLUSTER_TRIANGLES + 5;
+        let bounds: Vec<_> = (0..count)
+            .map(|i| {
+                let x = i as Float;
+                (Point3::new(x, 0.0, 0.0), Point3::new(x + 1.0, 1.0, 0.0))
             })
             .collect();
-        let bvh = Bvh3A::build(triangles);
-
-        // SAFTEY: This is safe. Only mutate once during construction to set the bvh.
-        unsafe {
-            let ptr = Arc::as_ptr(&mesh) as *mut mesh::Mesh;
-            let mesh = &mut *ptr;
-            mesh.bvh = bvh;
+        let mut clusters = Vec::new();
+        split_clusters((0..count).collect(), &bounds, &mut clusters);
+        assert_eq!(clusters.len(), 4);
+        assert!(clusters.iter().all(|c| c.len() <= CLUSTER_TRIANGLES));
+
+        let mut seen: Vec<_> = clusters.iter().flatten().copied().collect();
+        seen.sort_unstable();
+        assert!(seen.into_iter().eq(0..count));
+        // Each cluster is a run of neighbours
+        for cluster in &clusters {
+            let (min, max) = (cluster.iter().min().unwrap(), cluster.iter().max().unwrap());
+            assert_eq!(max - min + 1, cluster.len());
         }
 
-        mesh
+        let mut small = Vec::new();
+        split_clusters((0..10).collect(), &bounds, &mut small);
+        assert_eq!(small, vec![(0..10).collect::<Vec<_>>()]);
     }
 
-    pub fn from_obj(path: impl AsRef<Path> + Debug, material_key: MaterialKey) -> Arc<Self> {
-        let affine = Affine3A::from_scale_rotation_translation(
-            glam::Vec3::splat(10.0),
-            glam::Quat::from_rotation_x(3.14159 / 2.0),
-            glam::Vec3::new(550.0 / 2.0, 220.0, 550.0 / 2.0),
-        );
-        let obj = tobj::load_obj(
-            path,
-            &tobj::LoadOptions {
-                single_index: true,
-                triangulate: true,
-                ..Default::default()
-            },
+    #[test]
+    fn mismatched_mesh_data_is_an_error() {
+        let key = MaterialKey::default();
+        let vertices = vec![Point3::ZERO, Point3::X, Point3::Y];
+        assert!(Mesh::new(vertices.clone(), vec![(0, 1, 2)], key).is_ok());
+        assert!(Mesh::new(vertices.clone(), vec![(0, 1, 3)], key).is_err());
+        assert!(
+            Mesh::with_normals(vertices.clone(), vec![Vec3A::Z], vec![(0, 1, 2)], key).is_err()
         );
+        assert!(Mesh::with_source_faces(vertices, None, vec![(0, 1, 2)], vec![0, 0], key).is_err());
+    }
 
-        let (models, _) = obj.expect("Failed to load OBJ file");
-
-        let mut vertices = Vec::new();
-        let mut indices = Vec::new();
-        for model in models {
-            let mesh = &model.mesh;
-
-            let mesh_indices: Vec<_> = mesh
-                .indices
-                .chunks(3)
-                .map(|c| (c[0] as usize, c[1] as usize, c[2] as usize))
-                .collect();
-            let mesh_vertices: Vec<_> = mesh
-                .positions
-                .chunks(3)
-                .map(|c| affine.transform_point3a(Point3::new(c[0], c[1], c[2])))
-                .collect();
-
-            indices.extend(mesh_indices);
-            vertices.extend(mesh_vertices);
-        }
-
-        Self::new(vertices, indices, material_key)
+    #[test]
+    fn vertex_normals_are_interpolated() {
+        // A single triangle in the xz plane, with normals leaning outward
+        let vertices = vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, 1.0),
+        ];
+        let flat = vertex_normals(&vertices, &[(0, 2, 1)], 0);
+        assert!(flat.iter().all(|n| (*n - Vec3A::Y).length() < 1e-6));
+
+        let normals = vec![
+            Vec3A::new(-1.0, 1.0, -1.0).normalize(),
+            Vec3A::new(1.0, 1.0, 0.0).normalize(),
+            Vec3A::new(0.0, 1.0, 1.0).normalize(),
+        ];
+        let mesh =
+            Mesh::with_normals(vertices, normals, vec![(0, 2, 1)], MaterialKey::default()).unwrap();
+        let ray = Ray3A {
+            origin: Point3::new(0.25, 1.0, 0.25),
+            direction: -Vec3A::Y,
+        };
+        let (_, rec) = mesh.ray_hit(&ray, 0.0, Float::INFINITY).unwrap();
+        let expected = (0.5 * Vec3A::new(-1.0, 1.0, -1.0).normalize()
+            + 0.25 * Vec3A::new(1.0, 1.0, 0.0).normalize()
+            + 0.25 * Vec3A::new(0.0, 1.0, 1.0).normalize())
+        .normalize();
+        assert!((rec.normal - expected).length() < 1e-5);
+
+        // Seen from below the shading normal flips with the face
+        let ray = Ray3A {
+            origin: Point3::new(0.25, -1.0, 0.25),
+            direction: Vec3A::Y,
+        };
+        let (_, rec) = mesh.ray_hit(&ray, 0.0, Float::INFINITY).unwrap();
+        assert_eq!(rec.face, Face::Back);
+        assert!((rec.normal + expected).length() < 1e-5);
     }
-}
 
-impl Bounded<Bounds3A> for Mesh {
-    fn bounds(&self) -> Bounds3A {
-        self.bvh.bounds()
+    #[test]
+    fn polygons_remember_their_source_face() {
+        let path = std::env::temp_dir().join(format!("razz_faces_{}.obj", std::process::id()));
+        let obj = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 2 0 0\nf 1 2 3 4\nf 2 5 3\n";
+        std::fs::write(&path, obj).unwrap();
+        let mesh = Mesh::from_obj(
+            &path,
+            &AssetResolver::new(),
+            MaterialKey::default(),
+            ObjOptions::default(),
+        );
+        std::fs::remove_file(&path).unwrap();
+        let mesh = mesh.unwrap();
+
+        assert_eq!(mesh.triangles().count(), 3);
+        assert_eq!(
+            (0..3).map(|t| mesh.source_face(t)).collect::<Vec<_>>(),
+            [0, 0, 1]
+        );
+        assert_eq!(mesh.face_triangles(0).collect::<Vec<_>>(), [0, 1]);
     }
-}
 
-impl RayHittable<Bounds3A> for Mesh {
-    type Item = HitRecord;
+    #[test]
+    fn mixed_winding_is_oriented_outward() {
+        // A unit cube with every other triangle wound inward
+        let mut obj = String::new();
+        for i in 0..8 {
+            obj += &format!("v {} {} {}\n", i & 1, i >> 1 & 1, i >> 2);
+        }
+        let indices = [
+            (0, 2, 1),
+            (1, 2, 3),
+            (4, 5, 6),
+            (5, 6, 7),
+            (0, 1, 4),
+            (1, 4, 5),
+            (2, 6, 3),
+            (3, 6, 7),
+            (0, 4, 2),
+            (2, 4, 6),
+            (1, 3, 5),
+            (3, 5, 7),
+        ];
+        for (a, b, c) in indices.iter() {
+            obj += &format!("f {} {} {}\n", a + 1, b + 1, c + 1);
+        }
+        let path = std::env::temp_dir().join(format!("razz_winding_{}.obj", std::process::id()));
+        std::fs::write(&path, obj).unwrap();
+        let options = ObjOptions {
+            orient_outward: true,
+            ..ObjOptions::default()
+        };
+        let mesh = Mesh::from_obj(
+            &path,
+            &AssetResolver::new(),
+            MaterialKey::default(),
+            options,
+        );
+        std::fs::remove_file(&path).unwrap();
+        let mesh = mesh.unwrap();
 
-    fn ray_hit(&self, ray: &Ray3A, t_min: f32, t_max: f32) -> Option<(f32, Self::Item)> {
-        self.bvh.ray_hit(ray, t_min, t_max)
+        let center = Point3::splat(0.5);
+        for [v0, v1, v2] in mesh.triangles() {
+            let normal = (v1 - v0).cross(v2 - v0);
+            assert!(normal.dot((v0 + v1 + v2) / 3.0 - center) > 0.0);
+        }
     }
 }
@@ -1,13 +1,28 @@
+mod clouds;
+mod csg;
+mod curves;
+mod instance;
+mod medium;
 mod mesh;
+mod planar;
 mod sphere;
+mod water;
 
 use std::{fmt::Debug, path::Path, sync::Arc};
 
-use crate::{Float, MaterialKey, Point3, Ray3A, Vec3A};
-pub use mesh::{Mesh, Triangle};
+use crate::{AssetResolver, Float, MaterialKey, MediumRng, Point3, Ray3A, Vec3A};
+pub use clouds::{CloudSettings, Clouds};
+pub use csg::{Csg, CsgOp};
+pub use curves::{CurveBasis, CurveSegment, CurveShape, Curves, Strand};
+pub use instance::Instance;
+pub use medium::{ConstantMedium, DensityField, HeterogeneousMedium};
+pub use mesh::{Mesh, ObjOptions, Triangle};
+pub use planar::{Cuboid, Disk, Plane, Quad};
 pub use sphere::Sphere;
+pub use water::{Water, WaterSettings};
 
-use boxtree::{Bounded, Bounds3A, Bvh3A, RayHittable};
+use boxtree::Bvh3A;
+pub use boxtree::{Bounded, Bounds3A, RayHittable};
 use tobj;
 
 const PI: Float = std::f64::consts::PI as Float;
@@ -37,17 +52,69 @@ pub struct HitRecord {
     pub material_key: MaterialKey,
 }
 
-#[derive(Debug, Clone, Copy)]
+// Triangles standing in for a shape, wound counter-clockwise seen from outside
+#[derive(Debug, Clone, PartialEq)]
+pub struct Surface {
+    pub vertices: Vec<Point3>,
+    pub indices: Vec<(usize, usize, usize)>,
+    pub material_key: MaterialKey,
+}
+
+impl Surface {
+    fn append(&mut self, other: Surface) {
+        let offset = self.vertices.len();
+        self.vertices.extend(other.vertices);
+        self.indices.extend(
+            other
+                .indices
+                .into_iter()
+                .map(|(a, b, c)| (a + offset, b + offset, c + offset)),
+        );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Transform {
     pub translation: Vec3A,
     pub rotation: glam::Quat,
     pub scale: Float,
 }
 
+// Shapes defined outside razz_lib, added to a world with `Primative::custom`.
+// `ray_hit` gets rays in world space and `t` must be in units of the ray's
+// direction. Custom shapes can't be serialized.
+pub trait CustomShape:
+    Bounded<Bounds3A> + RayHittable<Bounds3A, Item = HitRecord> + Debug + Send + Sync
+{
+    // Usually `Box::new(self.clone())`
+    fn clone_box(&self) -> Box<dyn CustomShape>;
+}
+
+impl Clone for Box<dyn CustomShape> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Primative {
     Sphere(Sphere),
-    Mesh(Arc<Mesh>),
+    Quad(Quad),
+    Box(Cuboid),
+    Disk(Disk),
+    Plane(Plane),
+    Mesh(#[cfg_attr(feature = "serde", serde(with = "mesh::arc_mesh"))] Arc<Mesh>),
+    Curves(Curves),
+    Medium(ConstantMedium),
+    Volume(HeterogeneousMedium),
+    Clouds(Clouds),
+    Water(Water),
+    Csg(Csg),
+    Instance(Instance),
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Custom(Box<dyn CustomShape>),
 }
 
 impl Primative {
@@ -55,16 +122,184 @@ impl Primative {
         Self::Sphere(Sphere::new(center, radius, material_key))
     }
 
+    pub fn quad(corner: Point3, u: Vec3A, v: Vec3A, material_key: MaterialKey) -> Self {
+        Self::Quad(Quad::new(corner, u, v, material_key))
+    }
+
+    // Axis aligned, between opposite corners `a` and `b`
+    pub fn cuboid(a: Point3, b: Point3, material_key: MaterialKey) -> Self {
+        Self::Box(Cuboid::new(a, b, material_key))
+    }
+
+    pub fn disk(center: Point3, normal: Vec3A, radius: Float, material_key: MaterialKey) -> Self {
+        Self::Disk(Disk::new(center, normal, radius, material_key))
+    }
+
+    pub fn plane(point: Point3, normal: Vec3A, material_key: MaterialKey) -> Self {
+        Self::Plane(Plane::new(point, normal, material_key))
+    }
+
     pub fn mesh(
         vertices: Vec<Point3>,
         indices: Vec<(usize, usize, usize)>,
         material_key: MaterialKey,
+    ) -> std::io::Result<Self> {
+        Mesh::new(vertices, indices, material_key).map(Self::Mesh)
+    }
+
+    pub fn from_obj(
+        path: impl AsRef<Path> + Debug,
+        assets: &AssetResolver,
+        material_key: MaterialKey,
+        options: ObjOptions,
+    ) -> std::io::Result<Self> {
+        Mesh::from_obj(path, assets, material_key, options).map(Self::Mesh)
+    }
+
+    pub fn curves(
+        strands: &[Strand],
+        basis: CurveBasis,
+        shape: CurveShape,
+        material_key: MaterialKey,
+    ) -> Self {
+        Self::Curves(Curves::new(strands, basis, shape, material_key))
+    }
+
+    pub fn constant_medium(
+        boundary: Primative,
+        density: Float,
+        phase_material: MaterialKey,
     ) -> Self {
-        Self::Mesh(Mesh::new(vertices, indices, material_key))
+        Self::Medium(ConstantMedium::new(boundary, density, phase_material))
     }
 
-    pub fn from_obj(path: impl AsRef<Path> + Debug, material_key: MaterialKey) -> Self {
-        Self::Mesh(Mesh::from_obj(path, material_key))
+    pub fn heterogeneous_medium(
+        boundary: Primative,
+        density: DensityField,
+        phase_material: MaterialKey,
+    ) -> Self {
+        Self::Volume(HeterogeneousMedium::new(boundary, density, phase_material))
+    }
+
+    pub fn clouds<T: rand::Rng>(
+        boundary: Primative,
+        settings: CloudSettings,
+        phase_material: MaterialKey,
+        rng: &mut T,
+    ) -> Self {
+        Self::Clouds(Clouds::new(boundary, settings, phase_material, rng))
+    }
+
+    pub fn water<T: rand::Rng>(
+        settings: WaterSettings,
+        material_key: MaterialKey,
+        rng: &mut T,
+    ) -> Self {
+        Self::Water(Water::new(settings, material_key, rng))
+    }
+
+    pub fn union(a: Primative, b: Primative) -> Self {
+        Self::Csg(Csg::new(CsgOp::Union, a, b))
+    }
+
+    pub fn intersection(a: Primative, b: Primative) -> Self {
+        Self::Csg(Csg::new(CsgOp::Intersection, a, b))
+    }
+
+    // `a` with `b` carved out of it
+    pub fn difference(a: Primative, b: Primative) -> Self {
+        Self::Csg(Csg::new(CsgOp::Difference, a, b))
+    }
+
+    pub fn custom(shape: impl CustomShape + 'static) -> Self {
+        Self::Custom(Box::new(shape))
+    }
+
+    pub fn instance(object: Arc<Primative>, transform: Transform) -> Self {
+        Self::Instance(Instance::new(object, transform))
+    }
+
+    // Triangles approximating the shape, for exporting to other tools. Planes, curves,
+    // volumes, CSG and custom shapes have none.
+    pub fn surfaces(&self) -> Option<Vec<Surface>> {
+        match self {
+            Self::Sphere(s) => Some(vec![s.surface()]),
+            Self::Quad(q) => Some(vec![q.surface()]),
+            Self::Box(b) => Some(vec![b.surface()]),
+            Self::Disk(d) => Some(vec![d.surface()]),
+            Self::Mesh(m) => Some(vec![m.surface()]),
+            Self::Water(w) => Some(vec![w.surface()]),
+            Self::Instance(i) => i.surfaces(),
+            Self::Plane(_)
+            | Self::Curves(_)
+            | Self::Medium(_)
+            | Self::Volume(_)
+            | Self::Clouds(_)
+            | Self::Csg(_)
+            | Self::Custom(_) => None,
+        }
+    }
+
+    // Every material the primitive's hits can report. Custom shapes don't say, so
+    // they have none.
+    pub(crate) fn material_keys(&self) -> Vec<MaterialKey> {
+        match self {
+            Self::Sphere(s) => vec![s.material_key()],
+            Self::Quad(q) => vec![q.material_key()],
+            Self::Box(b) => vec![b.material_key()],
+            Self::Disk(d) => vec![d.material_key()],
+            Self::Plane(p) => vec![p.material_key()],
+            Self::Mesh(m) => vec![m.material_key()],
+            Self::Curves(c) => vec![c.material_key()],
+            Self::Medium(m) => vec![m.phase_material()],
+            Self::Volume(v) => vec![v.phase_material()],
+            Self::Clouds(c) => vec![c.phase_material()],
+            Self::Water(w) => vec![w.material_key()],
+            Self::Csg(c) => c.material_keys(),
+            Self::Instance(i) => i.material_keys(),
+            Self::Custom(_) => Vec::new(),
+        }
+    }
+
+    // Planes, and anything built from them, have no finite bounds to put in a BVH
+    pub fn is_bounded(&self) -> bool {
+        let bounds = self.bounds();
+        bounds.min.is_finite() && bounds.max.is_finite()
+    }
+
+    // `ray_hit`, with media drawing where they scatter from `media`
+    pub(crate) fn media_hit(
+        &self,
+        ray: &Ray3A,
+        t_min: Float,
+        t_max: Float,
+        media: &mut MediumRng,
+    ) -> Option<(Float, HitRecord)> {
+        match self {
+            Self::Medium(m) => m.scatter(ray, t_min, t_max, media),
+            Self::Volume(v) => v.scatter(ray, t_min, t_max, media),
+            Self::Clouds(c) => c.scatter(ray, t_min, t_max, media),
+            Self::Instance(i) => i.media_hit(ray, t_min, t_max, media),
+            primative => primative.ray_hit(ray, t_min, t_max),
+        }
+    }
+
+    // Whether anything of the primitive lies along the ray between `t_min` and
+    // `t_max`. Meshes stop at the first triangle they find, everything else falls
+    // back on its closest hit.
+    pub(crate) fn occluded(
+        &self,
+        ray: &Ray3A,
+        t_min: Float,
+        t_max: Float,
+        media: &mut MediumRng,
+    ) -> bool {
+        match self {
+            Self::Mesh(m) => m.occluded(ray, t_min, t_max),
+            Self::Water(w) => w.occluded(ray, t_min, t_max),
+            Self::Instance(i) => i.occluded(ray, t_min, t_max, media),
+            primative => primative.media_hit(ray, t_min, t_max, media).is_some(),
+        }
     }
 }
 
@@ -82,7 +317,19 @@ impl Bounded<Bounds3A> for Primative {
     fn bounds(&self) -> Bounds3A {
         match self {
             Self::Sphere(s) => s.bounds(),
+            Self::Quad(q) => q.bounds(),
+            Self::Box(b) => b.bounds(),
+            Self::Disk(d) => d.bounds(),
+            Self::Plane(p) => p.bounds(),
             Self::Mesh(m) => m.bounds(),
+            Self::Curves(c) => c.bounds(),
+            Self::Medium(m) => m.bounds(),
+            Self::Volume(v) => v.bounds(),
+            Self::Clouds(c) => c.bounds(),
+            Self::Water(w) => w.bounds(),
+            Self::Csg(c) => c.bounds(),
+            Self::Instance(i) => i.bounds(),
+            Self::Custom(c) => c.bounds(),
         }
     }
 }
@@ -93,7 +340,19 @@ impl RayHittable<Bounds3A> for Primative {
     fn ray_hit(&self, ray: &Ray3A, t_min: f32, t_max: f32) -> Option<(f32, Self::Item)> {
         match self {
             Self::Sphere(s) => s.ray_hit(ray, t_min, t_max).map(|t| t),
+            Self::Quad(q) => q.ray_hit(ray, t_min, t_max),
+            Self::Box(b) => b.ray_hit(ray, t_min, t_max),
+            Self::Disk(d) => d.ray_hit(ray, t_min, t_max),
+            Self::Plane(p) => p.ray_hit(ray, t_min, t_max),
             Self::Mesh(m) => m.ray_hit(ray, t_min, t_max).map(|t| t),
+            Self::Curves(c) => c.ray_hit(ray, t_min, t_max),
+            Self::Medium(m) => m.ray_hit(ray, t_min, t_max),
+            Self::Volume(v) => v.ray_hit(ray, t_min, t_max),
+            Self::Clouds(c) => c.ray_hit(ray, t_min, t_max),
+            Self::Water(w) => w.ray_hit(ray, t_min, t_max),
+            Self::Csg(c) => c.ray_hit(ray, t_min, t_max),
+            Self::Instance(i) => i.ray_hit(ray, t_min, t_max),
+            Self::Custom(c) => c.ray_hit(ray, t_min, t_max),
         }
     }
 }
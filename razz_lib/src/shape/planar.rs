@@ -0,0 +1,429 @@
+use super::*;
+
+// Parametric distance to the plane through `point` with `normal`, if it lies in range
+#[inline(always)]
+fn plane_hit(ray: &Ray3A, point: Point3, normal: Vec3A, t_min: f32, t_max: f32) -> Option<f32> {
+    let denom = normal.dot(ray.direction);
+    if denom.abs() < 1e-8 {
+        return None;
+    }
+    let t = normal.dot(point - ray.origin) / denom;
+    match t_min <= t && t <= t_max {
+        true => Some(t),
+        false => None,
+    }
+}
+
+// A parallelogram spanning `corner + a * u + b * v` for a and b in [0, 1],
+// which are also its texture coordinates
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Quad {
+    pub corner: Point3,
+    pub u: Vec3A,
+    pub v: Vec3A,
+    material_key: MaterialKey,
+}
+
+impl Quad {
+    pub fn material_key(&self) -> MaterialKey {
+        self.material_key
+    }
+
+    pub fn new(corner: Point3, u: Vec3A, v: Vec3A, material_key: MaterialKey) -> Self {
+        Self {
+            corner,
+            u,
+            v,
+            material_key,
+        }
+    }
+
+    // Facing along `u` cross `v`
+    pub(crate) fn surface(&self) -> Surface {
+        Surface {
+            vertices: vec![
+                self.corner,
+                self.corner + self.u,
+                self.corner + self.u + self.v,
+                self.corner + self.v,
+            ],
+            indices: vec![(0, 1, 2), (0, 2, 3)],
+            material_key: self.material_key,
+        }
+    }
+}
+
+impl Bounded<Bounds3A> for Quad {
+    fn bounds(&self) -> Bounds3A {
+        let corners = [
+            self.corner,
+            self.corner + self.u,
+            self.corner + self.v,
+            self.corner + self.u + self.v,
+        ];
+        let min = corners.iter().fold(corners[0], |m, c| m.min(*c));
+        let max = corners.iter().fold(corners[0], |m, c| m.max(*c));
+        // Pad so axis aligned quads don't have flat bounds
+        let pad = Vec3A::splat(1e-4 * (max - min).max_element().max(1.0));
+        Bounds3A::new(min - pad, max + pad)
+    }
+}
+
+impl RayHittable<Bounds3A> for Quad {
+    type Item = HitRecord;
+
+    fn ray_hit(&self, ray: &Ray3A, t_min: f32, t_max: f32) -> Option<(f32, HitRecord)> {
+        let n = self.u.cross(self.v);
+        let t = plane_hit(ray, self.corner, n, t_min, t_max)?;
+
+        // Coordinates of the hit along the edges, found by projecting onto them
+        let point = ray.at(t);
+        let w = n / n.length_squared();
+        let offset = point - self.corner;
+        let a = w.dot(offset.cross(self.v));
+        let b = w.dot(self.u.cross(offset));
+        if !(0.0..=1.0).contains(&a) || !(0.0..=1.0).contains(&b) {
+            return None;
+        }
+
+        let (face, normal) = get_face(ray, n.normalize());
+        Some((
+            t,
+            HitRecord {
+                point,
+                normal,
+                u: a,
+                v: b,
+                face,
+                material_key: self.material_key,
+            },
+        ))
+    }
+}
+
+// A flat circle. Texture coordinates are polar, u the angle around `normal` and v
+// the distance from the center as a fraction of `radius`.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Disk {
+    pub center: Point3,
+    pub normal: Vec3A,
+    pub radius: f32,
+    material_key: MaterialKey,
+}
+
+// Points around the rim when tessellated
+const DISK_SEGMENTS: usize = 32;
+
+impl Disk {
+    pub fn material_key(&self) -> MaterialKey {
+        self.material_key
+    }
+
+    pub fn new(center: Point3, normal: Vec3A, radius: f32, material_key: MaterialKey) -> Self {
+        Self {
+            center,
+            normal: normal.normalize(),
+            radius,
+            material_key,
+        }
+    }
+
+    // A fan around the center, facing along the normal
+    pub(crate) fn surface(&self) -> Surface {
+        let tangent = self.normal.any_orthonormal_vector();
+        let bitangent = self.normal.cross(tangent);
+        let rim = (0..DISK_SEGMENTS).map(|i| {
+            let phi = 2.0 * PI * i as Float / DISK_SEGMENTS as Float;
+            self.center + self.radius * (phi.cos() * tangent + phi.sin() * bitangent)
+        });
+        Surface {
+            vertices: std::iter::once(self.center).chain(rim).collect(),
+            indices: (0..DISK_SEGMENTS)
+                .map(|i| (0, i + 1, (i + 1) % DISK_SEGMENTS + 1))
+                .collect(),
+            material_key: self.material_key,
+        }
+    }
+}
+
+impl Bounded<Bounds3A> for Disk {
+    fn bounds(&self) -> Bounds3A {
+        // Extent along each axis of a circle facing `normal`
+        let n = self.normal;
+        let extent = self.radius
+            * Vec3A::new(
+                (1.0 - n.x * n.x).max(0.0).sqrt(),
+                (1.0 - n.y * n.y).max(0.0).sqrt(),
+                (1.0 - n.z * n.z).max(0.0).sqrt(),
+            );
+        let pad = Vec3A::splat(1e-4 * self.radius.max(1.0));
+        Bounds3A::new(self.center - extent - pad, self.center + extent + pad)
+    }
+}
+
+impl RayHittable<Bounds3A> for Disk {
+    type Item = HitRecord;
+
+    fn ray_hit(&self, ray: &Ray3A, t_min: f32, t_max: f32) -> Option<(f32, HitRecord)> {
+        let t = plane_hit(ray, self.center, self.normal, t_min, t_max)?;
+        let point = ray.at(t);
+        let offset = point - self.center;
+        let r2 = offset.length_squared();
+        if r2 > self.radius * self.radius {
+            return None;
+        }
+
+        let tangent = self.normal.any_orthonormal_vector();
+        let bitangent = self.normal.cross(tangent);
+        let angle = offset.dot(bitangent).atan2(offset.dot(tangent));
+        let (face, normal) = get_face(ray, self.normal);
+        Some((
+            t,
+            HitRecord {
+                point,
+                normal,
+                u: angle / (2.0 * PI) + 0.5,
+                v: r2.sqrt() / self.radius,
+                face,
+                material_key: self.material_key,
+            },
+        ))
+    }
+}
+
+// An infinite plane. It has no finite bounds, so the world tests it outside the
+// BVH. Texture coordinates are world units along the plane, so textures repeat
+// once per unit.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Plane {
+    pub point: Point3,
+    pub normal: Vec3A,
+    material_key: MaterialKey,
+}
+
+impl Plane {
+    pub fn material_key(&self) -> MaterialKey {
+        self.material_key
+    }
+
+    pub fn new(point: Point3, normal: Vec3A, material_key: MaterialKey) -> Self {
+        Self {
+            point,
+            normal: normal.normalize(),
+            material_key,
+        }
+    }
+}
+
+impl Bounded<Bounds3A> for Plane {
+    fn bounds(&self) -> Bounds3A {
+        Bounds3A::new(
+            Vec3A::splat(Float::NEG_INFINITY),
+            Vec3A::splat(Float::INFINITY),
+        )
+    }
+}
+
+impl RayHittable<Bounds3A> for Plane {
+    type Item = HitRecord;
+
+    fn ray_hit(&self, ray: &Ray3A, t_min: f32, t_max: f32) -> Option<(f32, HitRecord)> {
+        let t = plane_hit(ray, self.point, self.normal, t_min, t_max)?;
+        let point = ray.at(t);
+        let offset = point - self.point;
+        let tangent = self.normal.any_orthonormal_vector();
+        let bitangent = self.normal.cross(tangent);
+        let (face, normal) = get_face(ray, self.normal);
+        Some((
+            t,
+            HitRecord {
+                point,
+                normal,
+                u: offset.dot(tangent),
+                v: offset.dot(bitangent),
+                face,
+                material_key: self.material_key,
+            },
+        ))
+    }
+}
+
+// An axis aligned box, wrapped in an `Instance` to rotate it. Each face maps the
+// whole texture.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cuboid {
+    pub min: Point3,
+    pub max: Point3,
+    material_key: MaterialKey,
+}
+
+impl Cuboid {
+    pub fn material_key(&self) -> MaterialKey {
+        self.material_key
+    }
+
+    pub fn new(a: Point3, b: Point3, material_key: MaterialKey) -> Self {
+        Self {
+            min: a.min(b),
+            max: a.max(b),
+            material_key,
+        }
+    }
+
+    pub(crate) fn surface(&self) -> Surface {
+        let (min, max) = (self.min, self.max);
+        let size = max - min;
+        let (x, y, z) = (
+            Vec3A::new(size.x, 0.0, 0.0),
+            Vec3A::new(0.0, size.y, 0.0),
+            Vec3A::new(0.0, 0.0, size.z),
+        );
+        // Each face's edges ordered so their cross product points outward
+        let faces = [
+            (min, z, y),
+            (Vec3A::new(max.x, min.y, min.z), y, z),
+            (min, x, z),
+            (Vec3A::new(min.x, max.y, min.z), z, x),
+            (min, y, x),
+            (Vec3A::new(min.x, min.y, max.z), x, y),
+        ];
+
+        let mut surface = Surface {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            material_key: self.material_key,
+        };
+        for (corner, u, v) in faces {
+            surface.append(Quad::new(corner, u, v, self.material_key).surface());
+        }
+        surface
+    }
+}
+
+impl Bounded<Bounds3A> for Cuboid {
+    fn bounds(&self) -> Bounds3A {
+        Bounds3A::new(self.min, self.max)
+    }
+}
+
+impl RayHittable<Bounds3A> for Cuboid {
+    type Item = HitRecord;
+
+    fn ray_hit(&self, ray: &Ray3A, t_min: f32, t_max: f32) -> Option<(f32, HitRecord)> {
+        // Slab test, remembering which axis bounds each end of the span
+        let inv = ray.direction.recip();
+        let t0 = (self.min - ray.origin) * inv;
+        let t1 = (self.max - ray.origin) * inv;
+        let (near, far) = (t0.min(t1), t0.max(t1));
+
+        let mut enter = (Float::NEG_INFINITY, 0);
+        let mut exit = (Float::INFINITY, 0);
+        for axis in 0..3 {
+            if near[axis] > enter.0 {
+                enter = (near[axis], axis);
+            }
+            if far[axis] < exit.0 {
+                exit = (far[axis], axis);
+            }
+        }
+        if enter.0 > exit.0 {
+            return None;
+        }
+
+        // Rays enter against their direction of travel and leave along it
+        let ((t, axis), side) = if t_min <= enter.0 && enter.0 <= t_max {
+            (enter, -1.0)
+        } else if t_min <= exit.0 && exit.0 <= t_max {
+            (exit, 1.0)
+        } else {
+            return None;
+        };
+
+        let point = ray.at(t);
+        let mut outward = Vec3A::ZERO;
+        outward[axis] = side * ray.direction[axis].signum();
+        let local = (point - self.min) / (self.max - self.min);
+        let (u, v) = match axis {
+            0 => (local.z, local.y),
+            1 => (local.x, local.z),
+            _ => (local.x, local.y),
+        };
+
+        let (face, normal) = get_face(ray, outward);
+        Some((
+            t,
+            HitRecord {
+                point,
+                normal,
+                u,
+                v,
+                face,
+                material_key: self.material_key,
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ray(origin: [f32; 3], direction: [f32; 3]) -> Ray3A {
+        Ray3A {
+            origin: origin.into(),
+            direction: direction.into(),
+        }
+    }
+
+    #[test]
+    fn planar_shapes_hit_with_uvs() {
+        let key = MaterialKey::default();
+
+        let quad = Quad::new(Point3::ZERO, 2.0 * Vec3A::X, 4.0 * Vec3A::Z, key);
+        let (t, rec) = quad
+            .ray_hit(&ray([0.5, 3.0, 1.0], [0.0, -1.0, 0.0]), 0.001, 10.0)
+            .unwrap();
+        assert!((t - 3.0).abs() < 1e-5);
+        assert!((rec.u - 0.25).abs() < 1e-5 && (rec.v - 0.25).abs() < 1e-5);
+        assert_eq!((rec.normal, rec.face), (Vec3A::Y, Face::Back));
+        assert!(quad
+            .ray_hit(&ray([2.5, 3.0, 1.0], [0.0, -1.0, 0.0]), 0.001, 10.0)
+            .is_none());
+
+        let disk = Disk::new(Point3::ZERO, Vec3A::Y, 2.0, key);
+        let (_, rec) = disk
+            .ray_hit(&ray([1.0, 1.0, 0.0], [0.0, -1.0, 0.0]), 0.001, 10.0)
+            .unwrap();
+        assert!((rec.v - 0.5).abs() < 1e-5);
+        assert!(disk
+            .ray_hit(&ray([1.5, 1.0, 1.5], [0.0, -1.0, 0.0]), 0.001, 10.0)
+            .is_none());
+
+        let plane = Plane::new(Point3::ZERO, Vec3A::Y, key);
+        let (t, _) = plane
+            .ray_hit(&ray([100.0, 2.0, -50.0], [0.0, -1.0, 0.0]), 0.001, 10.0)
+            .unwrap();
+        assert!((t - 2.0).abs() < 1e-5);
+        assert!(plane
+            .ray_hit(&ray([0.0, 2.0, 0.0], [1.0, 0.0, 0.0]), 0.001, 10.0)
+            .is_none());
+
+        let cuboid = Cuboid::new(Point3::ONE, -Point3::ONE, key);
+        let (t, rec) = cuboid
+            .ray_hit(&ray([0.5, 0.0, 5.0], [0.0, 0.0, -1.0]), 0.001, 10.0)
+            .unwrap();
+        assert!((t - 4.0).abs() < 1e-5);
+        assert_eq!((rec.normal, rec.face), (Vec3A::Z, Face::Front));
+        assert!((rec.u - 0.75).abs() < 1e-5 && (rec.v - 0.5).abs() < 1e-5);
+        // From inside, the far face is hit from behind
+        let (t, rec) = cuboid
+            .ray_hit(&ray([0.0, 0.0, 0.0], [1.0, 0.0, 0.0]), 0.001, 10.0)
+            .unwrap();
+        assert!((t - 1.0).abs() < 1e-5);
+        assert_eq!((rec.normal, rec.face), (-Vec3A::X, Face::Back));
+    }
+}
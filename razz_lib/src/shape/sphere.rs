@@ -1,12 +1,17 @@
 use super::*;
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Sphere {
     pub center: Vec3A,
     pub radius: f32,
     material_key: MaterialKey,
 }
 
+// Rings of latitude and longitude when tessellated
+const SURFACE_STACKS: usize = 16;
+const SURFACE_SLICES: usize = 32;
+
 impl Sphere {
     pub fn new(center: Vec3A, radius: f32, material_key: MaterialKey) -> Sphere {
         Sphere {
@@ -15,6 +20,47 @@ impl Sphere {
             material_key,
         }
     }
+
+    pub fn material_key(&self) -> MaterialKey {
+        self.material_key
+    }
+
+    pub(crate) fn surface(&self) -> Surface {
+        let mut vertices = Vec::new();
+        for i in 0..=SURFACE_STACKS {
+            let theta = PI * i as Float / SURFACE_STACKS as Float;
+            for j in 0..=SURFACE_SLICES {
+                let phi = 2.0 * PI * j as Float / SURFACE_SLICES as Float;
+                let direction = Vec3A::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                vertices.push(self.center + self.radius * direction);
+            }
+        }
+
+        // Skipping the triangles that collapse at the poles
+        let row = SURFACE_SLICES + 1;
+        let mut indices = Vec::new();
+        for i in 0..SURFACE_STACKS {
+            for j in 0..SURFACE_SLICES {
+                let (a, b) = (i * row + j, (i + 1) * row + j);
+                if i > 0 {
+                    indices.push((a, a + 1, b));
+                }
+                if i + 1 < SURFACE_STACKS {
+                    indices.push((a + 1, b + 1, b));
+                }
+            }
+        }
+
+        Surface {
+            vertices,
+            indices,
+            material_key: self.material_key,
+        }
+    }
 }
 
 impl Bounded<Bounds3A> for Sphere {
@@ -0,0 +1,230 @@
+use super::*;
+use crate::noise::PerlinData;
+
+use rand::Rng;
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WaterSettings {
+    // Middle of the surface at rest, which spans `size` along x and z around it
+    pub center: Point3,
+    pub size: Float,
+    // Grid cells along each side
+    pub resolution: usize,
+    // Furthest the waves rise above or fall below the rest height
+    pub amplitude: Float,
+    // Length of the largest waves, and the fBm octaves of smaller ones on top
+    pub wavelength: Float,
+    pub octaves: usize,
+    // How far the waves drift along x and z per unit of time
+    pub velocity: (Float, Float),
+    // How quickly the waves change shape as they drift, in wavelengths per unit of time
+    pub churn: Float,
+}
+
+impl Default for WaterSettings {
+    fn default() -> Self {
+        Self {
+            center: Point3::ZERO,
+            size: 10.0,
+            resolution: 128,
+            amplitude: 0.1,
+            wavelength: 2.0,
+            octaves: 4,
+            velocity: (0.5, 0.0),
+            churn: 0.2,
+        }
+    }
+}
+
+// A square patch of water, a grid displaced by fBm that drifts and churns with time.
+// The grid is rebuilt whenever the time changes, so `World::set_time` animates it
+// along with the rest of the scene.
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "WaterParts", from = "WaterParts")
+)]
+pub struct Water {
+    settings: WaterSettings,
+    noise: Box<PerlinData>,
+    time: Float,
+    mesh: Arc<Mesh>,
+    material_key: MaterialKey,
+}
+
+impl Water {
+    pub fn material_key(&self) -> MaterialKey {
+        self.material_key
+    }
+
+    // `material_key` is usually `Material::Water`
+    pub fn new<T: Rng>(settings: WaterSettings, material_key: MaterialKey, rng: &mut T) -> Self {
+        Self::at_time(settings, Box::new(PerlinData::new(rng)), 0.0, material_key)
+    }
+
+    fn at_time(
+        settings: WaterSettings,
+        noise: Box<PerlinData>,
+        time: Float,
+        material_key: MaterialKey,
+    ) -> Self {
+        let mut water = Self {
+            settings,
+            noise,
+            time,
+            mesh: Mesh::build(Vec::new(), None, Vec::new(), None, material_key),
+            material_key,
+        };
+        water.mesh = water.build_mesh();
+        water
+    }
+
+    pub fn settings(&self) -> &WaterSettings {
+        &self.settings
+    }
+
+    pub fn time(&self) -> Float {
+        self.time
+    }
+
+    pub fn set_time(&mut self, time: Float) {
+        if time != self.time {
+            self.time = time;
+            self.mesh = self.build_mesh();
+        }
+    }
+
+    // Height of the surface above `(x, z)` at the current time
+    pub fn height(&self, x: Float, z: Float) -> Float {
+        let s = &self.settings;
+        let wavelength = s.wavelength.max(Float::EPSILON);
+        let p = Point3::new(
+            (x - s.velocity.0 * self.time) / wavelength,
+            s.churn * self.time,
+            (z - s.velocity.1 * self.time) / wavelength,
+        );
+        // fBm rarely strays beyond [-0.25, 0.25], so stretch it over [-1, 1]
+        s.center.y + s.amplitude * (4.0 * self.noise.fbm(p, s.octaves)).clamp(-1.0, 1.0)
+    }
+
+    fn build_mesh(&self) -> Arc<Mesh> {
+        let s = &self.settings;
+        let cells = s.resolution.max(1);
+        let step = s.size / cells as Float;
+        let corner = s.center - 0.5 * s.size * (Vec3A::X + Vec3A::Z);
+
+        let mut vertices = Vec::with_capacity((cells + 1) * (cells + 1));
+        let mut normals = Vec::with_capacity(vertices.capacity());
+        for j in 0..=cells {
+            for i in 0..=cells {
+                let x = corner.x + i as Float * step;
+                let z = corner.z + j as Float * step;
+                vertices.push(Point3::new(x, self.height(x, z), z));
+                // Central differences of the height field
+                let dx = self.height(x + 0.5 * step, z) - self.height(x - 0.5 * step, z);
+                let dz = self.height(x, z + 0.5 * step) - self.height(x, z - 0.5 * step);
+                normals.push(Vec3A::new(-dx, step, -dz).normalize());
+            }
+        }
+
+        let index = |i: usize, j: usize| j * (cells + 1) + i;
+        let mut indices = Vec::with_capacity(2 * cells * cells);
+        for j in 0..cells {
+            for i in 0..cells {
+                // Wound to face up
+                indices.push((index(i, j), index(i, j + 1), index(i + 1, j)));
+                indices.push((index(i + 1, j), index(i, j + 1), index(i + 1, j + 1)));
+            }
+        }
+        Mesh::build(vertices, Some(normals), indices, None, self.material_key)
+    }
+
+    pub(crate) fn surface(&self) -> Surface {
+        self.mesh.surface()
+    }
+
+    pub(crate) fn occluded(&self, ray: &Ray3A, t_min: Float, t_max: Float) -> bool {
+        self.mesh.occluded(ray, t_min, t_max)
+    }
+}
+
+impl Bounded<Bounds3A> for Water {
+    fn bounds(&self) -> Bounds3A {
+        self.mesh.bounds()
+    }
+}
+
+impl RayHittable<Bounds3A> for Water {
+    type Item = HitRecord;
+
+    fn ray_hit(&self, ray: &Ray3A, t_min: f32, t_max: f32) -> Option<(f32, Self::Item)> {
+        self.mesh.ray_hit(ray, t_min, t_max)
+    }
+}
+
+// Water is saved without its grid, which is rebuilt on load
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct WaterParts {
+    settings: WaterSettings,
+    noise: Box<PerlinData>,
+    time: Float,
+    material_key: MaterialKey,
+}
+
+#[cfg(feature = "serde")]
+impl From<Water> for WaterParts {
+    fn from(water: Water) -> Self {
+        Self {
+            settings: water.settings,
+            noise: water.noise,
+            time: water.time,
+            material_key: water.material_key,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<WaterParts> for Water {
+    fn from(parts: WaterParts) -> Self {
+        Self::at_time(parts.settings, parts.noise, parts.time, parts.material_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn waves_follow_the_height_field_and_move_with_time() {
+        let settings = WaterSettings {
+            resolution: 32,
+            amplitude: 0.2,
+            ..WaterSettings::default()
+        };
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        let mut water = Water::new(settings, MaterialKey::default(), &mut rng);
+
+        let bounds = water.bounds();
+        assert!(bounds.min.y >= -0.2 - 1e-4 && bounds.max.y <= 0.2 + 1e-4);
+
+        // Straight down onto a grid vertex lands on the height field
+        let (x, z) = (0.625, -1.25);
+        let ray = Ray3A {
+            origin: Point3::new(x, 5.0, z),
+            direction: -Vec3A::Y,
+        };
+        let (_, rec) = water.ray_hit(&ray, 0.0, Float::INFINITY).unwrap();
+        assert!((rec.point.y - water.height(x, z)).abs() < 1e-4);
+        assert_eq!(rec.face, Face::Front);
+
+        let before = rec.point.y;
+        water.set_time(3.0);
+        let (_, rec) = water.ray_hit(&ray, 0.0, Float::INFINITY).unwrap();
+        assert!((rec.point.y - water.height(x, z)).abs() < 1e-4);
+        assert!((rec.point.y - before).abs() > 1e-5);
+    }
+}
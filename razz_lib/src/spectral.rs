@@ -0,0 +1,85 @@
+use crate::{Float, Rgba};
+
+use std::sync::OnceLock;
+
+// The visible range wavelengths are drawn from, in nanometers
+pub const WAVELENGTH_MIN: Float = 380.0;
+pub const WAVELENGTH_MAX: Float = 780.0;
+
+// Sums of each channel's clamped response over the visible range, so that white light
+// averages back to white
+static CHANNEL_TOTALS: OnceLock<[Float; 3]> = OnceLock::new();
+
+// Piecewise Gaussian fit to the CIE 1931 color matching functions (Wyman et al. 2013)
+fn cie_xyz(wavelength: Float) -> [Float; 3] {
+    let g = |mean: Float, below: Float, above: Float| {
+        let sigma = match wavelength < mean {
+            true => below,
+            false => above,
+        };
+        let t = (wavelength - mean) / sigma;
+        (-0.5 * t * t).exp()
+    };
+    [
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    ]
+}
+
+// Linear sRGB of a single wavelength, with the negative lobes of colors outside the
+// gamut cut off
+fn response(wavelength: Float) -> [Float; 3] {
+    let [x, y, z] = cie_xyz(wavelength);
+    [
+        (3.2406 * x - 1.5372 * y - 0.4986 * z).max(0.0),
+        (-0.9689 * x + 1.8758 * y + 0.0415 * z).max(0.0),
+        (0.0557 * x - 0.2040 * y + 1.0570 * z).max(0.0),
+    ]
+}
+
+// Uniform over the visible range, from `u` in [0, 1)
+pub(crate) fn sample_wavelength(u: Float) -> Float {
+    WAVELENGTH_MIN + u * (WAVELENGTH_MAX - WAVELENGTH_MIN)
+}
+
+// What light carried at `wavelength` adds to each channel, over the pdf of drawing it.
+// Averages to one in every channel across the visible range.
+pub(crate) fn wavelength_weight(wavelength: Float) -> Rgba {
+    let totals = CHANNEL_TOTALS.get_or_init(|| {
+        let steps = 4000;
+        let mut totals = [0.0; 3];
+        for i in 0..steps {
+            let r = response(sample_wavelength((i as Float + 0.5) / steps as Float));
+            (0..3).for_each(|c| totals[c] += r[c] / steps as Float);
+        }
+        totals
+    });
+    let r = response(wavelength);
+    Rgba::new(r[0] / totals[0], r[1] / totals[1], r[2] / totals[2], 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wavelengths_average_to_white_and_run_blue_to_red() {
+        let steps = 997;
+        let mut sum = Rgba::ZERO;
+        for i in 0..steps {
+            sum = sum + wavelength_weight(sample_wavelength((i as Float + 0.5) / steps as Float));
+        }
+        let mean = (sum * (1.0 / steps as Float)).to_array();
+        for channel in &mean[..3] {
+            assert!((channel - 1.0).abs() < 1e-2);
+        }
+
+        let violet = wavelength_weight(440.0).to_array();
+        let green = wavelength_weight(530.0).to_array();
+        let red = wavelength_weight(650.0).to_array();
+        assert!(violet[2] > violet[1] && violet[2] > violet[0]);
+        assert!(green[1] > green[0] && green[1] > green[2]);
+        assert!(red[0] > red[1] && red[0] > red[2]);
+    }
+}
@@ -0,0 +1,102 @@
+use crate::{Float, Vec3A};
+
+const PI: Float = std::f64::consts::PI as Float;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SunPosition {
+    // Radians above the horizon
+    pub elevation: Float,
+    // Radians clockwise from north
+    pub azimuth: Float,
+}
+
+impl SunPosition {
+    // NOAA approximation, accurate to a fraction of a degree.
+    // Latitude is positive north and longitude positive east, both in degrees.
+    pub fn new(
+        year: i32,
+        month: u32,
+        day: u32,
+        utc_hours: Float,
+        latitude: Float,
+        longitude: Float,
+    ) -> Self {
+        let day_of_year = day_of_year(year, month, day) as Float;
+        let days_in_year = if is_leap_year(year) { 366.0 } else { 365.0 };
+        let gamma = 2.0 * PI / days_in_year * (day_of_year - 1.0 + (utc_hours - 12.0) / 24.0);
+
+        let equation_of_time = 229.18
+            * (0.000075 + 0.001868 * gamma.cos()
+                - 0.032077 * gamma.sin()
+                - 0.014615 * (2.0 * gamma).cos()
+                - 0.040849 * (2.0 * gamma).sin());
+        let declination = 0.006918 - 0.399912 * gamma.cos() + 0.070257 * gamma.sin()
+            - 0.006758 * (2.0 * gamma).cos()
+            + 0.000907 * (2.0 * gamma).sin()
+            - 0.002697 * (3.0 * gamma).cos()
+            + 0.00148 * (3.0 * gamma).sin();
+
+        let true_solar_minutes = utc_hours * 60.0 + equation_of_time + 4.0 * longitude;
+        let hour_angle = (true_solar_minutes / 4.0 - 180.0).to_radians();
+        let latitude = latitude.to_radians();
+
+        let cos_zenith = (latitude.sin() * declination.sin()
+            + latitude.cos() * declination.cos() * hour_angle.cos())
+        .clamp(-1.0, 1.0);
+        let zenith = cos_zenith.acos();
+
+        let azimuth = (-hour_angle.sin() * declination.cos()).atan2(
+            declination.sin() * latitude.cos()
+                - declination.cos() * latitude.sin() * hour_angle.cos(),
+        );
+
+        Self {
+            elevation: 0.5 * PI - zenith,
+            azimuth: azimuth.rem_euclid(2.0 * PI),
+        }
+    }
+
+    // Unit vector towards the sun with +y up, -z north and +x east
+    pub fn direction(&self) -> Vec3A {
+        let horizontal = self.elevation.cos();
+        Vec3A::new(
+            horizontal * self.azimuth.sin(),
+            self.elevation.sin(),
+            -horizontal * self.azimuth.cos(),
+        )
+    }
+}
+
+fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn day_of_year(year: i32, month: u32, day: u32) -> u32 {
+    const CUMULATIVE_DAYS: [u32; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+    let leap_day = if month > 2 && is_leap_year(year) {
+        1
+    } else {
+        0
+    };
+    CUMULATIVE_DAYS[(month.clamp(1, 12) - 1) as usize] + day + leap_day
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equinox_noon_at_equator_is_overhead() {
+        let sun = SunPosition::new(2021, 3, 20, 12.0, 0.0, 0.0);
+        assert!(sun.elevation.to_degrees() > 85.0);
+    }
+
+    #[test]
+    fn summer_morning_sun_rises_in_the_east() {
+        // London, mid June, 06:00 UTC
+        let sun = SunPosition::new(2021, 6, 15, 6.0, 51.5, -0.1);
+        let direction = sun.direction();
+        assert!(sun.elevation > 0.0);
+        assert!(direction.x > 0.0);
+    }
+}
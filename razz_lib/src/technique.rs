@@ -0,0 +1,214 @@
+use crate::image::{Image, Rgba};
+use crate::report::json_string;
+use crate::Float;
+
+use bumpalo::Bump;
+use std::fmt::Write;
+
+// How a path found the light it carries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Technique {
+    // Emitters reached by camera or scattered rays
+    Bsdf,
+    // Next event estimation toward registered lights
+    Light,
+    // Rays that left the scene
+    Environment,
+    // Indirect light read from the irradiance cache
+    Cache,
+}
+
+impl Technique {
+    pub const ALL: [Technique; 4] = [
+        Technique::Bsdf,
+        Technique::Light,
+        Technique::Environment,
+        Technique::Cache,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Technique::Bsdf => "bsdf",
+            Technique::Light => "light",
+            Technique::Environment => "environment",
+            Technique::Cache => "cache",
+        }
+    }
+}
+
+// One sample's radiance split by light, with a trailing entry for emitters that
+// aren't registered lights and for cached indirect light, and by technique
+#[derive(Debug)]
+pub struct PathContributions<'a> {
+    pub(crate) per_light: &'a mut [Rgba],
+    pub(crate) per_technique: [Rgba; 4],
+}
+
+impl<'a> PathContributions<'a> {
+    pub(crate) fn new(light_count: usize, arena: &'a Bump) -> Self {
+        Self {
+            per_light: arena.alloc_slice_fill_copy(light_count, Rgba::ZERO),
+            per_technique: [Rgba::ZERO; 4],
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.per_light.iter_mut().for_each(|c| *c = Rgba::ZERO);
+        self.per_technique = [Rgba::ZERO; 4];
+    }
+
+    pub fn add(&mut self, light: usize, technique: Technique, contribution: Rgba) {
+        self.per_light[light] = self.per_light[light] + contribution;
+        let technique = &mut self.per_technique[technique as usize];
+        *technique = *technique + contribution;
+    }
+}
+
+// Per pixel mean and variance of what each technique contributes to a sample. The
+// means sum to the image. Variances are of a single sample, so a technique's noise in
+// the image falls as its variance over the sample count.
+#[derive(Debug, Clone)]
+pub struct TechniqueAovs {
+    mean: Vec<Image>,
+    variance: Vec<Image>,
+}
+
+impl TechniqueAovs {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            mean: vec![Image::new(width, height); Technique::ALL.len()],
+            variance: vec![Image::new(width, height); Technique::ALL.len()],
+        }
+    }
+
+    pub fn mean(&self, technique: Technique) -> &Image {
+        &self.mean[technique as usize]
+    }
+
+    pub fn variance(&self, technique: Technique) -> &Image {
+        &self.variance[technique as usize]
+    }
+
+    // Folds a row of new samples into row `span` of each technique, `samples` being
+    // the row's count before them
+    pub(crate) fn accumulate(
+        &mut self,
+        span: std::ops::Range<usize>,
+        rows: &[Vec<Float>],
+        samples: usize,
+    ) {
+        let n = samples as Float;
+        for ((mean, variance), row) in self.mean.iter_mut().zip(&mut self.variance).zip(rows) {
+            let means = &mut mean.data[span.clone()];
+            let variances = &mut variance.data[span.clone()];
+            for ((mean, variance), x) in means.iter_mut().zip(variances.iter_mut()).zip(row) {
+                if samples == 0 {
+                    *mean = *x;
+                    *variance = 0.0;
+                    continue;
+                }
+                // Welford's update, kept as the population variance
+                let delta = x - *mean;
+                *mean += delta / (n + 1.0);
+                *variance = (n * *variance + delta * (x - *mean)) / (n + 1.0);
+            }
+        }
+    }
+
+    // Image averages of each technique's mean and variance over the color channels
+    pub fn summary(&self) -> Vec<(Technique, Float, Float)> {
+        let average = |image: &Image| {
+            let sum: f64 = image
+                .data
+                .chunks(4)
+                .map(|c| (c[0] + c[1] + c[2]) as f64 / 3.0)
+                .sum();
+            (sum / (image.data.len() / 4).max(1) as f64) as Float
+        };
+        Technique::ALL
+            .iter()
+            .map(|t| (*t, average(self.mean(*t)), average(self.variance(*t))))
+            .collect()
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        let _ = writeln!(json, "{{");
+        let _ = writeln!(json, "  \"techniques\": [");
+        let summary = self.summary();
+        for (index, (technique, mean, variance)) in summary.iter().enumerate() {
+            let separator = if index + 1 < summary.len() { "," } else { "" };
+            let _ = writeln!(
+                json,
+                "    {{ \"name\": {}, \"mean\": {}, \"variance\": {} }}{}",
+                json_string(technique.name()),
+                mean,
+                variance,
+                separator
+            );
+        }
+        let _ = writeln!(json, "  ]");
+        let _ = write!(json, "}}");
+        json
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Background, Camera, Material, Mesh, ParallelRenderer, Primative, Scene, Texture, Vec3A,
+        WorldBuilder,
+    };
+
+    #[test]
+    fn technique_means_add_up_to_the_image() {
+        let mut world_builder = WorldBuilder::default();
+        let grey = world_builder.push_texture(Texture::Solid {
+            color: Rgba::splat(0.5),
+        });
+        let grey = world_builder.push_material(Material::Lambertian { albedo: grey });
+        let white = world_builder.push_texture(Texture::Solid { color: Rgba::ONE });
+        let light = world_builder.push_material(Material::DiffuseLight {
+            emit: white,
+            intensity: 4.0,
+        });
+        world_builder.push_hittable(Primative::sphere(Vec3A::ZERO, 1.0, grey));
+        let lamp = Mesh::new(
+            vec![
+                Vec3A::new(-1.0, 2.0, -1.0),
+                Vec3A::new(1.0, 2.0, -1.0),
+                Vec3A::new(1.0, 2.0, 1.0),
+                Vec3A::new(-1.0, 2.0, 1.0),
+            ],
+            vec![(0, 2, 1), (0, 3, 2)],
+            light,
+        )
+        .unwrap();
+        world_builder.push_area_light(lamp, true);
+        world_builder.set_background(Background::rtiow_sky());
+        let camera = Camera::new(4.0 * Vec3A::Z, Vec3A::ZERO, 90.0, 1.0, 0.0, 1.0);
+        let scene = Scene::new(world_builder.into(), camera);
+
+        let mut renderer = ParallelRenderer::new(16, 16, 4);
+        renderer.enable_technique_aovs();
+        for _ in 0..4 {
+            renderer.render(&scene);
+        }
+        let aovs = renderer.technique_aovs().unwrap();
+        for (i, value) in renderer.image().data.iter().enumerate() {
+            let sum: Float = Technique::ALL.iter().map(|t| aovs.mean(*t).data[i]).sum();
+            assert!((sum - value).abs() < 1e-3 * value.abs().max(1.0));
+        }
+
+        let summary = aovs.summary();
+        for (technique, mean, variance) in summary {
+            assert!(variance >= 0.0);
+            match technique {
+                Technique::Cache => assert_eq!((mean, variance), (0.0, 0.0)),
+                _ => assert!(mean > 0.0, "{:?}", technique),
+            }
+        }
+        assert!(aovs.to_json().contains("\"name\": \"light\""));
+    }
+}
@@ -1,10 +1,41 @@
-use crate::image::Rgba;
+use crate::image::{Image, Rgba};
 use crate::noise::*;
-use crate::{Float, Point3, TextureKey};
+use crate::{Float, Point3, TextureKey, Vec3A};
 
-use slotmap::SlotMap;
+use slotmap::{SecondaryMap, SlotMap};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Checker textures nested deeper than this render magenta, like missing textures,
+// which also stops reference cycles from recursing forever
+pub const MAX_TEXTURE_DEPTH: usize = 16;
+
+// Entries each thread keeps for `Texture::Cached` before starting over
+const TEXTURE_CACHE_CAPACITY: usize = 1 << 18;
+
+thread_local! {
+    static TEXTURE_CACHE: RefCell<HashMap<CacheKey, Rgba>> = RefCell::new(HashMap::new());
+}
+
+// The cached texture's id, the generation it was filled in and the cell
+type CacheKey = (u64, u64, [i64; 3]);
+
+static NEXT_CACHE_ID: AtomicU64 = AtomicU64::new(0);
+static CACHE_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+fn next_cache_id() -> u64 {
+    NEXT_CACHE_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+// Retires every thread's `Texture::Cached` entries. Call it after changing a texture
+// in place that a cached one wraps, as `apply_bindings` does.
+pub fn invalidate_texture_caches() {
+    CACHE_GENERATION.fetch_add(1, Ordering::Relaxed);
+}
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Texture {
     Solid {
         color: Rgba,
@@ -13,11 +44,145 @@ pub enum Texture {
         odd: TextureKey,
         even: TextureKey,
         scale: Float,
+        #[cfg_attr(feature = "serde", serde(default))]
+        space: TextureSpace,
     },
     Noise {
         noise: Box<Noise>,
         scale: Float,
+        #[cfg_attr(feature = "serde", serde(default))]
+        space: TextureSpace,
+    },
+    Image {
+        image: Image,
+        flipbook: Option<Flipbook>,
+    },
+    // Remembers `texture` per thread on a grid of `cell_size` cubes, each evaluated at
+    // its center, for expensive procedural textures hit again on every pass. Only
+    // suits textures that depend on position alone, in world space, which
+    // `WorldBuilder::validate` checks. Changing them in place needs
+    // `invalidate_texture_caches` afterwards.
+    Cached {
+        texture: TextureKey,
+        cell_size: Float,
+        // Tells apart cache entries of different textures, even across worlds
+        #[cfg_attr(feature = "serde", serde(skip, default = "next_cache_id"))]
+        id: u64,
     },
+    // `texture` looked up at uv moved by `transform`. Checkers hand the moved uv on to
+    // both their textures. Their squares, like noise, only move in `TextureSpace::Uv`.
+    Transformed {
+        texture: TextureKey,
+        transform: UvTransform,
+    },
+}
+
+// Where checkers and noise are laid out
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TextureSpace {
+    // Over the hit position, running through objects like a solid block of material
+    #[default]
+    World,
+    // Over the surface's uv as the point (u, v, 0), so they follow the surface
+    Uv,
+}
+
+// How texture coordinates outside [0, 1] are brought back in
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WrapMode {
+    #[default]
+    Repeat,
+    Clamp,
+    // Repeats with every other tile flipped, so neighbouring tiles meet at matching edges
+    Mirror,
+}
+
+impl WrapMode {
+    fn wrap(self, t: Float) -> Float {
+        // Kept short of 1, which images would otherwise wrap round to their first texel
+        const LAST: Float = 1.0 - Float::EPSILON;
+        match self {
+            Self::Repeat => t.rem_euclid(1.0).min(LAST),
+            Self::Clamp => t.clamp(0.0, LAST),
+            Self::Mirror => {
+                let t = t.rem_euclid(2.0);
+                match t > 1.0 {
+                    true => 2.0 - t,
+                    false => t.min(LAST),
+                }
+            }
+        }
+    }
+}
+
+// Scales uv about the origin, rotates it counterclockwise by `rotation` radians, offsets
+// it and wraps the result back into [0, 1]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UvTransform {
+    pub offset: (Float, Float),
+    pub scale: (Float, Float),
+    pub rotation: Float,
+    pub wrap: WrapMode,
+}
+
+impl Default for UvTransform {
+    fn default() -> Self {
+        Self {
+            offset: (0.0, 0.0),
+            scale: (1.0, 1.0),
+            rotation: 0.0,
+            wrap: WrapMode::Repeat,
+        }
+    }
+}
+
+impl UvTransform {
+    pub fn apply(&self, u: Float, v: Float) -> (Float, Float) {
+        let (u, v) = (u * self.scale.0, v * self.scale.1);
+        let (sin, cos) = self.rotation.sin_cos();
+        (
+            self.wrap.wrap(cos * u - sin * v + self.offset.0),
+            self.wrap.wrap(sin * u + cos * v + self.offset.1),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Flipbook {
+    pub columns: usize,
+    pub rows: usize,
+    pub frame_rate: Float,
+    pub frame: usize,
+}
+
+impl Flipbook {
+    pub fn new(columns: usize, rows: usize, frame_rate: Float) -> Self {
+        Self {
+            columns,
+            rows,
+            frame_rate,
+            frame: 0,
+        }
+    }
+
+    pub fn set_time(&mut self, time: Float) {
+        let frame_count = (self.columns * self.rows).max(1);
+        self.frame = (time * self.frame_rate).max(0.0) as usize % frame_count;
+    }
+
+    // Maps uv in [0, 1] onto the cell of the current frame
+    fn frame_uv(&self, u: Float, v: Float) -> (Float, Float) {
+        let column = self.frame % self.columns.max(1);
+        let row = self.frame / self.columns.max(1);
+        (
+            (column as Float + u) / self.columns.max(1) as Float,
+            1.0 - (row as Float + 1.0 - v) / self.rows.max(1) as Float,
+        )
+    }
 }
 
 impl Default for Texture {
@@ -29,32 +194,465 @@ impl Default for Texture {
 }
 
 impl Texture {
+    pub fn cached(texture: TextureKey, cell_size: Float) -> Self {
+        Self::Cached {
+            texture,
+            cell_size,
+            id: next_cache_id(),
+        }
+    }
+
     pub fn value(
         &self,
         u: Float,
         v: Float,
         p: Point3,
         texture_map: &SlotMap<TextureKey, Texture>,
+    ) -> Rgba {
+        self.value_within(u, v, p, texture_map, MAX_TEXTURE_DEPTH)
+    }
+
+    // Follows at most `max_depth` references to other textures
+    pub fn value_within(
+        &self,
+        u: Float,
+        v: Float,
+        p: Point3,
+        texture_map: &SlotMap<TextureKey, Texture>,
+        max_depth: usize,
     ) -> Rgba {
         match self {
             Self::Solid { color } => *color,
-            Self::Checker { odd, even, scale } => {
-                let sines = (scale * p.x).sin() * (scale * p.y).sin() * (scale * p.z).sin();
-                if sines < 0.0 {
-                    match texture_map.get(*odd) {
-                        Some(texture) => texture.value(u, v, p, texture_map),
-                        None => Rgba::new(1.0, 0.0, 1.0, 1.0),
+            Self::Checker {
+                odd,
+                even,
+                scale,
+                space,
+            } => {
+                let sines = match space {
+                    TextureSpace::World => {
+                        (scale * p.x).sin() * (scale * p.y).sin() * (scale * p.z).sin()
                     }
-                } else {
-                    match texture_map.get(*even) {
-                        Some(texture) => texture.value(u, v, p, texture_map),
-                        None => Rgba::new(1.0, 0.0, 1.0, 1.0),
+                    TextureSpace::Uv => (scale * u).sin() * (scale * v).sin(),
+                };
+                let key = if sines < 0.0 { odd } else { even };
+                match texture_map.get(*key) {
+                    Some(texture) if max_depth > 0 => {
+                        texture.value_within(u, v, p, texture_map, max_depth - 1)
                     }
+                    _ => Rgba::new(1.0, 0.0, 1.0, 1.0),
                 }
             }
-            Self::Noise { noise, scale } => {
-                Rgba::ONE * 0.5 * (1.0 + (scale * p.z + 10.0 * noise.sample(p)).sin())
+            Self::Noise {
+                noise,
+                scale,
+                space,
+            } => {
+                // Veins run across z, or across v over uv
+                let (p, across) = match space {
+                    TextureSpace::World => (p, p.z),
+                    TextureSpace::Uv => (Point3::new(u, v, 0.0), v),
+                };
+                Rgba::ONE * 0.5 * (1.0 + (scale * across + 10.0 * noise.sample(p)).sin())
+            }
+            Self::Image { image, flipbook } => {
+                let (u, v) = (u.rem_euclid(1.0), v.rem_euclid(1.0));
+                let (u, v) = match flipbook {
+                    Some(flipbook) => flipbook.frame_uv(u, v),
+                    None => (u, v),
+                };
+                image.sample(u, v)
+            }
+            Self::Cached {
+                texture,
+                cell_size,
+                id,
+            } => {
+                let cell = (p / *cell_size).floor();
+                let generation = CACHE_GENERATION.load(Ordering::Relaxed);
+                let key = (
+                    *id,
+                    generation,
+                    [cell.x as i64, cell.y as i64, cell.z as i64],
+                );
+                if let Some(color) = TEXTURE_CACHE.with(|cache| cache.borrow().get(&key).copied()) {
+                    return color;
+                }
+
+                let center = (cell + Vec3A::splat(0.5)) * *cell_size;
+                let color = match texture_map.get(*texture) {
+                    Some(texture) if max_depth > 0 => {
+                        texture.value_within(u, v, center, texture_map, max_depth - 1)
+                    }
+                    _ => Rgba::new(1.0, 0.0, 1.0, 1.0),
+                };
+                TEXTURE_CACHE.with(|cache| {
+                    let mut cache = cache.borrow_mut();
+                    if cache.len() >= TEXTURE_CACHE_CAPACITY {
+                        cache.clear();
+                    }
+                    cache.insert(key, color);
+                });
+                color
+            }
+            Self::Transformed { texture, transform } => {
+                let (u, v) = transform.apply(u, v);
+                match texture_map.get(*texture) {
+                    Some(texture) if max_depth > 0 => {
+                        texture.value_within(u, v, p, texture_map, max_depth - 1)
+                    }
+                    _ => Rgba::new(1.0, 0.0, 1.0, 1.0),
+                }
+            }
+        }
+    }
+
+    // Whether the value changes with uv rather than position alone. Follows at most
+    // `max_depth` references, counting what's past them as depending on uv.
+    fn reads_uv(&self, texture_map: &SlotMap<TextureKey, Texture>, max_depth: usize) -> bool {
+        match self {
+            Self::Solid { .. } => false,
+            Self::Checker {
+                space: TextureSpace::Uv,
+                ..
+            }
+            | Self::Noise {
+                space: TextureSpace::Uv,
+                ..
+            }
+            | Self::Image { .. }
+            | Self::Transformed { .. } => true,
+            Self::Noise { .. } => false,
+            Self::Checker { .. } | Self::Cached { .. } => {
+                self.references()
+                    .into_iter()
+                    .any(|key| match texture_map.get(key) {
+                        Some(texture) if max_depth > 0 => {
+                            texture.reads_uv(texture_map, max_depth - 1)
+                        }
+                        Some(_) => true,
+                        None => false,
+                    })
+            }
+        }
+    }
+
+    // Textures this one looks up by key
+    fn references(&self) -> Vec<TextureKey> {
+        match self {
+            Self::Checker { odd, even, .. } => vec![*odd, *even],
+            Self::Cached { texture, .. } | Self::Transformed { texture, .. } => vec![*texture],
+            _ => Vec::new(),
+        }
+    }
+}
+
+// Cached textures wrapping one that reads uv, which a cache keyed on position can't hold
+pub(crate) fn uv_cached_textures(texture_map: &SlotMap<TextureKey, Texture>) -> usize {
+    texture_map
+        .values()
+        .filter(|texture| match texture {
+            Texture::Cached { .. } => texture.reads_uv(texture_map, MAX_TEXTURE_DEPTH),
+            _ => false,
+        })
+        .count()
+}
+
+// The keys of textures that reference each other in a loop, in order, if any do
+pub fn texture_cycle(texture_map: &SlotMap<TextureKey, Texture>) -> Option<Vec<TextureKey>> {
+    // Textures on the path being walked are false, ones known to be free of cycles true
+    let mut visited = SecondaryMap::new();
+    let mut path = Vec::new();
+    texture_map
+        .keys()
+        .find_map(|key| find_cycle(key, texture_map, &mut visited, &mut path))
+}
+
+fn find_cycle(
+    key: TextureKey,
+    texture_map: &SlotMap<TextureKey, Texture>,
+    visited: &mut SecondaryMap<TextureKey, bool>,
+    path: &mut Vec<TextureKey>,
+) -> Option<Vec<TextureKey>> {
+    match visited.get(key) {
+        Some(true) => return None,
+        Some(false) => {
+            let start = path.iter().position(|k| *k == key)?;
+            return Some(path[start..].to_vec());
+        }
+        None => {}
+    }
+
+    visited.insert(key, false);
+    path.push(key);
+    let references = texture_map.get(key).map(Texture::references);
+    for child in references.unwrap_or_default() {
+        if let Some(cycle) = find_cycle(child, texture_map, visited, path) {
+            return Some(cycle);
+        }
+    }
+    path.pop();
+    visited.insert(key, true);
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn flipbook_selects_frame_cell() {
+        let mut image = Image::new(2, 2);
+        image.set_pixel_color(0, 0, Rgba::splat(0.0));
+        image.set_pixel_color(1, 0, Rgba::splat(1.0));
+        image.set_pixel_color(0, 1, Rgba::splat(2.0));
+        image.set_pixel_color(1, 1, Rgba::splat(3.0));
+
+        let mut flipbook = Flipbook::new(2, 2, 1.0);
+        let texture_map = SlotMap::with_key();
+
+        for frame in 0..4 {
+            flipbook.set_time(frame as Float);
+            let texture = Texture::Image {
+                image: image.clone(),
+                flipbook: Some(flipbook),
+            };
+            let color = texture.value(0.5, 0.5, Point3::ZERO, &texture_map);
+            assert_eq!(color, Rgba::splat(frame as Float));
+        }
+    }
+
+    #[test]
+    fn uv_transforms_scale_rotate_and_wrap_lookups() {
+        // Red and green along the top, blue and white along the bottom
+        let mut image = Image::new(2, 2);
+        image.set_pixel_color(0, 0, Rgba::new(1.0, 0.0, 0.0, 1.0));
+        image.set_pixel_color(1, 0, Rgba::new(0.0, 1.0, 0.0, 1.0));
+        image.set_pixel_color(0, 1, Rgba::new(0.0, 0.0, 1.0, 1.0));
+        image.set_pixel_color(1, 1, Rgba::ONE);
+        let mut texture_map = SlotMap::with_key();
+        let image = texture_map.insert(Texture::Image {
+            image,
+            flipbook: None,
+        });
+        let lookup = |transform: UvTransform, u: Float, v: Float| {
+            let texture = Texture::Transformed {
+                texture: image,
+                transform,
+            };
+            texture.value(u, v, Point3::ZERO, &texture_map)
+        };
+        let (red, green) = (Rgba::new(1.0, 0.0, 0.0, 1.0), Rgba::new(0.0, 1.0, 0.0, 1.0));
+        let blue = Rgba::new(0.0, 0.0, 1.0, 1.0);
+
+        assert_eq!(lookup(UvTransform::default(), 0.25, 0.75), red);
+        // Tiled twice across, so 0.3 lands in the second tile's right half
+        let tiled = UvTransform {
+            scale: (2.0, 1.0),
+            ..UvTransform::default()
+        };
+        assert_eq!(lookup(tiled, 0.3, 0.75), green);
+        let clamped = UvTransform {
+            wrap: WrapMode::Clamp,
+            ..tiled
+        };
+        assert_eq!(lookup(clamped, 0.8, 0.75), green);
+        let mirrored = UvTransform {
+            wrap: WrapMode::Mirror,
+            ..tiled
+        };
+        assert_eq!(lookup(mirrored, 0.8, 0.75), red);
+
+        // Half a turn about the origin, moved back over the image, swaps opposite corners
+        let turned = UvTransform {
+            offset: (1.0, 1.0),
+            rotation: crate::PI,
+            ..UvTransform::default()
+        };
+        assert_eq!(lookup(turned, 0.25, 0.75), Rgba::ONE);
+        assert_eq!(lookup(turned, 0.75, 0.75), blue);
+
+        // A solid color has no uv to move
+        let solid = texture_map.insert(Texture::Solid { color: blue });
+        let texture = Texture::Transformed {
+            texture: solid,
+            transform: turned,
+        };
+        assert_eq!(texture.value(0.1, 0.9, Point3::ZERO, &texture_map), blue);
+    }
+
+    #[test]
+    fn transforms_move_uv_space_checkers_and_noise() {
+        let mut texture_map = SlotMap::with_key();
+        let black = texture_map.insert(Texture::Solid { color: Rgba::ZERO });
+        let white = texture_map.insert(Texture::Solid { color: Rgba::ONE });
+        let noise = Box::new(Noise::turbulent(&mut StdRng::seed_from_u64(1), 7));
+        let mut patterns = Vec::new();
+        for space in [TextureSpace::World, TextureSpace::Uv] {
+            // Two squares across and two down over uv
+            let checker = Texture::Checker {
+                odd: black,
+                even: white,
+                scale: 2.0 * crate::PI,
+                space,
+            };
+            let noise = Texture::Noise {
+                noise: noise.clone(),
+                scale: 4.0,
+                space,
+            };
+            patterns.push((space, texture_map.insert(checker)));
+            patterns.push((space, texture_map.insert(noise)));
+        }
+        // Half a square over
+        let shift = UvTransform {
+            offset: (0.5, 0.0),
+            ..UvTransform::default()
+        };
+
+        let p = Point3::new(0.1, 0.2, 0.3);
+        for (space, pattern) in patterns {
+            let moved = Texture::Transformed {
+                texture: pattern,
+                transform: shift,
+            };
+            let (mut changed, mut same) = (0, 0);
+            for k in 0..64 {
+                let (u, v) = ((k % 8) as Float / 8.0 + 0.01, (k / 8) as Float / 8.0 + 0.01);
+                let color = texture_map[pattern].value(u, v, p, &texture_map);
+                let moved = moved.value(u, v, p, &texture_map);
+                match color == moved {
+                    true => same += 1,
+                    false => changed += 1,
+                }
+                if space == TextureSpace::Uv {
+                    let u = (u + 0.5).rem_euclid(1.0);
+                    let shifted = texture_map[pattern].value(u, v, p, &texture_map);
+                    assert_eq!(moved, shifted);
+                }
+            }
+            match space {
+                TextureSpace::World => assert_eq!(changed, 0),
+                TextureSpace::Uv => assert!(changed > 48, "{} {}", changed, same),
             }
         }
     }
+
+    #[test]
+    fn checker_cycles_are_found_and_cut_off() {
+        let mut texture_map = SlotMap::with_key();
+        let solid = texture_map.insert(Texture::Solid { color: Rgba::ONE });
+        let a = texture_map.insert(Texture::default());
+        let b = texture_map.insert(Texture::Checker {
+            odd: a,
+            even: a,
+            scale: 1.0,
+            space: TextureSpace::World,
+        });
+        texture_map[a] = Texture::Checker {
+            odd: solid,
+            even: b,
+            scale: 1.0,
+            space: TextureSpace::World,
+        };
+
+        let cycle = texture_cycle(&texture_map).unwrap();
+        assert_eq!(cycle.len(), 2);
+        assert!(cycle.contains(&a) && cycle.contains(&b));
+        let color = texture_map[a].value(0.0, 0.0, Point3::ONE, &texture_map);
+        assert_eq!(color, Rgba::new(1.0, 0.0, 1.0, 1.0));
+
+        texture_map[a] = Texture::Checker {
+            odd: solid,
+            even: solid,
+            scale: 1.0,
+            space: TextureSpace::World,
+        };
+        assert!(texture_cycle(&texture_map).is_none());
+        let color = texture_map[b].value(0.0, 0.0, Point3::ONE, &texture_map);
+        assert_eq!(color, Rgba::ONE);
+
+        // Keys from another builder can point forward, closing a loop on build
+        let mut world_builder = crate::WorldBuilder::default();
+        let mut other = crate::WorldBuilder::default();
+        other.push_texture(Texture::default());
+        let second = other.push_texture(Texture::default());
+        let first = world_builder.push_texture(Texture::Checker {
+            odd: second,
+            even: second,
+            scale: 1.0,
+            space: TextureSpace::World,
+        });
+        let looped = world_builder.push_texture(Texture::Checker {
+            odd: first,
+            even: first,
+            scale: 1.0,
+            space: TextureSpace::World,
+        });
+        assert_eq!(looped, second);
+        assert!(world_builder.build().is_err());
+    }
+
+    #[test]
+    fn cached_textures_reuse_the_cell_center_value() {
+        let mut texture_map = SlotMap::with_key();
+        let noise = texture_map.insert(Texture::Noise {
+            noise: Box::new(Noise::turbulent(&mut StdRng::seed_from_u64(1), 7)),
+            scale: 4.0,
+            space: TextureSpace::World,
+        });
+        let cached = texture_map.insert(Texture::cached(noise, 0.5));
+
+        let center = texture_map[noise].value(0.0, 0.0, Point3::splat(0.25), &texture_map);
+        for p in [
+            Point3::splat(0.01),
+            Point3::splat(0.25),
+            Point3::splat(0.49),
+        ]
+        .iter()
+        {
+            let color = texture_map[cached].value(0.0, 0.0, *p, &texture_map);
+            assert_eq!(color, center);
+        }
+        let next = texture_map[noise].value(0.0, 0.0, Point3::splat(0.75), &texture_map);
+        let color = texture_map[cached].value(0.0, 0.0, Point3::splat(0.6), &texture_map);
+        assert_eq!(color, next);
+        assert_eq!(uv_cached_textures(&texture_map), 0);
+    }
+
+    #[test]
+    fn cached_textures_see_changes_once_invalidated() {
+        let mut texture_map = SlotMap::with_key();
+        let solid = texture_map.insert(Texture::Solid { color: Rgba::ONE });
+        let cached = texture_map.insert(Texture::cached(solid, 1.0));
+        let value = |texture_map: &SlotMap<TextureKey, Texture>| {
+            texture_map[cached].value(0.0, 0.0, Point3::splat(0.5), texture_map)
+        };
+        assert_eq!(value(&texture_map), Rgba::ONE);
+
+        texture_map[solid] = Texture::Solid { color: Rgba::ZERO };
+        invalidate_texture_caches();
+        assert_eq!(value(&texture_map), Rgba::ZERO);
+    }
+
+    #[test]
+    fn cached_textures_over_uv_are_rejected() {
+        let mut texture_map = SlotMap::with_key();
+        let solid = texture_map.insert(Texture::default());
+        let checker = texture_map.insert(Texture::Checker {
+            odd: solid,
+            even: solid,
+            scale: 10.0,
+            space: TextureSpace::Uv,
+        });
+        let moved = texture_map.insert(Texture::Transformed {
+            texture: solid,
+            transform: UvTransform::default(),
+        });
+        texture_map.insert(Texture::cached(checker, 0.5));
+        texture_map.insert(Texture::cached(moved, 0.5));
+        texture_map.insert(Texture::cached(solid, 0.5));
+        assert_eq!(uv_cached_textures(&texture_map), 2);
+    }
 }
@@ -0,0 +1,212 @@
+// Small preview renders for asset browsers
+
+use crate::scenes::quad;
+use crate::{
+    AssetResolver, Camera, Float, Image, Material, MaterialKey, Mesh, ObjOptions, ParallelRenderer,
+    Point3, Primative, Rgba, Scene, Texture, TextureKey, Transform, Vec3A, WorldBuilder,
+};
+
+use boxtree::Bounded;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub const THUMBNAIL_BUDGET: Duration = Duration::from_millis(250);
+
+// Passes stop here even with budget to spare
+const MAX_PASSES: usize = 256;
+const MAX_DEPTH: usize = 6;
+
+pub enum ThumbnailAsset {
+    // An OBJ file, read through `AssetResolver::new`, shown on a pedestal
+    Mesh(PathBuf),
+    // A material, built into the preview world, shown on a ball
+    Material(Box<dyn FnOnce(&mut WorldBuilder) -> MaterialKey>),
+    // A texture, built into the preview world, shown flat and unlit as a swatch
+    Texture(Box<dyn FnOnce(&mut WorldBuilder) -> TextureKey>),
+}
+
+impl From<PathBuf> for ThumbnailAsset {
+    fn from(path: PathBuf) -> Self {
+        Self::Mesh(path)
+    }
+}
+
+impl From<Material> for ThumbnailAsset {
+    fn from(material: Material) -> Self {
+        Self::Material(Box::new(move |world_builder| {
+            world_builder.push_material(material)
+        }))
+    }
+}
+
+impl From<Texture> for ThumbnailAsset {
+    fn from(texture: Texture) -> Self {
+        Self::Texture(Box::new(move |world_builder| {
+            world_builder.push_texture(texture)
+        }))
+    }
+}
+
+pub fn render_asset(asset: impl Into<ThumbnailAsset>, size: usize) -> io::Result<Image> {
+    render_asset_within(asset, size, THUMBNAIL_BUDGET)
+}
+
+// Renders passes of a `size` square preview until `budget` runs out. At least one
+// pass is always rendered, so tiny budgets still return an image. Meshes that fail
+// to load are an error.
+pub fn render_asset_within(
+    asset: impl Into<ThumbnailAsset>,
+    size: usize,
+    budget: Duration,
+) -> io::Result<Image> {
+    let start = Instant::now();
+    let scene = match asset.into() {
+        ThumbnailAsset::Mesh(path) => mesh_scene(path)?,
+        ThumbnailAsset::Material(material) => material_scene(material),
+        ThumbnailAsset::Texture(texture) => swatch_scene(texture),
+    };
+
+    let mut renderer = ParallelRenderer::new(size, size, MAX_DEPTH);
+    for _ in 0..MAX_PASSES {
+        renderer.render(&scene);
+        if start.elapsed() >= budget {
+            break;
+        }
+    }
+    Ok(renderer.display_image(&scene))
+}
+
+// Grey studio with a soft overhead key light and a pedestal whose top is at the
+// origin, seen from the front and slightly above
+fn studio(world_builder: &mut WorldBuilder) -> Camera {
+    let grey = world_builder.push_texture(Texture::Solid {
+        color: Rgba::new(0.5, 0.5, 0.5, 1.0),
+    });
+    let grey = world_builder.push_material(Material::Lambertian { albedo: grey });
+    world_builder.push_hittable(Primative::cuboid(
+        Vec3A::new(-1.2, -0.3, -1.2),
+        Vec3A::new(1.2, 0.0, 1.2),
+        grey,
+    ));
+
+    let white = world_builder.push_texture(Texture::Solid { color: Rgba::ONE });
+    let key = world_builder.push_material(Material::DiffuseLight {
+        emit: white,
+        intensity: 6.0,
+    });
+    world_builder.push_area_light(
+        quad(
+            [
+                Vec3A::new(-1.0, 4.0, -0.5),
+                Vec3A::new(1.0, 4.0, -0.5),
+                Vec3A::new(1.0, 4.0, 1.5),
+                Vec3A::new(-1.0, 4.0, 1.5),
+            ],
+            key,
+        ),
+        true,
+    );
+
+    let ambient = world_builder.push_material(Material::DiffuseLight {
+        emit: white,
+        intensity: 0.3,
+    });
+    world_builder.push_hittable(Primative::sphere(Vec3A::ZERO, 50.0, ambient));
+
+    Camera::new(
+        Vec3A::new(0.0, 1.6, 4.2),
+        Vec3A::new(0.0, 0.6, 0.0),
+        30.0,
+        1.0,
+        0.0,
+        1.0,
+    )
+}
+
+// Scaled to stand 1.2 units tall, or wide, on the pedestal
+fn mesh_scene(path: PathBuf) -> io::Result<Scene> {
+    let mut world_builder = WorldBuilder::default();
+    let camera = studio(&mut world_builder);
+
+    let albedo = world_builder.push_texture(Texture::Solid {
+        color: Rgba::new(0.8, 0.8, 0.8, 1.0),
+    });
+    let material = world_builder.push_material(Material::Lambertian { albedo });
+    // Downloaded models often have mixed winding
+    let options = ObjOptions {
+        orient_outward: true,
+        ..ObjOptions::default()
+    };
+    let mesh = Mesh::from_obj(path, &AssetResolver::new(), material, options)?;
+
+    let bounds = mesh.bounds();
+    let scale = 1.2 / (bounds.max - bounds.min).max_element().max(Float::EPSILON);
+    let base = Point3::new(
+        0.5 * (bounds.min.x + bounds.max.x),
+        bounds.min.y,
+        0.5 * (bounds.min.z + bounds.max.z),
+    );
+    world_builder.push_hittable(Primative::instance(
+        Arc::new(Primative::Mesh(mesh)),
+        Transform::new(-scale * base, glam::Quat::IDENTITY, scale),
+    ));
+
+    Ok(Scene::new(world_builder.into(), camera))
+}
+
+fn material_scene(material: Box<dyn FnOnce(&mut WorldBuilder) -> MaterialKey>) -> Scene {
+    let mut world_builder = WorldBuilder::default();
+    let camera = studio(&mut world_builder);
+    let material = material(&mut world_builder);
+    world_builder.push_hittable(Primative::sphere(Vec3A::new(0.0, 0.6, 0.0), 0.6, material));
+    Scene::new(world_builder.into(), camera)
+}
+
+// The texture as an emitter, so the swatch shows its colors exactly
+fn swatch_scene(texture: Box<dyn FnOnce(&mut WorldBuilder) -> TextureKey>) -> Scene {
+    let mut world_builder = WorldBuilder::default();
+    let emit = texture(&mut world_builder);
+    let material = world_builder.push_material(Material::DiffuseLight {
+        emit,
+        intensity: 1.0,
+    });
+    world_builder.push_hittable(Primative::quad(
+        Vec3A::new(-1.0, -1.0, 0.0),
+        Vec3A::new(2.0, 0.0, 0.0),
+        Vec3A::new(0.0, 2.0, 0.0),
+        material,
+    ));
+
+    // Just wide enough to fill the frame with the quad
+    let camera = Camera::new(Vec3A::new(0.0, 0.0, 10.0), Vec3A::ZERO, 11.0, 1.0, 0.0, 1.0);
+    Scene::new(world_builder.into(), camera)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DisplayTransform;
+
+    #[test]
+    fn swatch_shows_the_texture() {
+        let color = Rgba::new(0.2, 0.6, 0.4, 1.0);
+        let image = render_asset_within(Texture::Solid { color }, 8, Duration::ZERO).unwrap();
+        assert_eq!((image.width, image.height), (8, 8));
+
+        // Renderers store linear light, encoded for display by the post pipeline
+        let expected = DisplayTransform::default().apply(color).to_array();
+        let center = image.get_pixel_color(4, 4).to_array();
+        for c in 0..3 {
+            assert!((center[c] - expected[c]).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn missing_meshes_are_an_error() {
+        let path = std::env::temp_dir().join("razz_thumbnail_missing.obj");
+        let result = render_asset_within(path, 8, Duration::ZERO);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,320 @@
+use crate::image::{Image, Rgba};
+use crate::Float;
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const BYTES_PER_PIXEL: usize = 4 * std::mem::size_of::<Float>();
+
+#[derive(Debug)]
+struct CachedTile {
+    index: usize,
+    image: Image,
+    dirty: bool,
+    last_used: u64,
+}
+
+// An image too large to hold in memory. Pixels live in square tiles in a file on disk,
+// with only the most recently used tiles kept in memory. Tiles that were never written
+// read back as zero.
+#[derive(Debug)]
+pub struct TiledImage {
+    pub width: usize,
+    pub height: usize,
+    tile_size: usize,
+    tiles_x: usize,
+    file: File,
+    cache: Vec<CachedTile>,
+    cache_capacity: usize,
+    clock: u64,
+}
+
+impl TiledImage {
+    // Creates or truncates the backing file at `path`. The cache should hold at least
+    // one row of tiles for row-ordered access to stay cheap.
+    pub fn create(
+        path: impl AsRef<Path>,
+        width: usize,
+        height: usize,
+        tile_size: usize,
+        cache_capacity: usize,
+    ) -> io::Result<Self> {
+        let tile_size = tile_size.max(1);
+        let tiles_x = width.div_ceil(tile_size);
+        let tiles_y = height.div_ceil(tile_size);
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        // Edge tiles are padded to full size so every tile sits at a fixed offset
+        file.set_len((tiles_x * tiles_y * tile_size * tile_size * BYTES_PER_PIXEL) as u64)?;
+
+        Ok(Self {
+            width,
+            height,
+            tile_size,
+            tiles_x,
+            file,
+            cache: Vec::new(),
+            cache_capacity: cache_capacity.max(1),
+            clock: 0,
+        })
+    }
+
+    // An error unless the image is `width` by `height`, for renderers writing to it
+    pub fn check_size(&self, width: usize, height: usize) -> io::Result<()> {
+        match (width, height) == (self.width, self.height) {
+            true => Ok(()),
+            false => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "a {}x{} render doesn't fit a {}x{} image",
+                    width, height, self.width, self.height
+                ),
+            )),
+        }
+    }
+
+    pub fn tile_size(&self) -> usize {
+        self.tile_size
+    }
+
+    pub fn get_pixel_color(&mut self, x: usize, y: usize) -> io::Result<Rgba> {
+        let (tile, local_x, local_y) = self.locate(x, y);
+        Ok(self.tile(tile)?.image.get_pixel_color(local_x, local_y))
+    }
+
+    pub fn set_pixel_color(&mut self, x: usize, y: usize, color: Rgba) -> io::Result<()> {
+        let (tile, local_x, local_y) = self.locate(x, y);
+        let tile = self.tile(tile)?;
+        tile.image.set_pixel_color(local_x, local_y, color);
+        tile.dirty = true;
+        Ok(())
+    }
+
+    // Copies `region` in with its top left corner at (x, y)
+    pub fn write_region(&mut self, x: usize, y: usize, region: &Image) -> io::Result<()> {
+        self.blend_region(x, y, region, |old, new| old.copy_from_slice(new))
+    }
+
+    // Running average of `region` into the pixels at (x, y), which already hold
+    // `num_samples` passes
+    pub fn accumulate_region(
+        &mut self,
+        x: usize,
+        y: usize,
+        region: &Image,
+        num_samples: usize,
+    ) -> io::Result<()> {
+        let n = num_samples as Float;
+        self.blend_region(x, y, region, |old, new| {
+            old.iter_mut()
+                .zip(new)
+                .for_each(|(old, new)| *old = (*old * n + new) / (n + 1.0))
+        })
+    }
+
+    pub fn read_region(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> io::Result<Image> {
+        let mut region = Image::new(width, height);
+        if width == 0 {
+            return Ok(region);
+        }
+        let rows = height.min(self.height.saturating_sub(y));
+        for (row_index, row) in region.data.chunks_mut(width * 4).take(rows).enumerate() {
+            self.visit_row(x, y + row_index, width, |offset, tile_row| {
+                row[offset..offset + tile_row.len()].copy_from_slice(tile_row);
+            })?;
+        }
+        Ok(region)
+    }
+
+    // Writes every modified tile in the cache back to disk
+    pub fn flush(&mut self) -> io::Result<()> {
+        for index in 0..self.cache.len() {
+            self.write_back(index)?;
+        }
+        self.file.flush()
+    }
+
+    fn blend_region(
+        &mut self,
+        x: usize,
+        y: usize,
+        region: &Image,
+        blend: impl Fn(&mut [Float], &[Float]),
+    ) -> io::Result<()> {
+        // Anything past the right or bottom edge is dropped
+        if region.width == 0 {
+            return Ok(());
+        }
+        let rows = region.height.min(self.height.saturating_sub(y));
+        let end = (x + region.width).min(self.width);
+        for (row_index, row) in region.data.chunks(region.width * 4).take(rows).enumerate() {
+            let tile_size = self.tile_size;
+            let mut column = x;
+            while column < end {
+                let (tile, local_x, local_y) = self.locate(column, y + row_index);
+                let span = (tile_size - local_x).min(end - column);
+                let offset = (column - x) * 4;
+
+                let tile = self.tile(tile)?;
+                let start = (local_y * tile_size + local_x) * 4;
+                blend(
+                    &mut tile.image.data[start..start + span * 4],
+                    &row[offset..offset + span * 4],
+                );
+                tile.dirty = true;
+                column += span;
+            }
+        }
+        Ok(())
+    }
+
+    // Calls `f` with each tile's slice of a row and its offset in floats from `x`
+    fn visit_row(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        mut f: impl FnMut(usize, &[Float]),
+    ) -> io::Result<()> {
+        let tile_size = self.tile_size;
+        let end = (x + width).min(self.width);
+        let mut column = x;
+        while column < end {
+            let (tile, local_x, local_y) = self.locate(column, y);
+            let span = (tile_size - local_x).min(end - column);
+
+            let tile = self.tile(tile)?;
+            let start = (local_y * tile_size + local_x) * 4;
+            f((column - x) * 4, &tile.image.data[start..start + span * 4]);
+            column += span;
+        }
+        Ok(())
+    }
+
+    fn locate(&self, x: usize, y: usize) -> (usize, usize, usize) {
+        assert!(x < self.width && y < self.height, "pixel out of bounds");
+        let (tile_x, tile_y) = (x / self.tile_size, y / self.tile_size);
+        (
+            tile_y * self.tiles_x + tile_x,
+            x % self.tile_size,
+            y % self.tile_size,
+        )
+    }
+
+    fn tile(&mut self, index: usize) -> io::Result<&mut CachedTile> {
+        self.clock += 1;
+        let slot = match self.cache.iter().position(|t| t.index == index) {
+            Some(slot) => slot,
+            None => self.load(index)?,
+        };
+        let tile = &mut self.cache[slot];
+        tile.last_used = self.clock;
+        Ok(tile)
+    }
+
+    fn load(&mut self, index: usize) -> io::Result<usize> {
+        let floats = self.tile_size * self.tile_size * 4;
+        let mut bytes = vec![0u8; floats * std::mem::size_of::<Float>()];
+        self.file.seek(SeekFrom::Start(self.offset(index)))?;
+        self.file.read_exact(&mut bytes)?;
+        let data = bytes
+            .chunks_exact(std::mem::size_of::<Float>())
+            .map(|b| Float::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        let tile = CachedTile {
+            index,
+            image: Image::from_vec(self.tile_size, self.tile_size, data),
+            dirty: false,
+            last_used: self.clock,
+        };
+
+        if self.cache.len() < self.cache_capacity {
+            self.cache.push(tile);
+            return Ok(self.cache.len() - 1);
+        }
+
+        let (slot, _) = self
+            .cache
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, t)| t.last_used)
+            .unwrap();
+        self.write_back(slot)?;
+        self.cache[slot] = tile;
+        Ok(slot)
+    }
+
+    fn write_back(&mut self, slot: usize) -> io::Result<()> {
+        if !self.cache[slot].dirty {
+            return Ok(());
+        }
+        let offset = self.offset(self.cache[slot].index);
+        let bytes: Vec<u8> = self.cache[slot]
+            .image
+            .data
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(&bytes)?;
+        self.cache[slot].dirty = false;
+        Ok(())
+    }
+
+    fn offset(&self, index: usize) -> u64 {
+        (index * self.tile_size * self.tile_size * BYTES_PER_PIXEL) as u64
+    }
+}
+
+impl Drop for TiledImage {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_survive_eviction() {
+        let path = std::env::temp_dir().join(format!("razz_tiled_{}.bin", std::process::id()));
+        let (width, height) = (37, 23);
+        let mut source = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                source.set_pixel_color(x, y, Rgba::new(x as Float, y as Float, 0.5, 1.0));
+            }
+        }
+
+        // Single tile cache forces every tile to be written out and read back
+        let mut tiled = TiledImage::create(&path, width, height, 8, 1).unwrap();
+        tiled.write_region(0, 0, &source).unwrap();
+        let region = tiled.read_region(3, 5, 20, 11).unwrap();
+        // Empty regions read and write nothing
+        tiled.write_region(3, 5, &Image::new(0, 4)).unwrap();
+        assert!(tiled.read_region(3, 5, 0, 4).unwrap().data.is_empty());
+        std::fs::remove_file(&path).unwrap();
+
+        for y in 0..11 {
+            for x in 0..20 {
+                assert_eq!(
+                    region.get_pixel_color(x, y).to_array(),
+                    source.get_pixel_color(x + 3, y + 5).to_array()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,390 @@
+use crate::packet::{PacketBvh, RayPacket};
+use crate::shape::{HitRecord, Primative};
+use crate::{Float, MediumRng, Ray3A};
+
+use boxtree::{Bounded, Bounds3A};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+
+// Share of counted rays a subtree must be hit by to be visited first
+const HOT_SHARE: Float = 1.0 / 16.0;
+
+#[derive(Debug)]
+struct TraversalProfile {
+    // Closest hits per primitive
+    hits: Vec<AtomicU32>,
+    rays: AtomicU64,
+    ray_budget: u64,
+    // The tree ordered by the counts, set once `ray_budget` rays have been counted
+    ordered: OnceLock<PacketBvh>,
+}
+
+impl TraversalProfile {
+    fn prioritized(&self, tree: &PacketBvh) -> PacketBvh {
+        let rays = self.rays.load(Ordering::Relaxed) as Float;
+        let hits: Vec<u32> = self
+            .hits
+            .iter()
+            .map(|h| h.load(Ordering::Relaxed))
+            .collect();
+        let mut tree = tree.clone();
+        tree.prioritize(&hits, (HOT_SHARE * rays).ceil() as u64);
+        tree
+    }
+}
+
+// The world's top-level BVH. Single rays go down it as packets of one, so each
+// primitive is handed the ray's `MediumRng` along with it. With a profile enabled it
+// counts which primitives rays hit, and after `ray_budget` rays swaps in a copy of the
+// tree that visits the most hit subtrees first. A hit found early shortens the ray,
+// so the traversal skips every node behind it. Results are the same either way, only
+// the speed depends on the profile still matching the view.
+#[derive(Debug)]
+pub(crate) struct SceneBvh {
+    packet_bvh: PacketBvh,
+    primatives: Vec<Arc<Primative>>,
+    profile: Option<TraversalProfile>,
+}
+
+impl SceneBvh {
+    pub fn build(primatives: Vec<Primative>) -> Self {
+        let primatives: Vec<_> = primatives.into_iter().map(Arc::new).collect();
+        Self {
+            packet_bvh: PacketBvh::build(primatives.iter().map(|p| p.bounds())),
+            primatives,
+            profile: None,
+        }
+    }
+
+    // Swaps in a new primitive at `index`, which rays only see after `rebuild`
+    pub fn replace(&mut self, index: usize, primative: Primative) {
+        self.primatives[index] = Arc::new(primative);
+    }
+
+    // Rebuilds the tree over the current primitives. Meshes and instanced objects keep
+    // the hierarchies they were built with, so only the top level is sorted again.
+    pub fn rebuild(&mut self) {
+        self.packet_bvh = PacketBvh::build(self.primatives.iter().map(|p| p.bounds()));
+        // A finished profile still counts the same primitives, so it orders the new tree
+        if let Some(profile) = &mut self.profile {
+            if profile.ordered.get().is_some() {
+                let ordered = profile.prioritized(&self.packet_bvh);
+                profile.ordered = OnceLock::from(ordered);
+            }
+        }
+    }
+
+    // Starts counting again from nothing
+    pub fn enable_profile(&mut self, ray_budget: u64) {
+        self.profile = Some(TraversalProfile {
+            hits: self.primatives.iter().map(|_| AtomicU32::new(0)).collect(),
+            rays: AtomicU64::new(0),
+            ray_budget,
+            ordered: OnceLock::new(),
+        });
+    }
+
+    // The profile while it is still counting rays
+    fn counting(&self) -> Option<&TraversalProfile> {
+        self.profile
+            .as_ref()
+            .filter(|profile| profile.ordered.get().is_none())
+    }
+
+    // The profiled ordering once there is one
+    fn tree(&self) -> &PacketBvh {
+        self.profile
+            .as_ref()
+            .and_then(|profile| profile.ordered.get())
+            .unwrap_or(&self.packet_bvh)
+    }
+
+    pub fn primatives(&self) -> impl Iterator<Item = &Primative> + '_ {
+        self.primatives.iter().map(|primative| primative.as_ref())
+    }
+
+    pub fn primative(&self, index: usize) -> &Primative {
+        &self.primatives[index]
+    }
+
+    // Nearest hit along `ray`, with any media it passes through drawing from `media`
+    pub fn ray_hit(
+        &self,
+        ray: &Ray3A,
+        t_min: Float,
+        t_max: Float,
+        media: &mut MediumRng,
+    ) -> Option<(Float, HitRecord)> {
+        let mut packet = RayPacket::new(std::slice::from_ref(ray), t_min, t_max);
+        let mut closest = None;
+        self.tree().traverse(&mut packet, |index, _, packet| {
+            let t_max = packet.t_max(0);
+            if let Some((t, rec)) = self.primatives[index].media_hit(ray, t_min, t_max, media) {
+                packet.set_t_max(0, t);
+                closest = Some((t, rec, index));
+            }
+        });
+
+        if let Some(profile) = self.counting() {
+            if let Some((_, _, index)) = closest {
+                profile.hits[index].fetch_add(1, Ordering::Relaxed);
+            }
+            if profile.rays.fetch_add(1, Ordering::Relaxed) + 1 >= profile.ray_budget {
+                profile
+                    .ordered
+                    .get_or_init(|| profile.prioritized(&self.packet_bvh));
+            }
+        }
+        closest.map(|(t, rec, _)| (t, rec))
+    }
+}
+
+impl SceneBvh {
+    // `ray_hit` for each ray of the packet, keeping only hits closer than those already
+    // in `hits`. Each lane's media draw from its own entry of `media`. Meshes take the
+    // packet down their own trees, anything else is tried a ray at a time. While a
+    // profile is counting, packets go a ray at a time so it sees every ray.
+    pub fn packet_hit(
+        &self,
+        packet: &mut RayPacket,
+        hits: &mut [Option<HitRecord>],
+        media: &mut [MediumRng],
+    ) {
+        if self.counting().is_some() {
+            for (lane, hit) in hits.iter_mut().enumerate().take(packet.len()) {
+                let ray = packet.ray(lane);
+                let found =
+                    self.ray_hit(&ray, packet.t_min(), packet.t_max(lane), &mut media[lane]);
+                if let Some((t, rec)) = found {
+                    packet.set_t_max(lane, t);
+                    *hit = Some(rec);
+                }
+            }
+            return;
+        }
+
+        self.tree().traverse(packet, |index, mut lanes, packet| {
+            match &*self.primatives[index] {
+                Primative::Mesh(mesh) => mesh.packet_hit(packet, hits),
+                primative => {
+                    while lanes != 0 {
+                        let lane = lanes.trailing_zeros() as usize;
+                        lanes &= lanes - 1;
+                        let ray = packet.ray(lane);
+                        let (t_min, t_max) = (packet.t_min(), packet.t_max(lane));
+                        if let Some((t, rec)) =
+                            primative.media_hit(&ray, t_min, t_max, &mut media[lane])
+                        {
+                            packet.set_t_max(lane, t);
+                            hits[lane] = Some(rec);
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+impl SceneBvh {
+    // Any-hit counterpart of `ray_hit`, for shadow rays. It returns at the first
+    // primitive in the way, and leaves the profile alone as that only ranks closest hits,
+    // though it does follow the profiled ordering once there is one.
+    pub fn occluded(&self, ray: &Ray3A, t_min: Float, t_max: Float, media: &mut MediumRng) -> bool {
+        let mut packet = RayPacket::new(std::slice::from_ref(ray), t_min, t_max);
+        let mut occluded = false;
+        self.tree().traverse(&mut packet, |index, _, packet| {
+            if self.primatives[index].occluded(ray, t_min, t_max, media) {
+                occluded = true;
+                packet.end(0);
+            }
+        });
+        occluded
+    }
+}
+
+impl Bounded<Bounds3A> for SceneBvh {
+    fn bounds(&self) -> Bounds3A {
+        self.packet_bvh.bounds()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MaterialKey, Point3, Transform, Vec3A, World, WorldBuilder};
+
+    #[test]
+    fn profile_visits_the_most_hit_primative_first() {
+        let key = MaterialKey::default();
+        let spheres = (0..10)
+            .map(|i| Primative::sphere(Point3::new(3.0 * i as Float, 0.0, 0.0), 1.0, key))
+            .collect();
+        let mut bvh = SceneBvh::build(spheres);
+        bvh.enable_profile(100);
+
+        let ray = |x: Float, y: Float| Ray3A {
+            origin: Point3::new(x, y, 10.0),
+            direction: -Vec3A::Z,
+        };
+        for i in 0..100 {
+            let y = 0.5 * (i as Float / 100.0 - 0.5);
+            assert!(bvh
+                .ray_hit(
+                    &ray(6.0, y),
+                    0.001,
+                    Float::INFINITY,
+                    &mut MediumRng::default()
+                )
+                .is_some());
+        }
+        assert!(bvh.counting().is_none());
+
+        // Nearer-first would start from the left end, the profile goes to the hot one
+        let across = Ray3A {
+            origin: Point3::new(-10.0, 0.0, 0.0),
+            direction: Vec3A::X,
+        };
+        let mut packet = RayPacket::new(std::slice::from_ref(&across), 0.001, Float::INFINITY);
+        let mut visited = Vec::new();
+        bvh.tree()
+            .traverse(&mut packet, |index, _, _| visited.push(index));
+        assert_eq!(visited.first(), Some(&2));
+        assert_eq!(visited.len(), 10);
+
+        // Other primitives are still found behind or beside the hot one
+        let (t, rec) = bvh
+            .ray_hit(
+                &ray(9.0, 0.0),
+                0.001,
+                Float::INFINITY,
+                &mut MediumRng::default(),
+            )
+            .unwrap();
+        assert!((t - 9.0).abs() < 1e-4);
+        assert!((rec.point - Point3::new(9.0, 0.0, 1.0)).length() < 1e-4);
+        assert!(bvh
+            .ray_hit(
+                &ray(1.5, 0.0),
+                0.001,
+                Float::INFINITY,
+                &mut MediumRng::default()
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn media_draw_from_the_rng_they_are_handed() {
+        let key = MaterialKey::default();
+        let boundary = Primative::sphere(Point3::ZERO, 1.0, key);
+        let mut world_builder = WorldBuilder::default();
+        world_builder.push_hittable(Primative::constant_medium(boundary, 1.0, key));
+        let world: World = world_builder.into();
+
+        let ray = Ray3A {
+            origin: Point3::new(0.0, 0.0, -2.0),
+            direction: Vec3A::Z,
+        };
+        let scatter = |seed| {
+            let mut media = MediumRng::new(&ray, seed);
+            world.closest_hit(&ray, &mut media).map(|(t, _)| t)
+        };
+        assert_eq!(scatter(5), scatter(5));
+        let times: Vec<_> = (0..16).filter_map(scatter).collect();
+        assert!(times.len() > 4 && times.iter().any(|t| *t != times[0]));
+    }
+
+    #[test]
+    fn moved_instances_are_hit_in_their_new_place() {
+        let key = MaterialKey::default();
+        let object = Arc::new(
+            Primative::mesh(
+                vec![
+                    Point3::new(-1.0, -1.0, 0.0),
+                    Point3::new(1.0, -1.0, 0.0),
+                    Point3::new(0.0, 1.0, 0.0),
+                ],
+                vec![(0, 1, 2)],
+                key,
+            )
+            .unwrap(),
+        );
+        let at = |x: Float| Transform::new(Vec3A::new(x, 0.0, 0.0), glam::Quat::IDENTITY, 1.0);
+        let mut world_builder = WorldBuilder::default();
+        let moving = world_builder.push_instance(Arc::clone(&object), at(0.0));
+        world_builder.push_instance(object, at(-5.0));
+        world_builder.push_hittable(Primative::sphere(Point3::new(5.0, 0.0, -5.0), 1.0, key));
+        let mut world: World = world_builder.into();
+
+        let ray = |x: Float| Ray3A {
+            origin: Point3::new(x, 0.0, 10.0),
+            direction: -Vec3A::Z,
+        };
+        assert!(world
+            .closest_hit(&ray(0.0), &mut MediumRng::default())
+            .is_some());
+        assert!(world
+            .closest_hit(&ray(10.0), &mut MediumRng::default())
+            .is_none());
+
+        world.move_instances([(moving, at(10.0))]);
+        assert!(world
+            .closest_hit(&ray(0.0), &mut MediumRng::default())
+            .is_none());
+        let (t, _) = world
+            .closest_hit(&ray(10.0), &mut MediumRng::default())
+            .unwrap();
+        assert!((t - 10.0).abs() < 1e-4);
+        // Everything else stays where it was
+        assert!(world
+            .closest_hit(&ray(-5.0), &mut MediumRng::default())
+            .is_some());
+        assert!(world
+            .closest_hit(&ray(5.0), &mut MediumRng::default())
+            .is_some());
+        assert_eq!(world.primitive_count(), 3);
+    }
+
+    #[test]
+    fn occlusion_agrees_with_closest_hits() {
+        use rand::{Rng, SeedableRng};
+
+        let key = MaterialKey::default();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(11);
+        let mut world_builder = WorldBuilder::default();
+        // A field of triangles, placed directly and through instances, among spheres
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        for _ in 0..64 {
+            let p = Point3::new(rng.gen_range(-4.0..4.0), rng.gen_range(-4.0..4.0), 0.0);
+            let n = vertices.len();
+            vertices.extend([p, p + Vec3A::X, p + Vec3A::Y]);
+            indices.push((n, n + 1, n + 2));
+        }
+        let mesh = crate::Mesh::new(vertices, indices, key).unwrap();
+        let at = |z: Float| Transform::new(Vec3A::new(0.0, 0.0, z), glam::Quat::IDENTITY, 1.0);
+        world_builder.push_hittable(Primative::Mesh(Arc::clone(&mesh)));
+        world_builder.push_instance(Arc::new(Primative::Mesh(mesh)), at(-3.0));
+        for _ in 0..8 {
+            let center = Point3::new(rng.gen_range(-4.0..4.0), rng.gen_range(-4.0..4.0), 3.0);
+            world_builder.push_hittable(Primative::sphere(center, 0.5, key));
+        }
+        let world: World = world_builder.into();
+
+        for _ in 0..2000 {
+            let ray = Ray3A {
+                origin: Point3::new(rng.gen_range(-5.0..5.0), rng.gen_range(-5.0..5.0), 6.0),
+                direction: Vec3A::new(rng.gen_range(-0.3..0.3), rng.gen_range(-0.3..0.3), -1.0),
+            };
+            let t_max = rng.gen_range(0.0..12.0);
+            let expected = match world.closest_hit(&ray, &mut MediumRng::default()) {
+                Some((t, _)) if (t - t_max).abs() < 1e-3 => continue,
+                Some((t, _)) => t < t_max,
+                None => false,
+            };
+            assert_eq!(
+                world.ray_occluded(&ray, t_max, &mut MediumRng::default()),
+                expected
+            );
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use crate::image::{Image, Rgba};
+use crate::render::accumulate;
+use crate::{
+    HitRecord, Integrator, PathIntegrator, PathState, Ray3A, RenderReport, SampleSequence, Sampler,
+    Scene,
+};
+
+use rayon::prelude::*;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+// Paths in flight at once. Passes work through the image this many pixels at a time.
+const STREAM_SIZE: usize = 1 << 16;
+
+// Paths of one stream, a column per stage. Intersection reads the paths and writes
+// `hits`, shading reads `hits` back, and only retiring paths needs `pixels`.
+struct PathStream {
+    paths: Vec<PathState>,
+    samplers: Vec<Sampler>,
+    pixels: Vec<usize>,
+    hits: Vec<Option<HitRecord>>,
+}
+
+impl PathStream {
+    // Writes out the radiance of finished paths and closes the gaps they leave,
+    // keeping the rest in pixel order
+    fn retire(&mut self, colors: &mut [Rgba], first_pixel: usize, max_ray_depth: usize) {
+        let mut kept = 0;
+        for index in 0..self.paths.len() {
+            let path = &self.paths[index];
+            if path.done || path.bounce >= max_ray_depth {
+                colors[self.pixels[index] - first_pixel] = path.radiance;
+                continue;
+            }
+            self.paths.swap(kept, index);
+            self.samplers.swap(kept, index);
+            self.pixels.swap(kept, index);
+            kept += 1;
+        }
+        self.paths.truncate(kept);
+        self.samplers.truncate(kept);
+        self.pixels.truncate(kept);
+    }
+}
+
+// Renders the same passes as `ParallelRenderer`, but instead of following each path to
+// its end it moves a whole stream of paths through one stage at a time: generate camera
+// rays, intersect every live path, shade every hit, then drop the finished paths. Each
+// stage is a tight loop over its own buffers, which keeps caches warm on large scenes.
+// Only integrators built on those stages can be split up like that. AOVs aren't
+// supported.
+#[derive(Debug)]
+pub struct WavefrontRenderer {
+    width: usize,
+    height: usize,
+    max_ray_depth: usize,
+    image: Image,
+    samples: usize,
+    rays_traced: u64,
+    render_time: Duration,
+    sequence: SampleSequence,
+    integrator: Box<dyn Integrator>,
+}
+
+impl WavefrontRenderer {
+    pub fn new(width: usize, height: usize, max_ray_depth: usize) -> Self {
+        Self {
+            width,
+            height,
+            max_ray_depth,
+            image: Image::new(width, height),
+            samples: 0,
+            rays_traced: 0,
+            render_time: Duration::ZERO,
+            sequence: SampleSequence::default(),
+            integrator: Box::new(PathIntegrator {
+                max_depth: max_ray_depth,
+            }),
+        }
+    }
+
+    // Starts over, as samples from different sequences don't mix well
+    pub fn set_sample_sequence(&mut self, sequence: SampleSequence) {
+        self.sequence = sequence;
+        self.reset();
+    }
+
+    // Starts over, with every sample from `integrator`. Ones that aren't the staged
+    // path tracer are called a pixel at a time.
+    pub fn set_integrator(&mut self, integrator: impl Integrator + 'static) {
+        self.integrator = Box::new(integrator);
+        self.reset();
+    }
+
+    // Adds one sample to every pixel
+    pub fn render(&mut self, scene: &Scene) -> &Image {
+        let start = Instant::now();
+        let (width, height) = (self.width, self.height);
+
+        for first_pixel in (0..width * height).step_by(STREAM_SIZE) {
+            let end = (first_pixel + STREAM_SIZE).min(width * height);
+            let colors = match self.integrator.path_depth() {
+                Some(depth) => self.trace_stream(scene, first_pixel..end, depth),
+                None => self.trace_pixels(scene, first_pixel..end),
+            };
+
+            let row: Vec<_> = colors.iter().flat_map(|c| c.to_array()).collect();
+            accumulate(
+                &mut self.image.data[first_pixel * 4..end * 4],
+                &row,
+                self.samples,
+            );
+        }
+
+        self.samples += 1;
+        self.render_time += start.elapsed();
+        &self.image
+    }
+
+    // The camera ray of each pixel in `pixels`, with the sampler that drew it
+    fn camera_rays<'a>(
+        &'a self,
+        scene: &'a Scene,
+        pixels: Range<usize>,
+    ) -> impl IndexedParallelIterator<Item = (Sampler, Ray3A)> + 'a {
+        let (width, height) = (self.width, self.height);
+        let sample_index = self.samples as u64;
+        pixels.into_par_iter().map(move |pixel| {
+            let (i, j) = (pixel % width, pixel / width);
+            let mut sampler = Sampler::new(i, j, sample_index, 0).with_sequence(self.sequence);
+            let ray = scene.sampler.get_ray(i, j, width, height, &mut sampler);
+            (sampler, ray)
+        })
+    }
+
+    fn trace_stream(&mut self, scene: &Scene, pixels: Range<usize>, depth: usize) -> Vec<Rgba> {
+        let world = &scene.world;
+        let polarizer = scene.sampler.polarizer();
+        let first_pixel = pixels.start;
+
+        let (samplers, paths): (Vec<_>, Vec<_>) = self
+            .camera_rays(scene, pixels.clone())
+            .map(|(sampler, ray)| (sampler, world.start_path(&ray, polarizer, false)))
+            .unzip();
+        let mut stream = PathStream {
+            paths,
+            samplers,
+            pixels: pixels.clone().collect(),
+            hits: Vec::new(),
+        };
+        let mut colors = vec![Rgba::ZERO; pixels.len()];
+        stream.retire(&mut colors, first_pixel, depth);
+
+        while !stream.paths.is_empty() {
+            self.rays_traced += stream.paths.len() as u64;
+            stream
+                .paths
+                .par_iter_mut()
+                .zip(&mut stream.samplers)
+                .map(|(path, sampler)| world.intersect_path(path, sampler))
+                .collect_into_vec(&mut stream.hits);
+
+            self.rays_traced += stream
+                .paths
+                .par_iter_mut()
+                .zip(&mut stream.samplers)
+                .zip(&stream.hits)
+                .map(|((path, sampler), hit)| {
+                    let mut rays = 0;
+                    world.shade_path(path, *hit, sampler, &mut rays, None);
+                    rays
+                })
+                .sum::<u64>();
+
+            stream.retire(&mut colors, first_pixel, depth);
+        }
+        colors
+    }
+
+    fn trace_pixels(&mut self, scene: &Scene, pixels: Range<usize>) -> Vec<Rgba> {
+        let (colors, rays): (Vec<_>, Vec<_>) = self
+            .camera_rays(scene, pixels)
+            .map(|(mut sampler, ray)| {
+                let mut rays = 0;
+                let color = self.integrator.li(scene, &ray, &mut sampler, &mut rays);
+                (color, rays)
+            })
+            .unzip();
+        self.rays_traced += rays.iter().sum::<u64>();
+        colors
+    }
+
+    // The accumulated linear light
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    // The image through the scene's post stages and display transform, ready to show
+    // or save
+    pub fn display_image(&self, scene: &Scene) -> Image {
+        scene.post.apply(&self.image)
+    }
+
+    pub fn samples_per_pixel(&self) -> usize {
+        self.samples
+    }
+
+    pub fn reset(&mut self) {
+        self.samples = 0;
+        self.rays_traced = 0;
+        self.render_time = Duration::ZERO;
+    }
+
+    pub fn report(&self, scene: &Scene) -> RenderReport {
+        RenderReport::new(
+            scene,
+            &self.image,
+            self.samples,
+            self.max_ray_depth,
+            self.rays_traced,
+            self.render_time,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{scenes, ParallelRenderer};
+
+    #[test]
+    fn matches_the_per_pixel_renderer() {
+        let scene = scenes::cornell_box(1.0);
+        let mut wavefront = WavefrontRenderer::new(24, 16, 6);
+        let mut parallel = ParallelRenderer::new(24, 16, 6);
+        for _ in 0..2 {
+            wavefront.render(&scene);
+            parallel.render(&scene);
+        }
+
+        assert_eq!(wavefront.samples_per_pixel(), 2);
+        assert_eq!(
+            wavefront.report(&scene).rays_traced,
+            parallel.report(&scene).rays_traced
+        );
+        for (a, b) in wavefront.image().data.iter().zip(&parallel.image().data) {
+            assert!((a - b).abs() <= 1e-4 * b.abs().max(1.0), "{} {}", a, b);
+        }
+    }
+}